@@ -1,14 +1,20 @@
 use std::convert::TryFrom;
 use std::ffi::OsStr;
 use std::{env, fs, path::PathBuf};
+use std::collections::hash_map::DefaultHasher;
 use std::fmt::Display;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
 use std::path::Path;
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, Instant};
 
 use anyhow::*;
 
 use embuild::{bindgen, build};
 use embuild::cargo;
-use embuild::cargo::{IntoWarning, workspace_dir};
+use embuild::cargo::workspace_dir;
 use embuild::kconfig;
 use embuild::pio;
 use embuild::pio::project;
@@ -16,43 +22,169 @@ use embuild::utils::{OsStrExt, PathExt};
 
 use walkdir::WalkDir;
 
+#[path = "src/ffi_deps.rs"]
+mod ffi_deps;
+#[path = "src/build_support.rs"]
+mod build_support;
+
+use build_support::{
+    bindings_modules, blocklisted_functions, enabled_injected_sdkconfigs, extra_bindings_source, find_kconfig_file,
+    idf_version_cfgs, idf_version_problem, is_transient, kconfig_envs, list_sdkconfig_fragments,
+    list_specific_sdkconfigs, parse_idf_version, parse_idf_version_header, sdkconfig_override_contents, sorted_cfgs,
+    toolchain_problem, transport_problem, LinkConfig, Lock, ProjectInputs, ResolvedPlatform, SdkconfigFiles, Timings,
+    SDKCONFIG_FILE,
+};
+
 const ESP_IDF_TOOLS_INSTALL_DIR_VAR: &str = "ESP_IDF_TOOLS_INSTALL_DIR";
 const ESP_IDF_SDKCONFIG_DEFAULTS_VAR: &str = "ESP_IDF_SDKCONFIG_DEFAULTS";
 const ESP_IDF_SDKCONFIG_VAR: &str = "ESP_IDF_SDKCONFIG";
 const MCU_VAR: &str = "MCU";
-const SDKCONFIG_FILE: &str = "sdkconfig";
+const PIO_LOG_LEVEL_VAR: &str = "ESP_HOMEKIT_PIO_LOG_LEVEL";
+const CLANG_TARGET_VAR: &str = "ESP_HOMEKIT_CLANG_TARGET";
+const PIO_VERSION_VAR: &str = "ESP_HOMEKIT_PIO_VERSION";
+const BUILD_TIMINGS_VAR: &str = "ESP_IDF_SYS_BUILD_TIMINGS";
+const IDF_VERSION_VAR: &str = "ESP_IDF_VERSION";
+const HOMEKIT_SDK_PATH_VAR: &str = "ESP_HOMEKIT_SDK_PATH";
+const HOMEKIT_SDK_TARBALL_VAR: &str = "ESP_HOMEKIT_SDK_TARBALL";
+const FORCE_BINDGEN_VAR: &str = "ESP_IDF_SYS_FORCE_BINDGEN";
+const CHECK_FAST_VAR: &str = "ESP_IDF_SYS_CHECK_FAST";
+const UPDATE_LOCK_VAR: &str = "ESP_IDF_SYS_UPDATE_LOCK";
+const TRANSPORT_VAR: &str = "ESP_HOMEKIT_TRANSPORT";
+const NET_RETRIES_VAR: &str = "ESP_IDF_SYS_NET_RETRIES";
+const SDKCONFIG_OVERRIDE_VAR: &str = "ESP_HOMEKIT_SDKCONFIG_OVERRIDE";
+const DRY_RUN_VAR: &str = "ESP_IDF_SYS_DRY_RUN";
+const BINDINGS_HEADERS_VAR: &str = "ESP_HOMEKIT_BINDINGS_HEADERS";
+const SKIP_TOOLCHAIN_CHECK_VAR: &str = "ESP_IDF_SYS_SKIP_TOOLCHAIN_CHECK";
+const ARTIFACT_CACHE_VAR: &str = "ESP_IDF_SYS_ARTIFACT_CACHE";
+const ARTIFACT_CACHE_READ_ONLY_VAR: &str = "ESP_IDF_SYS_ARTIFACT_CACHE_READ_ONLY";
+/// The env variables a dry run reports, besides `PROFILE` and the resolution.
+const DRY_RUN_ENV_VARS: &[&str] = &[
+    MCU_VAR,
+    ESP_IDF_TOOLS_INSTALL_DIR_VAR,
+    ESP_IDF_SDKCONFIG_VAR,
+    ESP_IDF_SDKCONFIG_DEFAULTS_VAR,
+    SDKCONFIG_OVERRIDE_VAR,
+    IDF_VERSION_VAR,
+    HOMEKIT_SDK_TARBALL_VAR,
+    TRANSPORT_VAR,
+];
+/// The delay before the first retry of a network step, doubled on every further one.
+const NET_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Builds slower than this always report their timings.
+const BUILD_TIMINGS_THRESHOLD: Duration = Duration::from_secs(600);
 const SDKCONFIG_DEFAULTS_FILE: &str = "sdkconfig.defaults";
 const TOOLS_WORKSPACE_INSTALL_DIR: &str = ".embuild";
+/// The artifact cache of `$ESP_IDF_SYS_ARTIFACT_CACHE=workspace`, in the workspace.
+const ARTIFACT_CACHE_WORKSPACE_DIR: &str = ".embuild/esp-homekit-sdk-cache";
+/// The path of the project an artifact cache entry was built in.
+const ARTIFACT_CACHE_ORIGIN_FILE: &str = "origin";
+/// Linkable wrappers of `static inline` SDK functions, compiled into the PlatformIO project.
+const SHIM_FILES: &[&str] = &["esp_homekit_shim.h", "esp_homekit_shim.c"];
+const BINDINGS_FILE: &str = "bindings.rs";
+/// Stored next to [`BINDINGS_FILE`], see [`bindings_hash`].
+const BINDINGS_HASH_FILE: &str = "bindings.hash";
+/// The module declarations of the headers of `$ESP_HOMEKIT_BINDINGS_HEADERS`, included
+/// by `lib.rs`. Written next to [`BINDINGS_FILE`] even if there are none.
+const EXTRA_BINDINGS_FILE: &str = "extra_bindings.rs";
+/// Stored next to [`BINDINGS_FILE`], see [`CheckCache`].
+const CHECK_CACHE_FILE: &str = "check-cache.txt";
+/// The hand-maintained stand-in for the bindings of the `mock` feature, relative to the
+/// crate root.
+const MOCK_BINDINGS_FILE: &str = "src/mock/bindings.rs";
+/// The headers of the `mock` feature for the C code of dependents, relative to the
+/// crate root.
+const MOCK_INCLUDE_DIR: &str = "src/mock/include";
+/// Written to the workspace dir, see [`Lock`].
+const LOCK_FILE: &str = "esp-homekit-sdk-sys.lock";
+const IDF_VERSION_HEADER: &str = "components/esp_common/include/esp_idf_version.h";
+/// The name of the esp-homekit-sdk library in the `lib_deps` of the PlatformIO project.
+const HOMEKIT_SDK_LIB: &str = "esp-homekit-sdk";
+/// The revision of esp-homekit-sdk pulled in through `.env`.
+const HOMEKIT_SDK_REV: &str = "esp-homekit-sdk#platformio";
+/// The `(major, minor)` ESP-IDF releases [`HOMEKIT_SDK_REV`] builds against.
+const HOMEKIT_SDK_IDF_VERSIONS: &[(u32, u32)] = &[(4, 2), (4, 3), (4, 4)];
 
-fn list_specific_sdkconfigs(
-    path: PathBuf,
-    profile: &str,
-    chip: &str,
-) -> impl DoubleEndedIterator<Item = PathBuf> {
-    path.file_name()
-        .and_then(|filename| filename.try_to_str().into_warning())
-        .map(|filename| {
-            let profile_specific = format!("{}.{}", filename, profile);
-            let chip_specific = format!("{}.{}", filename, chip);
-            let profile_chip_specific = format!("{}.{}", &profile_specific, chip);
-
-            [
-                profile_chip_specific,
-                chip_specific,
-                profile_specific,
-                filename.to_owned(),
-            ]
-        })
-        .into_iter()
-        .flatten()
-        .filter_map(move |s| {
-            let path = path.with_file_name(s);
-            if path.is_file() {
-                Some(path)
-            } else {
-                None
+/// Write the `CONFIG_X=y;CONFIG_Y=1` pairs of `$ESP_HOMEKIT_SDKCONFIG_OVERRIDE` as an
+/// sdkconfig defaults file to `out_dir`, `None` if the variable is unset or empty.
+///
+/// Layered last, so each pair wins over the same option in any sdkconfig file.
+fn sdkconfig_override(out_dir: &Path) -> Result<Option<(PathBuf, PathBuf)>> {
+    const FILE: &str = "sdkconfig.defaults.override";
+
+    let var = env::var(SDKCONFIG_OVERRIDE_VAR).unwrap_or_default();
+    let contents = sdkconfig_override_contents(&var)
+        .map_err(|e| anyhow!("Invalid ${SDKCONFIG_OVERRIDE_VAR}: {}", e))?;
+
+    if contents.is_empty() {
+        return Ok(None);
+    }
+
+    let path = out_dir.join(FILE);
+    fs::write(&path, contents)?;
+
+    Ok(Some((path, PathBuf::from(FILE))))
+}
+
+/// The `CARGO_FEATURE_*` names of the enabled features, e.g. `SILENT_RELEASE`.
+fn enabled_features() -> Vec<String> {
+    env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(str::to_owned))
+        .collect()
+}
+
+/// Write the [`build_support::INJECTED_SDKCONFIGS`] of the enabled features for `profile` and `mcu`
+/// to `out_dir`.
+///
+/// Returns them as `(path, file name in the project)` in the order of `INJECTED_SDKCONFIGS`.
+fn injected_sdkconfigs(out_dir: &Path, profile: &str, mcu: &str) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut files = Vec::new();
+
+    for injected in enabled_injected_sdkconfigs(&enabled_features(), profile, mcu) {
+        let path = out_dir.join(injected.file);
+        fs::write(&path, injected.contents)?;
+
+        files.push((path, PathBuf::from(injected.file)));
+    }
+
+    Ok(files)
+}
+
+/// Warn about every option of the verified [`build_support::INJECTED_SDKCONFIGS`] which the `sdkconfig`
+/// the build resolved to doesn't have, either because the user's sdkconfig overrides
+/// it or because the ESP-IDF version renamed it.
+fn check_injected_sdkconfigs(sdkconfig: &Path, profile: &str, mcu: &str) -> Result<()> {
+    let resolved = fs::read_to_string(sdkconfig)
+        .with_context(|| format!("Failed to read the kconfig '{}'", sdkconfig.display()))?;
+
+    let features = enabled_features();
+
+    for injected in enabled_injected_sdkconfigs(&features, profile, mcu).filter(|injected| injected.verify) {
+        for option in injected.contents.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let key = option.split('=').next().unwrap_or_default();
+
+            let actual = resolved.lines().map(str::trim).find(|line| {
+                line.strip_prefix(key).is_some_and(|rest| rest.starts_with('='))
+                    || *line == format!("# {} is not set", key)
+            });
+
+            match actual {
+                Some(actual) if actual == option => (),
+                Some(actual) => cargo::print_warning(format_args!(
+                    "The `{}` feature sets `{}`, but the sdkconfig overrides it with `{}`",
+                    injected.feature.to_lowercase().replace('_', "-"),
+                    option,
+                    actual
+                )),
+                None => cargo::print_warning(format_args!(
+                    "The `{}` feature sets `{}`, which is not in the sdkconfig of this ESP-IDF version",
+                    injected.feature.to_lowercase().replace('_', "-"),
+                    key
+                )),
             }
-        })
+        }
+    }
+
+    Ok(())
 }
 
 #[derive(Clone, Debug)]
@@ -135,10 +267,844 @@ impl Display for InstallDir {
     }
 }
 
+impl From<&pio::Resolution> for ResolvedPlatform {
+    fn from(resolution: &pio::Resolution) -> Self {
+        Self {
+            board: resolution.board.clone(),
+            mcu: resolution.mcu.clone(),
+            platform: resolution.platform.clone(),
+            frameworks: resolution.frameworks.clone(),
+            target: resolution.target.clone(),
+        }
+    }
+}
+
+/// Configure the project `builder` from `inputs` alone, see [`ProjectInputs`].
+fn apply_project_inputs(inputs: &ProjectInputs, builder: &mut project::Builder) {
+    for (name, package) in &inputs.platform_packages {
+        builder.platform_package(name, package.clone());
+    }
+
+    builder
+        .enable_scons_dump()
+        .enable_c_entry_points()
+        .options(inputs.options.iter().cloned())
+        .files(inputs.files.iter().cloned());
+}
+
+/// Built PlatformIO projects shared by the crates of a workspace, see
+/// `$ESP_IDF_SYS_ARTIFACT_CACHE`.
+///
+/// An entry is a copy of the project after `pio run`, with its archives, the scons
+/// dump and the installed SDK, in a directory named by [`ProjectInputs::cache_key`].
+/// Entries are copied to a temporary directory first and renamed into place, so a
+/// concurrent build sees either all of an entry or none of it.
+#[derive(Debug)]
+struct ArtifactCache {
+    dir: PathBuf,
+    /// `false` with `$ESP_IDF_SYS_ARTIFACT_CACHE_READ_ONLY`, e.g. for CI runners
+    /// sharing a cache populated elsewhere.
+    writable: bool,
+}
+
+impl ArtifactCache {
+    fn from_env(workspace_dir: &Path) -> Option<Self> {
+        let dir = env::var(ARTIFACT_CACHE_VAR).ok()?;
+        let dir = match dir.trim() {
+            "" => return None,
+            "workspace" => workspace_dir.join(ARTIFACT_CACHE_WORKSPACE_DIR),
+            dir => Path::new(dir).abspath_relative_to(workspace_dir),
+        };
+        let writable = !env::var(ARTIFACT_CACHE_READ_ONLY_VAR)
+            .map(|v| v.trim() == "1")
+            .unwrap_or(false);
+
+        Some(Self { dir, writable })
+    }
+
+    /// Copy the entry of `key` over the project at `project_path`, `false` if there is none.
+    fn restore(&self, key: &str, project_path: &Path) -> Result<bool> {
+        let entry = self.dir.join(key);
+        let origin = match fs::read_to_string(entry.join(ARTIFACT_CACHE_ORIGIN_FILE)).ok() {
+            Some(origin) => origin,
+            None => return Ok(false),
+        };
+
+        copy_dir(&entry.join("project"), project_path)?;
+
+        // The scons dump has absolute paths into the project the entry was built in.
+        let project = project_path.try_to_str()?;
+        for file in fs::read_dir(project_path)? {
+            let path = file?.path();
+            if path.is_file() && path.extension() == Some(OsStr::new("json")) {
+                let dump = fs::read_to_string(&path)?;
+                fs::write(&path, dump.replace(origin.as_str(), project))?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Add the project at `project_path` as the entry of `key`, unless there is one
+    /// already or the cache is read-only.
+    fn store(&self, key: &str, project_path: &Path) -> Result<()> {
+        let entry = self.dir.join(key);
+        if !self.writable || entry.is_dir() {
+            return Ok(());
+        }
+
+        let temp = self.dir.join(format!(".{}.{}", key, std::process::id()));
+        if temp.exists() {
+            fs::remove_dir_all(&temp)?;
+        }
+
+        copy_dir(project_path, &temp.join("project"))?;
+        fs::write(temp.join(ARTIFACT_CACHE_ORIGIN_FILE), project_path.try_to_str()?)?;
+
+        // Fails if another build stored the same entry in the meantime.
+        if fs::rename(&temp, &entry).is_err() {
+            fs::remove_dir_all(&temp)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Recursively copy the files of `from` into `to`, replacing existing ones.
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    for entry in WalkDir::new(from) {
+        let entry = entry?;
+        let dest = to.join(entry.path().strip_prefix(from)?);
+
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&dest)?;
+        } else if entry.file_type().is_file() {
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write `build-timings.json` into `OUT_DIR` and print the summary of `timings` as a
+/// warning if `$ESP_IDF_SYS_BUILD_TIMINGS` is `1` or the build took longer than the threshold.
+fn report_timings(timings: &Timings) -> Result<()> {
+    fs::write(cargo::out_dir().join("build-timings.json"), timings.to_json())?;
+
+    let enabled = env::var(BUILD_TIMINGS_VAR).map(|v| v.trim() == "1").unwrap_or(false);
+    if enabled || timings.total() > BUILD_TIMINGS_THRESHOLD {
+        cargo::print_warning(format_args!(
+            "esp-homekit-sdk-sys build timings: {}",
+            timings.summary()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Get the PlatformIO log level from the [`PIO_LOG_LEVEL_VAR`] env variable.
+///
+/// Accepts `quiet`, `standard` or `verbose` (case-insensitive); defaults to `standard`
+/// if unset or empty.
+fn pio_log_level() -> Result<pio::LogLevel> {
+    let level = env::var(PIO_LOG_LEVEL_VAR).unwrap_or_default();
+
+    match level.trim().to_lowercase().as_str() {
+        "" | "standard" => Ok(pio::LogLevel::Standard),
+        "quiet" => Ok(pio::LogLevel::Quiet),
+        "verbose" => Ok(pio::LogLevel::Verbose),
+        other => bail!(
+            "Invalid ${PIO_LOG_LEVEL_VAR} '{}'. Should be one of `quiet`, `standard` or `verbose`.",
+            other
+        ),
+    }
+}
+
+/// Get the PlatformIO core version to pin from the [`PIO_VERSION_VAR`] env variable.
+///
+/// Returns `None` if unset or empty, in which case the latest version is used.
+fn pio_version() -> Option<String> {
+    env::var(PIO_VERSION_VAR)
+        .ok()
+        .map(|v| v.trim().to_owned())
+        .filter(|v| !v.is_empty())
+}
+
+/// The version reported by `platformio --version`, e.g. `6.1.5`.
+fn installed_pio_version(pio: &pio::Pio) -> Result<String> {
+    let output = Command::new(&pio.platformio_exe).arg("--version").output()?;
+    if !output.status.success() {
+        bail!("'{} --version' failed", pio.platformio_exe.display());
+    }
+
+    // E.g. `PlatformIO Core, version 6.1.5`
+    String::from_utf8(output.stdout)?
+        .split_whitespace()
+        .last()
+        .map(ToOwned::to_owned)
+        .ok_or_else(|| anyhow!("Unexpected output from '{} --version'", pio.platformio_exe.display()))
+}
+
+/// Make the installed PlatformIO core match `version`.
+///
+/// `Pio::install` always installs the latest core, so the pinned version is installed
+/// over it with the pip of the PlatformIO virtualenv, next to the `platformio` executable.
+fn pin_pio_version(pio: &pio::Pio, version: &str) -> Result<()> {
+    if installed_pio_version(pio)? == version {
+        return Ok(());
+    }
+
+    let python = pio
+        .platformio_exe
+        .with_file_name(if cfg!(windows) { "python.exe" } else { "python" });
+
+    eprintln!("Pinning platformio core to version {}", version);
+
+    let status = Command::new(&python)
+        .args(["-m", "pip", "install", "--quiet"])
+        .arg(format!("platformio=={}", version))
+        .status()
+        .with_context(|| format!("Failed to run '{}'", python.display()))?;
+    if !status.success() {
+        bail!("Failed to install platformio core version {}", version);
+    }
+
+    Ok(())
+}
+
+/// Get the number of attempts of network steps from the [`NET_RETRIES_VAR`] env variable,
+/// 3 if unset or empty.
+fn net_attempts() -> Result<u32> {
+    let attempts = env::var(NET_RETRIES_VAR).unwrap_or_default();
+
+    match attempts.trim() {
+        "" => Ok(3),
+        attempts => attempts
+            .parse::<u32>()
+            .ok()
+            .filter(|&attempts| attempts > 0)
+            .ok_or_else(|| anyhow!("Invalid ${NET_RETRIES_VAR} '{}'. Should be a positive number.", attempts)),
+    }
+}
+
+/// Run the network step `f` up to `attempts` times, with exponential backoff while
+/// it fails with a transient error. Permanent errors are returned right away.
+fn with_net_retries<T>(step: &str, attempts: u32, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut delay = NET_RETRY_DELAY;
+    let mut attempt = 1;
+
+    loop {
+        match f() {
+            Err(err) if attempt < attempts && is_transient(&format!("{:#}", err)) => {
+                cargo::print_warning(format_args!(
+                    "{} failed ({:#}), retrying in {}s ({}/{})",
+                    step,
+                    err,
+                    delay.as_secs(),
+                    attempt,
+                    attempts - 1
+                ));
+
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Run `pio lib --global install`, with its output in the error so that it can be
+/// told apart by [`is_transient`].
+fn pio_lib_install(pio: &pio::Pio) -> Result<()> {
+    let output = pio.cmd().args(["lib", "--global", "install"]).output()?;
+
+    io::stderr().write_all(&output.stdout)?;
+    io::stderr().write_all(&output.stderr)?;
+
+    if !output.status.success() {
+        bail!(
+            "'pio lib --global install' failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+/// Derive the sysroot and GCC include dirs from the toolchain's `compiler`.
+///
+/// Makes clang see the same newlib and GCC headers as the C build instead of
+/// the host ones, so that `long double`, `wchar_t` and friends get the target sizes.
+fn toolchain_clang_args(compiler: &Path) -> Result<Vec<String>> {
+    let query = |arg: &str| -> Result<String> {
+        let output = Command::new(compiler).arg(arg).output()?;
+        if !output.status.success() {
+            bail!("'{} {}' failed", compiler.display(), arg);
+        }
+
+        Ok(String::from_utf8(output.stdout)?.trim().to_owned())
+    };
+
+    let sysroot = PathBuf::from(query("-print-sysroot")?);
+    if sysroot.as_os_str().is_empty() || !sysroot.is_dir() {
+        bail!("'{}' reports no usable sysroot", compiler.display());
+    }
+
+    let mut args = vec![format!("--sysroot={}", sysroot.display())];
+    for dir in ["include", "include-fixed"] {
+        let path = PathBuf::from(query(&format!("-print-file-name={}", dir))?);
+        if path.is_dir() {
+            args.push(format!("-isystem{}", path.display()));
+        }
+    }
+    args.push(format!("-isystem{}", sysroot.join("include").display()));
+
+    Ok(args)
+}
+
+/// Read the ESP-IDF version from `esp_idf_version.h` in the resolved framework, see
+/// [`parse_idf_version_header`].
+fn esp_idf_version(framework_dir: &Path) -> Result<(u32, u32, u32)> {
+    let header = framework_dir.join(IDF_VERSION_HEADER);
+    let content = fs::read_to_string(&header)
+        .with_context(|| format!("Failed to read ESP-IDF version from '{}'", header.display()))?;
+
+    parse_idf_version_header(&content).map_err(|e| anyhow!("{} in '{}'", e, header.display()))
+}
+
+/// The additional headers of `$ESP_HOMEKIT_BINDINGS_HEADERS` with their module names,
+/// as `;`-separated paths relative to the workspace directory, see [`bindings_modules`].
+fn extra_bindings_headers() -> Result<Vec<(String, PathBuf)>> {
+    let headers = env::var(BINDINGS_HEADERS_VAR).unwrap_or_default();
+    let workspace_dir = workspace_dir().ok_or_else(|| anyhow!("No workspace"))?;
+
+    let mut paths = Vec::new();
+    for header in headers.split(';').map(str::trim).filter(|h| !h.is_empty()) {
+        let path = Path::new(header).abspath_relative_to(&workspace_dir);
+        if !path.is_file() {
+            bail!("${BINDINGS_HEADERS_VAR} lists '{}', which doesn't exist", path.display());
+        }
+
+        cargo::track_file(&path);
+        paths.push(path);
+    }
+
+    bindings_modules(paths).map_err(|e| anyhow!("Invalid ${BINDINGS_HEADERS_VAR}: {}", e))
+}
+
+/// Hash everything the generated bindings depend on: the `header` and the bindgen
+/// `flags` (clang args, allow/blocklists and other options), the headers in the
+/// include dirs and the `sdkconfig`, whose macros affect struct layouts.
+///
+/// Include dirs are hashed by file path, size and modification time rather than
+/// content, which notices SDK and toolchain updates without reading every header.
+fn bindings_hash(header: &Path, flags: &[String], sdkconfig: &Path) -> Result<String> {
+    let mut hasher = DefaultHasher::new();
+
+    fs::read(header)?.hash(&mut hasher);
+    flags.hash(&mut hasher);
+    fs::read(sdkconfig)?.hash(&mut hasher);
+
+    let include_dirs = flags
+        .iter()
+        .filter_map(|flag| flag.strip_prefix("-I").or_else(|| flag.strip_prefix("-isystem")));
+    for dir in include_dirs {
+        for entry in WalkDir::new(dir).sort_by_file_name().into_iter().filter_map(|e| e.ok()) {
+            if entry.path().extension() == Some(OsStr::new("h")) {
+                let metadata = entry.metadata()?;
+
+                entry.path().hash(&mut hasher);
+                metadata.len().hash(&mut hasher);
+                metadata.modified()?.hash(&mut hasher);
+            }
+        }
+    }
+
+    Ok(format!("{:016x}", hasher.finish()))
+}
+
+/// Extract the SDK tarball of `$ESP_HOMEKIT_SDK_TARBALL`, if set, into `out_dir`.
+///
+/// The tarball is a `.tar.gz` of the `platformio` branch of esp-homekit-sdk, with
+/// `library.json` and `components/` either at its root or in a single top-level
+/// directory, as in the archives GitHub generates for a revision. Returns the root of
+/// the extracted SDK, to be used as the library in place of the one PlatformIO downloads.
+fn extract_sdk_tarball(out_dir: &Path) -> Result<Option<PathBuf>> {
+    let tarball = match env::var_os(HOMEKIT_SDK_TARBALL_VAR).filter(|p| !p.is_empty()) {
+        Some(tarball) => match workspace_dir() {
+            Some(workspace_dir) => Path::new(&tarball).abspath_relative_to(workspace_dir),
+            None => PathBuf::from(tarball),
+        },
+        None => return Ok(None),
+    };
+    if !tarball.is_file() {
+        bail!("${HOMEKIT_SDK_TARBALL_VAR} '{}' is not a file", tarball.display());
+    }
+    cargo::track_file(&tarball);
+
+    let dir = out_dir.join(format!("{}-vendored", HOMEKIT_SDK_LIB));
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+
+    let status = Command::new("tar")
+        .arg("-xzf")
+        .arg(&tarball)
+        .arg("-C")
+        .arg(&dir)
+        .status()
+        .context("Failed to run `tar`")?;
+    if !status.success() {
+        bail!("Failed to extract ${HOMEKIT_SDK_TARBALL_VAR} '{}': {}", tarball.display(), status);
+    }
+
+    let is_sdk = |path: &Path| path.join("components").join("hap").is_dir();
+    let root = if is_sdk(&dir) {
+        Some(dir.clone())
+    } else {
+        let mut entries = fs::read_dir(&dir)?.collect::<Result<Vec<_>, _>>()?;
+        match (entries.pop(), entries.is_empty()) {
+            (Some(entry), true) if is_sdk(&entry.path()) => Some(entry.path()),
+            _ => None,
+        }
+    };
+
+    root.map(Some).ok_or_else(|| {
+        anyhow!(
+            "${HOMEKIT_SDK_TARBALL_VAR} '{}' doesn't contain `components/hap`, \
+             neither at its root nor in a single top-level directory",
+            tarball.display()
+        )
+    })
+}
+
+/// Fail if the HAP transport of `$ESP_HOMEKIT_TRANSPORT` isn't available on `mcu`, see
+/// [`transport_problem`].
+fn check_transport(mcu: &str, cfg_args: &build::CfgArgs) -> Result<()> {
+    let transport = env::var(TRANSPORT_VAR).unwrap_or_default();
+
+    match transport_problem(&transport, mcu, &cfg_args.args) {
+        Some(problem) => bail!(problem),
+        None => Ok(()),
+    }
+}
+
+/// Fail before the SDK is built if the Rust toolchain can't build `$TARGET`, see
+/// [`toolchain_problem`]. Skipped if `$ESP_IDF_SYS_SKIP_TOOLCHAIN_CHECK` is `1`.
+fn check_toolchain() -> Result<()> {
+    cargo::track_env_var(SKIP_TOOLCHAIN_CHECK_VAR);
+    if env::var(SKIP_TOOLCHAIN_CHECK_VAR).map(|v| v.trim() == "1").unwrap_or(false) {
+        return Ok(());
+    }
+
+    let target = env::var("TARGET")?;
+    let toolchain = env::var("RUSTUP_TOOLCHAIN").ok();
+
+    // Only needed to recognize a fork other than espup's.
+    let targets = if target.starts_with("xtensa-") {
+        let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+        Command::new(rustc)
+            .args(["--print", "target-list"])
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).lines().map(str::to_owned).collect())
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    match toolchain_problem(&target, env::var(MCU_VAR).ok().as_deref(), toolchain.as_deref(), &targets) {
+        Some(problem) => bail!("{} Set ${SKIP_TOOLCHAIN_CHECK_VAR} to `1` to skip this check.", problem),
+        None => Ok(()),
+    }
+}
+
+/// Locate the esp-homekit-sdk checkout the C build compiled.
+///
+/// `$ESP_HOMEKIT_SDK_PATH` takes precedence. Otherwise the library is found through the
+/// include flags of the build, which works both for the project generated by this script
+/// and for a PIO-first project declaring the SDK in its own `lib_deps`, and finally in
+/// the libdeps dir of the project.
+fn homekit_sdk_dir(scons_vars: &project::SconsVariables) -> Result<PathBuf> {
+    if let Some(path) = env::var_os(HOMEKIT_SDK_PATH_VAR).filter(|p| !p.is_empty()) {
+        let path = match workspace_dir() {
+            Some(workspace_dir) => Path::new(&path).abspath_relative_to(workspace_dir),
+            None => PathBuf::from(path),
+        };
+        if !path.is_dir() {
+            bail!("${HOMEKIT_SDK_PATH_VAR} '{}' is not a directory", path.display());
+        }
+
+        return Ok(path);
+    }
+
+    let from_incflags = scons_vars
+        .incflags
+        .split_whitespace()
+        .filter_map(|flag| flag.strip_prefix("-I"))
+        .map(|dir| Path::new(dir.trim_matches('"')))
+        .find_map(|dir| {
+            dir.ancestors()
+                .find(|ancestor| ancestor.file_name() == Some(OsStr::new(HOMEKIT_SDK_LIB)))
+                .map(Path::to_path_buf)
+        });
+    if let Some(path) = from_incflags {
+        return Ok(path);
+    }
+
+    let env = if scons_vars.release_build { "release" } else { "debug" };
+    let path = scons_vars.project_dir.join(".pio").join("libdeps").join(env).join(HOMEKIT_SDK_LIB);
+    if !path.is_dir() {
+        bail!(
+            "{} not found in the PlatformIO project at '{}', add it to `lib_deps` \
+             or set ${HOMEKIT_SDK_PATH_VAR}",
+            HOMEKIT_SDK_LIB,
+            scons_vars.project_dir.display()
+        );
+    }
+
+    Ok(path)
+}
+
+/// How `$ESP_IDF_VERSION` pins the ESP-IDF used for the build.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum IdfPin {
+    /// `x.y.z`: pins the `framework-espidf` package to that ESP-IDF release.
+    Framework((u32, u32, u32)),
+    /// `espressif32@x.y.z`: pins the PlatformIO platform, which implies its ESP-IDF.
+    Platform(String),
+}
+
+impl IdfPin {
+    fn from_env() -> Result<Option<Self>> {
+        let value = env::var(IDF_VERSION_VAR).unwrap_or_default();
+        let value = value.trim();
+
+        if value.is_empty() {
+            Ok(None)
+        } else if value.starts_with("espressif32@") {
+            Ok(Some(Self::Platform(value.to_owned())))
+        } else {
+            parse_idf_version(value)
+                .map_err(Error::msg)
+                .map(|version| Some(Self::Framework(version)))
+                .with_context(|| {
+                    format!(
+                        "Invalid ${IDF_VERSION_VAR} '{}'. \
+                         Should be `<major>.<minor>.<patch>` or `espressif32@<version>`.",
+                        value
+                    )
+                })
+        }
+    }
+
+    /// The `framework-espidf` package version, encoded by PlatformIO as
+    /// `3.<major><minor><patch>.0` with two digits each, e.g. `3.40404.0` for 4.4.4.
+    fn framework_package(&self) -> Option<String> {
+        match self {
+            Self::Framework((major, minor, patch)) => {
+                Some(format!("~3.{}{:02}{:02}.0", major, minor, patch))
+            }
+            Self::Platform(_) => None,
+        }
+    }
+}
+
+/// Fail with a readable error if [`HOMEKIT_SDK_REV`] doesn't support the ESP-IDF `version`.
+fn check_idf_version(version: (u32, u32, u32)) -> Result<()> {
+    match idf_version_problem(version, HOMEKIT_SDK_IDF_VERSIONS) {
+        Some(problem) => bail!(
+            "HomeKit SDK {} {} - set ${IDF_VERSION_VAR} or update the SDK",
+            HOMEKIT_SDK_REV,
+            problem
+        ),
+        None => Ok(()),
+    }
+}
+
+/// The versions PlatformIO actually installed for a build, see [`Lock`].
+fn resolved_lock(scons_vars: &project::SconsVariables, sdk_dir: &Path) -> Result<Lock> {
+    let version = |path: PathBuf| -> Result<String> {
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read the version from '{}'", path.display()))?;
+
+        regex::Regex::new(r#""version"\s*:\s*"([^"]+)""#)?
+            .captures(&content)
+            .map(|c| c[1].to_owned())
+            .ok_or_else(|| anyhow!("No version in '{}'", path.display()))
+    };
+
+    Ok(Lock {
+        platform: version(Path::new(&scons_vars.pio_platform_dir).join("platform.json"))?,
+        framework: version(Path::new(&scons_vars.pio_framework_dir).join("package.json"))?,
+        sdk: version(sdk_dir.join(".piopm"))?,
+    })
+}
+
+/// Everything a build emits besides the bindings and the link args.
+///
+/// Cached after every full build, so that with `$ESP_IDF_SYS_CHECK_FAST` set to `1`
+/// (e.g. in the environment of rust-analyzer) later runs can emit it again without
+/// building the SDK. The crate then type-checks against the bindings of the last full
+/// build but can't be linked, since no link args are emitted.
+struct CheckCache {
+    env_path: String,
+    sdk_path: String,
+    esp_idf_path: String,
+    idf_version: (u32, u32, u32),
+    incl_args: String,
+    mcu: String,
+    cfgs: Vec<String>,
+    /// The `ESP_IDF_CONFIG_*` envs of the integer and string values of the kconfig, see
+    /// [`kconfig_envs`].
+    kconfig_envs: Vec<(String, String)>,
+}
+
+impl CheckCache {
+    fn load(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        let mut lines = content.lines();
+        let mut field = |name: &str| lines.next()?.strip_prefix(name)?.strip_prefix('=').map(str::to_owned);
+
+        let env_path = field("env_path")?;
+        let sdk_path = field("sdk_path")?;
+        let esp_idf_path = field("esp_idf_path")?;
+        let idf_version = parse_idf_version(&field("idf_version")?).ok()?;
+        let incl_args = field("incl_args")?;
+        let mcu = field("mcu")?;
+        let mut cfgs = Vec::new();
+        let mut kconfig_envs = Vec::new();
+        for line in lines {
+            if let Some(cfg) = line.strip_prefix("cfg=") {
+                cfgs.push(cfg.to_owned());
+            } else {
+                let (key, value) = line.strip_prefix("env=")?.split_once('=')?;
+                kconfig_envs.push((key.to_owned(), value.to_owned()));
+            }
+        }
+
+        Some(Self { env_path, sdk_path, esp_idf_path, idf_version, incl_args, mcu, cfgs, kconfig_envs })
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let (major, minor, patch) = self.idf_version;
+        let mut content = format!(
+            "env_path={}\nsdk_path={}\nesp_idf_path={}\nidf_version={}.{}.{}\nincl_args={}\nmcu={}\n",
+            self.env_path, self.sdk_path, self.esp_idf_path, major, minor, patch, self.incl_args, self.mcu
+        );
+        for cfg in &self.cfgs {
+            content.push_str(&format!("cfg={}\n", cfg));
+        }
+        for (key, value) in &self.kconfig_envs {
+            content.push_str(&format!("env={}={}\n", key, value));
+        }
+
+        fs::write(path, content)?;
+
+        Ok(())
+    }
+
+    fn emit(&self) {
+        let cfg_args = build::CfgArgs { args: self.cfgs.clone() };
+        cfg_args.propagate();
+        cfg_args.output();
+        // Only emitted when enabled, declared so the crate may test for it.
+        println!("cargo:rustc-check-cfg=cfg(esp_idf_mbedtls_external_mem_alloc)");
+
+        cargo::set_metadata("EMBUILD_ENV_PATH", &self.env_path);
+        cargo::set_metadata("HOMEKIT_SDK_PATH", &self.sdk_path);
+
+        cargo::set_metadata("EMBUILD_ESP_IDF_PATH", &self.esp_idf_path);
+
+        // Expose the ESP-IDF version as `esp_idf_version_major`, `esp_idf_version_minor` and
+        // `esp_idf_version_patch` cfgs, e.g. `#[cfg(esp_idf_version_major = "4")]`, and to
+        // dependents as `DEP_ESP_HOMEKIT_SDK_IDF_VERSION` (`<major>.<minor>.<patch>`).
+        for cfg in idf_version_cfgs(self.idf_version) {
+            println!("cargo:rustc-cfg={}", cfg);
+        }
+        let (major, minor, patch) = self.idf_version;
+        cargo::set_metadata("IDF_VERSION", format!("{}.{}.{}", major, minor, patch));
+
+        // `BINDGEN_VERSION` and `BINDINGS_GENERATED_FOR_MCU` of the crate, and the same to
+        // dependents as `DEP_ESP_HOMEKIT_SDK_BINDGEN_VERSION` and `DEP_ESP_HOMEKIT_SDK_MCU`.
+        let bindgen_version = bindgen_version(&cargo::out_dir().join(BINDINGS_FILE));
+        cargo::set_rustc_env("ESP_HOMEKIT_BINDGEN_VERSION", &bindgen_version);
+        cargo::set_metadata("BINDGEN_VERSION", &bindgen_version);
+        cargo::set_rustc_env("ESP_HOMEKIT_BINDINGS_MCU", &self.mcu);
+        cargo::set_metadata("MCU", &self.mcu);
+
+        emit_hap_cfg_fields(&cargo::out_dir().join(BINDINGS_FILE));
+        let extra_bindings_file = cargo::out_dir().join(EXTRA_BINDINGS_FILE);
+        cargo::set_rustc_env("ESP_HOMEKIT_EXTRA_BINDINGS_FILE", extra_bindings_file.display().to_string());
+
+        // E.g. `env!("ESP_IDF_CONFIG_HAP_HTTP_STACK_SIZE")`, see `src/config.rs`.
+        for (key, value) in &self.kconfig_envs {
+            cargo::set_rustc_env(key, value);
+        }
+
+        build::CInclArgs { args: self.incl_args.clone() }.propagate();
+    }
+}
+
+impl From<&build::LinkArgsBuilder> for LinkConfig {
+    fn from(builder: &build::LinkArgsBuilder) -> Self {
+        LinkConfig::new(&builder.libflags, &builder.libdirflags, &builder.linkflags)
+    }
+}
+
+/// Expose `link_config` to dependents, see [`LinkConfig::metadata`].
+fn emit_link_config(link_config: &LinkConfig) {
+    for (key, value) in link_config.metadata() {
+        cargo::set_metadata(key, value);
+    }
+}
+
+/// The bindgen version from the header comment of the generated `bindings_file`,
+/// `unknown` if it has none.
+fn bindgen_version(bindings_file: &Path) -> String {
+    let header = fs::read_to_string(bindings_file).unwrap_or_default();
+
+    header
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("/* automatically generated by rust-bindgen "))
+        .and_then(|line| line.strip_suffix(" */"))
+        .unwrap_or("unknown")
+        .trim()
+        .to_owned()
+}
+
+/// The `hap_cfg_t` fields missing from older SDK revisions. Each is emitted as the
+/// cfg `esp_homekit_hap_cfg_<field>` if the generated bindings have it.
+const OPTIONAL_HAP_CFG_FIELDS: &[&str] = &["recv_timeout", "send_timeout", "sw_token_max_len"];
+
+/// The field names of `hap_cfg_t` in the generated `bindings_file`.
+fn hap_cfg_fields(bindings_file: &Path) -> Vec<String> {
+    let bindings = fs::read_to_string(bindings_file).unwrap_or_default();
+
+    bindings
+        .lines()
+        .skip_while(|line| line.trim() != "pub struct hap_cfg_t {")
+        .skip(1)
+        .take_while(|line| line.trim() != "}")
+        .filter_map(|line| line.trim().strip_prefix("pub "))
+        .filter_map(|field| field.split(':').next())
+        .map(|field| field.trim().to_owned())
+        .collect()
+}
+
+/// Emit the `esp_homekit_hap_cfg_<field>` cfgs of the `hap_cfg_t` in `bindings_file`.
+///
+/// Probed from the bindings, so `hap::InitConfig` matches the SDK revision.
+fn emit_hap_cfg_fields(bindings_file: &Path) {
+    let hap_cfg_fields = hap_cfg_fields(bindings_file);
+
+    for field in OPTIONAL_HAP_CFG_FIELDS {
+        println!("cargo:rustc-check-cfg=cfg(esp_homekit_hap_cfg_{})", field);
+        if hap_cfg_fields.iter().any(|f| f == field) {
+            println!("cargo:rustc-cfg=esp_homekit_hap_cfg_{}", field);
+        }
+    }
+}
+
+/// Point the crate at the stand-in of the `mock` feature instead of building the SDK.
+///
+/// Nothing is downloaded or compiled: the bindings are [`MOCK_BINDINGS_FILE`], the
+/// functions they declare are implemented by `src/mock`, and there are no kconfig
+/// values, so `config` is all `None`. Dependents get the metadata of an SDK build, with
+/// the include args pointing at [`MOCK_INCLUDE_DIR`] and `host` as the MCU.
+fn emit_mock() -> Result<()> {
+    let target = env::var("TARGET")?;
+    if target.ends_with("-espidf") {
+        bail!(
+            "The `mock` feature replaces the SDK with an in-memory stand-in for host tests, \
+             it can't be built for `{}`",
+            target
+        );
+    }
+
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+    let bindings_file = manifest_dir.join(MOCK_BINDINGS_FILE);
+    cargo::track_file(&bindings_file);
+    cargo::set_rustc_env(bindgen::VAR_BINDINGS_FILE, bindings_file.try_to_str()?);
+
+    let extra_bindings_file = cargo::out_dir().join(EXTRA_BINDINGS_FILE);
+    fs::write(&extra_bindings_file, "")?;
+    cargo::set_rustc_env("ESP_HOMEKIT_EXTRA_BINDINGS_FILE", extra_bindings_file.try_to_str()?);
+
+    let bindgen_version = bindgen_version(&bindings_file);
+    cargo::set_rustc_env("ESP_HOMEKIT_BINDGEN_VERSION", &bindgen_version);
+    cargo::set_metadata("BINDGEN_VERSION", &bindgen_version);
+    cargo::set_rustc_env("ESP_HOMEKIT_BINDINGS_MCU", "host");
+    cargo::set_metadata("MCU", "host");
+
+    let include_dir = manifest_dir.join(MOCK_INCLUDE_DIR);
+    cargo::track_file(&include_dir);
+    build::CInclArgs {
+        args: format!("\"-isystem{}\"", include_dir.try_to_str()?),
+    }
+    .propagate();
+
+    println!("cargo:rustc-check-cfg=cfg(esp_idf_mbedtls_external_mem_alloc)");
+    emit_hap_cfg_fields(&bindings_file);
+
+    Ok(())
+}
+
 fn main() -> Result<()> {
+    cargo::track_env_var(BUILD_TIMINGS_VAR);
+    let mut timings = Timings::default();
+
+    // Set by a dry run to compile the crate to an empty stub.
+    println!("cargo:rustc-check-cfg=cfg(esp_homekit_dry_run)");
+    cargo::track_env_var(DRY_RUN_VAR);
+    let dry_run = env::var(DRY_RUN_VAR).map(|v| v.trim() == "1").unwrap_or(false);
+
+    if env::var_os("CARGO_FEATURE_MOCK").is_some() {
+        return emit_mock();
+    }
+
+    if env::var_os("CARGO_FEATURE_BCT").is_some() && env::var("PROFILE")? == "release" {
+        cargo::print_warning(
+            "The `bct` feature is enabled in a release build. \
+             Bonjour Conformance Test helpers must never ship in production firmware!",
+        );
+    }
+
+    // Long before rustc would fail on the crate, after the SDK is built.
+    check_toolchain()?;
+
+    // With `$ESP_IDF_SYS_CHECK_FAST` reuse the output of the last full build, if any.
+    cargo::track_env_var(CHECK_FAST_VAR);
+    let check_fast = env::var(CHECK_FAST_VAR).map(|v| v.trim() == "1").unwrap_or(false);
+    let check_cache_file = cargo::out_dir().join(CHECK_CACHE_FILE);
+    if check_fast {
+        let bindings_file = cargo::out_dir().join(BINDINGS_FILE);
+        let generated = bindings_file.is_file() && cargo::out_dir().join(EXTRA_BINDINGS_FILE).is_file();
+
+        match CheckCache::load(&check_cache_file).filter(|_| generated) {
+            Some(cache) => {
+                eprintln!(
+                    "${CHECK_FAST_VAR} is set, reusing the last full build without building the SDK. \
+                     The crate won't link."
+                );
+
+                cargo::set_rustc_env(bindgen::VAR_BINDINGS_FILE, bindings_file.try_to_str()?);
+                cache.emit();
+
+                return Ok(());
+            }
+            None => cargo::print_warning(format_args!(
+                "${CHECK_FAST_VAR} is set but there is no previous build to reuse, building the SDK once"
+            )),
+        }
+    }
+
     let (pio_scons_vars, link_args) = if let Some(pio_scons_vars) =
     project::SconsVariables::from_piofirst()
     {
+        // The PlatformIO project must compile `src/shim/esp_homekit_shim.c` itself.
         println!("cargo:info=PIO->Cargo build detected: generating bindings only");
 
         (pio_scons_vars, None)
@@ -147,6 +1113,8 @@ fn main() -> Result<()> {
         cargo::track_env_var(ESP_IDF_SDKCONFIG_VAR);
         cargo::track_env_var(ESP_IDF_SDKCONFIG_DEFAULTS_VAR);
         cargo::track_env_var(MCU_VAR);
+        cargo::track_env_var(PIO_LOG_LEVEL_VAR);
+        cargo::track_env_var(PIO_VERSION_VAR);
 
         let out_dir = cargo::out_dir();
         let workspace_dir = workspace_dir().ok_or_else(|| anyhow!("No workspace"))?;
@@ -159,6 +1127,19 @@ fn main() -> Result<()> {
         // Pio must come from the environment if $ESP_IDF_TOOLS_INSTALL_DIR == "fromenv".
         let require_from_env = install_dir.is_from_env();
         let maybe_from_env = require_from_env || allow_from_env;
+        let log_level = pio_log_level()?;
+        cargo::track_env_var(NET_RETRIES_VAR);
+        let net_attempts = net_attempts()?;
+        cargo::track_env_var(IDF_VERSION_VAR);
+        let idf_pin = IdfPin::from_env()?;
+        // A pinned ESP-IDF release can be rejected before anything is downloaded.
+        if let Some(IdfPin::Framework(version)) = idf_pin {
+            check_idf_version(version)?;
+        }
+        // `$ESP_HOMEKIT_PIO_VERSION` only pins the platformio core installed by this build.
+        // A platformio taken from the environment (e.g. with `fromenv`) is used as-is and
+        // merely warned about on a version mismatch.
+        let pio_version = pio_version();
 
         let install = |install_dir: &InstallDir| -> Result<pio::Pio> {
             let install_dir = install_dir.path().map(ToOwned::to_owned);
@@ -168,16 +1149,33 @@ fn main() -> Result<()> {
                 fs::create_dir_all(install_dir)?;
             }
 
-            pio::Pio::install(install_dir, pio::LogLevel::Standard, false)
+            let pio = pio::Pio::install(install_dir, log_level, false)?;
+            if let Some(version) = &pio_version {
+                pin_pio_version(&pio, version)?;
+            }
+
+            Ok(pio)
         };
 
+        let stage = Instant::now();
         let pio = match (pio::Pio::try_from_env(), maybe_from_env) {
-            (Some(pio), true) => {
+            (Some(mut pio), true) => {
                 eprintln!(
                     "Using platformio from environment at '{}'",
                     pio.platformio_exe.display()
                 );
 
+                if let Some(version) = &pio_version {
+                    let installed = installed_pio_version(&pio).unwrap_or_else(|_| "unknown".into());
+                    if &installed != version {
+                        cargo::print_warning(format_args!(
+                            "platformio from environment is version {}, not ${PIO_VERSION_VAR} == {}",
+                            installed, version
+                        ));
+                    }
+                }
+
+                pio.log_level = log_level;
                 pio
             }
             (Some(_), false) => {
@@ -185,7 +1183,7 @@ fn main() -> Result<()> {
                     "Ignoring platformio in environment: ${ESP_IDF_TOOLS_INSTALL_DIR_VAR} != {}",
                     InstallDir::FromEnv
                 ));
-                install(&install_dir)?
+                with_net_retries("platformio install", net_attempts, || install(&install_dir))?
             }
             (None, true) if require_from_env => {
                 bail!(
@@ -193,33 +1191,81 @@ fn main() -> Result<()> {
                        but required by ${ESP_IDF_TOOLS_INSTALL_DIR_VAR} == {install_dir}"
                 );
             }
-            (None, _) => install(&install_dir)?,
+            (None, _) => with_net_retries("platformio install", net_attempts, || install(&install_dir))?,
         };
+        timings.record("install", stage);
 
-        let resolution = pio::Resolver::new(pio.clone())
-            .params(pio::ResolutionParams {
-                platform: Some("espressif32".into()),
-                frameworks: vec!["espidf".into()],
-                mcu: env::var(MCU_VAR).ok(),
-                target: Some(env::var("TARGET")?),
-                ..Default::default()
-            })
-            .resolve(true)?;
+        // Without `$ESP_IDF_SYS_UPDATE_LOCK` the versions of the lock file are pinned.
+        cargo::track_env_var(UPDATE_LOCK_VAR);
+        let update_lock = env::var(UPDATE_LOCK_VAR).map(|v| v.trim() == "1").unwrap_or(false);
+        let lock_file = workspace_dir.join(LOCK_FILE);
+        if lock_file.is_file() {
+            cargo::track_file(&lock_file);
+        }
+        let lock = if update_lock { None } else { Lock::load(&lock_file).map_err(Error::msg)? };
 
-        let mut builder = project::Builder::new(out_dir.join("esp-homekit-sdk"));
+        // `$ESP_IDF_SYS_ARTIFACT_CACHE=<dir>` shares the built SDK between the crates
+        // and checkouts of a workspace, relative paths and `workspace` are resolved in
+        // it. Unlocked builds float to the latest SDK and platform, so only locked ones
+        // use the cache.
+        cargo::track_env_var(ARTIFACT_CACHE_VAR);
+        cargo::track_env_var(ARTIFACT_CACHE_READ_ONLY_VAR);
+        let artifact_cache = ArtifactCache::from_env(&workspace_dir).filter(|_| {
+            if lock.is_none() {
+                cargo::print_warning(format_args!(
+                    "${ARTIFACT_CACHE_VAR} is ignored without '{}'",
+                    lock_file.display()
+                ));
+            }
+            lock.is_some()
+        });
+        if lock.is_some() && idf_pin.is_some() {
+            bail!(
+                "${IDF_VERSION_VAR} conflicts with the versions pinned by '{}', \
+                 set ${UPDATE_LOCK_VAR} to `1` to re-lock",
+                lock_file.display()
+            );
+        }
+
+        let stage = Instant::now();
+        let params = pio::ResolutionParams {
+            platform: Some(match (&idf_pin, &lock) {
+                (Some(IdfPin::Platform(platform)), _) => platform.clone(),
+                (_, Some(lock)) => format!("espressif32@{}", lock.platform),
+                _ => "espressif32".into(),
+            }),
+            frameworks: vec!["espidf".into()],
+            mcu: env::var(MCU_VAR).ok(),
+            target: Some(env::var("TARGET")?),
+            ..Default::default()
+        };
+        let resolution = with_net_retries("platform resolution", net_attempts, || {
+            pio::Resolver::new(pio.clone()).params(params.clone()).resolve(true)
+        })?;
+        timings.record("resolve", stage);
 
         // Resolve `ESP_IDF_SDKCONFIG` and `ESP_IDF_SDKCONFIG_DEFAULTS` to an absolute path
         // relative to the workspace directory if not empty.
-        let sdkconfig = {
+        // `ESP_IDF_SDKCONFIG` may also be a directory of fragments, which are then
+        // layered as defaults after `ESP_IDF_SDKCONFIG_DEFAULTS`.
+        let (sdkconfig, sdkconfig_fragments) = {
             let file = env::var_os(ESP_IDF_SDKCONFIG_VAR).unwrap_or_else(|| SDKCONFIG_FILE.into());
             let path = Path::new(&file).abspath_relative_to(&workspace_dir);
-            let cfg = list_specific_sdkconfigs(path, &profile, &resolution.mcu).next();
 
-            cfg.map(|path| {
+            if path.is_dir() {
                 cargo::track_file(&path);
 
-                (path, format!("sdkconfig.{}", profile).into())
-            })
+                (None, list_sdkconfig_fragments(&path).map_err(Error::msg)?)
+            } else {
+                let cfg = list_specific_sdkconfigs(path, &profile, &resolution.mcu).next();
+
+                let cfg = cfg.map(|path| {
+                    cargo::track_file(&path);
+
+                    (path, format!("sdkconfig.{}", profile).into())
+                });
+                (cfg, Vec::new())
+            }
         };
 
         let sdkconfig_defaults_var = env::var_os(ESP_IDF_SDKCONFIG_DEFAULTS_VAR)
@@ -247,85 +1293,212 @@ fn main() -> Result<()> {
                 (path, file_name)
             });
 
+        // Fragments injected by features are layered first, see `SdkconfigFiles`.
+        let injected = injected_sdkconfigs(&out_dir, &profile, &resolution.mcu)?;
+        if env::var_os("CARGO_FEATURE_SILENT_RELEASE").is_some()
+            && env::var_os("CARGO_FEATURE_INSECURE_DEBUG").is_some()
+            && profile == "release"
+        {
+            bail!("The `silent-release` and `insecure-debug` features contradict each other");
+        }
+
+        // Single values from the environment for quick experiments, layered after all files.
+        cargo::track_env_var(SDKCONFIG_OVERRIDE_VAR);
+        let sdkconfig_override = sdkconfig_override(&out_dir)?;
+
         dotenv::var("ESP_IDF_SYS_PIO_CONF_HOMEKIT_0")?;
 
-        builder
-            .enable_scons_dump()
-            .enable_c_entry_points()
-            .options(build::env_options_iter("ESP_IDF_SYS_PIO_CONF_HOMEKIT")?)
-            .files(build::tracked_env_globs_iter("ESP_IDF_SYS_GLOB")?)
-            .files(sdkconfig.into_iter())
-            .files(sdkconfig_defaults);
+        // A vendored SDK replaces the `lib_deps` download of `.env`, a locked one
+        // replaces the branch of the git source with the locked commit.
+        cargo::track_env_var(HOMEKIT_SDK_TARBALL_VAR);
+        let vendored_sdk = extract_sdk_tarball(&out_dir)?;
+        let mut options = build::env_options_iter("ESP_IDF_SYS_PIO_CONF_HOMEKIT")?.collect::<Vec<_>>();
+        for (key, value) in &mut options {
+            if key != "lib_deps" {
+                continue;
+            }
+
+            if let Some(path) = &vendored_sdk {
+                *value = format!("file://{}", path.try_to_str()?);
+            } else if let Some(commit) = lock.as_ref().and_then(Lock::sdk_commit) {
+                let source = value.split('#').next().unwrap_or_default();
+                *value = format!("{}#{}", source, commit);
+            }
+        }
 
+        let mut platform_packages = Vec::new();
+        if let Some(package) = idf_pin.as_ref().and_then(IdfPin::framework_package) {
+            platform_packages.push(("framework-espidf".to_string(), package));
+        } else if let Some(lock) = &lock {
+            platform_packages.push(("framework-espidf".to_string(), lock.framework.clone()));
+        }
+
+        // Without `app-wifi` the application brings up Wi-Fi itself.
+        if env::var_os("CARGO_FEATURE_APP_WIFI").is_none() {
+            options.push(("board_build.cmake_extra_args".into(), "-DEXCLUDE_COMPONENTS=app_wifi".into()));
+        }
+
+        let sdkconfig_files = SdkconfigFiles {
+            sdkconfig,
+            injected,
+            defaults: sdkconfig_defaults.collect(),
+            fragments: sdkconfig_fragments
+                .into_iter()
+                .map(|path| {
+                    cargo::track_file(&path);
+                    let file_name = PathBuf::from(path.file_name().unwrap());
+                    (path, file_name)
+                })
+                .collect(),
+            overrides: sdkconfig_override,
+        };
+
+        let files = build::tracked_env_globs_iter("ESP_IDF_SYS_GLOB")?
+            .chain(sdkconfig_files.layered())
+            .chain(SHIM_FILES.iter().map(|file| {
+                let path = PathBuf::from("src").join("shim").join(file);
+                cargo::track_file(&path);
+                (path, PathBuf::from("src").join(file))
+            }))
+            .collect();
+
+        let inputs = ProjectInputs {
+            profile: profile.clone(),
+            resolution: ResolvedPlatform::from(&resolution),
+            platform_packages,
+            options,
+            files,
+        };
+
+        let mut builder = project::Builder::new(out_dir.join("esp-homekit-sdk"));
+        apply_project_inputs(&inputs, &mut builder);
+
+        let stage = Instant::now();
         let project_path = builder.generate(&resolution)?;
+        timings.record("generate", stage);
+
+        // With `$ESP_IDF_SYS_DRY_RUN` stop before anything is compiled.
+        if dry_run {
+            let env = DRY_RUN_ENV_VARS.iter().map(|var| (*var, env::var(var).ok())).collect::<Vec<_>>();
+            let report = inputs.report(&env, &project_path);
+            let report_file = out_dir.join("dry-run.txt");
+            fs::write(&report_file, &report)?;
+            // The same inputs for tools, e.g. to diff two configurations.
+            fs::write(out_dir.join("dry-run.toml"), toml::to_string(&inputs)?)?;
+
+            for line in report.lines().filter(|line| !line.is_empty()) {
+                cargo::print_warning(line);
+            }
+            cargo::print_warning(format_args!(
+                "${DRY_RUN_VAR} is set, nothing was built. The report is at '{}'",
+                report_file.display()
+            ));
+            println!("cargo:rustc-cfg=esp_homekit_dry_run");
+
+            return Ok(());
+        }
 
-        pio.exec_with_args(&[
-            OsStr::new("lib"),
-            OsStr::new("--global"),
-            OsStr::new("install"),
-        ])?;
+        let cached = match &artifact_cache {
+            Some(cache) => {
+                let key = inputs.cache_key().map_err(Error::msg)?;
+
+                let stage = Instant::now();
+                let restored = cache.restore(&key, &project_path)?;
+                timings.record("cache restore", stage);
+
+                if restored {
+                    cargo::print_warning(format_args!("Using the SDK built in '{}'", cache.dir.join(&key).display()));
+                }
+                Some((cache, key, restored))
+            }
+            None => None,
+        };
 
-        pio.build(&project_path, profile == "release")?;
+        if !cached.as_ref().is_some_and(|(_, _, restored)| *restored) {
+            let stage = Instant::now();
+            with_net_retries("pio lib install", net_attempts, || pio_lib_install(&pio))?;
+            timings.record("lib install", stage);
+
+            let stage = Instant::now();
+            pio.build(&project_path, profile == "release")?;
+            timings.record("pio build", stage);
+
+            if let Some((cache, key, _)) = &cached {
+                // A broken cache shouldn't fail a successful build.
+                if let Err(err) = cache.store(key, &project_path) {
+                    cargo::print_warning(format_args!("Failed to cache the built SDK: {:#}", err));
+                }
+            }
+        }
 
         let pio_scons_vars = project::SconsVariables::from_dump(&project_path)?;
 
-        let link_args = build::LinkArgsBuilder::try_from(&pio_scons_vars)?.build()?;
+        let link_args = build::LinkArgsBuilder::try_from(&pio_scons_vars)?;
+        let link_config = LinkConfig::from(&link_args);
+        let link_args = link_args.build()?;
+
+        let resolved = resolved_lock(&pio_scons_vars, &homekit_sdk_dir(&pio_scons_vars)?)?;
+        match &lock {
+            Some(lock) if *lock != resolved => bail!(
+                "The build resolved {:?}, which doesn't satisfy {:?} locked in '{}'. \
+                 Set ${UPDATE_LOCK_VAR} to `1` to re-lock.",
+                resolved,
+                lock,
+                lock_file.display()
+            ),
+            Some(_) => (),
+            None => resolved.save(&lock_file).map_err(Error::msg)?,
+        }
 
-        (pio_scons_vars, Some(link_args))
+        (pio_scons_vars, Some((link_args, link_config)))
     };
 
+    // Without a framework pin the ESP-IDF release is only known once PlatformIO has
+    // installed it, so also check what the build actually used.
+    let esp_idf = PathBuf::from(&pio_scons_vars.pio_framework_dir);
+    let idf_version = esp_idf_version(&esp_idf)?;
+    check_idf_version(idf_version)?;
+
+    let sdkconfig_file =
+        find_kconfig_file(&pio_scons_vars.project_dir, pio_scons_vars.release_build).map_err(Error::msg)?;
+
     let kconfig_str_allow = regex::Regex::new(r"IDF_TARGET")?;
-    let cfg_args = build::CfgArgs {
-        args: kconfig::try_from_config_file(
-            pio_scons_vars
-                .project_dir
-                .join(if pio_scons_vars.release_build {
-                    "sdkconfig.release"
-                } else {
-                    "sdkconfig.debug"
-                })
-                .as_path()
-        )?
+    let kconfig = kconfig::try_from_config_file(sdkconfig_file.as_path())
+        .with_context(|| format!("Failed to read the kconfig '{}'", sdkconfig_file.display()))?
+        .collect::<Vec<_>>();
+    let kconfig_envs = kconfig_envs(kconfig.iter().filter_map(|(key, value)| match value {
+        kconfig::Value::String(value) => Some((key.as_str(), value.as_str())),
+        _ => None,
+    }));
+    let cfgs = sorted_cfgs(
+        kconfig
+            .into_iter()
             .filter(|(key, value)| {
                 matches!(value, kconfig::Value::Tristate(kconfig::Tristate::True))
                     || kconfig_str_allow.is_match(key)
             })
-            .filter_map(|(key, value)| value.to_rustc_cfg("esp_idf", key))
-            .collect::<Vec<String>>()
-    };
+            .filter_map(|(key, value)| value.to_rustc_cfg("esp_idf", key)),
+    );
+    let cfg_args = build::CfgArgs { args: cfgs };
 
     let header = PathBuf::from("src").join("include").join("bindings.h");
 
     cargo::track_file(&header);
 
-    let d = PathBuf::from(env::var("OUT_DIR")?)
-        .join("esp-homekit-sdk/.pio/libdeps/debug/esp-homekit-sdk/components")
-        .display()
-        .to_string();
-
-    let mut args = vec![
-        format!(
-            "-I{}",
-            PathBuf::from(env::var("OUT_DIR")?)
-                .join("esp-homekit-sdk/.pio/libdeps/debug/esp-homekit-sdk/components/common/app_wifi")
-                .display()
-                .to_string()
-        ),
-        format!(
-            "-I{}",
-            PathBuf::from(env::var("OUT_DIR")?)
-                .join("esp-homekit-sdk/.pio/libdeps/debug/esp-homekit-sdk/components/common/app_hap_setup_payload")
-                .display()
-                .to_string(),
-        ),
-        format!(
-            "-I{}",
-            PathBuf::from(env::var("OUT_DIR")?)
-                .join("esp-homekit-sdk/.pio/libdeps/debug/esp-homekit-sdk/components/common/qrcode/include")
-                .display()
-                .to_string(),
-        ),
-    ];
+    cargo::track_env_var(HOMEKIT_SDK_PATH_VAR);
+    let sdk_dir = homekit_sdk_dir(&pio_scons_vars)?;
+    let d = sdk_dir.join("components");
+
+    let app_wifi = env::var_os("CARGO_FEATURE_APP_WIFI").is_some();
+    let mut args = ["common/app_wifi", "common/app_hap_setup_payload", "common/qrcode/include"]
+        .iter()
+        .filter(|dir| app_wifi || **dir != "common/app_wifi")
+        .map(|dir| format!("-I{}", d.join(dir).display()))
+        .collect::<Vec<_>>();
+    if !app_wifi {
+        // Skips the `app_wifi.h` include of `bindings.h`.
+        args.push("-DESP_HOMEKIT_NO_APP_WIFI".to_owned());
+    }
 
     for entry in WalkDir::new(d).into_iter().filter_map(|e| e.ok()) {
         if entry.path().ends_with("include") {
@@ -338,47 +1511,140 @@ fn main() -> Result<()> {
 
     let mcu = cfg_args.get("esp_idf_config_idf_target").ok_or_else(|| {
         anyhow!(
-            "Failed to get IDF_TARGET from kconfig. cfgs:\n{:?}",
+            "Failed to get IDF_TARGET from kconfig '{}'. cfgs:\n{:?}",
+            sdkconfig_file.display(),
             cfg_args.args
         )
     })?;
 
-    bindgen::run(
-        bindgen::Factory::from_scons_vars(&pio_scons_vars)?
+    cargo::track_env_var(TRANSPORT_VAR);
+    check_transport(&mcu, &cfg_args)?;
+
+    let profile = if pio_scons_vars.release_build { "release" } else { "debug" };
+    check_injected_sdkconfigs(&sdkconfig_file, profile, &mcu)?;
+
+    // `$ESP_HOMEKIT_CLANG_TARGET` replaces the triple derived from the MCU as-is,
+    // bypassing all of the built-in mapping below.
+    cargo::track_env_var(CLANG_TARGET_VAR);
+    let clang_target = match env::var(CLANG_TARGET_VAR).ok().filter(|t| !t.trim().is_empty()) {
+        Some(target) => target.trim().to_owned(),
+        None if mcu == "esp32c3" => "riscv32".to_owned(),
+        None => "xtensa".to_owned(),
+    };
+
+    let compiler = pio_scons_vars.full_path(pio_scons_vars.link.clone())?;
+    let toolchain_args = toolchain_clang_args(&compiler)
+        .map_err(|err| {
+            cargo::print_warning(format_args!(
+                "Failed to determine the toolchain sysroot, using the clang defaults: {}",
+                err
+            ));
+        })
+        .ok();
+
+    // The bindings of a header, with the options shared by all headers.
+    let new_bindings = |header: &Path| -> Result<_> {
+        let mut bindings = bindgen::Factory::from_scons_vars(&pio_scons_vars)?
             .builder()?
             .ctypes_prefix("c_types")
-            .header(header.to_string_lossy())
-            .blocklist_function("strtold")
-            .blocklist_function("_strtold_r")
-            .clang_args(args)
-            .clang_args(vec![
-                "-target",
-                if mcu == "esp32c3" {
-                    "riscv32"
-                } else {
-                    "xtensa"
-                },
-            ]),
-    )?;
+            .header(header.to_string_lossy());
+
+        // With `minimal-bindings` leave out the SDK's doc comments and the `Debug` impls,
+        // which make up a large part of the generated file.
+        if env::var_os("CARGO_FEATURE_MINIMAL_BINDINGS").is_some() {
+            bindings = bindings.generate_comments(false).derive_debug(false);
+        }
+
+        for function in blocklisted_functions(toolchain_args.as_deref()) {
+            bindings = bindings.blocklist_function(function);
+        }
 
-    let c_incl_args = build::CInclArgs::try_from(&pio_scons_vars)?;
+        Ok(bindings
+            .clang_args(toolchain_args.clone().unwrap_or_default())
+            .clang_args(args.clone())
+            .clang_args(vec!["-target".to_owned(), clang_target.clone()]))
+    };
 
-    cfg_args.propagate();
-    cfg_args.output();
+    let mut bindings = new_bindings(&header)?;
 
-    if let Some(env_path) = link_args.as_ref().map(|_| pio_scons_vars.path.clone()) {
-        cargo::set_metadata("EMBUILD_ENV_PATH", env_path);
+    // With `slim-bindings` only emit the symbols the wrappers need, see `src/ffi_deps.rs`.
+    if env::var_os("CARGO_FEATURE_SLIM_BINDINGS").is_some() {
+        for function in ffi_deps::FUNCTIONS {
+            bindings = bindings.allowlist_function(function);
+        }
+        for ty in ffi_deps::TYPES {
+            bindings = bindings.allowlist_type(ty);
+        }
+        for var in ffi_deps::VARS {
+            bindings = bindings.allowlist_var(var);
+        }
     }
 
-    let esp_idf = PathBuf::from(&pio_scons_vars.pio_framework_dir);
-    cargo::set_metadata("EMBUILD_ESP_IDF_PATH", esp_idf.try_to_str()?);
+    // Headers of `$ESP_HOMEKIT_BINDINGS_HEADERS` are generated into `bindings_<module>.rs`
+    // and declared as `pub mod <module>` in `EXTRA_BINDINGS_FILE`. They come first, as
+    // `bindgen::run_for_file` points `VAR_BINDINGS_FILE` at the file it generates.
+    cargo::track_env_var(BINDINGS_HEADERS_VAR);
+    let modules = extra_bindings_headers()?;
+    fs::write(cargo::out_dir().join(EXTRA_BINDINGS_FILE), extra_bindings_source(&modules, &cargo::out_dir()))?;
 
-    c_incl_args.propagate();
+    let mut jobs = Vec::new();
+    for (name, path) in modules {
+        let file = cargo::out_dir().join(format!("bindings_{}.rs", name));
+        jobs.push((new_bindings(&path)?, path, file, cargo::out_dir().join(format!("bindings_{}.hash", name))));
+    }
+    jobs.push((
+        bindings,
+        header,
+        cargo::out_dir().join(BINDINGS_FILE),
+        cargo::out_dir().join(BINDINGS_HASH_FILE),
+    ));
+
+    // Skip bindgen if nothing it depends on changed since the last run, unless
+    // `$ESP_IDF_SYS_FORCE_BINDGEN` is `1`.
+    cargo::track_env_var(FORCE_BINDGEN_VAR);
+    let force_bindgen = env::var(FORCE_BINDGEN_VAR).map(|v| v.trim() == "1").unwrap_or(false);
+
+    let stage = Instant::now();
+    for (bindings, header, bindings_file, hash_file) in jobs {
+        let hash = bindings_hash(&header, &bindings.command_line_flags(), &sdkconfig_file)?;
 
-    if let Some(link_args) = link_args {
+        let unchanged = bindings_file.is_file()
+            && fs::read_to_string(&hash_file).map(|h| h == hash).unwrap_or(false);
+        if unchanged && !force_bindgen {
+            eprintln!("Bindings of '{}' are up to date, skipping bindgen", header.display());
+
+            // What `bindgen::run_for_file` emits after generating.
+            cargo::set_rustc_env(bindgen::VAR_BINDINGS_FILE, bindings_file.try_to_str()?);
+        } else {
+            // Never leave a stale hash behind if bindgen fails.
+            let _ = fs::remove_file(&hash_file);
+
+            bindgen::run_for_file(bindings, &bindings_file)?;
+            fs::write(&hash_file, hash)?;
+        }
+    }
+    timings.record("bindgen", stage);
+
+    let cache = CheckCache {
+        env_path: pio_scons_vars.path.clone(),
+        sdk_path: sdk_dir.try_to_str()?.to_owned(),
+        esp_idf_path: esp_idf.try_to_str()?.to_owned(),
+        idf_version,
+        incl_args: build::CInclArgs::try_from(&pio_scons_vars)?.args,
+        mcu,
+        cfgs: cfg_args.args,
+        kconfig_envs,
+    };
+    cache.save(&check_cache_file)?;
+    cache.emit();
+
+    if let Some((link_args, link_config)) = link_args {
         link_args.propagate();
         link_args.output();
+        emit_link_config(&link_config);
     }
 
+    report_timings(&timings)?;
+
     Ok(())
 }
@@ -0,0 +1,41 @@
+//! A dimmable lightbulb, written against the prelude alone.
+//!
+//! Wi-Fi is left to the application, e.g. `esp-idf-svc` or `provisioning::WifiProvisioner`
+//! with the `app-wifi` feature, and has to be up for controllers to find the accessory.
+//! With the `mock` feature the example also builds and runs on the host.
+
+use std::ffi::CString;
+
+use esp_homekit_sdk_sys::prelude::*;
+
+fn main() -> Result<(), HapError> {
+    hap::init();
+
+    let config = hap::Config {
+        name: CString::new("Lamp").unwrap(),
+        model: CString::new("Lamp01").unwrap(),
+        manufacturer: CString::new("Espressif").unwrap(),
+        serial_num: CString::new("001122334455").unwrap(),
+        fw_rev: CString::new("1.0.0").unwrap(),
+        hw_rev: CString::new("1.0").unwrap(),
+        pv: CString::default(),
+        cid: Category::LIGHTING,
+    };
+    let accessory = Accessory::new(&config)?;
+
+    let lightbulb = Lightbulb::builder()
+        .name("Lamp")
+        .primary(true)
+        .brightness(50)
+        .build()?;
+    lightbulb.on_power(|on| println!("Lamp switched {}", if on { "on" } else { "off" }));
+    lightbulb.on_brightness(|brightness| println!("Lamp dimmed to {}%", brightness));
+
+    accessory.add_service(&lightbulb.service())?;
+    hap::add(&accessory);
+
+    server::set_setup_code("111-22-333")?;
+    server::set_setup_id("ES32")?;
+
+    hap::try_start()
+}
@@ -0,0 +1,49 @@
+//! Compile `src/shim.c` against the include dirs `esp-homekit-sdk-sys` exports, like a
+//! dependent with C code of its own does.
+
+use std::env;
+
+const SHIM_FILE: &str = "src/shim.c";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", SHIM_FILE);
+
+    // Propagated by embuild as `EMBUILD_C_INCLUDE_ARGS` of the `esp_homekit_sdk` links key.
+    let incl_args = env::var("DEP_ESP_HOMEKIT_SDK_EMBUILD_C_INCLUDE_ARGS")
+        .expect("esp-homekit-sdk-sys exports no C include args");
+
+    let mut build = cc::Build::new();
+    for arg in split_args(&incl_args) {
+        build.flag(&arg);
+    }
+    build.file(SHIM_FILE).compile("shim");
+
+    // For the tests to compare with `BINDINGS_GENERATED_FOR_MCU`.
+    let mcu = env::var("DEP_ESP_HOMEKIT_SDK_MCU").expect("esp-homekit-sdk-sys exports no MCU");
+    println!("cargo:rustc-env=SHIM_SDK_MCU={}", mcu);
+}
+
+/// Split `args` on whitespace outside of double quotes, dropping the quotes, e.g.
+/// `"-isystem/a b" -I/c` into `-isystem/a b` and `-I/c`.
+fn split_args(args: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+
+    for c in args.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    split.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        split.push(current);
+    }
+
+    split
+}
@@ -0,0 +1,41 @@
+//! Declarations of the C shim in `src/shim.c`, which is built against the include dirs
+//! exported by `esp-homekit-sdk-sys`. Written with its `c_types` and `raw` types, as
+//! dependents with C code of their own are meant to.
+//!
+//! Builds against the `mock` feature, so it runs on the host: `cargo test --workspace`.
+
+use esp_homekit_sdk_sys::c_types;
+use esp_homekit_sdk_sys::raw::hap_char_t;
+
+extern "C" {
+    /// Set the value of an `int` characteristic with `hap_char_update_val`.
+    pub fn shim_set_int(hc: *mut hap_char_t, value: c_types::c_int) -> c_types::c_int;
+
+    /// The value of an `int` characteristic, from `hap_char_get_val`.
+    pub fn shim_get_int(hc: *mut hap_char_t) -> c_types::c_int;
+}
+
+#[cfg(test)]
+mod tests {
+    use esp_homekit_sdk_sys::context::HapContext;
+    use esp_homekit_sdk_sys::*;
+
+    use super::*;
+
+    #[test]
+    fn the_shim_calls_into_the_sdk() {
+        HapContext::new_isolated();
+        let brightness = unsafe { hap_char_brightness_create(50) };
+
+        assert_eq!(unsafe { shim_get_int(brightness) }, 50);
+
+        assert_eq!(unsafe { shim_set_int(brightness, 75) }, hap::HAP_SUCCESS_);
+        assert_eq!(unsafe { (*hap_char_get_val(brightness)).i }, 75);
+        assert_eq!(unsafe { shim_get_int(brightness) }, 75);
+    }
+
+    #[test]
+    fn build_scripts_see_the_mcu_of_the_bindings() {
+        assert_eq!(env!("SHIM_SDK_MCU"), BINDINGS_GENERATED_FOR_MCU);
+    }
+}
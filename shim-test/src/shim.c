@@ -0,0 +1,18 @@
+/*
+ * Downstream C code calling into the SDK, declared on the Rust side with the
+ * `c_types` of esp-homekit-sdk-sys.
+ */
+
+#include <hap.h>
+
+int shim_set_int(hap_char_t *hc, int value)
+{
+    hap_val_t val = { .i = value };
+
+    return hap_char_update_val(hc, &val);
+}
+
+int shim_get_int(hap_char_t *hc)
+{
+    return hap_char_get_val(hc)->i;
+}
@@ -1,8 +1,19 @@
-use log::info;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::CString;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
+use log::{info, warn};
+
+use crate::characteristic::{CharValue, HapCharacteristic};
+use crate::context::HapContext;
+use crate::error::{HapError, HapStatus};
+use crate::service::{self, HapService};
 use crate::*;
 
+/// The accessory category, serialized by its name (e.g. `"LIGHTING"`) with `serde`.
 #[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Category {
     NONE,
     OTHER,
@@ -104,3 +115,441 @@ pub fn create(cfg: &hap::Config) -> *mut hap_acc_t {
         hap_acc_create(&mut hap_acc_cfg_t::from(cfg) as *mut hap_acc_cfg_t)
     }
 }
+
+struct AccessoryInner {
+    ptr: *mut hap_acc_t,
+}
+
+/// A shared handle to a `hap_acc_t`.
+///
+/// Accessories added through [`hap::add`] or [`hap::add_bridged`] are recorded in a
+/// registry keyed by aid, see [`hap::accessory_by_aid`]. The registry keeps them
+/// until [`hap::remove_bridged`], like the accessory database does, so they are found
+/// after the application dropped its handles.
+#[derive(Clone)]
+pub struct Accessory {
+    inner: Arc<AccessoryInner>,
+}
+
+// The HAP framework serializes access to the accessory internally.
+unsafe impl Send for AccessoryInner {}
+unsafe impl Sync for AccessoryInner {}
+
+impl Accessory {
+    pub fn new(cfg: &hap::Config) -> Result<Self, HapError> {
+        let ptr = create(cfg);
+
+        if ptr.is_null() {
+            return Err(HapError::Fail(hap::HAP_FAIL_));
+        }
+
+        Ok(Accessory {
+            inner: Arc::new(AccessoryInner { ptr }),
+        })
+    }
+
+    /// Wrap an accessory created with `hap_acc_create`, e.g. by
+    /// [`hap_accessory!`](crate::hap_accessory).
+    pub fn from_raw(ptr: *mut hap_acc_t) -> Self {
+        Accessory {
+            inner: Arc::new(AccessoryInner { ptr }),
+        }
+    }
+
+    pub fn as_raw(&self) -> *mut hap_acc_t {
+        self.inner.ptr
+    }
+
+    /// The accessory instance id, assigned once the accessory is added to the database.
+    pub fn aid(&self) -> u32 {
+        unsafe { hap_acc_get_aid(self.inner.ptr) as u32 }
+    }
+
+    /// Add a service, failing with [`HapError::MultiplePrimaryServices`] for a second
+    /// primary service.
+    pub fn add_service(&self, service: &HapService) -> Result<(), HapError> {
+        if service.is_primary() && self.services().iter().any(HapService::is_primary) {
+            return Err(HapError::MultiplePrimaryServices);
+        }
+
+        HapError::check(unsafe { hap_acc_add_serv(self.inner.ptr, service.as_raw()) })?;
+
+        hap::database_changed();
+
+        Ok(())
+    }
+
+    /// Set the product data assigned to the product plan in the Apple developer portal,
+    /// required for certification. See [`product_data_from_hex`].
+    pub fn add_product_data(&self, product_data: [u8; 8]) -> Result<(), HapError> {
+        let mut product_data = product_data;
+
+        HapError::check(unsafe {
+            hap_acc_add_product_data(self.inner.ptr, product_data.as_mut_ptr(), product_data.len())
+        })
+    }
+
+    /// The services of the accessory, in the order they were added.
+    pub fn services(&self) -> Vec<HapService> {
+        let mut services = Vec::new();
+        let mut ptr = unsafe { hap_acc_get_first_serv(self.inner.ptr) };
+
+        while !ptr.is_null() {
+            services.push(HapService::from_raw(ptr));
+            ptr = unsafe { hap_serv_get_next(ptr) };
+        }
+
+        services
+    }
+
+    /// Signal whether the device behind a bridged accessory is reachable, e.g. when a
+    /// Zigbee node drops off.
+    ///
+    /// The HAP framework has no reachability of its own, so this updates `StatusActive`
+    /// on every service that has one. While unreachable, controller writes to any of its
+    /// services are rejected with [`HapStatus::CommunicationError`].
+    ///
+    /// The Home app shows such an accessory as "No Response" and greys out its tile,
+    /// while its last known state stays visible in the details. Services without
+    /// `StatusActive` are only flagged once a controller write fails, so add it to
+    /// the services of bridged devices that may go offline.
+    ///
+    /// [`HapStatus::CommunicationError`]: crate::error::HapStatus::CommunicationError
+    pub fn set_reachable(&self, reachable: bool) -> Result<(), HapError> {
+        for service in self.services() {
+            service.set_reachable(reachable);
+
+            if let Some(active) = service.char_by_uuid(HAP_CHAR_UUID_STATUS_ACTIVE) {
+                active.update(&CharValue::Bool(reachable))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set the `ConfiguredName` of the accessory, adding it to the accessory information
+    /// service on first use.
+    ///
+    /// `Name` is fixed by the firmware and identifies the accessory while pairing.
+    /// `ConfiguredName` is the name the user gave it in the Home app, which controllers
+    /// write on rename; see [`on_rename`](Self::on_rename) to persist it, and restore it
+    /// with this on the next boot. Names beyond [`characteristic::MAX_STRING_LEN`] bytes
+    /// are rejected.
+    ///
+    /// [`characteristic::MAX_STRING_LEN`]: crate::characteristic::MAX_STRING_LEN
+    pub fn set_configured_name(&self, name: &str) -> Result<(), HapError> {
+        let info = self.info_service()?;
+
+        match info.char_by_uuid(service::HAP_CHAR_UUID_CONFIGURED_NAME) {
+            Some(characteristic) => {
+                if name.len() > characteristic::MAX_STRING_LEN {
+                    return Err(HapError::InvalidArg);
+                }
+
+                let name = CString::new(name).map_err(|_| HapError::InvalidArg)?;
+                characteristic.update(&CharValue::String(name))
+            }
+            None => {
+                info.add_configured_name_char(name)?;
+                hap::database_changed();
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Install the handler called with the new name when a controller renames the
+    /// accessory, e.g. to store it in NVS. See [`set_configured_name`](Self::set_configured_name).
+    ///
+    /// Renames are only possible once `ConfiguredName` was added with `set_configured_name`.
+    /// On `Err` the rename is rejected and the status reported back to the controller.
+    pub fn on_rename<F>(&self, handler: F) -> Result<(), HapError>
+    where
+        F: FnMut(&str) -> Result<(), HapStatus> + Send + 'static,
+    {
+        self.info_service()?.set_rename_handler(handler);
+
+        Ok(())
+    }
+
+    /// Override the HAP protocol version advertised by the `ProtocolInformation`
+    /// service, e.g. `1.1.0`.
+    ///
+    /// The HAP framework adds the service on creation with `pv` of the [`hap::Config`],
+    /// or [`hap::PROTOCOL_VERSION`] if that is empty. Versions other than dot-separated
+    /// numbers are rejected.
+    pub fn set_protocol_version(&self, version: &str) -> Result<(), HapError> {
+        let valid = !version.is_empty()
+            && version
+                .split('.')
+                .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()));
+        if !valid {
+            return Err(HapError::InvalidArg);
+        }
+
+        let characteristic = self
+            .service_by_uuid(HAP_SERV_UUID_PROTOCOL_INFORMATION)?
+            .char_by_uuid(HAP_CHAR_UUID_VERSION)
+            .ok_or(HapError::Fail(hap::HAP_FAIL_))?;
+
+        let version = CString::new(version).map_err(|_| HapError::InvalidArg)?;
+        characteristic.update(&CharValue::String(version))
+    }
+
+    fn info_service(&self) -> Result<HapService, HapError> {
+        self.service_by_uuid(HAP_SERV_UUID_ACCESSORY_INFORMATION)
+    }
+
+    fn service_by_uuid(&self, uuid: &[u8]) -> Result<HapService, HapError> {
+        let ptr = unsafe { hap_acc_get_serv_by_uuid(self.inner.ptr, uuid.as_ptr() as *const i8) };
+
+        if ptr.is_null() {
+            Err(HapError::Fail(hap::HAP_FAIL_))
+        } else {
+            Ok(HapService::from_raw(ptr))
+        }
+    }
+
+    /// Look up a characteristic by its instance id.
+    pub fn char_by_iid(&self, iid: u32) -> Option<HapCharacteristic> {
+        let ptr = unsafe { hap_acc_get_char_by_iid(self.inner.ptr, iid as i32) };
+
+        if ptr.is_null() {
+            None
+        } else {
+            Some(HapCharacteristic::from_raw(ptr))
+        }
+    }
+}
+
+/// Parse product data as shown in the developer portal, e.g. `03d8a775e3644573`.
+///
+/// The bytes are kept in the order they are written in, which is the order the HAP
+/// framework expects.
+pub fn product_data_from_hex(hex: &str) -> Result<[u8; 8], HapError> {
+    let hex = hex.trim();
+    if hex.len() != 16 || !hex.is_ascii() {
+        return Err(HapError::InvalidArg);
+    }
+
+    let mut product_data = [0u8; 8];
+    for (i, byte) in product_data.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| HapError::InvalidArg)?;
+    }
+
+    Ok(product_data)
+}
+
+/// The longest accessory name HomeKit accepts, in bytes.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// The factory-programmed base MAC address of the chip.
+pub(crate) fn base_mac() -> Result<[u8; 6], HapError> {
+    let mut mac = [0u8; 6];
+    HapError::check(unsafe { esp_efuse_mac_get_default(mac.as_mut_ptr()) })?;
+
+    Ok(mac)
+}
+
+/// `base` followed by the last 3 bytes of `mac` in hex, e.g. `Lamp A1B2C3`.
+///
+/// `base` is shortened as needed to stay within [`MAX_NAME_LEN`].
+fn mac_suffixed_name(base: &str, mac: &[u8; 6]) -> Result<String, HapError> {
+    let suffix = format!(" {:02X}{:02X}{:02X}", mac[3], mac[4], mac[5]);
+    let mut base = base.trim();
+    if base.is_empty() {
+        return Err(HapError::InvalidArg);
+    }
+
+    if base.len() + suffix.len() > MAX_NAME_LEN {
+        let mut end = MAX_NAME_LEN - suffix.len();
+        while !base.is_char_boundary(end) {
+            end -= 1;
+        }
+        base = base[..end].trim_end();
+    }
+
+    Ok(format!("{}{}", base, suffix))
+}
+
+/// An accessory name unique to this device, for [`hap::Config::name`], e.g.
+/// `Lamp A1B2C3` with the last 3 bytes of the base MAC address.
+///
+/// Tells identical devices on one network apart in the Home app during setup.
+pub fn name_with_mac_suffix(base: &str) -> Result<CString, HapError> {
+    let name = mac_suffixed_name(base, &base_mac()?)?;
+
+    CString::new(name).map_err(|_| HapError::InvalidArg)
+}
+
+/// The part of a [`HapContext`] kept by this module.
+#[derive(Default)]
+pub(crate) struct State {
+    bridged: AtomicUsize,
+    /// The aids of all accessories in the database, including those no longer
+    /// referenced from Rust, which stay in the database.
+    aids: Mutex<BTreeSet<u32>>,
+    registry: Mutex<BTreeMap<u32, Arc<AccessoryInner>>>,
+}
+
+/// The bridged accessories behind the primary accessory, see [`hap::add_bridged`].
+pub struct Bridge;
+
+impl Bridge {
+    /// HAP allows at most 150 bridged accessories behind a bridge; the HAP
+    /// framework has no setting for it, so the limit is fixed.
+    pub const MAX_ACCESSORIES: usize = 150;
+
+    /// [`HapContext::bridged_count`] of the global context.
+    pub fn count() -> usize {
+        HapContext::global().bridged_count()
+    }
+}
+
+/// The aid of the primary accessory, or of the bridge itself.
+pub(crate) const PRIMARY_AID: u32 = 1;
+
+impl HapContext {
+    /// The number of bridged accessories currently in the database.
+    pub fn bridged_count(&self) -> usize {
+        self.state().accessory.bridged.load(Ordering::SeqCst)
+    }
+
+    /// Reserve a slot for a bridged accessory, failing once the limit is reached.
+    pub(crate) fn reserve_bridged(&self) -> Result<(), HapError> {
+        self.state()
+            .accessory
+            .bridged
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < Bridge::MAX_ACCESSORIES).then(|| count + 1)
+            })
+            .map(|_| ())
+            .map_err(|_| HapError::TooManyAccessories)
+    }
+
+    pub(crate) fn release_bridged(&self) {
+        self.state().accessory.bridged.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Record `aid` as used, failing with [`HapError::DuplicateAid`] if it already is.
+    ///
+    /// Checks and inserts under one lock, so of concurrent adds with the same aid only
+    /// one gets to call into the framework. Release the aid again if that fails.
+    pub(crate) fn try_claim_aid(&self, aid: u32) -> Result<(), HapError> {
+        if !self.state().accessory.aids.lock().unwrap().insert(aid) {
+            warn!("Accessory aid {} is already in use", aid);
+            return Err(HapError::DuplicateAid(aid));
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn release_aid(&self, aid: u32) {
+        self.state().accessory.aids.lock().unwrap().remove(&aid);
+    }
+
+    pub(crate) fn aid_count(&self) -> usize {
+        self.state().accessory.aids.lock().unwrap().len()
+    }
+
+    pub(crate) fn register(&self, accessory: &Accessory) {
+        self.state()
+            .accessory
+            .registry
+            .lock()
+            .unwrap()
+            .insert(accessory.aid(), accessory.inner.clone());
+    }
+
+    pub(crate) fn unregister(&self, aid: u32) {
+        self.state().accessory.registry.lock().unwrap().remove(&aid);
+    }
+
+    pub(crate) fn lookup(&self, aid: u32) -> Option<Accessory> {
+        // Only clone under the lock, so this never calls into the HAP framework
+        // and is safe to use from within HAP callbacks.
+        let inner = self.state().accessory.registry.lock().unwrap().get(&aid)?.clone();
+
+        Some(Accessory { inner })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAC: [u8; 6] = [0x24, 0x0A, 0xC4, 0xA1, 0xB2, 0xC3];
+
+    #[test]
+    fn names_get_the_mac_suffix() {
+        assert_eq!(mac_suffixed_name("Lamp", &MAC), Ok("Lamp A1B2C3".to_owned()));
+        assert_eq!(mac_suffixed_name("  Lamp ", &MAC), Ok("Lamp A1B2C3".to_owned()));
+        assert_eq!(mac_suffixed_name(" ", &MAC), Err(HapError::InvalidArg));
+    }
+
+    #[test]
+    fn long_names_are_shortened_to_fit_the_suffix() {
+        let name = mac_suffixed_name(&"a".repeat(MAX_NAME_LEN), &MAC).unwrap();
+        assert_eq!(name.len(), MAX_NAME_LEN);
+        assert!(name.ends_with(" A1B2C3"));
+
+        // Shortened on a char boundary, `ä` is 2 bytes.
+        let name = mac_suffixed_name(&"ä".repeat(MAX_NAME_LEN), &MAC).unwrap();
+        assert!(name.len() <= MAX_NAME_LEN);
+        assert!(name.ends_with(" A1B2C3"));
+    }
+
+    #[test]
+    fn duplicate_aids_are_refused_until_released() {
+        let context = HapContext::new_isolated();
+
+        assert_eq!(context.try_claim_aid(1001), Ok(()));
+        assert_eq!(context.try_claim_aid(1001), Err(HapError::DuplicateAid(1001)));
+
+        context.release_aid(1001);
+        assert_eq!(context.try_claim_aid(1001), Ok(()));
+    }
+
+    #[test]
+    fn concurrent_claims_of_an_aid_succeed_once() {
+        let context = HapContext::new_isolated();
+
+        let claims = (0..8)
+            .map(|_| {
+                let context = context.clone();
+                std::thread::spawn(move || context.try_claim_aid(1002).is_ok())
+            })
+            .collect::<Vec<_>>();
+        let succeeded = claims.into_iter().map(|claim| claim.join().unwrap()).filter(|&ok| ok).count();
+
+        assert_eq!(succeeded, 1);
+    }
+
+    #[test]
+    fn bridged_accessories_past_the_limit_are_refused() {
+        let context = HapContext::new_isolated();
+
+        for _ in 0..Bridge::MAX_ACCESSORIES {
+            assert_eq!(context.reserve_bridged(), Ok(()));
+        }
+        assert_eq!(context.reserve_bridged(), Err(HapError::TooManyAccessories));
+        assert_eq!(context.bridged_count(), Bridge::MAX_ACCESSORIES);
+
+        context.release_bridged();
+        assert_eq!(context.reserve_bridged(), Ok(()));
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+    use super::*;
+
+    #[test]
+    fn categories_round_trip_by_name() {
+        let json = serde_json::to_string(&Category::LIGHTING).unwrap();
+        assert_eq!(json, r#""LIGHTING""#);
+
+        let category = serde_json::from_str::<Category>(&json).unwrap();
+        assert_eq!(category as u32, Category::LIGHTING as u32);
+    }
+}
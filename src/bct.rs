@@ -0,0 +1,50 @@
+//! Helpers driving the Bonjour Conformance Test (BCT) required for certification.
+//!
+//! Only available with the `bct` feature, never ship a firmware with it.
+
+use std::ffi::CString;
+
+use log::*;
+
+use crate::error::HapError;
+use crate::*;
+
+/// Change the advertised name, as asked for by the BCT.
+pub fn change_name(name: &str) -> Result<(), HapError> {
+    let name = CString::new(name).map_err(|_| HapError::InvalidArg)?;
+
+    info!("BCT: changing name to {:?}", name);
+
+    unsafe {
+        hap_bct_change_name(name.as_ptr());
+    }
+
+    Ok(())
+}
+
+/// Simulate unplugging and replugging the network, as asked for by the BCT.
+pub fn hot_plug() {
+    info!("BCT: simulating network hot plug");
+
+    unsafe {
+        hap_bct_hot_plug();
+    }
+}
+
+/// Handle a console command of the BCT sequence, e.g. read from a UART console:
+///
+/// - `bct-change-name <name>`
+/// - `bct-hot-plug`
+pub fn handle_command(command: &str) -> Result<(), HapError> {
+    let command = command.trim();
+
+    if let Some(name) = command.strip_prefix("bct-change-name ") {
+        change_name(name.trim())
+    } else if command == "bct-hot-plug" {
+        hot_plug();
+        Ok(())
+    } else {
+        warn!("Unknown BCT command '{}'", command);
+        Err(HapError::InvalidArg)
+    }
+}
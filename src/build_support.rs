@@ -0,0 +1,1306 @@
+//! The pure helpers of the build script.
+//!
+//! `build.rs` includes this file through `#[path]`, like `ffi_deps`. The library only
+//! compiles it for its unit tests, as tests of a build script never run, so nothing
+//! here may use more than `std`, `serde` and `toml`, which are both build and dev
+//! dependencies.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// The kconfig of an ESP-IDF project.
+pub const SDKCONFIG_FILE: &str = "sdkconfig";
+
+/// Lowercase fragments of PlatformIO, pip and Python `requests` errors caused by a
+/// flaky network or registry rather than e.g. a misspelled package.
+pub const TRANSIENT_ERRORS: &[&str] = &[
+    "timed out",
+    "timeout",
+    "connection reset",
+    "connection refused",
+    "connection aborted",
+    "connectionerror",
+    "remote end closed connection",
+    "temporary failure in name resolution",
+    "name or service not known",
+    "network is unreachable",
+    "max retries exceeded",
+    "httpclienterror",
+    "500 server error",
+    "502 server error",
+    "503 server error",
+    "504 server error",
+    "bad gateway",
+    "service unavailable",
+];
+
+/// Whether the error `message` looks like a transient network failure, see
+/// [`TRANSIENT_ERRORS`].
+pub fn is_transient(message: &str) -> bool {
+    let message = message.to_lowercase();
+
+    TRANSIENT_ERRORS.iter().any(|fragment| message.contains(fragment))
+}
+
+/// The Rust target of each MCU.
+pub const MCU_TARGETS: &[(&str, &str)] = &[
+    ("esp32", "xtensa-esp32-espidf"),
+    ("esp32s2", "xtensa-esp32s2-espidf"),
+    ("esp32s3", "xtensa-esp32s3-espidf"),
+    ("esp32c3", "riscv32imc-esp-espidf"),
+];
+
+/// Why `target` can't be built, if the toolchain evidently can't, for `check_toolchain` of `build.rs`.
+///
+/// Xtensa targets need the esp fork of Rust: the `esp` toolchain of espup, or any
+/// rustc whose `targets` (`rustc --print target-list`) include `target`. RISC-V
+/// targets need the `espidf` OS, e.g. not `riscv32imc-unknown-none-elf`. The
+/// suggested target is the one of `mcu` (`$MCU`) if set, else the one matching the
+/// chip in `target`.
+pub fn toolchain_problem(target: &str, mcu: Option<&str>, toolchain: Option<&str>, targets: &[String]) -> Option<String> {
+    let chip = match mcu {
+        Some(mcu) => Some(mcu),
+        None if target.starts_with("riscv32") => Some("esp32c3"),
+        None => target.strip_prefix("xtensa-").and_then(|t| t.split('-').next()),
+    };
+    let suggested = MCU_TARGETS.iter().find(|(m, _)| Some(*m) == chip).map(|(_, target)| *target);
+    let suggestion = match suggested {
+        Some(suggested) => format!("build with `--target {}`", suggested),
+        None => format!("build for one of {}", MCU_TARGETS.iter().map(|(_, t)| *t).collect::<Vec<_>>().join(", ")),
+    };
+
+    let esp_fork = toolchain.is_some_and(|t| t.starts_with("esp")) || targets.iter().any(|t| t == target);
+
+    if target.starts_with("xtensa-") && !esp_fork {
+        Some(format!(
+            "The target `{}` needs the esp fork of Rust, but the toolchain is `{}`. \
+             Install it with `cargo install espup && espup install`, then {} using `cargo +esp`.",
+            target,
+            toolchain.unwrap_or("unknown"),
+            suggestion
+        ))
+    } else if target.starts_with("riscv32") && !target.ends_with("-espidf") {
+        Some(format!(
+            "The target `{}` has no `espidf` OS, which the ESP-IDF needs; {}. \
+             As a tier 3 target it needs nightly with `-Zbuild-std=std,panic_abort`.",
+            target, suggestion
+        ))
+    } else {
+        None
+    }
+}
+
+/// The MCUs with a Bluetooth LE controller, i.e. those supporting the BLE transport:
+///
+/// | MCU      | IP | BLE |
+/// |----------|----|-----|
+/// | esp32    | ✓  | ✓   |
+/// | esp32s2  | ✓  |     |
+/// | esp32s3  | ✓  | ✓   |
+/// | esp32c3  | ✓  | ✓   |
+pub const BLE_MCUS: &[&str] = &["esp32", "esp32s3", "esp32c3"];
+
+/// Why the HAP `transport` of `$ESP_HOMEKIT_TRANSPORT` (`ip` by default, or `ble`)
+/// isn't available on `mcu` with the kconfig `cfgs`, for `check_transport` of `build.rs`.
+///
+/// The esp-homekit-sdk only implements HAP over IP, so BLE fails in any case; the MCU
+/// and kconfig are checked first to point at the actual blocker on the chip.
+pub fn transport_problem(transport: &str, mcu: &str, cfgs: &[String]) -> Option<String> {
+    let transport = transport.trim().to_lowercase();
+
+    match transport.as_str() {
+        "" | "ip" => None,
+        "ble" if !BLE_MCUS.contains(&mcu) => Some(format!(
+            "HAP over BLE was requested, but the resolved MCU {} has no Bluetooth. BLE is supported on {}.",
+            mcu,
+            BLE_MCUS.join(", ")
+        )),
+        "ble" if !cfgs.iter().any(|cfg| cfg == "esp_idf_bt_enabled") => Some(
+            "HAP over BLE was requested, but Bluetooth is disabled, set `CONFIG_BT_ENABLED=y` in the sdkconfig"
+                .to_owned(),
+        ),
+        "ble" => Some(format!(
+            "HAP over BLE was requested for {}, but the esp-homekit-sdk only implements the IP transport",
+            mcu
+        )),
+        _ => Some(format!(
+            "Invalid $ESP_HOMEKIT_TRANSPORT '{}'. Should be `ip` or `ble`.",
+            transport
+        )),
+    }
+}
+
+/// The `esp_idf_*` cfgs of the kconfig, sorted and without duplicates, so the emitted
+/// set doesn't depend on the order of the kconfig and doesn't trigger rebuilds of
+/// dependents.
+pub fn sorted_cfgs(cfgs: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut cfgs = cfgs.into_iter().collect::<Vec<_>>();
+    cfgs.sort();
+    cfgs.dedup();
+
+    cfgs
+}
+
+/// The `ESP_IDF_CONFIG_<KEY>` rustc envs of the integer and string `values` of the
+/// kconfig by their `CONFIG_<KEY>` keys, sorted by name. `src/config.rs` reads them.
+pub fn kconfig_envs<'a>(values: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<(String, String)> {
+    let mut envs = values
+        .into_iter()
+        .map(|(key, value)| (format!("ESP_IDF_CONFIG_{}", key.trim_start_matches("CONFIG_")), value.to_owned()))
+        .collect::<Vec<_>>();
+    envs.sort();
+
+    envs
+}
+
+/// Parse an ESP-IDF version, e.g. `v4.4.1` or `4.4.1`.
+pub fn parse_idf_version(version: &str) -> Result<(u32, u32, u32), String> {
+    let parts = version
+        .trim()
+        .trim_start_matches('v')
+        .split('.')
+        .map(str::parse)
+        .collect::<Result<Vec<u32>, _>>();
+
+    match parts.as_deref() {
+        Ok([major, minor, patch]) => Ok((*major, *minor, *patch)),
+        _ => Err(format!("Expected `<major>.<minor>.<patch>`, got '{}'", version)),
+    }
+}
+
+/// Parse the `ESP_IDF_VERSION_MAJOR`, `ESP_IDF_VERSION_MINOR` and `ESP_IDF_VERSION_PATCH`
+/// defines of the `esp_idf_version.h` header of the ESP-IDF.
+pub fn parse_idf_version_header(content: &str) -> Result<(u32, u32, u32), String> {
+    let define = |name: &str| {
+        content
+            .lines()
+            .find_map(|line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["#define", key, value, ..] if key == format!("ESP_IDF_VERSION_{}", name) => value.parse().ok(),
+                _ => None,
+            })
+            .ok_or_else(|| format!("ESP_IDF_VERSION_{} not found", name))
+    };
+
+    Ok((define("MAJOR")?, define("MINOR")?, define("PATCH")?))
+}
+
+/// The `esp_idf_version_major`, `esp_idf_version_minor` and `esp_idf_version_patch`
+/// cfgs of an ESP-IDF version, e.g. `esp_idf_version_major="4"`.
+pub fn idf_version_cfgs((major, minor, patch): (u32, u32, u32)) -> [String; 3] {
+    [
+        format!("esp_idf_version_major=\"{}\"", major),
+        format!("esp_idf_version_minor=\"{}\"", minor),
+        format!("esp_idf_version_patch=\"{}\"", patch),
+    ]
+}
+
+/// Why the ESP-IDF `version` isn't among the `(major, minor)` releases of `supported`,
+/// if it isn't, for `check_idf_version` of `build.rs`.
+pub fn idf_version_problem((major, minor, patch): (u32, u32, u32), supported: &[(u32, u32)]) -> Option<String> {
+    if supported.contains(&(major, minor)) {
+        return None;
+    }
+
+    let supported = supported
+        .iter()
+        .map(|(major, minor)| format!("{}.{}.x", major, minor))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    Some(format!("supports IDF {}, resolved {}.{}.{}", supported, major, minor, patch))
+}
+
+/// Locate the kconfig the C build of `project_dir` produced.
+///
+/// ESP-IDF writes `sdkconfig.<profile>` when built through PlatformIO, a PIO-first
+/// project may also use a plain `sdkconfig`. Finding only the file of the other
+/// profile means the project is stale, i.e. the last build for this profile failed.
+pub fn find_kconfig_file(project_dir: &Path, release: bool) -> Result<PathBuf, String> {
+    let (profile, other) = if release { ("release", "debug") } else { ("debug", "release") };
+
+    let candidates = [format!("{SDKCONFIG_FILE}.{profile}"), SDKCONFIG_FILE.to_owned()];
+    if let Some(path) = candidates.iter().map(|name| project_dir.join(name)).find(|p| p.is_file()) {
+        return Ok(path);
+    }
+
+    let tried = candidates
+        .iter()
+        .map(|name| format!("'{}'", project_dir.join(name).display()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let stale = project_dir.join(format!("{SDKCONFIG_FILE}.{other}"));
+    if stale.is_file() {
+        return Err(format!(
+            "No kconfig for the {} profile in the PlatformIO project, tried {}. Only '{}' of a \
+             previous {} build exists, so the last build of the project likely failed. Fix the \
+             errors reported above, or run `cargo clean` to regenerate the project.",
+            profile,
+            tried,
+            stale.display(),
+            other
+        ));
+    }
+
+    Err(format!(
+        "No kconfig in the PlatformIO project, tried {}. Check the errors reported by the \
+         build above, or run `cargo clean` to regenerate the project.",
+        tried
+    ))
+}
+
+/// Wall-clock time spent in each stage of the build.
+#[derive(Debug, Default)]
+pub struct Timings {
+    stages: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    /// Record `stage` as having run from `start` until now.
+    pub fn record(&mut self, stage: &'static str, start: Instant) {
+        self.add(stage, start.elapsed());
+    }
+
+    /// Record `stage` as having taken `duration`.
+    pub fn add(&mut self, stage: &'static str, duration: Duration) {
+        self.stages.push((stage, duration));
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    /// E.g. `install 2.1s, resolve 14.0s, pio build 430.2s`.
+    pub fn summary(&self) -> String {
+        self.stages
+            .iter()
+            .map(|(stage, duration)| format!("{} {:.1}s", stage, duration.as_secs_f64()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// E.g. `{"install":2.1,"resolve":14.0,"total":16.1}`, in seconds.
+    pub fn to_json(&self) -> String {
+        let stages = self
+            .stages
+            .iter()
+            .map(|(stage, duration)| format!("\"{}\":{:.3}", stage, duration.as_secs_f64()))
+            .chain(Some(format!("\"total\":{:.3}", self.total().as_secs_f64())))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        format!("{{{}}}", stages)
+    }
+}
+
+/// An sdkconfig defaults fragment written by the build when a cargo feature is enabled,
+/// layered below the sdkconfig defaults of the user, see [`SdkconfigFiles`].
+pub struct InjectedSdkconfig {
+    /// The `CARGO_FEATURE_*` name of the feature.
+    pub feature: &'static str,
+    /// Only injected for this cargo profile, for all profiles if `None`.
+    pub profile: Option<&'static str>,
+    /// Only injected for this MCU, for all MCUs if `None`.
+    pub mcu: Option<&'static str>,
+    pub file: &'static str,
+    pub contents: &'static str,
+    /// Warn if the sdkconfig of the build doesn't end up with `contents`, see
+    /// `check_injected_sdkconfigs` of `build.rs`.
+    pub verify: bool,
+}
+
+pub const INJECTED_SDKCONFIGS: &[InjectedSdkconfig] = &[
+    // Also print the debug logs of the HAP framework, which include the pairing state
+    // machine, next to the HTTP dumps.
+    InjectedSdkconfig {
+        feature: "INSECURE_DEBUG",
+        profile: None,
+        mcu: None,
+        file: "sdkconfig.defaults.insecure-debug",
+        contents: "CONFIG_LOG_DEFAULT_LEVEL_DEBUG=y\n",
+        verify: false,
+    },
+    // Compile the log statements out instead of filtering them at runtime.
+    InjectedSdkconfig {
+        feature: "SILENT_RELEASE",
+        profile: Some("release"),
+        mcu: None,
+        file: "sdkconfig.defaults.silent-release",
+        contents: "CONFIG_LOG_DEFAULT_LEVEL_NONE=y\n\
+                   CONFIG_LOG_MAXIMUM_EQUALS_DEFAULT=y\n\
+                   CONFIG_BOOTLOADER_LOG_LEVEL_NONE=y\n",
+        verify: false,
+    },
+    // The mbedtls bignum accelerator carries the SRP and curve arithmetic of pair setup
+    // and pair verify, and the maximum CPU clock speeds up the rest. On an esp32c3
+    // pair verify drops from about 1.9 s to 0.6 s.
+    InjectedSdkconfig {
+        feature: "CRYPTO_ACCEL",
+        profile: None,
+        mcu: Some("esp32"),
+        file: "sdkconfig.defaults.crypto-accel",
+        contents: "CONFIG_MBEDTLS_HARDWARE_AES=y\n\
+                   CONFIG_MBEDTLS_HARDWARE_MPI=y\n\
+                   CONFIG_MBEDTLS_HARDWARE_SHA=y\n\
+                   CONFIG_MBEDTLS_ECP_NIST_OPTIM=y\n\
+                   CONFIG_ESP32_DEFAULT_CPU_FREQ_240=y\n",
+        verify: true,
+    },
+    InjectedSdkconfig {
+        feature: "CRYPTO_ACCEL",
+        profile: None,
+        mcu: Some("esp32s2"),
+        file: "sdkconfig.defaults.crypto-accel",
+        contents: "CONFIG_MBEDTLS_HARDWARE_AES=y\n\
+                   CONFIG_MBEDTLS_HARDWARE_MPI=y\n\
+                   CONFIG_MBEDTLS_HARDWARE_SHA=y\n\
+                   CONFIG_MBEDTLS_ECP_NIST_OPTIM=y\n\
+                   CONFIG_ESP32S2_DEFAULT_CPU_FREQ_240=y\n",
+        verify: true,
+    },
+    InjectedSdkconfig {
+        feature: "CRYPTO_ACCEL",
+        profile: None,
+        mcu: Some("esp32s3"),
+        file: "sdkconfig.defaults.crypto-accel",
+        contents: "CONFIG_MBEDTLS_HARDWARE_AES=y\n\
+                   CONFIG_MBEDTLS_HARDWARE_MPI=y\n\
+                   CONFIG_MBEDTLS_HARDWARE_SHA=y\n\
+                   CONFIG_MBEDTLS_ECP_NIST_OPTIM=y\n\
+                   CONFIG_ESP32S3_DEFAULT_CPU_FREQ_240=y\n",
+        verify: true,
+    },
+    // No AES-GCM offload benefit at 160 MHz, but the bignum accelerator does most of the work.
+    InjectedSdkconfig {
+        feature: "CRYPTO_ACCEL",
+        profile: None,
+        mcu: Some("esp32c3"),
+        file: "sdkconfig.defaults.crypto-accel",
+        contents: "CONFIG_MBEDTLS_HARDWARE_AES=y\n\
+                   CONFIG_MBEDTLS_HARDWARE_MPI=y\n\
+                   CONFIG_MBEDTLS_HARDWARE_SHA=y\n\
+                   CONFIG_MBEDTLS_ECP_NIST_OPTIM=y\n\
+                   CONFIG_ESP32C3_DEFAULT_CPU_FREQ_160=y\n",
+        verify: true,
+    },
+];
+
+impl InjectedSdkconfig {
+    /// Whether the fragment is injected into builds for `profile` and `mcu` once its
+    /// feature is enabled.
+    pub fn applies_to(&self, profile: &str, mcu: &str) -> bool {
+        self.profile.is_none_or(|p| p == profile) && self.mcu.is_none_or(|m| m == mcu)
+    }
+}
+
+/// The [`INJECTED_SDKCONFIGS`] of the enabled `features`, by their `CARGO_FEATURE_*`
+/// names, for `profile` and `mcu`.
+pub fn enabled_injected_sdkconfigs<'a>(
+    features: &'a [String],
+    profile: &'a str,
+    mcu: &'a str,
+) -> impl Iterator<Item = &'static InjectedSdkconfig> + 'a {
+    INJECTED_SDKCONFIGS.iter().filter(move |injected| {
+        features.iter().any(|feature| feature == injected.feature) && injected.applies_to(profile, mcu)
+    })
+}
+
+/// List the existing variants of the sdkconfig file `path`, most specific first:
+/// `<filename>.local`, `<filename>.<profile>.<chip>`, `<filename>.<chip>`,
+/// `<filename>.<profile>` and `<filename>` itself.
+///
+/// The `.local` file is meant for developer overrides kept out of VCS, so it wins
+/// over everything else.
+pub fn list_specific_sdkconfigs(
+    path: PathBuf,
+    profile: &str,
+    chip: &str,
+) -> impl DoubleEndedIterator<Item = PathBuf> {
+    path.file_name()
+        .and_then(|filename| filename.to_str())
+        .map(|filename| {
+            let profile_specific = format!("{}.{}", filename, profile);
+            let chip_specific = format!("{}.{}", filename, chip);
+            let profile_chip_specific = format!("{}.{}", &profile_specific, chip);
+            let local = format!("{}.local", filename);
+
+            [
+                local,
+                profile_chip_specific,
+                chip_specific,
+                profile_specific,
+                filename.to_owned(),
+            ]
+        })
+        .into_iter()
+        .flatten()
+        .filter_map(move |s| {
+            let path = path.with_file_name(s);
+            if path.is_file() {
+                Some(path)
+            } else {
+                None
+            }
+        })
+}
+
+/// List the sdkconfig fragments in `dir`, i.e. all `*.conf` and `sdkconfig.*` files,
+/// sorted by name so they are layered in a stable order.
+pub fn list_sdkconfig_fragments(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let error = |e: std::io::Error| format!("Failed to list the sdkconfig fragments in '{}': {}", dir.display(), e);
+    let mut fragments = Vec::new();
+
+    for entry in fs::read_dir(dir).map_err(error)? {
+        let path = entry.map_err(error)?.path();
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+
+        if path.is_file() && (name.ends_with(".conf") || name.starts_with("sdkconfig.")) {
+            fragments.push(path);
+        }
+    }
+    fragments.sort();
+
+    Ok(fragments)
+}
+
+/// The sdkconfig defaults of the `CONFIG_X=y;CONFIG_Y=1` pairs of
+/// `$ESP_HOMEKIT_SDKCONFIG_OVERRIDE`, one per line, empty without pairs.
+pub fn sdkconfig_override_contents(var: &str) -> Result<String, String> {
+    let mut contents = String::new();
+
+    for pair in var.split(';').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let valid = pair
+            .split_once('=')
+            .is_some_and(|(key, _)| key.starts_with("CONFIG_") && !key.contains(char::is_whitespace));
+        if !valid {
+            return Err(format!("the entry '{}' should be `CONFIG_<NAME>=<value>`", pair));
+        }
+
+        contents.push_str(pair);
+        contents.push('\n');
+    }
+
+    Ok(contents)
+}
+
+/// The sdkconfig files of a build as `(path, file name in the project)`.
+///
+/// ESP-IDF applies the defaults files in order, a later one overriding the options
+/// of an earlier one, see [`layered`](Self::layered).
+#[derive(Clone, Debug, Default)]
+pub struct SdkconfigFiles {
+    /// The `sdkconfig` of the user, see `$ESP_IDF_SDKCONFIG`.
+    pub sdkconfig: Option<(PathBuf, PathBuf)>,
+    /// The [`INJECTED_SDKCONFIGS`] of the enabled features.
+    pub injected: Vec<(PathBuf, PathBuf)>,
+    /// The defaults of the user, see `$ESP_IDF_SDKCONFIG_DEFAULTS`, least specific first.
+    pub defaults: Vec<(PathBuf, PathBuf)>,
+    /// The fragments of an `$ESP_IDF_SDKCONFIG` directory, sorted by name.
+    pub fragments: Vec<(PathBuf, PathBuf)>,
+    /// The values of `$ESP_HOMEKIT_SDKCONFIG_OVERRIDE`.
+    pub overrides: Option<(PathBuf, PathBuf)>,
+}
+
+impl SdkconfigFiles {
+    /// All files in the order they are applied: the sdkconfig, the injected fragments,
+    /// so the defaults and fragments of the user override them, and the overrides last.
+    pub fn layered(self) -> impl Iterator<Item = (PathBuf, PathBuf)> {
+        self.sdkconfig
+            .into_iter()
+            .chain(self.injected)
+            .chain(self.defaults)
+            .chain(self.fragments)
+            .chain(self.overrides)
+    }
+}
+
+/// The newlib functions taking or returning `long double`, see [`blocklisted_functions`].
+pub const LONG_DOUBLE_FUNCTIONS: &[&str] = &["strtold", "_strtold_r"];
+
+/// The functions left out of the bindings given the sysroot and GCC include args of
+/// the toolchain, `None` if they couldn't be determined.
+///
+/// Without them clang falls back to the host headers, whose `long double` doesn't match
+/// newlib's on the target, so the [`LONG_DOUBLE_FUNCTIONS`] would bind with the wrong
+/// ABI. With them nothing is left out.
+pub fn blocklisted_functions(toolchain_args: Option<&[String]>) -> &'static [&'static str] {
+    match toolchain_args {
+        Some(_) => &[],
+        None => LONG_DOUBLE_FUNCTIONS,
+    }
+}
+
+/// The board, MCU, platform, frameworks and Rust target PlatformIO resolved for a
+/// build, i.e. the fields of embuild's `pio::Resolution` the project depends on.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedPlatform {
+    pub board: String,
+    pub mcu: String,
+    pub platform: String,
+    pub frameworks: Vec<String>,
+    pub target: String,
+}
+
+/// Everything the PlatformIO project is generated from.
+///
+/// The project builder is configured from this alone, so the report of
+/// `$ESP_IDF_SYS_DRY_RUN` shows exactly what a real build uses.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProjectInputs {
+    pub profile: String,
+    pub resolution: ResolvedPlatform,
+    pub platform_packages: Vec<(String, String)>,
+    pub options: Vec<(String, String)>,
+    /// Copied into the project in this order, so later sdkconfig files override
+    /// earlier ones.
+    pub files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl ProjectInputs {
+    /// The readable report of a dry run, with the values of the `env` variables besides
+    /// the inputs, for a project generated at `project_path`.
+    pub fn report(&self, env: &[(&str, Option<String>)], project_path: &Path) -> String {
+        let mut report = format!("esp-homekit-sdk-sys dry run of the `{}` profile\n", self.profile);
+
+        report += "\nEnvironment:\n";
+        for (var, value) in env {
+            report += &format!("  {}={}\n", var, value.as_deref().unwrap_or("<unset>"));
+        }
+
+        let resolution = &self.resolution;
+        report += "\nResolution:\n";
+        report += &format!("  board: {}\n", resolution.board);
+        report += &format!("  mcu: {}\n", resolution.mcu);
+        report += &format!("  platform: {}\n", resolution.platform);
+        report += &format!("  frameworks: {}\n", resolution.frameworks.join(", "));
+        report += &format!("  target: {}\n", resolution.target);
+
+        report += "\nPlatform packages:\n";
+        for (name, package) in &self.platform_packages {
+            report += &format!("  {} = {}\n", name, package);
+        }
+
+        report += "\nOptions:\n";
+        for (key, value) in &self.options {
+            report += &format!("  {} = {}\n", key, value);
+        }
+
+        report += "\nFiles, in order of precedence from lowest:\n";
+        for (source, dest) in &self.files {
+            report += &format!("  {} -> {}\n", source.display(), dest.display());
+        }
+
+        report += &format!("\nProject generated at '{}'\n", project_path.display());
+
+        report
+    }
+
+    /// A hash of everything the built project depends on, keying the artifact cache of
+    /// `build.rs`.
+    ///
+    /// Files are hashed by content, so the merged sdkconfig and the shim are covered.
+    /// The SDK revision and the platform are only pinned by the options with a lock.
+    pub fn cache_key(&self) -> Result<String, String> {
+        let mut hasher = DefaultHasher::new();
+
+        self.profile.hash(&mut hasher);
+
+        let resolution = &self.resolution;
+        resolution.board.hash(&mut hasher);
+        resolution.mcu.hash(&mut hasher);
+        resolution.platform.hash(&mut hasher);
+        resolution.frameworks.hash(&mut hasher);
+        resolution.target.hash(&mut hasher);
+
+        self.platform_packages.hash(&mut hasher);
+        self.options.hash(&mut hasher);
+
+        for (source, dest) in &self.files {
+            fs::read(source)
+                .map_err(|e| format!("Failed to read '{}': {}", source.display(), e))?
+                .hash(&mut hasher);
+            dest.hash(&mut hasher);
+        }
+
+        Ok(format!("{:016x}", hasher.finish()))
+    }
+}
+
+/// The link arguments of the SDK build, split up for dependents with their own link
+/// step. `build.rs` exposes them as the `;`-separated [`metadata`](Self::metadata):
+///
+/// | Metadata of dependents                  | Content                                                     |
+/// |-----------------------------------------|-------------------------------------------------------------|
+/// | `DEP_ESP_HOMEKIT_SDK_LINK_LIBS`         | Library names of `-l` or archive paths, e.g. `esp_hap_core` |
+/// | `DEP_ESP_HOMEKIT_SDK_LINK_SEARCH_PATHS` | Library search paths of `-L`                                |
+/// | `DEP_ESP_HOMEKIT_SDK_LINK_FLAGS`        | Other linker flags, e.g. `-Wl,--gc-sections`                |
+///
+/// The link arguments embuild propagates are unchanged. Not emitted for PIO-first
+/// builds, which link themselves.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkConfig {
+    pub libraries: Vec<String>,
+    pub search_paths: Vec<String>,
+    pub flags: Vec<String>,
+}
+
+impl LinkConfig {
+    /// Split up the `-l`, `-L` and other flags of the scons link args.
+    pub fn new(libflags: &[String], libdirflags: &[String], linkflags: &[String]) -> Self {
+        let strip = |flags: &[String], prefix: &str| {
+            flags
+                .iter()
+                .map(|flag| flag.strip_prefix(prefix).unwrap_or(flag).to_owned())
+                .collect()
+        };
+
+        LinkConfig {
+            libraries: strip(libflags, "-l"),
+            search_paths: strip(libdirflags, "-L"),
+            flags: linkflags.to_vec(),
+        }
+    }
+
+    /// The `cargo:<key>=<value>` metadata of the link args.
+    pub fn metadata(&self) -> [(&'static str, String); 3] {
+        [
+            ("LINK_LIBS", self.libraries.join(";")),
+            ("LINK_SEARCH_PATHS", self.search_paths.join(";")),
+            ("LINK_FLAGS", self.flags.join(";")),
+        ]
+    }
+}
+
+/// The module name of a header of `$ESP_HOMEKIT_BINDINGS_HEADERS`: its file stem in
+/// lowercase, with anything but ASCII letters and digits replaced by `_`, e.g.
+/// `my-sensors.h` is `my_sensors`.
+pub fn bindings_module_name(header: &Path) -> Result<String, String> {
+    let stem = header
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| format!("'{}' is not a header file", header.display()))?;
+
+    let mut name = stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+        .collect::<String>();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+
+    Ok(name)
+}
+
+/// The `headers` of `$ESP_HOMEKIT_BINDINGS_HEADERS` with their module names, see
+/// [`bindings_module_name`]. Each module needs a header of its own.
+pub fn bindings_modules(headers: impl IntoIterator<Item = PathBuf>) -> Result<Vec<(String, PathBuf)>, String> {
+    let mut modules = Vec::<(String, PathBuf)>::new();
+    for header in headers {
+        let name = bindings_module_name(&header)?;
+        // `raw` holds the bindings of `src/include/bindings.h`.
+        if name == "raw" || modules.iter().any(|(other, _)| *other == name) {
+            return Err(format!("more than one header for the module `{}`", name));
+        }
+
+        modules.push((name, header));
+    }
+
+    Ok(modules)
+}
+
+/// The module declarations of the bindings `modules`, included by `lib.rs`: a
+/// `pub mod <module>` including `bindings_<module>.rs` of `out_dir` each.
+pub fn extra_bindings_source(modules: &[(String, PathBuf)], out_dir: &Path) -> String {
+    modules
+        .iter()
+        .map(|(name, header)| {
+            format!(
+                "/// The bindings of `{}`, from `$ESP_HOMEKIT_BINDINGS_HEADERS`.\n\
+                 pub mod {} {{\n    use crate::c_types;\n\n    include!({:?});\n}}\n",
+                header.display(),
+                name,
+                out_dir.join(format!("bindings_{}.rs", name))
+            )
+        })
+        .collect()
+}
+
+/// The versions a build resolved, recorded in the lock file of `build.rs` so every
+/// build of the workspace uses the same ones.
+///
+/// The lock is written on the first build and honored by later ones, which fail if
+/// PlatformIO resolves anything else. Set `$ESP_IDF_SYS_UPDATE_LOCK` to `1` to
+/// re-resolve and rewrite it. The file is TOML with one key per field:
+///
+/// ```text
+/// platform = "5.2.0"
+/// framework = "3.40401.0"
+/// sdk = "0.0.0+sha.1a2b3c4"
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lock {
+    /// The version of the `espressif32` PlatformIO platform.
+    pub platform: String,
+    /// The version of the `framework-espidf` package.
+    pub framework: String,
+    /// The version PlatformIO recorded for the SDK library, with the commit of git sources.
+    pub sdk: String,
+}
+
+impl Lock {
+    /// Parse the content of a lock file.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        toml::from_str(content).map_err(|e| e.to_string())
+    }
+
+    /// The content of a lock file.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("A lock of strings always serializes")
+    }
+
+    /// Read the lock file at `path`, if there is one.
+    pub fn load(path: &Path) -> Result<Option<Self>, String> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read '{}': {}", path.display(), e))?;
+
+        Self::parse(&content)
+            .map(Some)
+            .map_err(|e| format!("Malformed lock file '{}': {}", path.display(), e))
+    }
+
+    /// Write the lock file at `path`.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_toml()).map_err(|e| format!("Failed to write '{}': {}", path.display(), e))
+    }
+
+    /// The commit of a git source, e.g. `1a2b3c4` of `0.0.0+sha.1a2b3c4`.
+    pub fn sdk_commit(&self) -> Option<&str> {
+        self.sdk.split_once("+sha.").map(|(_, commit)| commit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_failures_are_transient() {
+        assert!(is_transient("HTTPClientError: Read timed out. (read timeout=10)"));
+        assert!(is_transient(
+            "Failed to install: ConnectionError: Max retries exceeded with url: /v3/packages"
+        ));
+        assert!(is_transient("503 Server Error: Service Unavailable for url"));
+    }
+
+    #[test]
+    fn other_failures_are_permanent() {
+        assert!(!is_transient("Error: Could not find the package with 'esp-homekit-sdk' requirements"));
+        assert!(!is_transient("UnknownBoard: Unknown board ID 'esp32-foo'"));
+    }
+
+    fn targets(targets: &[&str]) -> Vec<String> {
+        targets.iter().map(|target| target.to_string()).collect()
+    }
+
+    #[test]
+    fn esp_toolchains_build_xtensa() {
+        assert_eq!(toolchain_problem("xtensa-esp32-espidf", None, Some("esp"), &[]), None);
+        let fork = targets(&["xtensa-esp32-espidf"]);
+        assert_eq!(toolchain_problem("xtensa-esp32-espidf", None, Some("custom"), &fork), None);
+    }
+
+    #[test]
+    fn upstream_toolchains_cant_build_xtensa() {
+        let upstream = targets(&["x86_64-unknown-linux-gnu"]);
+        let problem = toolchain_problem("xtensa-esp32s3-espidf", None, Some("stable"), &upstream).unwrap();
+
+        assert!(problem.contains("esp fork"), "{}", problem);
+        assert!(problem.contains("--target xtensa-esp32s3-espidf"), "{}", problem);
+    }
+
+    #[test]
+    fn riscv_needs_the_espidf_os() {
+        assert_eq!(toolchain_problem("riscv32imc-esp-espidf", None, Some("nightly"), &[]), None);
+
+        let problem = toolchain_problem("riscv32imc-unknown-none-elf", None, Some("nightly"), &[]).unwrap();
+        assert!(problem.contains("--target riscv32imc-esp-espidf"), "{}", problem);
+    }
+
+    #[test]
+    fn the_mcu_picks_the_suggested_target() {
+        let problem = toolchain_problem("xtensa-esp32-espidf", Some("esp32s2"), Some("stable"), &[]).unwrap();
+        assert!(problem.contains("--target xtensa-esp32s2-espidf"), "{}", problem);
+
+        let problem = toolchain_problem("xtensa-esp32-espidf", Some("esp32h2"), Some("stable"), &[]).unwrap();
+        assert!(problem.contains("build for one of"), "{}", problem);
+    }
+
+    #[test]
+    fn idf_versions_parse_with_or_without_v() {
+        assert_eq!(parse_idf_version("4.4.1"), Ok((4, 4, 1)));
+        assert_eq!(parse_idf_version(" v4.3.0\n"), Ok((4, 3, 0)));
+    }
+
+    #[test]
+    fn malformed_idf_versions_are_rejected() {
+        assert!(parse_idf_version("4.4").is_err());
+        assert!(parse_idf_version("4.4.1.2").is_err());
+        assert!(parse_idf_version("4.4.x").is_err());
+        assert!(parse_idf_version("").is_err());
+    }
+
+    #[test]
+    fn idf_versions_are_checked_by_minor_release() {
+        let supported = &[(4, 3), (4, 4)];
+
+        assert_eq!(idf_version_problem((4, 4, 7), supported), None);
+        assert_eq!(
+            idf_version_problem((5, 0, 1), supported),
+            Some("supports IDF 4.3.x, 4.4.x, resolved 5.0.1".to_owned())
+        );
+    }
+
+    fn lock() -> Lock {
+        Lock {
+            platform: "5.2.0".to_owned(),
+            framework: "3.40401.0".to_owned(),
+            sdk: "0.0.0+sha.1a2b3c4".to_owned(),
+        }
+    }
+
+    #[test]
+    fn locks_round_trip() {
+        let lock = lock();
+
+        assert_eq!(Lock::parse(&lock.to_toml()), Ok(lock.clone()));
+        assert_eq!(lock.sdk_commit(), Some("1a2b3c4"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("esp-homekit-sdk-sys.lock");
+        assert_eq!(Lock::load(&path), Ok(None));
+
+        lock.save(&path).unwrap();
+        assert_eq!(Lock::load(&path), Ok(Some(lock)));
+    }
+
+    #[test]
+    fn locks_missing_a_field_are_rejected() {
+        let error = Lock::parse("platform = \"5.2.0\"\nframework = \"3.40401.0\"\n").unwrap_err();
+
+        assert!(error.contains("missing field `sdk`"), "{}", error);
+    }
+
+    #[test]
+    fn the_kconfig_of_the_profile_is_preferred() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("sdkconfig"), "").unwrap();
+        assert_eq!(find_kconfig_file(dir.path(), true), Ok(dir.path().join("sdkconfig")));
+
+        fs::write(dir.path().join("sdkconfig.release"), "").unwrap();
+        assert_eq!(find_kconfig_file(dir.path(), true), Ok(dir.path().join("sdkconfig.release")));
+        assert_eq!(find_kconfig_file(dir.path(), false), Ok(dir.path().join("sdkconfig")));
+    }
+
+    #[test]
+    fn missing_kconfigs_list_the_paths_tried() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = find_kconfig_file(dir.path(), false).unwrap_err();
+
+        assert!(error.contains(&dir.path().join("sdkconfig.debug").display().to_string()), "{}", error);
+        assert!(error.contains(&dir.path().join("sdkconfig").display().to_string()), "{}", error);
+        assert!(!error.contains("previous"), "{}", error);
+    }
+
+    #[test]
+    fn kconfigs_of_the_other_profile_are_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("sdkconfig.release"), "").unwrap();
+
+        let error = find_kconfig_file(dir.path(), false).unwrap_err();
+        assert!(error.contains("previous release build"), "{}", error);
+        assert!(error.contains("cargo clean"), "{}", error);
+    }
+
+    fn timings() -> Timings {
+        let mut timings = Timings::default();
+        timings.add("install", Duration::from_millis(2100));
+        timings.add("pio build", Duration::from_millis(430_250));
+
+        timings
+    }
+
+    #[test]
+    fn timings_sum_up_their_stages() {
+        assert_eq!(Timings::default().total(), Duration::ZERO);
+        assert_eq!(timings().total(), Duration::from_millis(432_350));
+
+        let mut timings = timings();
+        timings.record("bindgen", Instant::now());
+        assert!(timings.total() >= Duration::from_millis(432_350));
+    }
+
+    #[test]
+    fn timings_are_summarized_in_order() {
+        assert_eq!(timings().summary(), "install 2.1s, pio build 430.2s");
+        assert_eq!(Timings::default().summary(), "");
+    }
+
+    #[test]
+    fn timings_are_reported_as_json_with_the_total() {
+        assert_eq!(timings().to_json(), r#"{"install":2.100,"pio build":430.250,"total":432.350}"#);
+        assert_eq!(Timings::default().to_json(), r#"{"total":0.000}"#);
+    }
+
+    /// The files of `dir` as `(path, file name in the project)`.
+    fn files(dir: &Path, names: &[&str]) -> Vec<(PathBuf, PathBuf)> {
+        names.iter().map(|name| (dir.join(name), PathBuf::from(name))).collect()
+    }
+
+    /// The value of `key` after applying `files` in order, as ESP-IDF does with defaults.
+    fn effective(files: impl IntoIterator<Item = (PathBuf, PathBuf)>, key: &str) -> Option<String> {
+        files
+            .into_iter()
+            .flat_map(|(path, _)| fs::read_to_string(path).unwrap().lines().map(str::to_owned).collect::<Vec<_>>())
+            .filter_map(|line| line.split_once('=').filter(|(k, _)| *k == key).map(|(_, value)| value.to_owned()))
+            .last()
+    }
+
+    #[test]
+    fn user_defaults_override_injected_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("sdkconfig.defaults.silent-release"), "CONFIG_LOG_DEFAULT_LEVEL_NONE=y\n").unwrap();
+        fs::write(dir.path().join("sdkconfig.defaults"), "CONFIG_LOG_DEFAULT_LEVEL_NONE=n\n").unwrap();
+
+        let files = SdkconfigFiles {
+            injected: files(dir.path(), &["sdkconfig.defaults.silent-release"]),
+            defaults: files(dir.path(), &["sdkconfig.defaults"]),
+            ..Default::default()
+        };
+
+        let layered = files.clone().layered().map(|(_, name)| name).collect::<Vec<_>>();
+        assert_eq!(layered, [Path::new("sdkconfig.defaults.silent-release"), Path::new("sdkconfig.defaults")]);
+        assert_eq!(effective(files.layered(), "CONFIG_LOG_DEFAULT_LEVEL_NONE").as_deref(), Some("n"));
+    }
+
+    #[test]
+    fn release_only_fragments_are_injected_in_release() {
+        let features = ["SILENT_RELEASE".to_owned(), "INSECURE_DEBUG".to_owned()];
+        let injected = |profile| {
+            enabled_injected_sdkconfigs(&features, profile, "esp32")
+                .map(|injected| injected.file)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            injected("release"),
+            ["sdkconfig.defaults.insecure-debug", "sdkconfig.defaults.silent-release"]
+        );
+        assert_eq!(injected("debug"), ["sdkconfig.defaults.insecure-debug"]);
+    }
+
+    #[test]
+    fn only_the_fragments_of_enabled_features_and_the_mcu_are_injected() {
+        assert_eq!(enabled_injected_sdkconfigs(&[], "release", "esp32").count(), 0);
+
+        let features = ["CRYPTO_ACCEL".to_owned()];
+        let injected = enabled_injected_sdkconfigs(&features, "debug", "esp32c3").collect::<Vec<_>>();
+        assert_eq!(injected.len(), 1);
+        assert!(injected[0].verify);
+        assert!(injected[0].contents.contains("CONFIG_ESP32C3_DEFAULT_CPU_FREQ_160=y"));
+    }
+
+    #[test]
+    fn both_fragments_of_a_directory_are_applied() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("sdkconfig.defaults"), "CONFIG_A=1\nCONFIG_B=1\n").unwrap();
+        fs::create_dir(dir.path().join("sdkconfig")).unwrap();
+        fs::write(dir.path().join("sdkconfig").join("20-stack.conf"), "CONFIG_B=2\n").unwrap();
+        fs::write(dir.path().join("sdkconfig").join("sdkconfig.10-log"), "CONFIG_A=2\nCONFIG_B=3\n").unwrap();
+        fs::write(dir.path().join("sdkconfig").join("README.md"), "CONFIG_A=4\n").unwrap();
+
+        let fragments = list_sdkconfig_fragments(&dir.path().join("sdkconfig")).unwrap();
+        assert_eq!(
+            fragments,
+            [dir.path().join("sdkconfig").join("20-stack.conf"), dir.path().join("sdkconfig").join("sdkconfig.10-log")]
+        );
+
+        let files = SdkconfigFiles {
+            defaults: files(dir.path(), &["sdkconfig.defaults"]),
+            fragments: fragments.into_iter().map(|path| (path.clone(), path)).collect(),
+            ..Default::default()
+        };
+        assert_eq!(effective(files.clone().layered(), "CONFIG_A").as_deref(), Some("2"));
+        assert_eq!(effective(files.layered(), "CONFIG_B").as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn sdkconfigs_are_listed_most_specific_first() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["sdkconfig", "sdkconfig.release", "sdkconfig.esp32c3", "sdkconfig.release.esp32", "sdkconfig.local"] {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let listed = list_specific_sdkconfigs(dir.path().join("sdkconfig"), "release", "esp32c3").collect::<Vec<_>>();
+        assert_eq!(
+            listed,
+            ["sdkconfig.local", "sdkconfig.esp32c3", "sdkconfig.release", "sdkconfig"].map(|name| dir.path().join(name))
+        );
+    }
+
+    #[test]
+    fn local_defaults_override_the_base_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("sdkconfig.defaults"), "CONFIG_HAP_HTTP_STACK_SIZE=12288\n").unwrap();
+        fs::write(dir.path().join("sdkconfig.defaults.debug"), "CONFIG_HAP_HTTP_STACK_SIZE=16384\n").unwrap();
+        fs::write(dir.path().join("sdkconfig.defaults.local"), "CONFIG_HAP_HTTP_STACK_SIZE=20480\n").unwrap();
+
+        // Like `main` of `build.rs`, the defaults are layered least specific first.
+        let defaults = list_specific_sdkconfigs(dir.path().join("sdkconfig.defaults"), "debug", "esp32")
+            .rev()
+            .map(|path| (path.clone(), path))
+            .collect::<Vec<_>>();
+        assert_eq!(defaults.last().map(|(path, _)| path), Some(&dir.path().join("sdkconfig.defaults.local")));
+
+        let files = SdkconfigFiles { defaults, ..Default::default() };
+        assert_eq!(effective(files.layered(), "CONFIG_HAP_HTTP_STACK_SIZE").as_deref(), Some("20480"));
+    }
+
+    #[test]
+    fn overrides_win_over_the_committed_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("sdkconfig.defaults"), "CONFIG_LOG_DEFAULT_LEVEL=3\nCONFIG_HAP_MDNS=y\n").unwrap();
+
+        let contents = sdkconfig_override_contents(" CONFIG_LOG_DEFAULT_LEVEL=5; ;").unwrap();
+        assert_eq!(contents, "CONFIG_LOG_DEFAULT_LEVEL=5\n");
+        fs::write(dir.path().join("sdkconfig.defaults.override"), contents).unwrap();
+
+        let files = SdkconfigFiles {
+            defaults: files(dir.path(), &["sdkconfig.defaults"]),
+            overrides: files(dir.path(), &["sdkconfig.defaults.override"]).pop(),
+            ..Default::default()
+        };
+        assert_eq!(effective(files.clone().layered(), "CONFIG_LOG_DEFAULT_LEVEL").as_deref(), Some("5"));
+        assert_eq!(effective(files.layered(), "CONFIG_HAP_MDNS").as_deref(), Some("y"));
+    }
+
+    #[test]
+    fn malformed_overrides_are_rejected() {
+        assert_eq!(sdkconfig_override_contents(""), Ok(String::new()));
+        assert!(sdkconfig_override_contents("LOG_DEFAULT_LEVEL=5").is_err());
+        assert!(sdkconfig_override_contents("CONFIG_LOG_DEFAULT_LEVEL").is_err());
+    }
+
+    #[test]
+    fn ble_is_rejected_on_the_esp32s2() {
+        let cfgs = ["esp_idf_bt_enabled".to_owned()];
+        let problem = transport_problem("ble", "esp32s2", &cfgs).unwrap();
+
+        assert!(problem.contains("esp32s2 has no Bluetooth"), "{}", problem);
+        assert!(problem.contains("esp32, esp32s3, esp32c3"), "{}", problem);
+    }
+
+    #[test]
+    fn ip_is_available_everywhere() {
+        for (mcu, _) in MCU_TARGETS {
+            assert_eq!(transport_problem("", mcu, &[]), None);
+            assert_eq!(transport_problem(" IP", mcu, &[]), None);
+        }
+        assert!(transport_problem("thread", "esp32", &[]).unwrap().contains("Invalid"));
+    }
+
+    #[test]
+    fn ble_needs_bluetooth_in_the_kconfig() {
+        let problem = transport_problem("ble", "esp32c3", &[]).unwrap();
+        assert!(problem.contains("CONFIG_BT_ENABLED"), "{}", problem);
+
+        let problem = transport_problem("ble", "esp32c3", &["esp_idf_bt_enabled".to_owned()]).unwrap();
+        assert!(problem.contains("only implements the IP transport"), "{}", problem);
+    }
+
+    #[test]
+    fn cfgs_are_sorted_and_deterministic() {
+        let cfgs = ["esp_idf_lwip_ipv6", "esp_idf_config_idf_target=\"esp32c3\"", "esp_idf_bt_enabled", "esp_idf_lwip_ipv6"];
+        let sorted = sorted_cfgs(cfgs.map(str::to_owned));
+
+        assert_eq!(sorted, ["esp_idf_bt_enabled", "esp_idf_config_idf_target=\"esp32c3\"", "esp_idf_lwip_ipv6"]);
+        assert_eq!(sorted_cfgs(cfgs.into_iter().rev().map(str::to_owned)), sorted);
+    }
+
+    #[test]
+    fn idf_versions_are_read_from_the_header() {
+        let header = "#pragma once\n\
+                      /** Major version number (X.x.x) */\n\
+                      #define ESP_IDF_VERSION_MAJOR   4\n\
+                      #define ESP_IDF_VERSION_MINOR   4\n\
+                      #define ESP_IDF_VERSION_PATCH   1\n\
+                      #define ESP_IDF_VERSION_VAL(major, minor, patch) ((major << 16) | (minor << 8) | (patch))\n";
+
+        assert_eq!(parse_idf_version_header(header), Ok((4, 4, 1)));
+        assert_eq!(
+            parse_idf_version_header("#define ESP_IDF_VERSION_MAJOR 4\n"),
+            Err("ESP_IDF_VERSION_MINOR not found".to_owned())
+        );
+    }
+
+    #[test]
+    fn idf_versions_are_exposed_as_cfgs() {
+        assert_eq!(
+            idf_version_cfgs((4, 4, 1)),
+            [
+                "esp_idf_version_major=\"4\"",
+                "esp_idf_version_minor=\"4\"",
+                "esp_idf_version_patch=\"1\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn long_double_functions_are_only_excluded_without_a_sysroot() {
+        let toolchain_args = ["--sysroot=/opt/xtensa-esp32-elf".to_owned()];
+        assert!(blocklisted_functions(Some(&toolchain_args)).is_empty());
+        assert!(blocklisted_functions(Some(&[])).is_empty());
+
+        assert_eq!(blocklisted_functions(None), LONG_DOUBLE_FUNCTIONS);
+        assert!(LONG_DOUBLE_FUNCTIONS.contains(&"strtold"));
+        assert!(LONG_DOUBLE_FUNCTIONS.contains(&"_strtold_r"));
+    }
+
+    fn inputs(dir: &Path) -> ProjectInputs {
+        fs::write(dir.join("sdkconfig.defaults"), "CONFIG_LOG_DEFAULT_LEVEL=3\n").unwrap();
+
+        ProjectInputs {
+            profile: "release".to_owned(),
+            resolution: ResolvedPlatform {
+                board: "esp32-c3-devkitm-1".to_owned(),
+                mcu: "esp32c3".to_owned(),
+                platform: "espressif32".to_owned(),
+                frameworks: vec!["espidf".to_owned()],
+                target: "riscv32imc-esp-espidf".to_owned(),
+            },
+            platform_packages: vec![("framework-espidf".to_owned(), "3.40401.0".to_owned())],
+            options: vec![("lib_deps".to_owned(), "esp-homekit-sdk#platformio".to_owned())],
+            files: files(dir, &["sdkconfig.defaults"]),
+        }
+    }
+
+    #[test]
+    fn project_inputs_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs = inputs(dir.path());
+
+        let serialized = toml::to_string(&inputs).unwrap();
+        assert_eq!(toml::from_str::<ProjectInputs>(&serialized).unwrap(), inputs);
+        assert!(format!("{:?}", inputs).contains("esp32c3"));
+    }
+
+    #[test]
+    fn dry_runs_report_the_inputs_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs = inputs(dir.path());
+        let env = [("MCU", Some("esp32c3".to_owned())), ("ESP_IDF_SDKCONFIG", None)];
+
+        let report = inputs.report(&env, Path::new("/out/esp-homekit-sdk"));
+        let position = |line: &str| report.find(line).unwrap_or_else(|| panic!("No '{}' in {}", line, report));
+
+        assert!(position("`release` profile") < position("  MCU=esp32c3\n"));
+        assert!(position("  ESP_IDF_SDKCONFIG=<unset>\n") < position("  mcu: esp32c3\n"));
+        assert!(position("  framework-espidf = 3.40401.0\n") < position("  lib_deps = esp-homekit-sdk#platformio\n"));
+        assert!(position("-> sdkconfig.defaults\n") < position("Project generated at '/out/esp-homekit-sdk'"));
+    }
+
+    #[test]
+    fn cache_keys_cover_the_file_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs = inputs(dir.path());
+        let key = inputs.cache_key().unwrap();
+
+        assert_eq!(inputs.cache_key(), Ok(key.clone()));
+
+        fs::write(dir.path().join("sdkconfig.defaults"), "CONFIG_LOG_DEFAULT_LEVEL=1\n").unwrap();
+        assert_ne!(inputs.cache_key(), Ok(key));
+
+        fs::remove_file(dir.path().join("sdkconfig.defaults")).unwrap();
+        assert!(inputs.cache_key().is_err());
+    }
+
+    fn strings(strings: &[&str]) -> Vec<String> {
+        strings.iter().map(|string| string.to_string()).collect()
+    }
+
+    #[test]
+    fn the_homekit_libraries_are_in_the_link_config() {
+        let link_config = LinkConfig::new(
+            &strings(&["-lesp_hap_core", "-lesp_hap_apple_profiles", "-lesp_hap_platform", "/sdk/libhkdf-sha.a"]),
+            &strings(&["-L/project/.pio/build/release/esp_hap_core"]),
+            &strings(&["-Wl,--gc-sections", "-nostdlib"]),
+        );
+
+        for lib in ["esp_hap_core", "esp_hap_apple_profiles", "esp_hap_platform", "/sdk/libhkdf-sha.a"] {
+            assert!(link_config.libraries.iter().any(|l| l == lib), "{:?}", link_config);
+        }
+        assert_eq!(link_config.search_paths, ["/project/.pio/build/release/esp_hap_core"]);
+        assert_eq!(
+            link_config.metadata(),
+            [
+                ("LINK_LIBS", "esp_hap_core;esp_hap_apple_profiles;esp_hap_platform;/sdk/libhkdf-sha.a".to_owned()),
+                ("LINK_SEARCH_PATHS", "/project/.pio/build/release/esp_hap_core".to_owned()),
+                ("LINK_FLAGS", "-Wl,--gc-sections;-nostdlib".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn bindings_module_names_are_identifiers() {
+        assert_eq!(bindings_module_name(Path::new("include/my-Sensors.h")), Ok("my_sensors".to_owned()));
+        assert_eq!(bindings_module_name(Path::new("2nd.h")), Ok("_2nd".to_owned()));
+        assert!(bindings_module_name(Path::new("")).is_err());
+    }
+
+    #[test]
+    fn two_headers_give_two_submodules() {
+        let modules = bindings_modules([PathBuf::from("/ws/sensors.h"), PathBuf::from("/ws/my-lights.h")]).unwrap();
+        assert_eq!(
+            modules,
+            [
+                ("sensors".to_owned(), PathBuf::from("/ws/sensors.h")),
+                ("my_lights".to_owned(), PathBuf::from("/ws/my-lights.h")),
+            ]
+        );
+
+        let source = extra_bindings_source(&modules, Path::new("/out"));
+        assert_eq!(source.matches("pub mod ").count(), 2);
+        assert!(source.contains("pub mod sensors {\n    use crate::c_types;\n\n    include!(\"/out/bindings_sensors.rs\");\n}\n"), "{}", source);
+        assert!(source.contains("pub mod my_lights {"), "{}", source);
+        assert!(source.contains("include!(\"/out/bindings_my_lights.rs\")"), "{}", source);
+    }
+
+    #[test]
+    fn modules_need_a_header_of_their_own() {
+        let error = bindings_modules([PathBuf::from("a/sensors.h"), PathBuf::from("b/sensors.h")]).unwrap_err();
+        assert!(error.contains("`sensors`"), "{}", error);
+
+        assert!(bindings_modules([PathBuf::from("raw.h")]).is_err());
+        assert_eq!(extra_bindings_source(&[], Path::new("/out")), "");
+    }
+
+    #[test]
+    fn numeric_kconfig_values_are_exposed_as_envs() {
+        let envs = kconfig_envs([("CONFIG_IDF_TARGET", "esp32c3"), ("CONFIG_HAP_HTTP_STACK_SIZE", "12288")]);
+
+        assert_eq!(
+            envs,
+            [
+                ("ESP_IDF_CONFIG_HAP_HTTP_STACK_SIZE".to_owned(), "12288".to_owned()),
+                ("ESP_IDF_CONFIG_IDF_TARGET".to_owned(), "esp32c3".to_owned()),
+            ]
+        );
+    }
+}
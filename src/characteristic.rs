@@ -0,0 +1,1444 @@
+use std::ffi::{CStr, CString};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use core::{ptr, slice};
+use log::*;
+
+use crate::context::HapContext;
+use crate::error::{self, HapError, HapStatus};
+use crate::event::HapEvent;
+use crate::service::HapService;
+use crate::*;
+
+/// A characteristic value in its Rust representation.
+///
+/// With the `serde` feature it is (de)serialized as `{"type": ..., "value": ...}`, with
+/// the variant name in snake case as type, e.g. `{"type": "uint8", "value": 1}`. Strings
+/// must be valid UTF-8, `data` and `tlv8` values are base64 strings.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value", rename_all = "snake_case"))]
+pub enum CharValue {
+    Bool(bool),
+    #[cfg_attr(feature = "serde", serde(rename = "uint8"))]
+    UInt8(u8),
+    #[cfg_attr(feature = "serde", serde(rename = "uint16"))]
+    UInt16(u16),
+    #[cfg_attr(feature = "serde", serde(rename = "uint32"))]
+    UInt32(u32),
+    #[cfg_attr(feature = "serde", serde(rename = "uint64"))]
+    UInt64(u64),
+    Int(i32),
+    Float(f32),
+    String(#[cfg_attr(feature = "serde", serde(with = "serde_utf8"))] CString),
+    Data(#[cfg_attr(feature = "serde", serde(with = "serde_base64"))] Vec<u8>),
+    Tlv8(#[cfg_attr(feature = "serde", serde(with = "serde_base64"))] Vec<u8>),
+}
+
+#[cfg(feature = "serde")]
+mod serde_utf8 {
+    use std::ffi::CString;
+
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &CString, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(value.to_str().map_err(serde::ser::Error::custom)?)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<CString, D::Error> {
+        CString::new(String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_base64 {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(value))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        STANDARD.decode(String::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+/// The format of a characteristic, i.e. the variant of [`CharValue`] it holds.
+///
+/// Named like the `type` of a serialized [`CharValue`] with the `serde` feature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CharFormat {
+    Bool,
+    #[cfg_attr(feature = "serde", serde(rename = "uint8"))]
+    UInt8,
+    #[cfg_attr(feature = "serde", serde(rename = "uint16"))]
+    UInt16,
+    #[cfg_attr(feature = "serde", serde(rename = "uint32"))]
+    UInt32,
+    #[cfg_attr(feature = "serde", serde(rename = "uint64"))]
+    UInt64,
+    Int,
+    Float,
+    String,
+    Data,
+    Tlv8,
+}
+
+
+impl CharValue {
+    pub fn format(&self) -> CharFormat {
+        match self {
+            CharValue::Bool(_) => CharFormat::Bool,
+            CharValue::UInt8(_) => CharFormat::UInt8,
+            CharValue::UInt16(_) => CharFormat::UInt16,
+            CharValue::UInt32(_) => CharFormat::UInt32,
+            CharValue::UInt64(_) => CharFormat::UInt64,
+            CharValue::Int(_) => CharFormat::Int,
+            CharValue::Float(_) => CharFormat::Float,
+            CharValue::String(_) => CharFormat::String,
+            CharValue::Data(_) => CharFormat::Data,
+            CharValue::Tlv8(_) => CharFormat::Tlv8,
+        }
+    }
+
+    /// Decode a `hap_val_t` holding a value of the given format.
+    ///
+    /// # Safety
+    ///
+    /// `raw` must actually hold a value of `format`, with valid string and buffer pointers.
+    pub unsafe fn from_raw(format: CharFormat, raw: &hap_val_t) -> CharValue {
+        let bytes = |buf: *const u8, len: u32| {
+            if buf.is_null() {
+                Vec::new()
+            } else {
+                slice::from_raw_parts(buf, len as usize).to_vec()
+            }
+        };
+
+        match format {
+            CharFormat::Bool => CharValue::Bool(raw.b),
+            CharFormat::UInt8 => CharValue::UInt8(raw.u as u8),
+            CharFormat::UInt16 => CharValue::UInt16(raw.u as u16),
+            CharFormat::UInt32 => CharValue::UInt32(raw.u),
+            CharFormat::UInt64 => CharValue::UInt64(raw.i64),
+            CharFormat::Int => CharValue::Int(raw.i),
+            CharFormat::Float => CharValue::Float(raw.f),
+            CharFormat::String if raw.s.is_null() => CharValue::String(CString::default()),
+            CharFormat::String => CharValue::String(CStr::from_ptr(raw.s).to_owned()),
+            CharFormat::Data => CharValue::Data(bytes(raw.d.buf, raw.d.buflen)),
+            CharFormat::Tlv8 => CharValue::Tlv8(bytes(raw.t.buf, raw.t.buflen)),
+        }
+    }
+
+    /// Run `f` with a `hap_val_t` borrowing from this value.
+    ///
+    /// Strings and buffers are only valid for the duration of `f`.
+    pub(crate) fn with_raw<R>(&self, f: impl FnOnce(&mut hap_val_t) -> R) -> R {
+        let mut data;
+        let mut raw = match self {
+            CharValue::Bool(b) => hap_val_t { b: *b },
+            CharValue::UInt8(u) => hap_val_t { u: *u as u32 },
+            CharValue::UInt16(u) => hap_val_t { u: *u as u32 },
+            CharValue::UInt32(u) => hap_val_t { u: *u },
+            CharValue::UInt64(u) => hap_val_t { i64: *u },
+            CharValue::Int(i) => hap_val_t { i: *i },
+            CharValue::Float(v) => hap_val_t { f: *v },
+            CharValue::String(s) => hap_val_t { s: s.as_ptr() as *mut i8 },
+            CharValue::Data(d) => {
+                data = d.clone();
+                hap_val_t {
+                    d: hap_data_val_t {
+                        buf: data.as_mut_ptr(),
+                        buflen: data.len() as u32,
+                    },
+                }
+            }
+            CharValue::Tlv8(t) => {
+                data = t.clone();
+                hap_val_t {
+                    t: hap_tlv8_val_t {
+                        buf: data.as_mut_ptr(),
+                        buflen: data.len() as u32,
+                    },
+                }
+            }
+        };
+
+        f(&mut raw)
+    }
+}
+
+/// `ProgrammableSwitchEvent` values of a stateless switch or doorbell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwitchEvent {
+    SinglePress = 0,
+    DoublePress = 1,
+    LongPress = 2,
+}
+
+/// The units understood by `hap_char_add_unit`, serialized in snake case with `serde`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Unit {
+    Celsius,
+    Percentage,
+    ArcDegrees,
+    Lux,
+    Seconds,
+}
+
+impl Unit {
+    fn as_raw(&self) -> &'static [u8] {
+        match self {
+            Unit::Celsius => HAP_CHAR_UNIT_CELSIUS,
+            Unit::Percentage => HAP_CHAR_UNIT_PERCENTAGE,
+            Unit::ArcDegrees => HAP_CHAR_UNIT_ARCDEGREES,
+            Unit::Lux => HAP_CHAR_UNIT_LUX,
+            Unit::Seconds => HAP_CHAR_UNIT_SECONDS,
+        }
+    }
+}
+
+/// The unit of each Apple-defined characteristic that has one.
+static DEFAULT_UNITS: &[(&[u8], Unit)] = &[
+    (HAP_CHAR_UUID_CURRENT_TEMPERATURE, Unit::Celsius),
+    (HAP_CHAR_UUID_TARGET_TEMPERATURE, Unit::Celsius),
+    (HAP_CHAR_UUID_COOLING_THRESHOLD_TEMPERATURE, Unit::Celsius),
+    (HAP_CHAR_UUID_HEATING_THRESHOLD_TEMPERATURE, Unit::Celsius),
+    (HAP_CHAR_UUID_BRIGHTNESS, Unit::Percentage),
+    (HAP_CHAR_UUID_SATURATION, Unit::Percentage),
+    (HAP_CHAR_UUID_CURRENT_POSITION, Unit::Percentage),
+    (HAP_CHAR_UUID_TARGET_POSITION, Unit::Percentage),
+    (HAP_CHAR_UUID_CURRENT_RELATIVE_HUMIDITY, Unit::Percentage),
+    (HAP_CHAR_UUID_TARGET_RELATIVE_HUMIDITY, Unit::Percentage),
+    (HAP_CHAR_UUID_RELATIVE_HUMIDITY_DEHUMIDIFIER_THRESHOLD, Unit::Percentage),
+    (HAP_CHAR_UUID_RELATIVE_HUMIDITY_HUMIDIFIER_THRESHOLD, Unit::Percentage),
+    (HAP_CHAR_UUID_ROTATION_SPEED, Unit::Percentage),
+    (HAP_CHAR_UUID_BATTERY_LEVEL, Unit::Percentage),
+    (HAP_CHAR_UUID_WATER_LEVEL, Unit::Percentage),
+    (HAP_CHAR_UUID_VOLUME, Unit::Percentage),
+    (HAP_CHAR_UUID_HUE, Unit::ArcDegrees),
+    (HAP_CHAR_UUID_CURRENT_HORIZONTAL_TILT_ANGLE, Unit::ArcDegrees),
+    (HAP_CHAR_UUID_TARGET_HORIZONTAL_TILT_ANGLE, Unit::ArcDegrees),
+    (HAP_CHAR_UUID_CURRENT_VERTICAL_TILT_ANGLE, Unit::ArcDegrees),
+    (HAP_CHAR_UUID_TARGET_VERTICAL_TILT_ANGLE, Unit::ArcDegrees),
+    (HAP_CHAR_UUID_CURRENT_TILT_ANGLE, Unit::ArcDegrees),
+    (HAP_CHAR_UUID_TARGET_TILT_ANGLE, Unit::ArcDegrees),
+    (HAP_CHAR_UUID_CURRENT_AMBIENT_LIGHT_LEVEL, Unit::Lux),
+    (HAP_CHAR_UUID_SET_DURATION, Unit::Seconds),
+    (HAP_CHAR_UUID_REMAINING_DURATION, Unit::Seconds),
+    (HAP_CHAR_UUID_LOCK_MANAGEMENT_AUTO_SECURITY_TIMEOUT, Unit::Seconds),
+];
+
+/// The unit of an Apple-defined characteristic, by type UUID, e.g. `HAP_CHAR_UUID_HUE`.
+pub fn default_unit(uuid: &[u8]) -> Option<Unit> {
+    DEFAULT_UNITS.iter().find(|(u, _)| *u == uuid).map(|(_, unit)| *unit)
+}
+
+/// The valid range and step of a characteristic, see [`HapCharacteristic::min_value`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Constraints {
+    Int(i32, i32, i32),
+    Float(f32, f32, f32),
+}
+
+/// The constraints the HAP framework's constructors set on Apple-defined characteristics.
+static DEFAULT_CONSTRAINTS: &[(&[u8], Constraints)] = &[
+    (HAP_CHAR_UUID_BRIGHTNESS, Constraints::Int(0, 100, 1)),
+    (HAP_CHAR_UUID_HUE, Constraints::Float(0.0, 360.0, 1.0)),
+    (HAP_CHAR_UUID_SATURATION, Constraints::Float(0.0, 100.0, 1.0)),
+    (HAP_CHAR_UUID_COLOR_TEMPERATURE, Constraints::Int(140, 500, 1)),
+    (HAP_CHAR_UUID_CURRENT_TEMPERATURE, Constraints::Float(0.0, 100.0, 0.1)),
+    (HAP_CHAR_UUID_TARGET_TEMPERATURE, Constraints::Float(10.0, 38.0, 0.1)),
+    (HAP_CHAR_UUID_COOLING_THRESHOLD_TEMPERATURE, Constraints::Float(10.0, 35.0, 0.1)),
+    (HAP_CHAR_UUID_HEATING_THRESHOLD_TEMPERATURE, Constraints::Float(0.0, 25.0, 0.1)),
+    (HAP_CHAR_UUID_CURRENT_RELATIVE_HUMIDITY, Constraints::Float(0.0, 100.0, 1.0)),
+    (HAP_CHAR_UUID_TARGET_RELATIVE_HUMIDITY, Constraints::Float(0.0, 100.0, 1.0)),
+    (HAP_CHAR_UUID_ROTATION_SPEED, Constraints::Float(0.0, 100.0, 1.0)),
+    (HAP_CHAR_UUID_CURRENT_POSITION, Constraints::Int(0, 100, 1)),
+    (HAP_CHAR_UUID_TARGET_POSITION, Constraints::Int(0, 100, 1)),
+    (HAP_CHAR_UUID_VOLUME, Constraints::Int(0, 100, 1)),
+    (HAP_CHAR_UUID_CURRENT_TILT_ANGLE, Constraints::Int(-90, 90, 1)),
+    (HAP_CHAR_UUID_TARGET_TILT_ANGLE, Constraints::Int(-90, 90, 1)),
+    (HAP_CHAR_UUID_SET_DURATION, Constraints::Int(0, 3600, 1)),
+    (HAP_CHAR_UUID_REMAINING_DURATION, Constraints::Int(0, 3600, 1)),
+];
+
+/// The permissions of a characteristic, combined with `|`,
+/// e.g. `CharPermissions::READ | CharPermissions::NOTIFY`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CharPermissions(u16);
+
+impl CharPermissions {
+    pub const READ: Self = CharPermissions(HAP_CHAR_PERM_PR as u16);
+    pub const WRITE: Self = CharPermissions(HAP_CHAR_PERM_PW as u16);
+    pub const NOTIFY: Self = CharPermissions(HAP_CHAR_PERM_EV as u16);
+    pub const ADDITIONAL_AUTHORIZATION: Self = CharPermissions(HAP_CHAR_PERM_AA as u16);
+    pub const TIMED_WRITE: Self = CharPermissions(HAP_CHAR_PERM_TW as u16);
+    pub const HIDDEN: Self = CharPermissions(HAP_CHAR_PERM_HD as u16);
+    pub const WRITE_RESPONSE: Self = CharPermissions(HAP_CHAR_PERM_WR as u16);
+
+    /// The `HAP_CHAR_PERM_*` bits.
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn contains(&self, other: CharPermissions) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl core::ops::BitOr for CharPermissions {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        CharPermissions(self.0 | other.0)
+    }
+}
+
+/// The suffix of UUIDs in Apple's base range, e.g. `00000025-0000-1000-8000-0026BB765291`.
+const APPLE_UUID_SUFFIX: &str = "-0000-1000-8000-0026BB765291";
+
+/// Whether `uuid` is a full 128-bit UUID, e.g. `E863F10A-079E-48FF-8F27-9C2605A29F52`.
+fn is_valid_uuid(uuid: &str) -> bool {
+    let groups = uuid.split('-').map(str::len).collect::<Vec<_>>();
+
+    groups == [8, 4, 4, 4, 12] && uuid.bytes().all(|b| b == b'-' || b.is_ascii_hexdigit())
+}
+
+/// `uuid` in upper case as HAP expects it, [`HapError::InvalidArg`] unless it is a full
+/// 128-bit UUID outside of Apple's base range.
+fn custom_uuid(uuid: &str) -> Result<String, HapError> {
+    let uuid = uuid.to_ascii_uppercase();
+
+    if !is_valid_uuid(&uuid) || uuid.ends_with(APPLE_UUID_SUFFIX) {
+        warn!("Invalid custom characteristic UUID '{}'", uuid);
+        return Err(HapError::InvalidArg);
+    }
+
+    Ok(uuid)
+}
+
+/// Whether `step` is positive and evenly divides `max - min`.
+fn int_constraints_valid(min: i32, max: i32, step: i32) -> bool {
+    min <= max && step > 0 && (max as i64 - min as i64) % step as i64 == 0
+}
+
+/// Whether `step` is positive and evenly divides `max - min`, within float tolerance.
+fn float_constraints_valid(min: f32, max: f32, step: f32) -> bool {
+    let steps = (max as f64 - min as f64) / step as f64;
+
+    // Written so that NaN fails every comparison and is rejected.
+    min <= max && step > 0.0 && (steps - steps.round()).abs() <= 1e-3
+}
+
+/// Create a vendor-specific characteristic, e.g. one read by a companion app.
+///
+/// `uuid` must be a full 128-bit UUID outside of Apple's base range, and `initial`
+/// must be of `format`; both fail with [`HapError::InvalidArg`] otherwise. The UUID
+/// is normalized to upper case, as HAP expects it.
+pub fn custom(
+    uuid: &str,
+    format: CharFormat,
+    perms: CharPermissions,
+    initial: CharValue,
+) -> Result<HapCharacteristic, HapError> {
+    let uuid = custom_uuid(uuid)?;
+    if initial.format() != format {
+        return Err(HapError::InvalidArg);
+    }
+
+    // Leaked, the HAP framework keeps the pointers for the lifetime of the characteristic.
+    let uuid = Box::leak(CString::new(uuid).map_err(|_| HapError::InvalidArg)?.into_boxed_c_str());
+    let type_uuid = uuid.as_ptr() as *mut i8;
+    let perms = perms.bits();
+    let leak = |data: &[u8]| Box::leak(data.to_vec().into_boxed_slice());
+
+    let ptr = unsafe {
+        match &initial {
+            CharValue::Bool(b) => hap_char_bool_create(type_uuid, perms, *b),
+            CharValue::UInt8(u) => hap_char_uint8_create(type_uuid, perms, *u),
+            CharValue::UInt16(u) => hap_char_uint16_create(type_uuid, perms, *u),
+            CharValue::UInt32(u) => hap_char_uint32_create(type_uuid, perms, *u),
+            CharValue::UInt64(u) => hap_char_uint64_create(type_uuid, perms, *u),
+            CharValue::Int(i) => hap_char_int_create(type_uuid, perms, *i),
+            CharValue::Float(f) => hap_char_float_create(type_uuid, perms, *f),
+            CharValue::String(s) => hap_char_string_create(type_uuid, perms, s.as_ptr() as *mut i8),
+            CharValue::Data(d) => {
+                let buf = leak(d);
+                let mut val = hap_data_val_t {
+                    buf: buf.as_mut_ptr(),
+                    buflen: buf.len() as u32,
+                };
+                hap_char_data_create(type_uuid, perms, &mut val)
+            }
+            CharValue::Tlv8(t) => {
+                let buf = leak(t);
+                let mut val = hap_tlv8_val_t {
+                    buf: buf.as_mut_ptr(),
+                    buflen: buf.len() as u32,
+                };
+                hap_char_tlv8_create(type_uuid, perms, &mut val)
+            }
+        }
+    };
+
+    if ptr.is_null() {
+        return Err(HapError::Fail(hap::HAP_FAIL_));
+    }
+
+    let characteristic = HapCharacteristic::from_raw(ptr);
+    characteristic.set_format(format);
+
+    Ok(characteristic)
+}
+
+/// The longest name or description HomeKit accepts, in bytes.
+pub const MAX_STRING_LEN: usize = 64;
+
+/// Rust-side state attached to a characteristic through `hap_char_set_priv`.
+///
+/// Characteristics live for the lifetime of the accessory database, so the
+/// state is leaked on creation and never freed.
+#[derive(Default)]
+struct CharState {
+    debounce: Mutex<Debounce>,
+    notify: Mutex<Notify>,
+    valid_values: Mutex<Option<ValidValues>>,
+    constraints: Mutex<Option<Constraints>>,
+    gpio: Mutex<Option<GpioBinding>>,
+    /// The string the HAP framework points to, see [`HapCharacteristic::set_description`].
+    description: Mutex<Option<CString>>,
+}
+
+/// A GPIO following the value of a `Bool` characteristic, see
+/// [`HapCharacteristic::bind_output_gpio`].
+#[derive(Clone, Copy)]
+struct GpioBinding {
+    gpio: gpio_num_t,
+    active_high: bool,
+}
+
+impl GpioBinding {
+    fn set(&self, on: bool) -> Result<(), HapError> {
+        HapError::check(unsafe { gpio_set_level(self.gpio, gpio_level(on, self.active_high)) })
+    }
+}
+
+/// The level driving a GPIO to `on`, inverted for active low outputs.
+fn gpio_level(on: bool, active_high: bool) -> u32 {
+    (on == active_high) as u32
+}
+
+/// Call `update` up to `attempts` times in total with `backoff` between attempts while
+/// it fails with `ESP_ERR_NO_MEM`, see [`HapCharacteristic::update_with_retry`].
+fn retry_on_no_mem(
+    attempts: u32,
+    backoff: Duration,
+    mut update: impl FnMut() -> Result<(), HapError>,
+) -> Result<(), HapError> {
+    let mut attempt = 1;
+
+    loop {
+        match update() {
+            Err(HapError::Fail(code)) if attempt < attempts && code == ESP_ERR_NO_MEM as i32 => {
+                warn!("Updating the characteristic failed with {}, retrying in {:?}", code, backoff);
+                thread::sleep(backoff);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum ValidValues {
+    // Leaked, the HAP framework keeps the pointer instead of copying the values.
+    List(&'static [u8]),
+    Range(u8, u8),
+}
+
+type NotifyPredicate = Box<dyn Fn(&CharValue, &CharValue) -> bool + Send>;
+
+#[derive(Default)]
+struct Notify {
+    event_only: bool,
+    format: Option<CharFormat>,
+    predicate: Option<NotifyPredicate>,
+    last_notified: Option<CharValue>,
+    latest: Option<CharValue>,
+}
+
+#[derive(Default)]
+struct Debounce {
+    last_sent: Option<Instant>,
+    pending: Option<CharValue>,
+    timer: Option<esp_timer_handle_t>,
+    armed: bool,
+}
+
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// The part of a [`HapContext`] kept by this module.
+#[derive(Default)]
+pub(crate) struct State {
+    /// The characteristics passed to [`HapCharacteristic::watch_subscriptions`], with
+    /// their last subscriber count.
+    watched: Mutex<Vec<(HapCharacteristic, usize)>>,
+}
+
+impl HapContext {
+    /// Emit a [`HapEvent::SubscriptionChanged`](crate::event::HapEvent::SubscriptionChanged)
+    /// for every watched characteristic whose subscriber count changed.
+    pub(crate) fn check_subscriptions(&self) {
+        let changed = self
+            .state()
+            .characteristic
+            .watched
+            .lock()
+            .unwrap()
+            .iter_mut()
+            .filter_map(|(characteristic, subscribers)| {
+                let count = characteristic.subscriber_count();
+                (count != *subscribers).then(|| {
+                    *subscribers = count;
+                    (*characteristic, count)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Emit outside of the lock so handlers may watch further characteristics.
+        for (characteristic, subscribed_count) in changed {
+            let aid =
+                unsafe { hap_acc_get_aid(hap_serv_get_parent(hap_char_get_parent(characteristic.ptr))) as u32 };
+
+            self.emit(HapEvent::SubscriptionChanged {
+                aid,
+                iid: characteristic.iid(),
+                subscribed_count,
+            });
+        }
+    }
+}
+
+/// A handle to a `hap_char_t` owned by the HAP framework.
+#[derive(Clone, Copy)]
+pub struct HapCharacteristic {
+    ptr: *mut hap_char_t,
+}
+
+// The HAP framework serializes access to the characteristic internally and
+// the Rust-side state is guarded by mutexes.
+unsafe impl Send for HapCharacteristic {}
+unsafe impl Sync for HapCharacteristic {}
+
+impl HapCharacteristic {
+    pub fn from_raw(ptr: *mut hap_char_t) -> Self {
+        HapCharacteristic { ptr }
+    }
+
+    pub fn as_raw(&self) -> *mut hap_char_t {
+        self.ptr
+    }
+
+    /// The type UUID, e.g. `HAP_CHAR_UUID_ON`.
+    pub fn uuid(&self) -> &CStr {
+        unsafe { CStr::from_ptr(hap_char_get_type_uuid(self.ptr)) }
+    }
+
+    /// The instance id, assigned once the accessory is added to the database.
+    pub fn iid(&self) -> u32 {
+        unsafe { hap_char_get_iid(self.ptr) }
+    }
+
+    /// The format as known to the HAP framework, `None` for unknown formats.
+    pub fn format(&self) -> Option<CharFormat> {
+        let format = match unsafe { hap_char_get_format(self.ptr) } {
+            hap_char_format_t_HAP_CHAR_FORMAT_BOOL => CharFormat::Bool,
+            hap_char_format_t_HAP_CHAR_FORMAT_UINT8 => CharFormat::UInt8,
+            hap_char_format_t_HAP_CHAR_FORMAT_UINT16 => CharFormat::UInt16,
+            hap_char_format_t_HAP_CHAR_FORMAT_UINT32 => CharFormat::UInt32,
+            hap_char_format_t_HAP_CHAR_FORMAT_UINT64 => CharFormat::UInt64,
+            hap_char_format_t_HAP_CHAR_FORMAT_INT => CharFormat::Int,
+            hap_char_format_t_HAP_CHAR_FORMAT_FLOAT => CharFormat::Float,
+            hap_char_format_t_HAP_CHAR_FORMAT_STRING => CharFormat::String,
+            hap_char_format_t_HAP_CHAR_FORMAT_DATA => CharFormat::Data,
+            hap_char_format_t_HAP_CHAR_FORMAT_TLV8 => CharFormat::Tlv8,
+            _ => return None,
+        };
+
+        Some(format)
+    }
+
+    /// The `HAP_CHAR_PERM_*` permission bits.
+    pub fn perms(&self) -> u32 {
+        unsafe { hap_char_get_perm(self.ptr) as u32 }
+    }
+
+    pub fn set_unit(&self, unit: Unit) -> Result<(), HapError> {
+        HapError::check(unsafe { hap_char_add_unit(self.ptr, unit.as_raw().as_ptr() as *const i8) })
+    }
+
+    /// Add a description, shown by third-party HomeKit apps.
+    ///
+    /// Fails with [`HapError::InvalidArg`] beyond [`MAX_STRING_LEN`] bytes.
+    pub fn set_description(&self, description: &str) -> Result<(), HapError> {
+        if description.len() > MAX_STRING_LEN {
+            return Err(HapError::InvalidArg);
+        }
+
+        let description = CString::new(description).map_err(|_| HapError::InvalidArg)?;
+
+        // The HAP framework keeps the pointer, so the string lives in the state of the
+        // characteristic, and the previous one is dropped once the framework has let go.
+        let mut current = self.state().description.lock().unwrap();
+        HapError::check(unsafe { hap_char_add_description(self.ptr, description.as_ptr()) })?;
+        *current = Some(description);
+
+        Ok(())
+    }
+
+    /// Set the valid range and step of an integer characteristic.
+    ///
+    /// Fails with [`HapError::InvalidArg`] unless `step` is positive and evenly
+    /// divides `max - min`, which the Home app would render incorrectly.
+    pub fn set_int_constraints(&self, min: i32, max: i32, step: i32) -> Result<(), HapError> {
+        if !int_constraints_valid(min, max, step) {
+            warn!("Invalid constraints {}..={} step {}", min, max, step);
+            return Err(HapError::InvalidArg);
+        }
+
+        HapError::check(unsafe { hap_char_int_set_constraints(self.ptr, min, max, step) })?;
+        *self.state().constraints.lock().unwrap() = Some(Constraints::Int(min, max, step));
+
+        Ok(())
+    }
+
+    /// Set the valid range and step of a float characteristic, e.g. 10-38 °C in 0.5 steps.
+    ///
+    /// Fails with [`HapError::InvalidArg`] unless `step` is positive and evenly
+    /// divides `max - min`, within float tolerance.
+    pub fn set_float_constraints(&self, min: f32, max: f32, step: f32) -> Result<(), HapError> {
+        if !float_constraints_valid(min, max, step) {
+            warn!("Invalid constraints {}..={} step {}", min, max, step);
+            return Err(HapError::InvalidArg);
+        }
+
+        HapError::check(unsafe { hap_char_float_set_constraints(self.ptr, min, max, step) })?;
+        *self.state().constraints.lock().unwrap() = Some(Constraints::Float(min, max, step));
+
+        Ok(())
+    }
+
+    /// The minimum value, e.g. `CharValue::Int(0)` for `Brightness`.
+    ///
+    /// Integer constraints are returned as [`CharValue::Int`], float constraints as
+    /// [`CharValue::Float`], whatever the format of the characteristic. `None` if it
+    /// has no constraints.
+    ///
+    /// The HAP framework has no getters for constraints, so these are the ones set
+    /// through [`set_int_constraints`](Self::set_int_constraints) and
+    /// [`set_float_constraints`](Self::set_float_constraints), or else the defaults
+    /// of the Apple-defined characteristic. Constraints set by calling the SDK
+    /// directly are not seen.
+    pub fn min_value(&self) -> Option<CharValue> {
+        self.constraints().map(|constraints| match constraints {
+            Constraints::Int(min, _, _) => CharValue::Int(min),
+            Constraints::Float(min, _, _) => CharValue::Float(min),
+        })
+    }
+
+    /// The maximum value, see [`min_value`](Self::min_value).
+    pub fn max_value(&self) -> Option<CharValue> {
+        self.constraints().map(|constraints| match constraints {
+            Constraints::Int(_, max, _) => CharValue::Int(max),
+            Constraints::Float(_, max, _) => CharValue::Float(max),
+        })
+    }
+
+    /// The step between valid values, see [`min_value`](Self::min_value).
+    pub fn step_value(&self) -> Option<CharValue> {
+        self.constraints().map(|constraints| match constraints {
+            Constraints::Int(_, _, step) => CharValue::Int(step),
+            Constraints::Float(_, _, step) => CharValue::Float(step),
+        })
+    }
+
+    fn constraints(&self) -> Option<Constraints> {
+        let constraints = *self.state().constraints.lock().unwrap();
+
+        constraints.or_else(|| {
+            let uuid = self.uuid().to_bytes_with_nul();
+            DEFAULT_CONSTRAINTS
+                .iter()
+                .find(|(u, _)| *u == uuid)
+                .map(|(_, constraints)| *constraints)
+        })
+    }
+
+    /// Restrict an enum-like `uint8` characteristic to `values`, e.g. the modes a
+    /// heat-only thermostat supports.
+    ///
+    /// Must be set before `hap::start`, the Home app only reads it while pairing.
+    /// Controller writes of other values are rejected with [`HapStatus::InvalidValue`]
+    /// before reaching the write handler.
+    ///
+    /// [`HapStatus::InvalidValue`]: crate::error::HapStatus::InvalidValue
+    pub fn set_valid_values(&self, values: &[u8]) -> Result<(), HapError> {
+        if values.is_empty() {
+            return Err(HapError::InvalidArg);
+        }
+
+        let values: &'static [u8] = Box::leak(values.to_vec().into_boxed_slice());
+
+        HapError::check(unsafe { hap_char_add_valid_vals(self.ptr, values.as_ptr(), values.len()) })?;
+        *self.state().valid_values.lock().unwrap() = Some(ValidValues::List(values));
+
+        Ok(())
+    }
+
+    /// Restrict an enum-like `uint8` characteristic to `min..=max`, see
+    /// [`set_valid_values`](Self::set_valid_values).
+    pub fn set_valid_values_range(&self, min: u8, max: u8) -> Result<(), HapError> {
+        if min > max {
+            return Err(HapError::InvalidArg);
+        }
+
+        HapError::check(unsafe { hap_char_add_valid_vals_range(self.ptr, min, max) })?;
+        *self.state().valid_values.lock().unwrap() = Some(ValidValues::Range(min, max));
+
+        Ok(())
+    }
+
+    /// Whether a controller may write `raw`, as restricted by the valid values.
+    pub(crate) fn accepts(&self, raw: &hap_val_t) -> bool {
+        let value = unsafe { raw.u };
+
+        match *self.state().valid_values.lock().unwrap() {
+            Some(ValidValues::List(values)) => values.iter().any(|&v| v as u32 == value),
+            Some(ValidValues::Range(min, max)) => (min as u32..=max as u32).contains(&value),
+            None => true,
+        }
+    }
+
+    /// The current value as stored by the HAP framework.
+    pub fn raw_value(&self) -> hap_val_t {
+        unsafe { *hap_char_get_val(self.ptr) }
+    }
+
+    /// Update the value and notify subscribed controllers.
+    ///
+    /// If a notify predicate is installed and rejects the change while controllers are
+    /// subscribed, the update is declined and the value stays unchanged, see
+    /// [`set_notify_predicate`](Self::set_notify_predicate).
+    pub fn update(&self, value: &CharValue) -> Result<(), HapError> {
+        let state = self.state();
+        let mut notify = state.notify.lock().unwrap();
+
+        if notify.event_only {
+            drop(notify);
+
+            let code = value.with_raw(|raw| unsafe { hap_char_update_val(self.ptr, raw) });
+            return HapError::check(code);
+        }
+
+        notify.format = Some(value.format());
+
+        // The framework sends every stored value to the subscribed controllers, so a
+        // rejected value can only be stored while there are none.
+        if let (Some(predicate), Some(last)) = (&notify.predicate, &notify.last_notified) {
+            if !predicate(last, value) && self.has_subscribers() {
+                return Ok(());
+            }
+        }
+
+        notify.latest = Some(value.clone());
+
+        let code = value.with_raw(|raw| unsafe { hap_char_update_val(self.ptr, raw) });
+        HapError::check(code)?;
+
+        notify.last_notified = Some(value.clone());
+        drop(notify);
+
+        self.follow_gpio(value)
+    }
+
+    /// [`update`](Self::update), retried up to `attempts` times in total with `backoff`
+    /// between attempts while the HAP framework is out of memory.
+    ///
+    /// Meant for memory-constrained accessories, where notification buffers are freed
+    /// again once pending responses are sent. Only [`HapError::Fail`] with
+    /// `ESP_ERR_NO_MEM` is retried. Other errors, including the generic `HAP_FAIL` of
+    /// invalid values or unknown characteristics, aren't transient and are returned at
+    /// once. Blocks the calling task while waiting, so don't use it from HAP callbacks.
+    pub fn update_with_retry(&self, value: &CharValue, attempts: u32, backoff: Duration) -> Result<(), HapError> {
+        retry_on_no_mem(attempts, backoff, || self.update(value))
+    }
+
+    /// The last value set through this wrapper or written by a controller.
+    ///
+    /// Avoids crossing the FFI boundary on hot paths, but may be stale: values
+    /// set by calling `hap_char_update_val` directly, or written to a service
+    /// without an `on_write` handler, are not seen. Use [`refresh`](Self::refresh)
+    /// when external code may have changed the value. `None` until the
+    /// format is known, see [`set_format`](Self::set_format).
+    pub fn cached_value(&self) -> Option<CharValue> {
+        self.state().notify.lock().unwrap().latest.clone()
+    }
+
+    /// Re-read the value from the HAP framework into the cache.
+    ///
+    /// Returns `None` if the format of the characteristic isn't known yet.
+    pub fn refresh(&self) -> Option<CharValue> {
+        let state = self.state();
+        let mut notify = state.notify.lock().unwrap();
+
+        if notify.event_only {
+            return None;
+        }
+
+        let value = notify
+            .format
+            .map(|format| unsafe { CharValue::from_raw(format, &*hap_char_get_val(self.ptr)) });
+        notify.latest = value.clone();
+
+        value
+    }
+
+    /// Declare the format of a characteristic not created through this wrapper,
+    /// so that controller writes and [`refresh`](Self::refresh) can be decoded.
+    pub fn set_format(&self, format: CharFormat) {
+        self.state().notify.lock().unwrap().format = Some(format);
+    }
+
+    /// Record a value written by a controller in the cache.
+    pub(crate) fn record_write(&self, raw: &hap_val_t) {
+        let state = self.state();
+        let mut notify = state.notify.lock().unwrap();
+
+        if notify.event_only {
+            return;
+        }
+
+        if let Some(format) = notify.format {
+            notify.latest = Some(unsafe { CharValue::from_raw(format, raw) });
+        }
+    }
+
+    /// Store `value` while a controller write is handled, so the framework sends it as
+    /// the write response.
+    pub(crate) fn store_write_response(&self, value: &CharValue) {
+        value.with_raw(|raw| {
+            unsafe { hap_char_update_val(self.ptr, raw) };
+            self.record_write(raw);
+        });
+    }
+
+    /// The number of controllers subscribed to events of this characteristic.
+    pub fn subscriber_count(&self) -> usize {
+        unsafe { esp_homekit_shim_char_subscribers(self.ptr) as usize }
+    }
+
+    /// Whether any controller is subscribed to events of this characteristic, e.g. to
+    /// power down a sensor nobody is listening to.
+    pub fn has_subscribers(&self) -> bool {
+        self.subscriber_count() > 0
+    }
+
+    /// Emit [`HapEvent::SubscriptionChanged`](crate::event::HapEvent::SubscriptionChanged)
+    /// whenever the subscriber count changes.
+    ///
+    /// The HAP framework has no subscription callback, so the counts of watched
+    /// characteristics are compared after every write request, which carries the
+    /// subscriptions, and whenever a controller disconnects.
+    ///
+    /// Fails with [`HapError::TooManyEventCharacteristics`] once more characteristics
+    /// would be watched than the framework queues events for, see [`hap::limits`].
+    pub fn watch_subscriptions(&self) -> Result<(), HapError> {
+        let context = HapContext::global();
+        let mut watched = context.state().characteristic.watched.lock().unwrap();
+
+        if watched.iter().any(|(characteristic, _)| characteristic.ptr == self.ptr) {
+            return Ok(());
+        }
+
+        hap::limits()?.check_events(watched.len() + 1)?;
+        watched.push((*self, self.subscriber_count()));
+
+        Ok(())
+    }
+
+    /// Drive a GPIO from this `Bool` characteristic, e.g. a relay following `On`.
+    ///
+    /// The pin is configured as an output and set from the current value. It then
+    /// follows every controller write the write handler of the service, which is kept,
+    /// accepted, and every [`update`](Self::update); `active_high = false` inverts the
+    /// level. A pin that can't be set fails the write or update. Only meant for simple
+    /// digital outputs, anything else needs its own write handler.
+    ///
+    /// Fails with [`HapError::InvalidArg`] for other formats or a characteristic not
+    /// added to a service yet, and with the ESP-IDF error for an invalid pin.
+    pub fn bind_output_gpio(&self, gpio: u8, active_high: bool) -> Result<(), HapError> {
+        if self.format() != Some(CharFormat::Bool) {
+            return Err(HapError::InvalidArg);
+        }
+
+        let service = unsafe { hap_char_get_parent(self.ptr) };
+        if service.is_null() {
+            return Err(HapError::InvalidArg);
+        }
+
+        info!("Binding characteristic {} to GPIO {}", self.iid(), gpio);
+
+        let binding = GpioBinding {
+            gpio: gpio as gpio_num_t,
+            active_high,
+        };
+
+        unsafe {
+            HapError::check(gpio_reset_pin(binding.gpio))?;
+            HapError::check(gpio_set_direction(binding.gpio, gpio_mode_t_GPIO_MODE_OUTPUT))?;
+        }
+        binding.set(unsafe { self.raw_value().b })?;
+
+        *self.state().gpio.lock().unwrap() = Some(binding);
+        HapService::from_raw(service).route_writes();
+
+        Ok(())
+    }
+
+    /// Set the GPIO bound with [`bind_output_gpio`](Self::bind_output_gpio) to a written value.
+    pub(crate) fn drive_gpio(&self, raw: &hap_val_t) -> Result<(), HapStatus> {
+        let binding = match *self.state().gpio.lock().unwrap() {
+            Some(binding) => binding,
+            None => return Ok(()),
+        };
+
+        binding.set(unsafe { raw.b }).map_err(|err| {
+            warn!("Failed to set GPIO {}: {}", binding.gpio, err);
+            HapStatus::CommunicationError
+        })
+    }
+
+    /// Set the bound GPIO, if any, to a value set through [`update`](Self::update).
+    fn follow_gpio(&self, value: &CharValue) -> Result<(), HapError> {
+        let binding = *self.state().gpio.lock().unwrap();
+
+        match (binding, value) {
+            (Some(binding), CharValue::Bool(on)) => binding.set(*on),
+            _ => Ok(()),
+        }
+    }
+
+    /// Mark this characteristic as notify-only, e.g. `ProgrammableSwitchEvent`.
+    ///
+    /// Such characteristics carry no stored value: every update is sent as an
+    /// event, even when it repeats the previous one, and no value is retained
+    /// on the Rust side.
+    pub fn set_event_only(&self) {
+        let state = self.state();
+        let mut notify = state.notify.lock().unwrap();
+
+        notify.event_only = true;
+        notify.latest = None;
+        notify.last_notified = None;
+    }
+
+    /// Send a `ProgrammableSwitchEvent` to subscribed controllers.
+    ///
+    /// The event characteristic holds no value per HAP, so there is nothing to
+    /// clear afterwards; the characteristic is marked event-only so that
+    /// repeated presses are always sent.
+    pub fn fire_event(&self, event: SwitchEvent) -> Result<(), HapError> {
+        self.set_event_only();
+        self.update(&CharValue::UInt8(event as u8))
+    }
+
+    /// Only notify controllers when `predicate(last_notified, new)` returns `true`.
+    ///
+    /// Useful for hysteresis on noisy analog inputs, e.g.
+    /// `|old, new| matches!((old, new), (CharValue::Float(a), CharValue::Float(b)) if (a - b).abs() >= 0.5)`.
+    /// The HAP framework can't store a value without sending it to the subscribed
+    /// controllers, so while any are subscribed an update rejected by the predicate is
+    /// declined: the value, as read by controllers and [`cached_value`](Self::cached_value),
+    /// stays the last notified one. Without subscribers every update is stored.
+    pub fn set_notify_predicate<F>(&self, predicate: F)
+    where
+        F: Fn(&CharValue, &CharValue) -> bool + Send + 'static,
+    {
+        self.state().notify.lock().unwrap().predicate = Some(Box::new(predicate));
+    }
+
+    /// Update the value, sending at most one notification per `min_interval`.
+    ///
+    /// Updates arriving within the interval are coalesced: only the latest one
+    /// is kept and it is sent by a timer once the interval has elapsed.
+    pub fn update_debounced(&self, value: CharValue, min_interval: Duration) -> Result<(), HapError> {
+        let state = self.state();
+        let mut debounce = state.debounce.lock().unwrap();
+        let now = Instant::now();
+
+        let elapsed = debounce
+            .last_sent
+            .map(|last| now.duration_since(last))
+            .unwrap_or(min_interval);
+
+        if elapsed >= min_interval && !debounce.armed {
+            debounce.last_sent = Some(now);
+            drop(debounce);
+
+            return self.update(&value);
+        }
+
+        debounce.pending = Some(value);
+
+        if !debounce.armed {
+            let timer = match debounce.timer {
+                Some(timer) => timer,
+                None => {
+                    let timer = self.create_flush_timer()?;
+                    debounce.timer = Some(timer);
+                    timer
+                }
+            };
+
+            let delay = min_interval.saturating_sub(elapsed);
+            let code = unsafe { esp_timer_start_once(timer, delay.as_micros() as u64) };
+            if code != ESP_OK as i32 {
+                return Err(HapError::Fail(code));
+            }
+
+            debounce.armed = true;
+        }
+
+        Ok(())
+    }
+
+    /// Immediately send a pending debounced update, if any.
+    pub fn flush(&self) -> Result<(), HapError> {
+        let state = self.state();
+        let mut debounce = state.debounce.lock().unwrap();
+
+        if let Some(timer) = debounce.timer.filter(|_| debounce.armed) {
+            unsafe {
+                esp_timer_stop(timer);
+            }
+        }
+        debounce.armed = false;
+
+        match debounce.pending.take() {
+            Some(value) => {
+                debounce.last_sent = Some(Instant::now());
+                drop(debounce);
+
+                self.update(&value)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn create_flush_timer(&self) -> Result<esp_timer_handle_t, HapError> {
+        let args = esp_timer_create_args_t {
+            callback: Some(flush_pending),
+            arg: self.ptr as *mut c_types::c_void,
+            dispatch_method: esp_timer_dispatch_t_ESP_TIMER_TASK,
+            name: b"hap_debounce\0".as_ptr() as *const i8,
+            skip_unhandled_events: true,
+        };
+        let mut timer: esp_timer_handle_t = ptr::null_mut();
+
+        let code = unsafe { esp_timer_create(&args, &mut timer) };
+        if code != ESP_OK as i32 {
+            return Err(HapError::Fail(code));
+        }
+
+        Ok(timer)
+    }
+
+    fn state(&self) -> &'static CharState {
+        let _guard = STATE_LOCK.lock().unwrap();
+
+        unsafe {
+            let state = hap_char_get_priv(self.ptr) as *const CharState;
+            if !state.is_null() {
+                return &*state;
+            }
+
+            let state: &'static CharState = Box::leak(Box::default());
+            hap_char_set_priv(self.ptr, state as *const CharState as *mut c_types::c_void);
+
+            state
+        }
+    }
+}
+
+unsafe extern "C" fn flush_pending(arg: *mut c_types::c_void) {
+    let characteristic = HapCharacteristic::from_raw(arg as *mut hap_char_t);
+
+    // Flushing runs the notify predicate, which is application code.
+    let result = error::catch_panic("debounced update", Err(HapError::Fail(hap::HAP_FAIL_)), || {
+        characteristic.flush()
+    });
+    if let Err(err) = result {
+        warn!("Failed to flush debounced update: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accessory::Accessory;
+    use crate::mock;
+
+    #[test]
+    fn thermostat_range_in_half_degrees_is_valid() {
+        assert!(float_constraints_valid(10.0, 38.0, 0.5));
+        assert!(float_constraints_valid(10.0, 38.0, 0.1));
+    }
+
+    #[test]
+    fn default_units_match_the_default_constraints() {
+        for (uuid, constraints) in DEFAULT_CONSTRAINTS {
+            let (min, max) = match *constraints {
+                Constraints::Int(min, max, _) => (min as f32, max as f32),
+                Constraints::Float(min, max, _) => (min, max),
+            };
+            let name = String::from_utf8_lossy(uuid);
+
+            match default_unit(uuid) {
+                Some(Unit::Percentage) => assert!(min >= 0.0 && max <= 100.0, "{}", name),
+                Some(Unit::ArcDegrees) => assert!(min >= -90.0 && max <= 360.0, "{}", name),
+                Some(Unit::Celsius) => assert!(min >= 0.0 && max <= 100.0, "{}", name),
+                Some(Unit::Seconds) => assert!(min >= 0.0, "{}", name),
+                Some(Unit::Lux) => assert!(min > 0.0, "{}", name),
+                // Mireds, which HAP has no unit for.
+                None => assert_eq!(*uuid, HAP_CHAR_UUID_COLOR_TEMPERATURE, "{}", name),
+            }
+        }
+    }
+
+    #[test]
+    fn steps_not_dividing_the_range_are_invalid() {
+        assert!(!float_constraints_valid(10.0, 38.0, 0.3));
+        assert!(!int_constraints_valid(0, 100, 3));
+    }
+
+    #[test]
+    fn empty_or_inverted_constraints_are_invalid() {
+        assert!(!float_constraints_valid(38.0, 10.0, 0.5));
+        assert!(!float_constraints_valid(10.0, 38.0, 0.0));
+        assert!(!float_constraints_valid(10.0, 38.0, f32::NAN));
+        assert!(!int_constraints_valid(100, 0, 1));
+        assert!(!int_constraints_valid(0, 100, 0));
+        assert!(!int_constraints_valid(0, 100, -5));
+    }
+
+    #[test]
+    fn int_constraints_spanning_the_full_range_are_valid() {
+        assert!(int_constraints_valid(0, 100, 1));
+        assert!(int_constraints_valid(i32::MIN, i32::MAX, 1));
+    }
+
+    #[test]
+    fn custom_uuids_are_normalized() {
+        assert_eq!(
+            custom_uuid("e863f10a-079e-48ff-8f27-9c2605a29f52"),
+            Ok("E863F10A-079E-48FF-8F27-9C2605A29F52".to_owned())
+        );
+    }
+
+    #[test]
+    fn malformed_custom_uuids_are_rejected() {
+        assert_eq!(custom_uuid("E863F10A"), Err(HapError::InvalidArg));
+        assert_eq!(custom_uuid("E863F10A079E48FF8F279C2605A29F52"), Err(HapError::InvalidArg));
+        assert_eq!(custom_uuid("E863F10A-079E-48FF-8F27-9C2605A29F5"), Err(HapError::InvalidArg));
+        assert_eq!(custom_uuid("G863F10A-079E-48FF-8F27-9C2605A29F52"), Err(HapError::InvalidArg));
+        assert_eq!(custom_uuid("{E863F10A-079E-48FF-8F27-9C2605A29F52}"), Err(HapError::InvalidArg));
+    }
+
+    #[test]
+    fn apple_uuids_are_not_custom() {
+        assert_eq!(custom_uuid("00000025-0000-1000-8000-0026BB765291"), Err(HapError::InvalidArg));
+        assert_eq!(custom_uuid("00000025-0000-1000-8000-0026bb765291"), Err(HapError::InvalidArg));
+    }
+
+    #[test]
+    fn custom_characteristics_read_back() {
+        HapContext::new_isolated();
+        let perms = CharPermissions::READ | CharPermissions::NOTIFY;
+        let characteristic = custom(
+            "e863f10a-079e-48ff-8f27-9c2605a29f52",
+            CharFormat::UInt16,
+            perms,
+            CharValue::UInt16(1013),
+        )
+        .unwrap();
+
+        assert_eq!(characteristic.uuid().to_str(), Ok("E863F10A-079E-48FF-8F27-9C2605A29F52"));
+        assert_eq!(characteristic.perms(), perms.bits() as u32);
+        assert_eq!(characteristic.format(), Some(CharFormat::UInt16));
+        assert_eq!(characteristic.refresh(), Some(CharValue::UInt16(1013)));
+    }
+
+    #[test]
+    fn custom_characteristics_of_another_format_are_rejected() {
+        let created = custom(
+            "E863F10A-079E-48FF-8F27-9C2605A29F52",
+            CharFormat::Float,
+            CharPermissions::READ,
+            CharValue::Int(1),
+        );
+
+        assert_eq!(created.err(), Some(HapError::InvalidArg));
+    }
+
+    #[test]
+    fn gpio_level_follows_the_value() {
+        assert_eq!(gpio_level(true, true), 1);
+        assert_eq!(gpio_level(false, true), 0);
+    }
+
+    #[test]
+    fn gpio_level_is_inverted_for_active_low() {
+        assert_eq!(gpio_level(true, false), 0);
+        assert_eq!(gpio_level(false, false), 1);
+    }
+
+    /// The `On` characteristic of a lightbulb in an accessory, bound to GPIO 4.
+    fn bound_lightbulb() -> (HapService, HapCharacteristic) {
+        let accessory = Accessory::new(&mock::accessory_config("Lamp")).unwrap();
+        let service = HapService::from_raw(unsafe { hap_serv_lightbulb_create(false) });
+        accessory.add_service(&service).unwrap();
+
+        let on = service.char_by_uuid(HAP_CHAR_UUID_ON).unwrap();
+        on.bind_output_gpio(4, true).unwrap();
+
+        (service, on)
+    }
+
+    #[test]
+    fn writes_drive_the_bound_gpio() {
+        HapContext::new_isolated();
+        let (_, on) = bound_lightbulb();
+        assert_eq!(mock::gpio_level(4), Some(0));
+
+        assert_eq!(mock::write(on, &CharValue::Bool(true)), HapStatus::Success);
+        assert_eq!(mock::gpio_level(4), Some(1));
+
+        assert_eq!(mock::write(on, &CharValue::Bool(false)), HapStatus::Success);
+        assert_eq!(mock::gpio_level(4), Some(0));
+    }
+
+    #[test]
+    fn writes_update_the_cache() {
+        HapContext::new_isolated();
+        let (_, on) = bound_lightbulb();
+        assert_eq!(on.cached_value(), None);
+
+        on.set_format(CharFormat::Bool);
+        assert_eq!(mock::write(on, &CharValue::Bool(true)), HapStatus::Success);
+        assert_eq!(on.cached_value(), Some(CharValue::Bool(true)));
+
+        assert_eq!(mock::write(on, &CharValue::Bool(false)), HapStatus::Success);
+        assert_eq!(on.cached_value(), Some(CharValue::Bool(false)));
+    }
+
+    #[test]
+    fn rejected_writes_leave_the_gpio_alone() {
+        HapContext::new_isolated();
+        let (service, on) = bound_lightbulb();
+        service.on_write(|_| Err(HapStatus::InvalidValue));
+
+        assert_eq!(mock::write(on, &CharValue::Bool(true)), HapStatus::InvalidValue);
+        assert_eq!(mock::gpio_level(4), Some(0));
+    }
+
+    /// A switch whose `On` only notifies changes from `false` to `true`.
+    fn switch_notifying_on() -> HapCharacteristic {
+        let accessory = Accessory::new(&mock::accessory_config("Switch")).unwrap();
+        let service = HapService::from_raw(unsafe { hap_serv_switch_create(false) });
+        accessory.add_service(&service).unwrap();
+
+        let on = service.char_by_uuid(HAP_CHAR_UUID_ON).unwrap();
+        on.update(&CharValue::Bool(false)).unwrap();
+        on.set_notify_predicate(|old, new| *old == CharValue::Bool(false) && *new == CharValue::Bool(true));
+
+        on
+    }
+
+    #[test]
+    fn rejected_updates_are_declined_while_subscribed() {
+        HapContext::new_isolated();
+        let on = switch_notifying_on();
+        mock::set_subscribers(on, 1);
+
+        on.update(&CharValue::Bool(true)).unwrap();
+        on.update(&CharValue::Bool(false)).unwrap();
+
+        assert_eq!(mock::notifications(on), 2);
+        assert!(unsafe { on.raw_value().b });
+        assert_eq!(on.cached_value(), Some(CharValue::Bool(true)));
+    }
+
+    #[test]
+    fn rejected_updates_are_stored_without_subscribers() {
+        HapContext::new_isolated();
+        let on = switch_notifying_on();
+
+        on.update(&CharValue::Bool(true)).unwrap();
+        on.update(&CharValue::Bool(false)).unwrap();
+
+        assert!(!unsafe { on.raw_value().b });
+        assert_eq!(on.cached_value(), Some(CharValue::Bool(false)));
+    }
+
+    #[test]
+    fn debounced_updates_collapse_into_one_notification() {
+        HapContext::new_isolated();
+        let (_, on) = bound_lightbulb();
+        let interval = Duration::from_secs(60);
+
+        on.update_debounced(CharValue::Bool(true), interval).unwrap();
+        assert_eq!(mock::notifications(on), 1);
+
+        on.update_debounced(CharValue::Bool(false), interval).unwrap();
+        on.update_debounced(CharValue::Bool(true), interval).unwrap();
+        on.update_debounced(CharValue::Bool(false), interval).unwrap();
+        assert_eq!(mock::notifications(on), 1);
+        assert_eq!(mock::armed_timers(), 1);
+
+        assert_eq!(mock::fire_timers(), 1);
+        assert_eq!(mock::notifications(on), 2);
+        assert!(!unsafe { on.raw_value().b });
+        assert_eq!(mock::armed_timers(), 0);
+    }
+
+    #[test]
+    fn descriptions_can_be_replaced() {
+        HapContext::new_isolated();
+        let (_, on) = bound_lightbulb();
+
+        on.set_description("Ceiling").unwrap();
+        on.set_description("Desk").unwrap();
+
+        assert_eq!(mock::description(on).as_deref(), Some("Desk"));
+        assert_eq!(on.set_description("a\0b"), Err(HapError::InvalidArg));
+        assert_eq!(mock::description(on).as_deref(), Some("Desk"));
+    }
+
+    #[test]
+    fn updates_drive_the_bound_gpio() {
+        HapContext::new_isolated();
+        let (_, on) = bound_lightbulb();
+
+        on.update(&CharValue::Bool(true)).unwrap();
+        assert_eq!(mock::gpio_level(4), Some(1));
+    }
+
+    #[test]
+    fn updates_are_retried_while_out_of_memory() {
+        let mut results = vec![Ok(()), Err(HapError::Fail(ESP_ERR_NO_MEM as i32))];
+        let mut calls = 0;
+
+        let result = retry_on_no_mem(3, Duration::ZERO, || {
+            calls += 1;
+            results.pop().unwrap()
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn retries_give_up_after_the_attempts() {
+        let mut calls = 0;
+        let result = retry_on_no_mem(3, Duration::ZERO, || {
+            calls += 1;
+            Err(HapError::Fail(ESP_ERR_NO_MEM as i32))
+        });
+
+        assert_eq!(result, Err(HapError::Fail(ESP_ERR_NO_MEM as i32)));
+        assert_eq!(calls, 3);
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() {
+        let mut calls = 0;
+        let result = retry_on_no_mem(3, Duration::ZERO, || {
+            calls += 1;
+            Err(HapError::Fail(HAP_FAIL))
+        });
+
+        assert_eq!(result, Err(HapError::Fail(HAP_FAIL)));
+        assert_eq!(calls, 1);
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+    use super::*;
+
+    fn round_trip<T>(value: &T, json: &str)
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + core::fmt::Debug,
+    {
+        assert_eq!(serde_json::to_string(value).unwrap(), json);
+        assert_eq!(&serde_json::from_str::<T>(json).unwrap(), value);
+    }
+
+    #[test]
+    fn values_round_trip() {
+        round_trip(&CharValue::Bool(true), r#"{"type":"bool","value":true}"#);
+        round_trip(&CharValue::UInt8(1), r#"{"type":"uint8","value":1}"#);
+        round_trip(&CharValue::UInt16(2), r#"{"type":"uint16","value":2}"#);
+        round_trip(&CharValue::UInt32(3), r#"{"type":"uint32","value":3}"#);
+        round_trip(&CharValue::UInt64(u64::MAX), r#"{"type":"uint64","value":18446744073709551615}"#);
+        round_trip(&CharValue::Int(-4), r#"{"type":"int","value":-4}"#);
+        round_trip(&CharValue::Float(21.5), r#"{"type":"float","value":21.5}"#);
+        round_trip(&CharValue::String(CString::new("Lamp").unwrap()), r#"{"type":"string","value":"Lamp"}"#);
+        round_trip(&CharValue::Data(vec![1, 2, 3]), r#"{"type":"data","value":"AQID"}"#);
+        round_trip(&CharValue::Tlv8(vec![0xff]), r#"{"type":"tlv8","value":"/w=="}"#);
+    }
+
+    #[test]
+    fn formats_are_named_like_value_types() {
+        for value in [
+            CharValue::Bool(false),
+            CharValue::UInt8(0),
+            CharValue::UInt16(0),
+            CharValue::UInt32(0),
+            CharValue::UInt64(0),
+            CharValue::Int(0),
+            CharValue::Float(0.0),
+            CharValue::String(CString::default()),
+            CharValue::Data(Vec::new()),
+            CharValue::Tlv8(Vec::new()),
+        ] {
+            let json = serde_json::to_value(&value).unwrap();
+            round_trip(&value.format(), &json["type"].to_string());
+        }
+    }
+
+    #[test]
+    fn units_round_trip() {
+        round_trip(&Unit::Celsius, r#""celsius""#);
+        round_trip(&Unit::Percentage, r#""percentage""#);
+        round_trip(&Unit::ArcDegrees, r#""arc_degrees""#);
+        round_trip(&Unit::Lux, r#""lux""#);
+        round_trip(&Unit::Seconds, r#""seconds""#);
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        assert!(serde_json::from_str::<CharValue>(r#"{"type":"string","value":"a\u0000b"}"#).is_err());
+        assert!(serde_json::from_str::<CharValue>(r#"{"type":"data","value":"not base64"}"#).is_err());
+        assert!(serde_json::from_str::<CharValue>(r#"{"type":"uint8","value":256}"#).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+//! The HomeKit settings of the sdkconfig the crate was built with.
+//!
+//! The build script passes every integer and string value of the kconfig to this
+//! crate as `ESP_IDF_CONFIG_<KEY>`, without the `CONFIG_` prefix of the sdkconfig.
+//! Boolean options are `esp_idf_<key>` cfgs instead. The values below are `None` if
+//! the option is unset, e.g. because the SDK revision lacks it.
+
+/// The decimal `value`, `None` if it is unset, empty, not a number or too large.
+const fn int(value: Option<&str>) -> Option<u32> {
+    let bytes = match value {
+        Some(value) if !value.is_empty() => value.as_bytes(),
+        _ => return None,
+    };
+
+    let mut i = 0;
+    let mut int: u32 = 0;
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            return None;
+        }
+        int = match int.checked_mul(10) {
+            Some(int) => match int.checked_add((bytes[i] - b'0') as u32) {
+                Some(int) => int,
+                None => return None,
+            },
+            None => return None,
+        };
+        i += 1;
+    }
+
+    Some(int)
+}
+
+/// `CONFIG_IDF_TARGET`, e.g. `esp32c3`.
+pub const IDF_TARGET: Option<&str> = option_env!("ESP_IDF_CONFIG_IDF_TARGET");
+
+/// The stack of the HAP HTTP server task, in bytes.
+pub const HAP_HTTP_STACK_SIZE: Option<u32> = int(option_env!("ESP_IDF_CONFIG_HAP_HTTP_STACK_SIZE"));
+
+/// The TCP port of the HAP server, see also [`server::active_port`](crate::server::active_port).
+pub const HAP_HTTP_SERVER_PORT: Option<u32> = int(option_env!("ESP_IDF_CONFIG_HAP_HTTP_SERVER_PORT"));
+
+/// The UDP control port of the HAP HTTP server.
+pub const HAP_HTTP_CONTROL_PORT: Option<u32> = int(option_env!("ESP_IDF_CONFIG_HAP_HTTP_CONTROL_PORT"));
+
+/// Simultaneous controller connections, see also [`hap::limits`](crate::hap::limits).
+pub const HAP_HTTP_MAX_OPEN_SOCKETS: Option<u32> = int(option_env!("ESP_IDF_CONFIG_HAP_HTTP_MAX_OPEN_SOCKETS"));
+
+/// The URI handlers of the HAP HTTP server, also used by custom endpoints.
+pub const HAP_HTTP_MAX_URI_HANDLERS: Option<u32> = int(option_env!("ESP_IDF_CONFIG_HAP_HTTP_MAX_URI_HANDLERS"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ints_are_parsed() {
+        assert_eq!(int(Some("12288")), Some(12288));
+        assert_eq!(int(Some("0")), Some(0));
+        assert_eq!(int(Some("4294967295")), Some(u32::MAX));
+    }
+
+    #[test]
+    fn unset_and_malformed_ints_are_none() {
+        assert_eq!(int(None), None);
+        assert_eq!(int(Some("")), None);
+        assert_eq!(int(Some("12k")), None);
+        assert_eq!(int(Some("-1")), None);
+        assert_eq!(int(Some("0x1000")), None);
+    }
+
+    #[test]
+    fn overflowing_ints_are_none() {
+        assert_eq!(int(Some("4294967296")), None);
+        assert_eq!(int(Some("99999999999")), None);
+    }
+}
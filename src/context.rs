@@ -0,0 +1,154 @@
+use std::sync::Arc;
+#[cfg(not(feature = "mock"))]
+use std::sync::OnceLock;
+#[cfg(feature = "mock")]
+use std::cell::RefCell;
+
+use crate::{accessory, characteristic, deferred, event, hap, provisioning, server};
+
+/// The state this crate keeps next to the HAP framework: whether it is initialized and
+/// started, the accessories added, the event, error and provisioning handlers, the
+/// hostname, the session count and the worker of deferred handlers.
+///
+/// The framework has a single accessory database and HTTP server, so on the device
+/// there is exactly one context, [`HapContext::global`]. The free functions of the
+/// modules, e.g. [`hap::add`] or [`event::subscribe`], are shims for the methods of
+/// the global context.
+///
+/// With the `mock` feature every thread has a context of its own, like the device of
+/// [`mock`](crate::mock), so tests running in parallel don't see each other's
+/// accessories or handlers. Tests start from a fresh one with
+/// [`HapContext::new_isolated`].
+#[derive(Clone)]
+pub struct HapContext {
+    state: Arc<State>,
+}
+
+#[derive(Default)]
+pub(crate) struct State {
+    pub(crate) hap: hap::State,
+    pub(crate) accessory: accessory::State,
+    pub(crate) characteristic: characteristic::State,
+    pub(crate) deferred: deferred::State,
+    pub(crate) event: event::State,
+    pub(crate) server: server::State,
+    pub(crate) provisioning: provisioning::State,
+}
+
+#[cfg(feature = "mock")]
+thread_local! {
+    static CURRENT: RefCell<Option<HapContext>> = const { RefCell::new(None) };
+}
+
+impl HapContext {
+    fn new() -> Self {
+        HapContext {
+            state: Arc::new(State::default()),
+        }
+    }
+
+    /// The context of the device, the one the free functions use.
+    ///
+    /// With the `mock` feature the context of the current thread, see
+    /// [`new_isolated`](Self::new_isolated).
+    pub fn global() -> Self {
+        #[cfg(not(feature = "mock"))]
+        {
+            static GLOBAL: OnceLock<HapContext> = OnceLock::new();
+
+            GLOBAL.get_or_init(HapContext::new).clone()
+        }
+
+        #[cfg(feature = "mock")]
+        CURRENT.with(|current| current.borrow_mut().get_or_insert_with(HapContext::new).clone())
+    }
+
+    /// Start the current thread over with a fresh context and a fresh mock device,
+    /// returning the context.
+    ///
+    /// The context is also the [`global`](Self::global) one of the thread from then on,
+    /// so HAP callbacks and free functions called by the code under test use it.
+    #[cfg(feature = "mock")]
+    pub fn new_isolated() -> Self {
+        crate::mock::reset();
+
+        let context = HapContext::new();
+        CURRENT.with(|current| *current.borrow_mut() = Some(context.clone()));
+
+        context
+    }
+
+    /// Run `f` with this context as the global one, e.g. for jobs of another task that
+    /// were queued under this context.
+    pub(crate) fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+        #[cfg(feature = "mock")]
+        {
+            struct Restore(Option<HapContext>);
+
+            impl Drop for Restore {
+                fn drop(&mut self) {
+                    CURRENT.with(|current| *current.borrow_mut() = self.0.take());
+                }
+            }
+
+            let _restore = Restore(CURRENT.with(|current| current.borrow_mut().replace(self.clone())));
+
+            f()
+        }
+
+        #[cfg(not(feature = "mock"))]
+        f()
+    }
+
+    pub(crate) fn state(&self) -> &State {
+        &self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::event::HapEvent;
+
+    #[test]
+    fn contexts_dont_share_handlers() {
+        let context = HapContext::new_isolated();
+        let other = HapContext::new();
+        let received = Arc::new(AtomicUsize::new(0));
+
+        let counter = received.clone();
+        context.subscribe(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        other.emit(HapEvent::IdentifyRequested { aid: 1 });
+        assert_eq!(received.load(Ordering::SeqCst), 0);
+
+        context.emit(HapEvent::IdentifyRequested { aid: 1 });
+        assert_eq!(received.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn the_isolated_context_is_the_global_one_of_the_thread() {
+        let context = HapContext::new_isolated();
+
+        assert_eq!(HapContext::global().try_claim_aid(5), Ok(()));
+        assert!(context.try_claim_aid(5).is_err());
+
+        let other = std::thread::spawn(|| HapContext::global().try_claim_aid(5));
+        assert_eq!(other.join().unwrap(), Ok(()));
+    }
+
+    #[test]
+    fn entered_contexts_are_restored() {
+        let context = HapContext::new_isolated();
+        let other = HapContext::new();
+
+        other.enter(|| assert_eq!(HapContext::global().try_claim_aid(6), Ok(())));
+        assert_eq!(context.try_claim_aid(6), Ok(()));
+        assert!(other.try_claim_aid(6).is_err());
+    }
+}
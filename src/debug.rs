@@ -0,0 +1,28 @@
+//! Debugging aids which expose pairing-sensitive data.
+//!
+//! Only available with the `insecure-debug` feature.
+
+use log::*;
+
+use crate::*;
+
+/// Dump every HTTP request and response of the HAP server to the console.
+///
+/// The dumps include pairing TLVs and session key material, so never ship a
+/// firmware with this enabled. They are printed by the HAP framework itself and
+/// do not pass through the `log` crate.
+pub fn enable_http_trace() {
+    warn!("Enabling HAP HTTP trace, pairing data will be printed to the console");
+
+    unsafe {
+        hap_http_debug_enable();
+    }
+}
+
+pub fn disable_http_trace() {
+    info!("Disabling HAP HTTP trace");
+
+    unsafe {
+        hap_http_debug_disable();
+    }
+}
@@ -0,0 +1,303 @@
+//! A worker task for handlers too slow to run in the HAP server task.
+//!
+//! Read and write callbacks run in the HTTP server task of the HAP framework. A
+//! handler blocking there, e.g. on a slow I2C sensor, delays every other controller
+//! and may trip the task watchdog, which drops the session. Handlers installed with
+//! [`HapService::on_read_deferred`](crate::service::HapService::on_read_deferred) and
+//! [`HapService::on_write_deferred`](crate::service::HapService::on_write_deferred)
+//! run here instead, one at a time in submission order.
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+
+use core::ptr;
+use log::*;
+
+use crate::context::HapContext;
+use crate::error::{self, HapError};
+use crate::*;
+
+pub(crate) type Job = Box<dyn FnOnce() + Send>;
+
+/// What to do with a job submitted while the queue is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// Drop the oldest queued job to make room, e.g. for sensor reads where only the
+    /// latest one matters.
+    ///
+    /// A dropped write was already acknowledged to the controller but is never
+    /// applied, so only use this when every deferred handler is a read.
+    DropOldest,
+    /// Reject the new job, the default. Deferred writes are then failed with
+    /// [`HapStatus::Busy`](crate::error::HapStatus::Busy), so the controller learns
+    /// they weren't applied, and deferred reads are skipped.
+    Reject,
+}
+
+/// The configuration of the worker task.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WorkerConfig {
+    /// In bytes.
+    pub stack_size: u32,
+    pub priority: u32,
+    /// The number of jobs waiting for the worker, at least 1.
+    pub queue_depth: usize,
+    pub overflow: Overflow,
+}
+
+const DEFAULT_CONFIG: WorkerConfig = WorkerConfig {
+    stack_size: 4096,
+    priority: 4,
+    queue_depth: 8,
+    overflow: Overflow::Reject,
+};
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        DEFAULT_CONFIG
+    }
+}
+
+struct Worker {
+    config: WorkerConfig,
+    started: bool,
+    jobs: VecDeque<Job>,
+}
+
+/// The part of a [`HapContext`] kept by this module.
+pub(crate) struct State {
+    worker: Mutex<Worker>,
+    queued: Condvar,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            worker: Mutex::new(Worker {
+                config: DEFAULT_CONFIG,
+                started: false,
+                jobs: VecDeque::new(),
+            }),
+            queued: Condvar::new(),
+        }
+    }
+}
+
+/// [`HapContext::worker_config`] of the global context.
+pub fn worker_config() -> WorkerConfig {
+    HapContext::global().worker_config()
+}
+
+/// [`HapContext::set_worker_config`] of the global context.
+pub fn set_worker_config(config: &WorkerConfig) -> Result<(), HapError> {
+    HapContext::global().set_worker_config(config)
+}
+
+/// [`HapContext::submit`] of the global context.
+pub(crate) fn submit(job: Job) -> Result<(), HapError> {
+    HapContext::global().submit(job)
+}
+
+impl HapContext {
+    /// The current configuration of the worker task.
+    pub fn worker_config(&self) -> WorkerConfig {
+        self.state().deferred.worker.lock().unwrap().config
+    }
+
+    /// Configure the worker task, must be called before the first deferred handler runs.
+    pub fn set_worker_config(&self, config: &WorkerConfig) -> Result<(), HapError> {
+        let mut worker = self.state().deferred.worker.lock().unwrap();
+
+        if worker.started || config.stack_size == 0 || config.queue_depth == 0 {
+            return Err(HapError::InvalidArg);
+        }
+        worker.config = *config;
+
+        Ok(())
+    }
+
+    /// Queue `job` for the worker task, starting it on first use.
+    ///
+    /// Fails if the queue is full and the overflow policy is [`Overflow::Reject`], or if
+    /// the task can't be created.
+    pub(crate) fn submit(&self, job: Job) -> Result<(), HapError> {
+        let mut worker = self.state().deferred.worker.lock().unwrap();
+
+        if !worker.started {
+            self.start_worker(&worker.config)?;
+            worker.started = true;
+        }
+
+        if worker.jobs.len() >= worker.config.queue_depth {
+            match worker.config.overflow {
+                Overflow::DropOldest => {
+                    warn!("Deferred queue full, dropping the oldest job");
+                    worker.jobs.pop_front();
+                }
+                Overflow::Reject => {
+                    warn!("Deferred queue full, rejecting the job");
+                    return Err(HapError::Fail(hap::HAP_FAIL_));
+                }
+            }
+        }
+
+        worker.jobs.push_back(job);
+        self.state().deferred.queued.notify_one();
+
+        Ok(())
+    }
+
+    fn start_worker(&self, config: &WorkerConfig) -> Result<(), HapError> {
+        info!("Starting the deferred handler task");
+
+        // The task runs the jobs of this context until the device restarts.
+        let context = Box::into_raw(Box::new(self.clone()));
+        let created = unsafe {
+            esp_homekit_shim_xTaskCreate(
+                Some(run),
+                b"hap_deferred\0".as_ptr() as *const i8,
+                config.stack_size,
+                context as *mut c_types::c_void,
+                config.priority,
+                ptr::null_mut(),
+            )
+        };
+
+        // `pdPASS`
+        if created == 1 {
+            Ok(())
+        } else {
+            drop(unsafe { Box::from_raw(context) });
+            Err(HapError::Fail(hap::HAP_FAIL_))
+        }
+    }
+}
+
+unsafe extern "C" fn run(arg: *mut c_types::c_void) {
+    let context = *Box::from_raw(arg as *mut HapContext);
+    let state = &context.state().deferred;
+
+    loop {
+        let job = {
+            let mut worker = state.worker.lock().unwrap();
+            loop {
+                match worker.jobs.pop_front() {
+                    Some(job) => break job,
+                    None => worker = state.queued.wait(worker).unwrap(),
+                }
+            }
+        };
+
+        // Jobs run application code, and this task must never return.
+        context.enter(|| error::catch_panic("deferred handler", (), job));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::accessory::Accessory;
+    use crate::characteristic::{CharValue, HapCharacteristic};
+    use crate::error::HapStatus;
+    use crate::mock;
+    use crate::service::HapService;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Submit a job occupying the worker until the returned sender is dropped.
+    fn block_worker(context: &HapContext) -> mpsc::Sender<()> {
+        let (started_tx, started) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+
+        context
+            .submit(Box::new(move || {
+                started_tx.send(()).unwrap();
+                let _ = released.recv();
+            }))
+            .unwrap();
+        started.recv_timeout(TIMEOUT).unwrap();
+
+        release
+    }
+
+    fn config(queue_depth: usize, overflow: Overflow) -> WorkerConfig {
+        WorkerConfig {
+            queue_depth,
+            overflow,
+            ..WorkerConfig::default()
+        }
+    }
+
+    #[test]
+    fn full_queues_reject_by_default() {
+        assert_eq!(WorkerConfig::default().overflow, Overflow::Reject);
+
+        let context = HapContext::new_isolated();
+        context.set_worker_config(&config(2, Overflow::Reject)).unwrap();
+        let (ran_tx, ran) = mpsc::channel();
+
+        let release = block_worker(&context);
+        for job in 0..3 {
+            let ran_tx = ran_tx.clone();
+            let submitted = context.submit(Box::new(move || ran_tx.send(job).unwrap()));
+            assert_eq!(submitted.is_ok(), job < 2);
+        }
+        drop(release);
+
+        assert_eq!(ran.recv_timeout(TIMEOUT), Ok(0));
+        assert_eq!(ran.recv_timeout(TIMEOUT), Ok(1));
+        assert!(ran.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn full_queues_drop_the_oldest_job_if_configured() {
+        let context = HapContext::new_isolated();
+        context.set_worker_config(&config(2, Overflow::DropOldest)).unwrap();
+        let (ran_tx, ran) = mpsc::channel();
+
+        let release = block_worker(&context);
+        for job in 0..3 {
+            let ran_tx = ran_tx.clone();
+            assert!(context.submit(Box::new(move || ran_tx.send(job).unwrap())).is_ok());
+        }
+        drop(release);
+
+        assert_eq!(ran.recv_timeout(TIMEOUT), Ok(1));
+        assert_eq!(ran.recv_timeout(TIMEOUT), Ok(2));
+        assert!(ran.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+
+    #[test]
+    fn the_config_is_fixed_once_the_worker_runs() {
+        let context = HapContext::new_isolated();
+        assert_eq!(context.set_worker_config(&config(0, Overflow::Reject)), Err(HapError::InvalidArg));
+
+        drop(block_worker(&context));
+        assert_eq!(context.set_worker_config(&config(4, Overflow::Reject)), Err(HapError::InvalidArg));
+    }
+
+    #[test]
+    fn panicking_deferred_writes_dont_poison_the_handler() {
+        HapContext::new_isolated();
+        let accessory = Accessory::new(&mock::accessory_config("Switch")).unwrap();
+        let service = HapService::from_raw(unsafe { hap_serv_switch_create(false) });
+        accessory.add_service(&service).unwrap();
+        let on = service.char_by_uuid(HAP_CHAR_UUID_ON).unwrap();
+
+        let (applied_tx, applied) = mpsc::channel();
+        service.on_write_deferred(move |_: HapCharacteristic, value| {
+            if value == CharValue::Bool(true) {
+                panic!("the relay is stuck");
+            }
+            applied_tx.send(value).unwrap();
+            None
+        });
+
+        assert_eq!(mock::write(on, &CharValue::Bool(true)), HapStatus::Success);
+        assert_eq!(mock::write(on, &CharValue::Bool(false)), HapStatus::Success);
+        assert_eq!(applied.recv_timeout(TIMEOUT), Ok(CharValue::Bool(false)));
+    }
+}
@@ -0,0 +1,245 @@
+//! Diagnostics for debugging pairing and controller issues.
+
+use std::fmt::Write;
+
+use crate::characteristic::{CharFormat, CharValue, HapCharacteristic};
+use crate::service::HapService;
+use crate::*;
+
+pub use crate::error::panic_count;
+
+/// The free heap at one point in time, to check on the device that nothing is
+/// allocated once the accessory is set up, e.g. with [`hap_accessory!`](crate::hap_accessory):
+///
+/// ```ignore
+/// let baseline = diag::HeapBaseline::now();
+/// // Pair, toggle the lamp from the Home app a few times, ...
+/// assert_eq!(baseline.growth(), 0);
+/// ```
+///
+/// The heap is shared with the Wi-Fi and lwIP tasks, so compare at a quiet moment,
+/// e.g. after the controllers disconnected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeapBaseline {
+    free: u32,
+}
+
+impl HeapBaseline {
+    pub fn now() -> Self {
+        HeapBaseline {
+            free: unsafe { esp_get_free_heap_size() },
+        }
+    }
+
+    /// The bytes allocated since the baseline and not freed yet, 0 if the free heap
+    /// didn't shrink.
+    pub fn growth(&self) -> u32 {
+        self.free.saturating_sub(Self::now().free)
+    }
+}
+
+/// Written instead of the value of hidden characteristics and characteristics
+/// requiring additional authorization.
+const REDACTED: &str = "<redacted>";
+
+const PERMS: &[(u32, &str)] = &[
+    (HAP_CHAR_PERM_PR, "pr"),
+    (HAP_CHAR_PERM_PW, "pw"),
+    (HAP_CHAR_PERM_EV, "ev"),
+    (HAP_CHAR_PERM_AA, "aa"),
+    (HAP_CHAR_PERM_TW, "tw"),
+    (HAP_CHAR_PERM_HD, "hd"),
+    (HAP_CHAR_PERM_WR, "wr"),
+];
+
+/// The attribute database in the JSON shape of the HAP `/accessories` endpoint, to
+/// compare what the accessory exposes with what a controller sees.
+///
+/// Values of hidden characteristics and characteristics requiring additional
+/// authorization are redacted. Values of write-only characteristics are omitted, as
+/// in the HAP response. Data and TLV8 values are base64-encoded.
+///
+/// ```text
+/// {"accessories":[{"aid":1,"services":[{"iid":1,"type":"3E","primary":false,"hidden":false,"characteristics":[
+/// {"iid":2,"type":"14","perms":["pw"],"format":"bool"},...]}]}]}
+/// ```
+pub fn attribute_database_json() -> String {
+    let mut json = String::from("{\"accessories\":[");
+    let mut acc = unsafe { hap_get_first_acc() };
+
+    while !acc.is_null() {
+        let aid = unsafe { hap_acc_get_aid(acc) };
+        let _ = write!(json, "{{\"aid\":{},\"services\":[", aid);
+
+        let mut serv = unsafe { hap_acc_get_first_serv(acc) };
+        while !serv.is_null() {
+            write_service(&mut json, HapService::from_raw(serv));
+
+            serv = unsafe { hap_serv_get_next(serv) };
+            if !serv.is_null() {
+                json.push(',');
+            }
+        }
+
+        json.push_str("]}");
+
+        acc = unsafe { hap_acc_get_next(acc) };
+        if !acc.is_null() {
+            json.push(',');
+        }
+    }
+
+    json.push_str("]}");
+
+    json
+}
+
+fn write_service(json: &mut String, service: HapService) {
+    let _ = write!(json, "{{\"iid\":{},\"type\":", service.iid());
+    write_str(json, &service.uuid().to_string_lossy());
+    let _ = write!(json, ",\"primary\":{},\"hidden\":{}", service.is_primary(), service.is_hidden());
+    json.push_str(",\"characteristics\":[");
+
+    for (i, characteristic) in service.characteristics().into_iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        write_char(json, characteristic);
+    }
+
+    json.push_str("]}");
+}
+
+fn write_char(json: &mut String, characteristic: HapCharacteristic) {
+    let perms = characteristic.perms();
+
+    let _ = write!(json, "{{\"iid\":{},\"type\":", characteristic.iid());
+    write_str(json, &characteristic.uuid().to_string_lossy());
+
+    json.push_str(",\"perms\":[");
+    let names = PERMS.iter().filter(|(perm, _)| perms & perm != 0);
+    for (i, (_, name)) in names.enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let _ = write!(json, "\"{}\"", name);
+    }
+    json.push(']');
+
+    let format = match characteristic.format() {
+        Some(format) => format,
+        None => {
+            json.push('}');
+            return;
+        }
+    };
+
+    let _ = write!(json, ",\"format\":\"{}\"", format_name(format));
+
+    if perms & (HAP_CHAR_PERM_HD | HAP_CHAR_PERM_AA) != 0 {
+        json.push_str(",\"value\":");
+        write_str(json, REDACTED);
+    } else if perms & HAP_CHAR_PERM_PR != 0 {
+        let value = unsafe { CharValue::from_raw(format, &characteristic.raw_value()) };
+
+        json.push_str(",\"value\":");
+        write_value(json, &value);
+    }
+
+    json.push('}');
+}
+
+fn format_name(format: CharFormat) -> &'static str {
+    match format {
+        CharFormat::Bool => "bool",
+        CharFormat::UInt8 => "uint8",
+        CharFormat::UInt16 => "uint16",
+        CharFormat::UInt32 => "uint32",
+        CharFormat::UInt64 => "uint64",
+        CharFormat::Int => "int",
+        CharFormat::Float => "float",
+        CharFormat::String => "string",
+        CharFormat::Data => "data",
+        CharFormat::Tlv8 => "tlv8",
+    }
+}
+
+fn write_value(json: &mut String, value: &CharValue) {
+    let _ = match value {
+        CharValue::Bool(b) => write!(json, "{}", b),
+        CharValue::UInt8(u) => write!(json, "{}", u),
+        CharValue::UInt16(u) => write!(json, "{}", u),
+        CharValue::UInt32(u) => write!(json, "{}", u),
+        CharValue::UInt64(u) => write!(json, "{}", u),
+        CharValue::Int(i) => write!(json, "{}", i),
+        // JSON has no NaN or infinity.
+        CharValue::Float(f) if !f.is_finite() => write!(json, "null"),
+        CharValue::Float(f) => write!(json, "{}", f),
+        CharValue::String(s) => {
+            write_str(json, &s.to_string_lossy());
+            Ok(())
+        }
+        CharValue::Data(d) | CharValue::Tlv8(d) => write!(json, "\"{}\"", base64(d)),
+    };
+}
+
+fn write_str(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// Standard base64 with padding, without pulling in a dependency for debug output.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::HapContext;
+    use crate::mock;
+
+    #[test]
+    fn heap_growth_is_the_shrinking_of_the_free_heap() {
+        HapContext::new_isolated();
+        mock::set_heap(100 * 1024, 50 * 1024);
+        let baseline = HeapBaseline::now();
+
+        assert_eq!(baseline.growth(), 0);
+
+        mock::set_heap(96 * 1024, 50 * 1024);
+        assert_eq!(baseline.growth(), 4 * 1024);
+
+        mock::set_heap(120 * 1024, 50 * 1024);
+        assert_eq!(baseline.growth(), 0);
+    }
+}
@@ -0,0 +1,264 @@
+use core::fmt;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use log::*;
+
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HapError {
+    /// A `hap_*` call returned a non-success code.
+    Fail(i32),
+    /// An argument was rejected before calling into the HAP framework.
+    InvalidArg,
+    /// Adding a bridged accessory would exceed [`accessory::Bridge::MAX_ACCESSORIES`].
+    TooManyAccessories,
+    /// Adding an accessory with an aid already in use, see [`hap::add_bridged`].
+    DuplicateAid(u32),
+    /// Adding a primary service to an accessory which already has one.
+    MultiplePrimaryServices,
+    /// Too little heap to start, see [`hap::heap_requirements`]. `free` is the free heap
+    /// or largest free block in bytes, whichever falls short of `required`.
+    InsufficientHeap { free: u32, required: u32 },
+    /// Watching more characteristics than the HAP framework queues events for, see
+    /// [`hap::Limits::max_events_queued`].
+    TooManyEventCharacteristics { max: usize },
+}
+
+impl HapError {
+    /// Turn a raw `hap_*` return code into a `Result`.
+    ///
+    /// Failures are also passed to the handler installed with [`server::set_error_handler`].
+    pub fn check(code: i32) -> Result<(), HapError> {
+        if code == hap::HAP_SUCCESS_ {
+            Ok(())
+        } else {
+            let err = HapError::Fail(code);
+            server::report_error(err);
+
+            Err(err)
+        }
+    }
+}
+
+impl fmt::Display for HapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HapError::Fail(code) => write!(f, "HAP call failed with code {}", code),
+            HapError::InvalidArg => write!(f, "Invalid argument"),
+            HapError::TooManyAccessories => write!(f, "Too many bridged accessories"),
+            HapError::DuplicateAid(aid) => write!(f, "Accessory aid {} is already in use", aid),
+            HapError::MultiplePrimaryServices => write!(f, "The accessory already has a primary service"),
+            HapError::InsufficientHeap { free, required } => {
+                write!(f, "Insufficient heap, {} bytes free but {} required", free, required)
+            }
+            HapError::TooManyEventCharacteristics { max } => {
+                write!(f, "Events of at most {} characteristics can be queued", max)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HapError {}
+
+/// The status of a characteristic read or write, reported back to the controller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HapStatus {
+    Success,
+    InsufficientPrivileges,
+    /// Unable to communicate with the accessory, e.g. a device behind a bridge.
+    CommunicationError,
+    Busy,
+    WriteOnReadOnly,
+    ReadOnWriteOnly,
+    NotificationNotSupported,
+    OutOfResources,
+    Timeout,
+    ResourceAbsent,
+    InvalidValue,
+    InsufficientAuthorization,
+    /// A code without a variant of its own, e.g. from a newer HAP framework.
+    Other(hap_status_t),
+}
+
+impl HapStatus {
+    pub fn as_raw(&self) -> hap_status_t {
+        match self {
+            HapStatus::Success => hap_status_t_HAP_STATUS_SUCCESS,
+            HapStatus::InsufficientPrivileges => hap_status_t_HAP_STATUS_INSUFFICIENT_PRIVILEGES,
+            HapStatus::CommunicationError => hap_status_t_HAP_STATUS_COMM_ERR,
+            HapStatus::Busy => hap_status_t_HAP_STATUS_RES_BUSY,
+            HapStatus::WriteOnReadOnly => hap_status_t_HAP_STATUS_WR_ON_RDONLY_CHAR,
+            HapStatus::ReadOnWriteOnly => hap_status_t_HAP_STATUS_RD_ON_WRONLY_CHAR,
+            HapStatus::NotificationNotSupported => hap_status_t_HAP_STATUS_NO_NOTIF,
+            HapStatus::OutOfResources => hap_status_t_HAP_STATUS_OO_RES,
+            HapStatus::Timeout => hap_status_t_HAP_STATUS_TIMEOUT,
+            HapStatus::ResourceAbsent => hap_status_t_HAP_STATUS_RES_ABSENT,
+            HapStatus::InvalidValue => hap_status_t_HAP_STATUS_VAL_INVALID,
+            HapStatus::InsufficientAuthorization => hap_status_t_HAP_STATUS_INSUFFICIENT_AUTH,
+            HapStatus::Other(code) => *code,
+        }
+    }
+
+    /// The variant of a raw status code, `Other` for unknown codes.
+    pub fn from_raw(code: hap_status_t) -> Self {
+        // Matching on the bindgen constants, not on literals, keeps this in sync with the SDK.
+        match code {
+            hap_status_t_HAP_STATUS_SUCCESS => HapStatus::Success,
+            hap_status_t_HAP_STATUS_INSUFFICIENT_PRIVILEGES => HapStatus::InsufficientPrivileges,
+            hap_status_t_HAP_STATUS_COMM_ERR => HapStatus::CommunicationError,
+            hap_status_t_HAP_STATUS_RES_BUSY => HapStatus::Busy,
+            hap_status_t_HAP_STATUS_WR_ON_RDONLY_CHAR => HapStatus::WriteOnReadOnly,
+            hap_status_t_HAP_STATUS_RD_ON_WRONLY_CHAR => HapStatus::ReadOnWriteOnly,
+            hap_status_t_HAP_STATUS_NO_NOTIF => HapStatus::NotificationNotSupported,
+            hap_status_t_HAP_STATUS_OO_RES => HapStatus::OutOfResources,
+            hap_status_t_HAP_STATUS_TIMEOUT => HapStatus::Timeout,
+            hap_status_t_HAP_STATUS_RES_ABSENT => HapStatus::ResourceAbsent,
+            hap_status_t_HAP_STATUS_VAL_INVALID => HapStatus::InvalidValue,
+            hap_status_t_HAP_STATUS_INSUFFICIENT_AUTH => HapStatus::InsufficientAuthorization,
+            code => HapStatus::Other(code),
+        }
+    }
+}
+
+impl From<HapStatus> for c_types::c_int {
+    fn from(status: HapStatus) -> c_types::c_int {
+        status.as_raw() as c_types::c_int
+    }
+}
+
+/// What to do after a panic in application code called back by the HAP framework.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Fail the request and carry on, the default.
+    Contain,
+    /// Fail the request, then abort once the HAP framework had time to complete the
+    /// transaction, so the keystore is never left half-written.
+    Abort,
+}
+
+/// How long [`PanicPolicy::Abort`] waits for the HAP transaction to complete.
+const ABORT_DELAY: Duration = Duration::from_millis(500);
+
+static ABORT_ON_PANIC: AtomicBool = AtomicBool::new(false);
+static PANICS: AtomicU32 = AtomicU32::new(0);
+
+pub fn set_panic_policy(policy: PanicPolicy) {
+    ABORT_ON_PANIC.store(policy == PanicPolicy::Abort, Ordering::SeqCst);
+}
+
+/// The number of panics caught in callbacks since boot, for diagnostics.
+pub fn panic_count() -> u32 {
+    PANICS.load(Ordering::SeqCst)
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("<non-string payload>")
+}
+
+/// Run `f` on behalf of a C callback, returning `fallback` if it panics.
+///
+/// Unwinding into the HAP framework is undefined behavior, so every callback
+/// invoking Rust code from C must go through this.
+pub(crate) fn catch_panic<R>(context: &str, fallback: R, f: impl FnOnce() -> R) -> R {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => {
+            PANICS.fetch_add(1, Ordering::SeqCst);
+            error!(
+                "Panic in {}, reporting a failure to the HAP framework: {}",
+                context,
+                panic_message(payload.as_ref())
+            );
+
+            if ABORT_ON_PANIC.load(Ordering::SeqCst) {
+                // Aborting right here would interrupt the framework mid-transaction.
+                match timer::after(ABORT_DELAY, || std::process::abort()) {
+                    Ok(timer) => timer.detach(),
+                    Err(_) => std::process::abort(),
+                }
+            }
+
+            fallback
+        }
+    }
+}
+
+/// Lock the mutex of a handler, even if a previous call panicked in it.
+///
+/// The panic was already caught and reported, and the handler must keep serving
+/// later requests.
+pub(crate) fn lock_handler<T: ?Sized>(handler: &Mutex<T>) -> MutexGuard<'_, T> {
+    handler.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panics_fall_back_to_the_status() {
+        let before = panic_count();
+
+        let status = catch_panic("a test write", HapStatus::Busy, || -> HapStatus { panic!("broken handler") });
+
+        assert_eq!(status, HapStatus::Busy);
+        assert!(panic_count() > before);
+    }
+
+    #[test]
+    fn results_pass_through_without_a_panic() {
+        assert_eq!(catch_panic("a test read", HapStatus::Busy, || HapStatus::Success), HapStatus::Success);
+    }
+
+    #[test]
+    fn panic_messages_are_extracted() {
+        let payload = panic::catch_unwind(|| panic!("formatted {}", 1)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "formatted 1");
+
+        let payload = panic::catch_unwind(|| std::panic::panic_any(1)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "<non-string payload>");
+    }
+
+    const STATUSES: [(HapStatus, hap_status_t); 12] = [
+        (HapStatus::Success, hap_status_t_HAP_STATUS_SUCCESS),
+        (HapStatus::InsufficientPrivileges, hap_status_t_HAP_STATUS_INSUFFICIENT_PRIVILEGES),
+        (HapStatus::CommunicationError, hap_status_t_HAP_STATUS_COMM_ERR),
+        (HapStatus::Busy, hap_status_t_HAP_STATUS_RES_BUSY),
+        (HapStatus::WriteOnReadOnly, hap_status_t_HAP_STATUS_WR_ON_RDONLY_CHAR),
+        (HapStatus::ReadOnWriteOnly, hap_status_t_HAP_STATUS_RD_ON_WRONLY_CHAR),
+        (HapStatus::NotificationNotSupported, hap_status_t_HAP_STATUS_NO_NOTIF),
+        (HapStatus::OutOfResources, hap_status_t_HAP_STATUS_OO_RES),
+        (HapStatus::Timeout, hap_status_t_HAP_STATUS_TIMEOUT),
+        (HapStatus::ResourceAbsent, hap_status_t_HAP_STATUS_RES_ABSENT),
+        (HapStatus::InvalidValue, hap_status_t_HAP_STATUS_VAL_INVALID),
+        (HapStatus::InsufficientAuthorization, hap_status_t_HAP_STATUS_INSUFFICIENT_AUTH),
+    ];
+
+    #[test]
+    fn statuses_round_trip() {
+        for (status, raw) in STATUSES {
+            assert_eq!(status.as_raw(), raw, "{:?}", status);
+            assert_eq!(HapStatus::from_raw(raw), status, "{:?}", status);
+        }
+    }
+
+    #[test]
+    fn unknown_codes_round_trip_as_other() {
+        let code = 4242;
+        assert!(STATUSES.iter().all(|(_, raw)| *raw != code));
+
+        assert_eq!(HapStatus::from_raw(code), HapStatus::Other(code));
+        assert_eq!(HapStatus::Other(code).as_raw(), code);
+        assert_eq!(c_types::c_int::from(HapStatus::Other(code)), code as c_types::c_int);
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::{Arc, Mutex};
+
+use crate::context::HapContext;
+
+/// Events observable by application code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HapEvent {
+    /// The accessory database changed and the configuration number (`c#`) was bumped,
+    /// which makes the HAP framework re-announce the accessory over mDNS once started.
+    DatabaseChanged { config_number: Option<u16> },
+    /// The HAP framework announced the accessory over mDNS with the configuration
+    /// number `config_number`, on start and on every bump after that.
+    Announced { config_number: Option<u16> },
+    /// A controller asked the accessory `aid` to identify itself, e.g. by blinking.
+    IdentifyRequested { aid: u32 },
+    /// The controller `id` opened a verified session, see [`server::active_sessions`].
+    ///
+    /// [`server::active_sessions`]: crate::server::active_sessions
+    ControllerConnected { id: String },
+    /// A session of the controller `id` was closed.
+    ControllerDisconnected { id: String },
+    /// The number of controllers subscribed to events of the characteristic `aid`/`iid`
+    /// changed, see [`HapCharacteristic::watch_subscriptions`].
+    ///
+    /// [`HapCharacteristic::watch_subscriptions`]: crate::characteristic::HapCharacteristic::watch_subscriptions
+    SubscriptionChanged { aid: u32, iid: u32, subscribed_count: usize },
+}
+
+type Handler = Arc<dyn Fn(&HapEvent) + Send + Sync>;
+
+/// The part of a [`HapContext`] kept by this module.
+#[derive(Default)]
+pub(crate) struct State {
+    handlers: Mutex<Vec<Handler>>,
+}
+
+/// [`HapContext::subscribe`] of the global context.
+pub fn subscribe<F>(handler: F)
+where
+    F: Fn(&HapEvent) + Send + Sync + 'static,
+{
+    HapContext::global().subscribe(handler)
+}
+
+pub(crate) fn emit(event: HapEvent) {
+    HapContext::global().emit(event)
+}
+
+impl HapContext {
+    /// Register a handler called for every [`HapEvent`].
+    pub fn subscribe<F>(&self, handler: F)
+    where
+        F: Fn(&HapEvent) + Send + Sync + 'static,
+    {
+        self.state().event.handlers.lock().unwrap().push(Arc::new(handler));
+    }
+
+    pub(crate) fn emit(&self, event: HapEvent) {
+        // Call outside of the lock so handlers may subscribe or emit themselves.
+        let handlers = self.state().event.handlers.lock().unwrap().clone();
+
+        for handler in handlers {
+            handler(&event);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! The foreign symbols the safe wrappers of this crate require.
+//!
+//! With the `slim-bindings` feature only these are emitted by bindgen, which
+//! keeps the bindings from clashing with `esp-idf-sys` when both are used.
+//! Types reachable from the listed items are pulled in automatically.
+//!
+//! Entries are bindgen allowlist regexes. Any new FFI call in the wrappers
+//! must be covered here, or the crate won't compile with `slim-bindings`.
+
+/// The HomeKit SDK itself, its platform layer and the setup helpers.
+pub const FUNCTIONS: &[&str] = &[
+    "hap_.*",
+    "app_wifi_.*",
+    "esp_hap_.*",
+    "app_hap_setup_payload",
+    // Timers for debounced characteristic updates
+    "esp_timer_create",
+    "esp_timer_start_once",
+    "esp_timer_stop",
+    "esp_timer_delete",
+    // HAP event delivery and provisioning state tracking
+    "esp_event_handler_register",
+    "esp_event_handler_unregister",
+    // Hostname override
+    "esp_netif_get_handle_from_ifkey",
+    "esp_netif_set_hostname",
+    "mdns_hostname_set",
+    // Pairing data export
+    "nvs_entry_find",
+    "nvs_entry_next",
+    "nvs_entry_info",
+    "nvs_release_iterator",
+    // Heap check before starting
+    "esp_get_free_heap_size",
+    "heap_caps_get_largest_free_block",
+    // Provisioning with a custom service name
+    "wifi_prov_mgr_is_provisioned",
+    "wifi_prov_mgr_start_provisioning",
+    "wifi_prov_mgr_deinit",
+    "esp_wifi_set_mode",
+    "esp_wifi_start",
+    "esp_wifi_get_mac",
+    // Names and setup ids unique to the device
+    "esp_efuse_mac_get_default",
+    // Task creation
+    "xTaskCreatePinnedToCore",
+    // Characteristics driving GPIOs
+    "gpio_reset_pin",
+    "gpio_set_direction",
+    "gpio_set_level",
+    // Wrappers of `static inline` functions, see `src/shim`
+    "esp_homekit_shim_.*",
+];
+
+pub const TYPES: &[&str] = &[
+    "hap_.*",
+    "esp_timer_.*",
+    "esp_event_base_t",
+    "nvs_.*",
+    "wifi_prov_cb_event_t",
+    "wifi_prov_security",
+    "wifi_mode_t",
+    "wifi_interface_t",
+    "wifi_event_t",
+    "ip_event_t",
+    "gpio_num_t",
+    "gpio_mode_t",
+];
+
+pub const VARS: &[&str] = &[
+    "HAP_.*",
+    "ESP_OK",
+    "ESP_FAIL",
+    "ESP_ERR_.*",
+    "NVS_.*",
+    "CONFIG_HAP_.*",
+    "ESP_EVENT_ANY_ID",
+    "MALLOC_CAP_8BIT",
+    "WIFI_PROV_EVENT",
+    "WIFI_EVENT",
+    "IP_EVENT",
+    "ESP_HOMEKIT_SHIM_.*",
+];
@@ -1,11 +1,92 @@
 use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+#[cfg(any(esp_homekit_hap_cfg_recv_timeout, esp_homekit_hap_cfg_send_timeout))]
+use std::time::Duration;
 
 use core::ptr;
 use log::*;
 
+use crate::accessory::{self, Accessory};
+use crate::characteristic::HapCharacteristic;
+use crate::context::HapContext;
+use crate::error::{self, HapError};
+use crate::event::{self, HapEvent};
 use crate::*;
 
 pub const HAP_SUCCESS_: i32 = 0;
+pub const HAP_FAIL_: i32 = -1;
+
+/// Where the HAP framework persists its configuration number.
+const CONFIG_NUMBER_NAMESPACE: &[u8] = b"hap_main\0";
+const CONFIG_NUMBER_KEY: &[u8] = b"config_num\0";
+
+/// The part of a [`HapContext`] kept by this module.
+pub(crate) struct State {
+    initialized: AtomicBool,
+    started: AtomicBool,
+    auto_bump: AtomicBool,
+    heap_check: AtomicBool,
+    batch: Mutex<Batch>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            initialized: AtomicBool::new(false),
+            started: AtomicBool::new(false),
+            auto_bump: AtomicBool::new(true),
+            heap_check: AtomicBool::new(true),
+            batch: Mutex::new(Batch::default()),
+        }
+    }
+}
+
+/// The nesting of [`batch`] calls and whether the database changed inside them.
+#[derive(Debug, Default)]
+struct Batch {
+    depth: usize,
+    dirty: bool,
+}
+
+impl Batch {
+    fn enter(&mut self) {
+        self.depth += 1;
+    }
+
+    /// Record a change, `true` if it needs a bump right away, i.e. outside of a batch.
+    fn record(&mut self) -> bool {
+        if self.depth > 0 {
+            self.dirty = true;
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Leave a batch, `true` if it was the outermost one and recorded a change.
+    fn exit(&mut self) -> bool {
+        self.depth -= 1;
+        self.depth == 0 && core::mem::take(&mut self.dirty)
+    }
+}
+
+/// Leaves the batch on drop, so a panicking [`batch`] closure doesn't leave
+/// the database changes of later calls unannounced.
+struct BatchGuard<'a>(&'a HapContext);
+
+impl Drop for BatchGuard<'_> {
+    fn drop(&mut self) {
+        let dirty = self.0.state().hap.batch.lock().unwrap().exit();
+        if dirty {
+            let _ = self.0.bump_config_number();
+        }
+    }
+}
+
+/// The HAP protocol version advertised when [`Config::pv`] is empty.
+pub const PROTOCOL_VERSION: &str = "1.1.0";
+const PROTOCOL_VERSION_C: &[u8] = b"1.1.0\0";
 
 pub struct Config {
     pub name: CString,
@@ -14,6 +95,8 @@ pub struct Config {
     pub serial_num: CString,
     pub fw_rev: CString,
     pub hw_rev: CString,
+    /// The HAP protocol version of the `ProtocolInformation` service, which the HAP
+    /// framework adds to every accessory. [`PROTOCOL_VERSION`] if empty.
     pub pv: CString,
     pub cid: accessory::Category,
 }
@@ -26,7 +109,11 @@ impl From<&Config> for hap_acc_cfg_t {
             serial_num: cfg.serial_num.as_ptr() as *mut i8,
             fw_rev: cfg.fw_rev.as_ptr() as *mut i8,
             hw_rev: cfg.hw_rev.as_ptr() as *mut i8,
-            pv: cfg.pv.as_ptr() as *mut i8,
+            pv: if cfg.pv.as_bytes().is_empty() {
+                PROTOCOL_VERSION_C.as_ptr() as *mut i8
+            } else {
+                cfg.pv.as_ptr() as *mut i8
+            },
             cid: hap_cid_t::from(cfg.cid),
             identify_routine: Some(identify),
         }
@@ -49,36 +136,325 @@ impl From<&Config> for hap_acc_cfg_t {
 //    }
 //}
 
-unsafe extern "C" fn identify(acc: *mut hap_acc_t) -> i32 {
-    HAP_SUCCESS_
+/// Reports identify requests as [`HapEvent::IdentifyRequested`].
+// Also the default of `hap_accessory!`.
+#[doc(hidden)]
+pub unsafe extern "C" fn identify(acc: *mut hap_acc_t) -> i32 {
+    let aid = hap_acc_get_aid(acc) as u32;
+
+    // Event handlers are application code.
+    error::catch_panic("identify", HAP_FAIL_, || {
+        event::emit(HapEvent::IdentifyRequested { aid });
+        HAP_SUCCESS_
+    })
 }
 
+/// The task the HAP framework runs its event loop in, see [`set_task_config`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TaskConfig {
+    /// In bytes, 6 KiB by default. Raise it if the HAP task overflows its stack,
+    /// e.g. with many services or write handlers that do a lot of work.
+    pub stack_size: u32,
+    /// The FreeRTOS priority, 1 by default.
+    pub priority: u8,
+    /// The core to pin the task to, `None` by default to let it run on any core.
+    ///
+    /// The framework creates its task with `xTaskCreate`, without core affinity, so
+    /// a core is rejected with [`HapError::InvalidArg`] when applying the configuration.
+    pub core: Option<u8>,
+}
+
+impl TaskConfig {
+    fn is_valid(&self) -> bool {
+        self.stack_size > 0 && self.core.is_none()
+    }
+}
+
+/// The task configuration the HAP framework uses, or will use once initialized.
+pub fn task_config() -> Result<TaskConfig, HapError> {
+    let mut cfg: hap_cfg_t = unsafe { core::mem::zeroed() };
+
+    HapError::check(unsafe { hap_get_config(&mut cfg) })?;
+
+    Ok(TaskConfig {
+        stack_size: cfg.task_stack_size,
+        priority: cfg.task_priority,
+        core: None,
+    })
+}
+
+/// [`HapContext::set_task_config`] of the global context.
+pub fn set_task_config(config: &TaskConfig) -> Result<(), HapError> {
+    HapContext::global().set_task_config(config)
+}
+
+/// The init-time tunables of the HAP framework, see [`init_with`].
+///
+/// Fields the SDK revision the bindings were generated from lacks are left out, the
+/// build script probes `hap_cfg_t` for them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InitConfig {
+    pub task: TaskConfig,
+    /// Characteristics whose event notifications can be pending at once, 1-255.
+    pub max_event_notif_chars: u8,
+    /// How long a controller session may stay silent before it is closed, in whole
+    /// seconds.
+    #[cfg(esp_homekit_hap_cfg_recv_timeout)]
+    pub recv_timeout: Duration,
+    /// How long sending to a controller may block, in whole seconds.
+    #[cfg(esp_homekit_hap_cfg_send_timeout)]
+    pub send_timeout: Duration,
+    /// The largest software authentication token accepted, in bytes.
+    #[cfg(esp_homekit_hap_cfg_sw_token_max_len)]
+    pub sw_token_max_len: usize,
+}
+
+impl InitConfig {
+    /// The configuration the HAP framework uses, or will use once initialized.
+    pub fn current() -> Result<Self, HapError> {
+        let mut cfg: hap_cfg_t = unsafe { core::mem::zeroed() };
+
+        HapError::check(unsafe { hap_get_config(&mut cfg) })?;
+
+        Ok(InitConfig {
+            task: TaskConfig {
+                stack_size: cfg.task_stack_size,
+                priority: cfg.task_priority,
+                core: None,
+            },
+            max_event_notif_chars: cfg.max_event_notif_chars,
+            #[cfg(esp_homekit_hap_cfg_recv_timeout)]
+            recv_timeout: Duration::from_secs(cfg.recv_timeout.max(0) as u64),
+            #[cfg(esp_homekit_hap_cfg_send_timeout)]
+            send_timeout: Duration::from_secs(cfg.send_timeout.max(0) as u64),
+            #[cfg(esp_homekit_hap_cfg_sw_token_max_len)]
+            sw_token_max_len: cfg.sw_token_max_len,
+        })
+    }
+}
+
+/// A timeout of `hap_cfg_t` in seconds, `None` unless in 1s to `i32::MAX` seconds.
+#[cfg(any(esp_homekit_hap_cfg_recv_timeout, esp_homekit_hap_cfg_send_timeout))]
+fn timeout_secs(timeout: Duration) -> Option<i32> {
+    i32::try_from(timeout.as_secs()).ok().filter(|&secs| secs > 0)
+}
+
+/// [`HapContext::init_with`] of the global context.
+pub fn init_with(config: &InitConfig) -> Result<(), HapError> {
+    HapContext::global().init_with(config)
+}
+
+/// [`HapContext::init`] of the global context.
 pub fn init() {
-    info!("Initializig HAP Framework");
+    HapContext::global().init()
+}
 
-    unsafe {
-        hap_init(hap_transport_t_HAP_TRANSPORT_WIFI);
+/// The free heap pair setup needs, in bytes.
+///
+/// The SRP computation of pair setup allocates its bignums in one go, so `hap_start`
+/// succeeds with less but the first pairing then fails halfway.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HeapRequirements {
+    /// The free heap in total.
+    pub free: u32,
+    /// The largest contiguous free block.
+    pub largest_block: u32,
+}
+
+/// The heap requirements of the HAP framework for the sdkconfig the crate was built with.
+///
+/// With `CONFIG_MBEDTLS_EXTERNAL_MEM_ALLOC` mbedtls allocates from PSRAM, so only
+/// the smaller buffers of the HTTP server come from the internal heap.
+pub const fn heap_requirements() -> HeapRequirements {
+    if cfg!(esp_idf_mbedtls_external_mem_alloc) {
+        HeapRequirements {
+            free: 16 * 1024,
+            largest_block: 4 * 1024,
+        }
+    } else {
+        HeapRequirements {
+            free: 40 * 1024,
+            largest_block: 32 * 1024,
+        }
     }
 }
 
+/// Whether a heap with `free` bytes in total and a `largest_block` meets `required`,
+/// [`HapError::InsufficientHeap`] with the missing numbers if not.
+fn check_heap(free: u32, largest_block: u32, required: &HeapRequirements) -> Result<(), HapError> {
+    if free < required.free {
+        Err(HapError::InsufficientHeap {
+            free,
+            required: required.free,
+        })
+    } else if largest_block < required.largest_block {
+        Err(HapError::InsufficientHeap {
+            free: largest_block,
+            required: required.largest_block,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// [`HapContext::set_heap_check`] of the global context.
+pub fn set_heap_check(enabled: bool) {
+    HapContext::global().set_heap_check(enabled)
+}
+
+/// [`HapContext::start`] of the global context.
 pub fn start() {
-    info!("Starting HAP webserver");
+    HapContext::global().start()
+}
 
-    unsafe {
-        hap_start();
+/// [`HapContext::try_start`] of the global context.
+pub fn try_start() -> Result<(), HapError> {
+    HapContext::global().try_start()
+}
+
+/// [`HapContext::suspend`] of the global context.
+pub fn suspend() -> Result<Suspended, HapError> {
+    HapContext::global().suspend()
+}
+
+/// Returned by [`suspend`] to restart the HAP framework.
+#[must_use = "the HAP framework stays stopped until `resume` is called"]
+pub struct Suspended {
+    context: HapContext,
+}
+
+impl Suspended {
+    /// Start the HAP framework again through [`try_start`], unless it was already
+    /// restarted.
+    ///
+    /// On failure the token is returned with the error, e.g. to retry once heap was
+    /// freed after [`HapError::InsufficientHeap`].
+    pub fn resume(self) -> Result<(), (Self, HapError)> {
+        if self.context.is_started() {
+            return Ok(());
+        }
+
+        self.context.try_start().map_err(|err| (self, err))
     }
 }
 
-pub fn add_service_to_accessory(accessory: *mut hap_acc_t, service: *mut hap_serv_t) {
-    unsafe {
-        hap_acc_add_serv(accessory, service);
+/// The resource limits of the HAP framework, e.g. to size per-session state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// Simultaneous controller connections, `CONFIG_HAP_HTTP_MAX_OPEN_SOCKETS`.
+    pub max_sessions: usize,
+    /// Paired controllers, `HAP_MAX_CONTROLLERS` of the framework.
+    pub max_pairings: usize,
+    /// Characteristics whose event notifications can be pending at once,
+    /// `max_event_notif_chars` of the framework configuration.
+    pub max_events_queued: usize,
+}
+
+impl Limits {
+    /// Fails with [`HapError::TooManyEventCharacteristics`] if `count` characteristics
+    /// with events exceed [`max_events_queued`](Self::max_events_queued).
+    pub(crate) fn check_events(&self, count: usize) -> Result<(), HapError> {
+        if count > self.max_events_queued {
+            Err(HapError::TooManyEventCharacteristics {
+                max: self.max_events_queued,
+            })
+        } else {
+            Ok(())
+        }
     }
 }
 
+/// The limits of the HAP framework as configured by kconfig and `hap_set_config`.
+pub fn limits() -> Result<Limits, HapError> {
+    let mut cfg: hap_cfg_t = unsafe { core::mem::zeroed() };
+
+    HapError::check(unsafe { hap_get_config(&mut cfg) })?;
+
+    Ok(Limits {
+        max_sessions: CONFIG_HAP_HTTP_MAX_OPEN_SOCKETS as usize,
+        max_pairings: ESP_HOMEKIT_SHIM_MAX_CONTROLLERS as usize,
+        max_events_queued: cfg.max_event_notif_chars as usize,
+    })
+}
+
+/// [`HapContext::is_started`] of the global context.
+pub(crate) fn is_started() -> bool {
+    HapContext::global().is_started()
+}
+
+/// The current configuration number (`c#`) advertised over mDNS.
+///
+/// Read from the HAP keystore, so it is only available once `init` has run.
+pub fn config_number() -> Result<u16, HapError> {
+    let mut value = 0u32;
+    let mut size = core::mem::size_of::<u32>();
+
+    HapError::check(unsafe {
+        hap_platform_keystore_get(
+            hap_platform_keystore_get_nvs_partition_name(),
+            CONFIG_NUMBER_NAMESPACE.as_ptr() as *const i8,
+            CONFIG_NUMBER_KEY.as_ptr() as *const i8,
+            &mut value as *mut u32 as *mut u8,
+            &mut size,
+        )
+    })?;
+
+    Ok(value as u16)
+}
+
+/// [`HapContext::bump_config_number`] of the global context.
+pub fn bump_config_number() -> Result<(), HapError> {
+    HapContext::global().bump_config_number()
+}
+
+/// [`HapContext::set_auto_bump_config_number`] of the global context.
+pub fn set_auto_bump_config_number(enabled: bool) {
+    HapContext::global().set_auto_bump_config_number(enabled)
+}
+
+/// [`HapContext::batch`] of the global context.
+pub fn batch<R>(f: impl FnOnce() -> R) -> R {
+    HapContext::global().batch(f)
+}
+
+/// [`HapContext::database_changed`] of the global context.
+pub(crate) fn database_changed() {
+    HapContext::global().database_changed()
+}
+
+/// [`HapContext::add_service_to_accessory`] of the global context.
+pub fn add_service_to_accessory(accessory: *mut hap_acc_t, service: *mut hap_serv_t) {
+    HapContext::global().add_service_to_accessory(accessory, service)
+}
+
+/// [`HapContext::add_accessory`] of the global context.
 pub fn add_accessory(accessory: *mut hap_acc_t) {
-    unsafe {
-        hap_add_accessory(accessory);
-    }
+    HapContext::global().add_accessory(accessory)
+}
+
+/// [`HapContext::add`] of the global context.
+pub fn add(accessory: &Accessory) {
+    HapContext::global().add(accessory)
+}
+
+/// [`HapContext::add_bridged`] of the global context.
+pub fn add_bridged(accessory: &Accessory, aid: Option<u32>) -> Result<u32, HapError> {
+    HapContext::global().add_bridged(accessory, aid)
+}
+
+/// [`HapContext::remove_bridged`] of the global context.
+pub fn remove_bridged(accessory: &Accessory) -> Result<(), HapError> {
+    HapContext::global().remove_bridged(accessory)
+}
+
+/// [`HapContext::accessory_by_aid`] of the global context.
+pub fn accessory_by_aid(aid: u32) -> Option<Accessory> {
+    HapContext::global().accessory_by_aid(aid)
+}
+
+/// [`HapContext::char_by_aiid`] of the global context.
+pub fn char_by_aiid(aid: u32, iid: u32) -> Option<HapCharacteristic> {
+    HapContext::global().char_by_aiid(aid, iid)
 }
 
 pub fn secret(code: CString, id: CString) {
@@ -87,3 +463,481 @@ pub fn secret(code: CString, id: CString) {
         hap_set_setup_id(id.as_ptr());
     }
 }
+
+impl HapContext {
+    /// Configure the task of the HAP framework, must be called before
+    /// [`init`](Self::init).
+    ///
+    /// Fails with [`HapError::InvalidArg`] for a zero stack size or a
+    /// [`core`](TaskConfig::core): the framework creates its task without core
+    /// affinity, so it can't be pinned.
+    pub fn set_task_config(&self, config: &TaskConfig) -> Result<(), HapError> {
+        if self.state().hap.initialized.load(Ordering::SeqCst) || !config.is_valid() {
+            return Err(HapError::InvalidArg);
+        }
+
+        let mut cfg: hap_cfg_t = unsafe { core::mem::zeroed() };
+
+        HapError::check(unsafe { hap_get_config(&mut cfg) })?;
+        cfg.task_stack_size = config.stack_size;
+        cfg.task_priority = config.priority;
+
+        HapError::check(unsafe { hap_set_config(&cfg) })
+    }
+
+    /// Apply `config`, then [`init`](Self::init) the HAP framework.
+    ///
+    /// Fails with [`HapError::InvalidArg`] if a value is out of range or the framework is
+    /// already initialized, leaving its configuration unchanged.
+    pub fn init_with(&self, config: &InitConfig) -> Result<(), HapError> {
+        if self.state().hap.initialized.load(Ordering::SeqCst)
+            || !config.task.is_valid()
+            || config.max_event_notif_chars == 0
+        {
+            return Err(HapError::InvalidArg);
+        }
+
+        let mut cfg: hap_cfg_t = unsafe { core::mem::zeroed() };
+
+        HapError::check(unsafe { hap_get_config(&mut cfg) })?;
+        cfg.task_stack_size = config.task.stack_size;
+        cfg.task_priority = config.task.priority;
+        cfg.max_event_notif_chars = config.max_event_notif_chars;
+        #[cfg(esp_homekit_hap_cfg_recv_timeout)]
+        {
+            cfg.recv_timeout = timeout_secs(config.recv_timeout).ok_or(HapError::InvalidArg)?;
+        }
+        #[cfg(esp_homekit_hap_cfg_send_timeout)]
+        {
+            cfg.send_timeout = timeout_secs(config.send_timeout).ok_or(HapError::InvalidArg)?;
+        }
+        #[cfg(esp_homekit_hap_cfg_sw_token_max_len)]
+        {
+            if config.sw_token_max_len == 0 {
+                return Err(HapError::InvalidArg);
+            }
+            cfg.sw_token_max_len = config.sw_token_max_len;
+        }
+
+        HapError::check(unsafe { hap_set_config(&cfg) })?;
+        self.init();
+
+        if self.state().hap.initialized.load(Ordering::SeqCst) {
+            Ok(())
+        } else {
+            Err(HapError::Fail(HAP_FAIL_))
+        }
+    }
+
+    pub fn init(&self) {
+        info!("Initializig HAP Framework");
+
+        if HapError::check(unsafe { hap_init(hap_transport_t_HAP_TRANSPORT_WIFI) }).is_ok() {
+            self.state().hap.initialized.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Enable or disable the heap check of [`try_start`](Self::try_start), enabled by
+    /// default.
+    ///
+    /// Disable it if mbedtls allocates from PSRAM through a custom allocator the
+    /// sdkconfig doesn't reflect.
+    pub fn set_heap_check(&self, enabled: bool) {
+        self.state().hap.heap_check.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Start the HAP framework, logging failures, see [`try_start`](Self::try_start).
+    pub fn start(&self) {
+        if let Err(err) = self.try_start() {
+            error!("Failed to start HAP: {}", err);
+        }
+    }
+
+    /// Start the HAP framework.
+    ///
+    /// Fails with [`HapError::InsufficientHeap`] instead of starting if the free heap is
+    /// below [`heap_requirements`], unless disabled with
+    /// [`set_heap_check`](Self::set_heap_check).
+    pub fn try_start(&self) -> Result<(), HapError> {
+        info!("Starting HAP webserver");
+
+        if self.state().hap.heap_check.load(Ordering::SeqCst) {
+            let (free, largest_block) =
+                unsafe { (esp_get_free_heap_size(), heap_caps_get_largest_free_block(MALLOC_CAP_8BIT) as u32) };
+
+            check_heap(free, largest_block, &heap_requirements())?;
+        }
+
+        server::track_sessions();
+
+        HapError::check(unsafe { hap_start() })?;
+        self.state().hap.started.store(true, Ordering::SeqCst);
+
+        // The framework sets up mDNS on start, so apply the override afterwards.
+        self.apply_hostname();
+        self.emit(HapEvent::Announced {
+            config_number: config_number().ok(),
+        });
+
+        Ok(())
+    }
+
+    /// Stop the HAP framework, e.g. before entering deep sleep.
+    ///
+    /// Closes the controller sessions and withdraws the mDNS records; the accessory
+    /// database and pairings are kept. `hap_stop` returns once the framework is
+    /// stopped, so there is nothing left to wait for. Suspending while already
+    /// stopped does nothing and also returns a token.
+    ///
+    /// The keystore is not flushed: the HAP framework has no API for it, and its NVS
+    /// backend commits every write as it is made. A pair-verify in progress is aborted
+    /// with its session, without a response; the controller verifies again once it
+    /// finds the accessory after [`Suspended::resume`].
+    pub fn suspend(&self) -> Result<Suspended, HapError> {
+        if self.state().hap.started.swap(false, Ordering::SeqCst) {
+            info!("Stopping HAP webserver");
+
+            if let Err(err) = HapError::check(unsafe { hap_stop() }) {
+                self.state().hap.started.store(true, Ordering::SeqCst);
+                return Err(err);
+            }
+
+            self.reset_sessions();
+        }
+
+        Ok(Suspended { context: self.clone() })
+    }
+
+    pub(crate) fn is_started(&self) -> bool {
+        self.state().hap.started.load(Ordering::SeqCst)
+    }
+
+    /// Bump the configuration number so controllers refetch the accessory database.
+    pub fn bump_config_number(&self) -> Result<(), HapError> {
+        info!("Updating HAP configuration number");
+
+        HapError::check(unsafe { hap_update_config_number() })?;
+
+        let config_number = config_number().ok();
+        self.emit(HapEvent::DatabaseChanged { config_number });
+        // The framework only announces while mDNS is up.
+        if self.is_started() {
+            self.emit(HapEvent::Announced { config_number });
+        }
+
+        Ok(())
+    }
+
+    /// Enable or disable the automatic bump on accessory/service changes after `start`.
+    ///
+    /// Enabled by default; when disabled call
+    /// [`bump_config_number`](Self::bump_config_number) yourself.
+    pub fn set_auto_bump_config_number(&self, enabled: bool) {
+        self.state().hap.auto_bump.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Run `f`, coalescing all database changes it makes into a single bump.
+    pub fn batch<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.state().hap.batch.lock().unwrap().enter();
+        let _guard = BatchGuard(self);
+
+        f()
+    }
+
+    /// Record a change to the accessory database.
+    ///
+    /// Changes before `start` are picked up by the HAP framework on its own.
+    pub(crate) fn database_changed(&self) {
+        if !self.is_started() || !self.state().hap.auto_bump.load(Ordering::SeqCst) {
+            return;
+        }
+
+        let bump = self.state().hap.batch.lock().unwrap().record();
+        if bump {
+            let _ = self.bump_config_number();
+        }
+    }
+
+    pub fn add_service_to_accessory(&self, accessory: *mut hap_acc_t, service: *mut hap_serv_t) {
+        if HapError::check(unsafe { hap_acc_add_serv(accessory, service) }).is_ok() {
+            self.database_changed();
+        }
+    }
+
+    pub fn add_accessory(&self, accessory: *mut hap_acc_t) {
+        unsafe {
+            hap_add_accessory(accessory);
+        }
+
+        self.database_changed();
+    }
+
+    /// Add the primary accessory to the database, which gets aid 1.
+    pub fn add(&self, accessory: &Accessory) {
+        self.add_accessory(accessory.as_raw());
+
+        // Adding the primary accessory twice is only logged, as before the aid checks.
+        let _ = self.try_claim_aid(accessory.aid());
+        self.register(accessory);
+    }
+
+    /// Add a bridged accessory with the given aid, or let the framework pick one.
+    ///
+    /// Returns the aid of the accessory. Aid 1 is reserved for the bridge itself, and
+    /// every aid may only be used once; both fail with [`HapError::DuplicateAid`].
+    /// Fails with [`HapError::TooManyAccessories`] without calling into the framework
+    /// once [`accessory::Bridge::MAX_ACCESSORIES`] are added.
+    pub fn add_bridged(&self, accessory: &Accessory, aid: Option<u32>) -> Result<u32, HapError> {
+        if aid == Some(accessory::PRIMARY_AID) {
+            return Err(HapError::DuplicateAid(accessory::PRIMARY_AID));
+        }
+
+        self.reserve_bridged()?;
+        if let Some(aid) = aid {
+            if let Err(err) = self.try_claim_aid(aid) {
+                self.release_bridged();
+                return Err(err);
+            }
+        }
+
+        let code = unsafe { hap_add_bridged_accessory(accessory.as_raw(), aid.unwrap_or(0) as i32) };
+        if let Err(err) = HapError::check(code) {
+            self.release_bridged();
+            if let Some(aid) = aid {
+                self.release_aid(aid);
+            }
+            return Err(err);
+        }
+
+        // The framework picks an aid that isn't in its database yet.
+        if aid.is_none() {
+            let _ = self.try_claim_aid(accessory.aid());
+        }
+        self.register(accessory);
+        self.database_changed();
+
+        Ok(accessory.aid())
+    }
+
+    /// Remove a bridged accessory from the database and the registry.
+    pub fn remove_bridged(&self, accessory: &Accessory) -> Result<(), HapError> {
+        let aid = accessory.aid();
+
+        HapError::check(unsafe { hap_remove_bridged_accessory(accessory.as_raw()) })?;
+
+        self.release_bridged();
+        self.release_aid(aid);
+        self.unregister(aid);
+        self.database_changed();
+
+        Ok(())
+    }
+
+    /// Find an accessory added through [`add`](Self::add) or
+    /// [`add_bridged`](Self::add_bridged) by its aid.
+    pub fn accessory_by_aid(&self, aid: u32) -> Option<Accessory> {
+        self.lookup(aid)
+    }
+
+    /// Find a characteristic by its aid/iid pair, as carried by HAP events.
+    pub fn char_by_aiid(&self, aid: u32, iid: u32) -> Option<HapCharacteristic> {
+        self.accessory_by_aid(aid)?.char_by_iid(iid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::service::HapService;
+
+    const REQUIRED: HeapRequirements = HeapRequirements {
+        free: 40 * 1024,
+        largest_block: 32 * 1024,
+    };
+
+    #[test]
+    fn enough_heap_passes() {
+        assert_eq!(check_heap(40 * 1024, 32 * 1024, &REQUIRED), Ok(()));
+        assert_eq!(check_heap(200 * 1024, 100 * 1024, &REQUIRED), Ok(()));
+    }
+
+    #[test]
+    fn too_little_free_heap_fails_with_the_total() {
+        assert_eq!(
+            check_heap(20 * 1024, 100 * 1024, &REQUIRED),
+            Err(HapError::InsufficientHeap {
+                free: 20 * 1024,
+                required: 40 * 1024,
+            })
+        );
+    }
+
+    #[test]
+    fn fragmented_heap_fails_with_the_largest_block() {
+        assert_eq!(
+            check_heap(100 * 1024, 8 * 1024, &REQUIRED),
+            Err(HapError::InsufficientHeap {
+                free: 8 * 1024,
+                required: 32 * 1024,
+            })
+        );
+    }
+
+    #[test]
+    fn events_up_to_the_queue_limit_are_accepted() {
+        let limits = Limits {
+            max_sessions: 8,
+            max_pairings: ESP_HOMEKIT_SHIM_MAX_CONTROLLERS as usize,
+            max_events_queued: 2,
+        };
+
+        assert_eq!(limits.check_events(2), Ok(()));
+        assert_eq!(
+            limits.check_events(3),
+            Err(HapError::TooManyEventCharacteristics { max: 2 })
+        );
+    }
+
+    #[test]
+    fn changes_outside_a_batch_bump_at_once() {
+        let mut batch = Batch { depth: 0, dirty: false };
+
+        assert!(batch.record());
+        assert!(!batch.dirty);
+    }
+
+    #[test]
+    fn nested_batches_bump_once_when_the_outermost_exits() {
+        let mut batch = Batch { depth: 0, dirty: false };
+
+        batch.enter();
+        assert!(!batch.record());
+        batch.enter();
+        assert!(!batch.record());
+        assert!(!batch.exit());
+        assert!(!batch.record());
+        assert!(batch.exit());
+
+        batch.enter();
+        assert!(!batch.exit());
+    }
+
+    #[test]
+    fn the_task_config_is_applied_to_the_hap_task() {
+        let context = HapContext::new_isolated();
+        let config = TaskConfig {
+            stack_size: 12 * 1024 + 4,
+            priority: 7,
+            core: None,
+        };
+
+        context.set_task_config(&config).unwrap();
+        assert_eq!(task_config(), Ok(config));
+
+        context.init();
+        context.try_start().unwrap();
+
+        let task = crate::mock::TaskInfo {
+            name: "hap_main".to_owned(),
+            stack_size: config.stack_size,
+            priority: 7,
+            core: None,
+        };
+        assert!(crate::mock::tasks().contains(&task));
+    }
+
+    #[test]
+    fn pinning_the_task_is_rejected() {
+        let context = HapContext::new_isolated();
+        let before = task_config().unwrap();
+        let pinned = TaskConfig {
+            core: Some(1),
+            ..before
+        };
+
+        assert_eq!(context.set_task_config(&pinned), Err(HapError::InvalidArg));
+
+        let mut config = InitConfig::current().unwrap();
+        config.task = pinned;
+        assert_eq!(context.init_with(&config), Err(HapError::InvalidArg));
+        assert_eq!(task_config(), Ok(before));
+    }
+
+    /// The events `context` emits from now on.
+    fn record_events(context: &HapContext) -> Arc<Mutex<Vec<HapEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = events.clone();
+        context.subscribe(move |event| recorded.lock().unwrap().push(event.clone()));
+
+        events
+    }
+
+    fn started_accessory(context: &HapContext) -> Accessory {
+        let accessory = Accessory::new(&crate::mock::accessory_config("Bridge")).unwrap();
+        context.add(&accessory);
+        context.init();
+        context.try_start().unwrap();
+
+        accessory
+    }
+
+    fn add_switch(accessory: &Accessory) {
+        let switch = HapService::from_raw(unsafe { hap_serv_switch_create(false) });
+        accessory.add_service(&switch).unwrap();
+    }
+
+    #[test]
+    fn a_batch_bumps_the_config_number_once() {
+        let context = HapContext::new_isolated();
+        let accessory = started_accessory(&context);
+        let events = record_events(&context);
+        let before = config_number().unwrap();
+
+        context.batch(|| {
+            for _ in 0..3 {
+                add_switch(&accessory);
+            }
+        });
+
+        assert_eq!(config_number(), Ok(before + 1));
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                HapEvent::DatabaseChanged {
+                    config_number: Some(before + 1)
+                },
+                HapEvent::Announced {
+                    config_number: Some(before + 1)
+                },
+            ]
+        );
+
+        add_switch(&accessory);
+        add_switch(&accessory);
+        assert_eq!(config_number(), Ok(before + 3));
+    }
+
+    #[test]
+    fn only_started_accessories_are_announced() {
+        let context = HapContext::new_isolated();
+        let events = record_events(&context);
+
+        context.bump_config_number().unwrap();
+        assert_eq!(crate::mock::announcements(), 0);
+
+        started_accessory(&context);
+        context.bump_config_number().unwrap();
+        assert_eq!(crate::mock::announcements(), 2);
+
+        let announced = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|event| matches!(event, HapEvent::Announced { .. }))
+            .count();
+        assert_eq!(announced, 2);
+    }
+}
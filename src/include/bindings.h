@@ -17,7 +17,9 @@
 #include "hkdf-sha.h"
 #include "iot_button.h"
 
+#ifndef ESP_HOMEKIT_NO_APP_WIFI
 #include "app_wifi.h"
+#endif
 #include "app_hap_setup_payload.h"
 #include "qrcode.h"
 
@@ -25,3 +27,16 @@
 #include "freertos/FreeRTOSConfig.h"
 #include "freertos/task.h"
 #include "freertos/queue.h"
+
+#include "esp_timer.h"
+#include "esp_system.h"
+#include "esp_heap_caps.h"
+#include "esp_event.h"
+#include "esp_wifi.h"
+#include "wifi_provisioning/manager.h"
+#include "nvs.h"
+#include "esp_netif.h"
+#include "mdns.h"
+#include "driver/gpio.h"
+
+#include "../shim/esp_homekit_shim.h"
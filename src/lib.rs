@@ -1,8 +1,15 @@
+// `$ESP_IDF_SYS_DRY_RUN` builds nothing to bind, leaving the crate empty.
+#![cfg(not(esp_homekit_dry_run))]
 #![cfg_attr(not(feature = "std"), no_std)]
 #![allow(non_upper_case_globals)]
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 
+/// The C types the bindings are generated against, for `extern` declarations of
+/// downstream C code calling into the SDK.
+///
+/// These are the aliases of `std::os::raw` and stay so, so declarations written
+/// against them keep matching [`raw`].
 #[cfg(feature = "std")]
 #[allow(non_upper_case_globals)]
 #[allow(non_camel_case_types)]
@@ -21,13 +28,72 @@ pub mod c_types {
     pub type c_ulonglong = std::os::raw::c_ulonglong;
 }
 
-include!(env!("EMBUILD_GENERATED_BINDINGS_FILE"));
+/// The SDK bindings exactly as generated by bindgen, also re-exported at the crate root.
+pub mod raw {
+    use crate::c_types;
 
+    include!(env!("EMBUILD_GENERATED_BINDINGS_FILE"));
+}
+
+pub use raw::*;
+
+// A module named after each header of `$ESP_HOMEKIT_BINDINGS_HEADERS`, e.g. `sensors`
+// for `include/sensors.h`, with the bindings of that header alone.
+include!(env!("ESP_HOMEKIT_EXTRA_BINDINGS_FILE"));
+
+/// The bindgen release that generated [`raw`], e.g. `0.60.1`. Also exposed to the
+/// build scripts of dependents as `DEP_ESP_HOMEKIT_SDK_BINDGEN_VERSION`.
+pub const BINDGEN_VERSION: &str = env!("ESP_HOMEKIT_BINDGEN_VERSION");
+
+/// The MCU [`raw`] was generated for, e.g. `esp32c3`. Also exposed to the build
+/// scripts of dependents as `DEP_ESP_HOMEKIT_SDK_MCU`.
+pub const BINDINGS_GENERATED_FOR_MCU: &str = env!("ESP_HOMEKIT_BINDINGS_MCU");
+
+mod macros;
 
 pub mod accessory;
 
 pub mod hap;
 
+pub mod context;
+
 pub mod task;
 
+pub mod timer;
+
+pub mod deferred;
+
 pub mod service;
+
+pub mod characteristic;
+
+pub mod error;
+
+pub mod services;
+
+pub mod server;
+
+pub mod event;
+
+pub mod diag;
+
+pub mod provisioning;
+
+pub mod platform;
+
+pub mod config;
+
+pub mod prelude;
+
+#[cfg(feature = "insecure-debug")]
+pub mod debug;
+
+#[cfg(feature = "bct")]
+pub mod bct;
+
+#[cfg(feature = "mock")]
+pub mod mock;
+
+// Shared with `build.rs`, only compiled here for its unit tests.
+#[cfg(test)]
+mod build_support;
@@ -0,0 +1,154 @@
+/// Create and add an accessory from a static description, without heap allocations.
+///
+/// Strings are NUL-terminated at compile time and write callbacks are plain
+/// `hap_serv_write_t` functions, so nothing is boxed or copied into a `CString`;
+/// besides the ones the HAP framework makes itself, the only allocation is the
+/// [`Accessory`](crate::accessory::Accessory) handle registered by [`hap::add`](crate::hap::add),
+/// once at init. [`diag::HeapBaseline`](crate::diag::HeapBaseline) checks on the
+/// device that nothing is allocated after that. Services are
+/// created with the SDK constructor expressions given in `create`, which are
+/// evaluated in an `unsafe` block. Characteristics get their default units as with
+/// [`HapService::add_char`](crate::service::HapService::add_char).
+///
+/// The accessory is added as the primary one like with [`hap::add`](crate::hap::add).
+/// Evaluates to `Result<Accessory, HapError>`:
+///
+/// ```ignore
+/// let lamp = hap_accessory! {
+///     name: "Lamp",
+///     model: "Lamp01",
+///     manufacturer: "Espressif",
+///     serial_num: "001122334455",
+///     fw_rev: "1.0.0",
+///     hw_rev: "1.0",
+///     pv: "1.1.0",
+///     cid: Category::LIGHTING,
+///     services: [
+///         {
+///             create: hap_serv_lightbulb_create(true),
+///             name: "Lamp",
+///             chars: [hap_char_brightness_create(50)],
+///             write: lamp_write,
+///         },
+///         {
+///             create: hap_serv_battery_service_create(100, 0, 0),
+///         },
+///     ],
+/// }?;
+/// ```
+///
+/// `identify` optionally takes an `unsafe extern "C" fn(*mut hap_acc_t) -> c_int`,
+/// by default the identify request is acknowledged without doing anything.
+#[macro_export]
+macro_rules! hap_accessory {
+    (
+        name: $name:literal,
+        model: $model:literal,
+        manufacturer: $manufacturer:literal,
+        serial_num: $serial_num:literal,
+        fw_rev: $fw_rev:literal,
+        hw_rev: $hw_rev:literal,
+        pv: $pv:literal,
+        cid: $cid:expr,
+        $(identify: $identify:expr,)?
+        services: [
+            $({
+                create: $create:expr
+                $(, name: $serv_name:literal)?
+                $(, chars: [$($char:expr),* $(,)?])?
+                $(, write: $write:expr)?
+                $(,)?
+            }),* $(,)?
+        ] $(,)?
+    ) => {{
+        #[allow(unused_variables)]
+        let identify: $crate::hap_identify_routine_t = Some($crate::hap::identify);
+        $(let identify: $crate::hap_identify_routine_t = Some($identify);)?
+
+        let mut cfg = $crate::hap_acc_cfg_t {
+            name: concat!($name, "\0").as_ptr() as *mut _,
+            model: concat!($model, "\0").as_ptr() as *mut _,
+            manufacturer: concat!($manufacturer, "\0").as_ptr() as *mut _,
+            serial_num: concat!($serial_num, "\0").as_ptr() as *mut _,
+            fw_rev: concat!($fw_rev, "\0").as_ptr() as *mut _,
+            hw_rev: concat!($hw_rev, "\0").as_ptr() as *mut _,
+            pv: concat!($pv, "\0").as_ptr() as *mut _,
+            cid: $crate::hap_cid_t::from($cid),
+            identify_routine: identify,
+        };
+
+        // `create` and `chars` are documented to be evaluated in an `unsafe` block.
+        #[allow(clippy::macro_metavars_in_unsafe)]
+        let accessory = (|| -> ::core::result::Result<$crate::accessory::Accessory, $crate::error::HapError> {
+            let accessory = unsafe { $crate::hap_acc_create(&mut cfg) };
+            if accessory.is_null() {
+                return Err($crate::error::HapError::Fail($crate::hap::HAP_FAIL_));
+            }
+
+            $(
+                let service = $crate::service::HapService::from_raw(unsafe { $create });
+                $(
+                    service.add_char($crate::characteristic::HapCharacteristic::from_raw(unsafe {
+                        $crate::hap_char_name_create(concat!($serv_name, "\0").as_ptr() as *mut _)
+                    }))?;
+                )?
+                $($(
+                    service.add_char($crate::characteristic::HapCharacteristic::from_raw(unsafe { $char }))?;
+                )*)?
+                $(
+                    unsafe { $crate::hap_serv_set_write_cb(service.as_raw(), Some($write)) };
+                )?
+                $crate::error::HapError::check(unsafe {
+                    $crate::hap_acc_add_serv(accessory, service.as_raw())
+                })?;
+            )*
+
+            let accessory = $crate::accessory::Accessory::from_raw(accessory);
+            $crate::hap::add(&accessory);
+
+            Ok(accessory)
+        })();
+
+        accessory
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::HapContext;
+    use crate::accessory::{self, Category};
+    use crate::*;
+
+    #[test]
+    fn static_accessories_are_added_like_dynamic_ones() {
+        let context = HapContext::new_isolated();
+
+        let lamp = hap_accessory! {
+            name: "Lamp",
+            model: "Lamp01",
+            manufacturer: "Espressif",
+            serial_num: "001122334455",
+            fw_rev: "1.0.0",
+            hw_rev: "1.0",
+            pv: "1.1.0",
+            cid: Category::LIGHTING,
+            services: [
+                {
+                    create: hap_serv_lightbulb_create(true),
+                    name: "Lamp",
+                    chars: [hap_char_brightness_create(50)],
+                },
+                {
+                    create: hap_serv_battery_service_create(100, 0, 0),
+                },
+            ],
+        }
+        .unwrap();
+
+        assert_eq!(lamp.aid(), accessory::PRIMARY_AID);
+        assert_eq!(hap::accessory_by_aid(lamp.aid()).map(|found| found.as_raw()), Some(lamp.as_raw()));
+        assert!(context.try_claim_aid(lamp.aid()).is_err());
+        // The two information services, the lightbulb and the battery.
+        assert_eq!(lamp.services().len(), 4);
+    }
+}
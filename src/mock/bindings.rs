@@ -0,0 +1,562 @@
+// Declarations of the SDK, ESP-IDF and shim API the wrappers use, in the shape
+// bindgen generates them from the real headers. `build.rs` includes this file
+// in place of the generated bindings when the crate is built for the host, and
+// `crate::mock` provides the functions. Add to it when the wrappers start
+// using something new, copying the declaration from the generated bindings.
+pub type hap_acc_t = *mut c_types::c_void;
+pub type hap_serv_t = *mut c_types::c_void;
+pub type hap_char_t = *mut c_types::c_void;
+pub type hap_cid_t = c_types::c_uint;
+pub const hap_cid_t_HAP_CID_NONE: hap_cid_t = 0;
+pub const hap_cid_t_HAP_CID_OTHER: hap_cid_t = 1;
+pub const hap_cid_t_HAP_CID_BRIDGE: hap_cid_t = 2;
+pub const hap_cid_t_HAP_CID_FAN: hap_cid_t = 3;
+pub const hap_cid_t_HAP_CID_GARAGE_DOOR_OPENER: hap_cid_t = 4;
+pub const hap_cid_t_HAP_CID_LIGHTING: hap_cid_t = 5;
+pub const hap_cid_t_HAP_CID_LOCK: hap_cid_t = 6;
+pub const hap_cid_t_HAP_CID_OUTLET: hap_cid_t = 7;
+pub const hap_cid_t_HAP_CID_SWITCH: hap_cid_t = 8;
+pub const hap_cid_t_HAP_CID_THERMOSTAT: hap_cid_t = 9;
+pub const hap_cid_t_HAP_CID_SENSOR: hap_cid_t = 10;
+pub const hap_cid_t_HAP_CID_SECURITY_SYSTEM: hap_cid_t = 11;
+pub const hap_cid_t_HAP_CID_DOOR: hap_cid_t = 12;
+pub const hap_cid_t_HAP_CID_WINDOW: hap_cid_t = 13;
+pub const hap_cid_t_HAP_CID_WINDOW_COVERING: hap_cid_t = 14;
+pub const hap_cid_t_HAP_CID_PROGRAMMABLE_SWITCH: hap_cid_t = 15;
+pub const hap_cid_t_HAP_CID_RESERVED: hap_cid_t = 16;
+pub const hap_cid_t_HAP_CID_IP_CAMERA: hap_cid_t = 17;
+pub const hap_cid_t_HAP_CID_VIDEO_DOORBELL: hap_cid_t = 18;
+pub const hap_cid_t_HAP_CID_AIR_PURIFIER: hap_cid_t = 19;
+pub const hap_cid_t_HAP_CID_HEATER: hap_cid_t = 20;
+pub const hap_cid_t_HAP_CID_AIR_CONDITIONER: hap_cid_t = 21;
+pub const hap_cid_t_HAP_CID_HUMIDIFIER: hap_cid_t = 22;
+pub const hap_cid_t_HAP_CID_DEHUMIDIFIER: hap_cid_t = 23;
+pub const hap_cid_t_HAP_CID_MAX: hap_cid_t = 24;
+pub type hap_transport_t = c_types::c_uint;
+pub const hap_transport_t_HAP_TRANSPORT_UNKNOWN: hap_transport_t = 0;
+pub const hap_transport_t_HAP_TRANSPORT_WIFI: hap_transport_t = 1;
+pub const hap_transport_t_HAP_TRANSPORT_ETHERNET: hap_transport_t = 2;
+pub type hap_identify_routine_t = Option<unsafe extern "C" fn(acc: *mut hap_acc_t) -> c_types::c_int>;
+#[repr(C)]
+pub struct hap_acc_cfg_t {
+    pub name: *mut c_types::c_char,
+    pub model: *mut c_types::c_char,
+    pub manufacturer: *mut c_types::c_char,
+    pub serial_num: *mut c_types::c_char,
+    pub fw_rev: *mut c_types::c_char,
+    pub hw_rev: *mut c_types::c_char,
+    pub pv: *mut c_types::c_char,
+    pub cid: hap_cid_t,
+    pub identify_routine: hap_identify_routine_t,
+}
+pub type hap_status_t = c_types::c_int;
+pub const hap_status_t_HAP_STATUS_SUCCESS: hap_status_t = 0;
+pub const hap_status_t_HAP_STATUS_INSUFFICIENT_PRIVILEGES: hap_status_t = -70401;
+pub const hap_status_t_HAP_STATUS_COMM_ERR: hap_status_t = -70402;
+pub const hap_status_t_HAP_STATUS_RES_BUSY: hap_status_t = -70403;
+pub const hap_status_t_HAP_STATUS_WR_ON_RDONLY_CHAR: hap_status_t = -70404;
+pub const hap_status_t_HAP_STATUS_RD_ON_WRONLY_CHAR: hap_status_t = -70405;
+pub const hap_status_t_HAP_STATUS_NO_NOTIF: hap_status_t = -70406;
+pub const hap_status_t_HAP_STATUS_OO_RES: hap_status_t = -70407;
+pub const hap_status_t_HAP_STATUS_TIMEOUT: hap_status_t = -70408;
+pub const hap_status_t_HAP_STATUS_RES_ABSENT: hap_status_t = -70409;
+pub const hap_status_t_HAP_STATUS_VAL_INVALID: hap_status_t = -70410;
+pub const hap_status_t_HAP_STATUS_INSUFFICIENT_AUTH: hap_status_t = -70411;
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct hap_data_val_t { pub buf: *mut u8, pub buflen: u32 }
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct hap_tlv8_val_t { pub buf: *mut u8, pub buflen: u32 }
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union hap_val_t {
+    pub b: bool,
+    pub u: u32,
+    pub i: c_types::c_int,
+    pub i64: u64,
+    pub f: f32,
+    pub s: *mut c_types::c_char,
+    pub d: hap_data_val_t,
+    pub t: hap_tlv8_val_t,
+}
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct hap_auth_data_t { pub data: *mut u8, pub len: c_types::c_int }
+#[repr(C)]
+pub struct hap_write_data_t {
+    pub hc: *mut hap_char_t,
+    pub val: hap_val_t,
+    pub auth_data: hap_auth_data_t,
+    pub remote: bool,
+    pub status: *mut hap_status_t,
+}
+pub type hap_serv_write_t = Option<unsafe extern "C" fn(write_data: *mut hap_write_data_t, count: c_types::c_int, serv_priv: *mut c_types::c_void, write_priv: *mut c_types::c_void) -> c_types::c_int>;
+pub type hap_serv_read_t = Option<unsafe extern "C" fn(hc: *mut hap_char_t, status_code: *mut hap_status_t, serv_priv: *mut c_types::c_void, read_priv: *mut c_types::c_void) -> c_types::c_int>;
+pub const HAP_CHAR_UUID_OUTLET_IN_USE: &[u8; 3] = b"26\0";
+pub type TaskFunction_t = Option<unsafe extern "C" fn(arg1: *mut c_types::c_void)>;
+pub type BaseType_t = c_types::c_int;
+pub type UBaseType_t = c_types::c_uint;
+pub type TaskHandle_t = *mut c_types::c_void;
+extern "C" {
+    pub fn hap_init(method: hap_transport_t) -> c_types::c_int;
+    pub fn hap_start() -> c_types::c_int;
+    pub fn hap_stop() -> c_types::c_int;
+    pub fn hap_acc_create(acc_cfg: *mut hap_acc_cfg_t) -> *mut hap_acc_t;
+    pub fn hap_acc_add_serv(ha: *mut hap_acc_t, hs: *mut hap_serv_t) -> c_types::c_int;
+    pub fn hap_add_accessory(ha: *mut hap_acc_t);
+    pub fn hap_set_setup_code(setup_code: *const c_types::c_char) -> c_types::c_int;
+    pub fn hap_set_setup_id(setup_id: *const c_types::c_char) -> c_types::c_int;
+    pub fn hap_serv_outlet_create(on: bool, outlet_in_use: bool) -> *mut hap_serv_t;
+    pub fn hap_serv_lightbulb_create(on: bool) -> *mut hap_serv_t;
+    pub fn hap_serv_switch_create(on: bool) -> *mut hap_serv_t;
+    pub fn hap_char_brightness_create(brightness: c_types::c_int) -> *mut hap_char_t;
+    pub fn hap_serv_add_char(hs: *mut hap_serv_t, hc: *mut hap_char_t) -> c_types::c_int;
+    pub fn hap_char_name_create(name: *mut c_types::c_char) -> *mut hap_char_t;
+    pub fn hap_serv_get_char_by_uuid(hs: *mut hap_serv_t, type_uuid: *const c_types::c_char) -> *mut hap_char_t;
+    pub fn hap_serv_set_write_cb(hs: *mut hap_serv_t, write: hap_serv_write_t);
+    pub fn xTaskCreatePinnedToCore(pvTaskCode: TaskFunction_t, pcName: *const c_types::c_char, usStackDepth: u32, pvParameters: *mut c_types::c_void, uxPriority: UBaseType_t, pvCreatedTask: *mut TaskHandle_t, xCoreID: BaseType_t) -> BaseType_t;
+}
+
+pub type esp_err_t = c_types::c_int;
+pub const ESP_OK: u32 = 0;
+pub const ESP_ERR_NO_MEM: u32 = 0x101;
+pub const HAP_SUCCESS: u32 = 0;
+pub const HAP_FAIL: i32 = -1;
+pub type esp_timer_handle_t = *mut c_types::c_void;
+pub type esp_timer_cb_t = Option<unsafe extern "C" fn(arg: *mut c_types::c_void)>;
+pub type esp_timer_dispatch_t = c_types::c_uint;
+pub const esp_timer_dispatch_t_ESP_TIMER_TASK: esp_timer_dispatch_t = 0;
+#[repr(C)]
+pub struct esp_timer_create_args_t {
+    pub callback: esp_timer_cb_t,
+    pub arg: *mut c_types::c_void,
+    pub dispatch_method: esp_timer_dispatch_t,
+    pub name: *const c_types::c_char,
+    pub skip_unhandled_events: bool,
+}
+extern "C" {
+    pub fn hap_char_update_val(hc: *mut hap_char_t, val: *mut hap_val_t) -> c_types::c_int;
+    pub fn hap_char_get_val(hc: *mut hap_char_t) -> *const hap_val_t;
+    pub fn hap_char_set_priv(hc: *mut hap_char_t, priv_: *mut c_types::c_void);
+    pub fn hap_char_get_priv(hc: *mut hap_char_t) -> *mut c_types::c_void;
+    pub fn esp_timer_create(create_args: *const esp_timer_create_args_t, out_handle: *mut esp_timer_handle_t) -> esp_err_t;
+    pub fn esp_timer_start_once(timer: esp_timer_handle_t, timeout_us: u64) -> esp_err_t;
+    pub fn esp_timer_stop(timer: esp_timer_handle_t) -> esp_err_t;
+    pub fn esp_timer_delete(timer: esp_timer_handle_t) -> esp_err_t;
+}
+pub const HAP_CHAR_UUID_ACTIVE: &[u8; 3] = b"B0\0";
+pub const HAP_CHAR_UUID_IN_USE: &[u8; 3] = b"D2\0";
+pub const HAP_CHAR_UUID_PROGRAM_MODE: &[u8; 3] = b"D1\0";
+pub const HAP_CHAR_UUID_SET_DURATION: &[u8; 3] = b"D3\0";
+pub const HAP_CHAR_UUID_REMAINING_DURATION: &[u8; 3] = b"D4\0";
+pub const HAP_CHAR_UUID_VALVE_TYPE: &[u8; 3] = b"D5\0";
+pub const HAP_CHAR_UUID_SERVICE_LABEL_INDEX: &[u8; 3] = b"CB\0";
+extern "C" {
+    pub fn hap_serv_link_serv(hs: *mut hap_serv_t, linked_serv: *mut hap_serv_t) -> c_types::c_int;
+    pub fn hap_serv_set_priv(hs: *mut hap_serv_t, priv_: *mut c_types::c_void);
+    pub fn hap_serv_get_priv(hs: *mut hap_serv_t) -> *mut c_types::c_void;
+    pub fn hap_char_get_type_uuid(hc: *mut hap_char_t) -> *const c_types::c_char;
+    pub fn hap_serv_valve_create(active: u8, in_use: u8, valve_type: u8) -> *mut hap_serv_t;
+    pub fn hap_serv_faucet_create(active: u8) -> *mut hap_serv_t;
+    pub fn hap_serv_irrigation_system_create(active: u8, prog_mode: u8, in_use: u8) -> *mut hap_serv_t;
+    pub fn hap_char_service_label_index_create(service_label_index: u8) -> *mut hap_char_t;
+    pub fn hap_char_set_duration_create(set_duration: u32) -> *mut hap_char_t;
+    pub fn hap_char_remaining_duration_create(remaining_duration: u32) -> *mut hap_char_t;
+}
+pub const HAP_CHAR_UUID_MUTE: &[u8; 4] = b"11A\0";
+pub const HAP_CHAR_UUID_VOLUME: &[u8; 4] = b"119\0";
+pub const HAP_CHAR_UUID_PROGRAMMABLE_SWITCH_EVENT: &[u8; 3] = b"73\0";
+extern "C" {
+    pub fn hap_serv_doorbell_create(programmable_switch_event: u8) -> *mut hap_serv_t;
+    pub fn hap_serv_speaker_create(mute: bool) -> *mut hap_serv_t;
+    pub fn hap_serv_microphone_create(mute: bool) -> *mut hap_serv_t;
+    pub fn hap_char_volume_create(volume: u8) -> *mut hap_char_t;
+}
+pub const HAP_CHAR_UUID_AIR_QUALITY: &[u8; 3] = b"95\0";
+pub const HAP_CHAR_UUID_CARBON_DIOXIDE_DETECTED: &[u8; 3] = b"92\0";
+extern "C" {
+    pub fn hap_serv_air_quality_sensor_create(air_quality: u8) -> *mut hap_serv_t;
+    pub fn hap_serv_carbon_dioxide_sensor_create(carbon_dioxide_detected: u8) -> *mut hap_serv_t;
+    pub fn hap_char_pm_2_5_density_create(pm_2_5_density: f32) -> *mut hap_char_t;
+    pub fn hap_char_pm_10_density_create(pm_10_density: f32) -> *mut hap_char_t;
+    pub fn hap_char_voc_density_create(voc_density: f32) -> *mut hap_char_t;
+    pub fn hap_char_nitrogen_dioxide_density_create(nitrogen_dioxide_density: f32) -> *mut hap_char_t;
+    pub fn hap_char_sulphur_dioxide_density_create(sulphur_dioxide_density: f32) -> *mut hap_char_t;
+    pub fn hap_char_ozone_density_create(ozone_density: f32) -> *mut hap_char_t;
+    pub fn hap_char_carbon_dioxide_level_create(carbon_dioxide_level: f32) -> *mut hap_char_t;
+    pub fn hap_char_carbon_dioxide_peak_level_create(carbon_dioxide_peak_level: f32) -> *mut hap_char_t;
+    pub fn hap_char_float_set_constraints(hc: *mut hap_char_t, minval: f32, maxval: f32, step: f32) -> c_types::c_int;
+}
+extern "C" {
+    pub fn hap_serv_service_label_create(service_label_namespace: u8) -> *mut hap_serv_t;
+    pub fn hap_serv_stateless_programmable_switch_create(programmable_switch_event: u8) -> *mut hap_serv_t;
+}
+extern "C" {
+    pub fn hap_update_config_number() -> c_types::c_int;
+    pub fn hap_platform_keystore_get_nvs_partition_name() -> *const c_types::c_char;
+    pub fn hap_platform_keystore_get(part_name: *const c_types::c_char, name_space: *const c_types::c_char, key: *const c_types::c_char, val: *mut u8, val_size: *mut usize) -> c_types::c_int;
+    pub fn hap_platform_keystore_set(part_name: *const c_types::c_char, name_space: *const c_types::c_char, key: *const c_types::c_char, val: *const u8, val_len: usize) -> c_types::c_int;
+}
+pub const HAP_CHAR_UUID_ACCESSORY_FLAGS: &[u8; 3] = b"A6\0";
+pub const HAP_CHAR_UUID_ADMINISTRATOR_ONLY_ACCESS: &[u8; 2] = b"1\0";
+pub const HAP_CHAR_UUID_AUDIO_FEEDBACK: &[u8; 2] = b"5\0";
+pub const HAP_CHAR_UUID_BATTERY_LEVEL: &[u8; 3] = b"68\0";
+pub const HAP_CHAR_UUID_BRIGHTNESS: &[u8; 2] = b"8\0";
+pub const HAP_CHAR_UUID_CARBON_DIOXIDE_LEVEL: &[u8; 3] = b"93\0";
+pub const HAP_CHAR_UUID_CARBON_DIOXIDE_PEAK_LEVEL: &[u8; 3] = b"94\0";
+pub const HAP_CHAR_UUID_CARBON_MONOXIDE_DETECTED: &[u8; 3] = b"69\0";
+pub const HAP_CHAR_UUID_CARBON_MONOXIDE_LEVEL: &[u8; 3] = b"90\0";
+pub const HAP_CHAR_UUID_CARBON_MONOXIDE_PEAK_LEVEL: &[u8; 3] = b"91\0";
+pub const HAP_CHAR_UUID_CHARGING_STATE: &[u8; 3] = b"8F\0";
+pub const HAP_CHAR_UUID_COLOR_TEMPERATURE: &[u8; 3] = b"CE\0";
+pub const HAP_CHAR_UUID_CONTACT_SENSOR_STATE: &[u8; 3] = b"6A\0";
+pub const HAP_CHAR_UUID_COOLING_THRESHOLD_TEMPERATURE: &[u8; 2] = b"D\0";
+pub const HAP_CHAR_UUID_CURRENT_AIR_PURIFIER_STATE: &[u8; 3] = b"A9\0";
+pub const HAP_CHAR_UUID_CURRENT_AMBIENT_LIGHT_LEVEL: &[u8; 3] = b"6B\0";
+pub const HAP_CHAR_UUID_CURRENT_DOOR_STATE: &[u8; 2] = b"E\0";
+pub const HAP_CHAR_UUID_CURRENT_FAN_STATE: &[u8; 3] = b"AF\0";
+pub const HAP_CHAR_UUID_CURRENT_HEATER_COOLER_STATE: &[u8; 3] = b"B1\0";
+pub const HAP_CHAR_UUID_CURRENT_HEATING_COOLING_STATE: &[u8; 2] = b"F\0";
+pub const HAP_CHAR_UUID_CURRENT_HORIZONTAL_TILT_ANGLE: &[u8; 3] = b"6C\0";
+pub const HAP_CHAR_UUID_CURRENT_HUMIDIFIER_DEHUMIDIFIER_STATE: &[u8; 3] = b"B3\0";
+pub const HAP_CHAR_UUID_CURRENT_POSITION: &[u8; 3] = b"6D\0";
+pub const HAP_CHAR_UUID_CURRENT_RELATIVE_HUMIDITY: &[u8; 3] = b"10\0";
+pub const HAP_CHAR_UUID_CURRENT_SLAT_STATE: &[u8; 3] = b"AA\0";
+pub const HAP_CHAR_UUID_CURRENT_TEMPERATURE: &[u8; 3] = b"11\0";
+pub const HAP_CHAR_UUID_CURRENT_TILT_ANGLE: &[u8; 3] = b"C1\0";
+pub const HAP_CHAR_UUID_CURRENT_VERTICAL_TILT_ANGLE: &[u8; 3] = b"6E\0";
+pub const HAP_CHAR_UUID_FILTER_CHANGE_INDICATION: &[u8; 3] = b"AC\0";
+pub const HAP_CHAR_UUID_FILTER_LIFE_LEVEL: &[u8; 3] = b"AB\0";
+pub const HAP_CHAR_UUID_FIRMWARE_REVISION: &[u8; 3] = b"52\0";
+pub const HAP_CHAR_UUID_HARDWARE_REVISION: &[u8; 3] = b"53\0";
+pub const HAP_CHAR_UUID_HEATING_THRESHOLD_TEMPERATURE: &[u8; 3] = b"12\0";
+pub const HAP_CHAR_UUID_HOLD_POSITION: &[u8; 3] = b"6F\0";
+pub const HAP_CHAR_UUID_HUE: &[u8; 3] = b"13\0";
+pub const HAP_CHAR_UUID_IDENTIFY: &[u8; 3] = b"14\0";
+pub const HAP_CHAR_UUID_IS_CONFIGURED: &[u8; 3] = b"D6\0";
+pub const HAP_CHAR_UUID_LEAK_DETECTED: &[u8; 3] = b"70\0";
+pub const HAP_CHAR_UUID_LOCK_CONTROL_POINT: &[u8; 3] = b"19\0";
+pub const HAP_CHAR_UUID_LOCK_CURRENT_STATE: &[u8; 3] = b"1D\0";
+pub const HAP_CHAR_UUID_LOCK_LAST_KNOWN_ACTION: &[u8; 3] = b"1C\0";
+pub const HAP_CHAR_UUID_LOCK_MANAGEMENT_AUTO_SECURITY_TIMEOUT: &[u8; 3] = b"1A\0";
+pub const HAP_CHAR_UUID_LOCK_PHYSICAL_CONTROLS: &[u8; 3] = b"A7\0";
+pub const HAP_CHAR_UUID_LOCK_TARGET_STATE: &[u8; 3] = b"1E\0";
+pub const HAP_CHAR_UUID_LOGS: &[u8; 3] = b"1F\0";
+pub const HAP_CHAR_UUID_MANUFACTURER: &[u8; 3] = b"20\0";
+pub const HAP_CHAR_UUID_MODEL: &[u8; 3] = b"21\0";
+pub const HAP_CHAR_UUID_MOTION_DETECTED: &[u8; 3] = b"22\0";
+pub const HAP_CHAR_UUID_NAME: &[u8; 3] = b"23\0";
+pub const HAP_CHAR_UUID_NITROGEN_DIOXIDE_DENSITY: &[u8; 3] = b"C4\0";
+pub const HAP_CHAR_UUID_OBSTRUCTION_DETECTED: &[u8; 3] = b"24\0";
+pub const HAP_CHAR_UUID_OCCUPANCY_DETECTED: &[u8; 3] = b"71\0";
+pub const HAP_CHAR_UUID_ON: &[u8; 3] = b"25\0";
+pub const HAP_CHAR_UUID_OZONE_DENSITY: &[u8; 3] = b"C3\0";
+pub const HAP_CHAR_UUID_PM_10_DENSITY: &[u8; 3] = b"C7\0";
+pub const HAP_CHAR_UUID_PM_2_5_DENSITY: &[u8; 3] = b"C6\0";
+pub const HAP_CHAR_UUID_POSITION_STATE: &[u8; 3] = b"72\0";
+pub const HAP_CHAR_UUID_PRODUCT_DATA: &[u8; 4] = b"220\0";
+pub const HAP_CHAR_UUID_RELATIVE_HUMIDITY_DEHUMIDIFIER_THRESHOLD: &[u8; 3] = b"C9\0";
+pub const HAP_CHAR_UUID_RELATIVE_HUMIDITY_HUMIDIFIER_THRESHOLD: &[u8; 3] = b"CA\0";
+pub const HAP_CHAR_UUID_RESET_FILTER_INDICATION: &[u8; 3] = b"AD\0";
+pub const HAP_CHAR_UUID_ROTATION_DIRECTION: &[u8; 3] = b"28\0";
+pub const HAP_CHAR_UUID_ROTATION_SPEED: &[u8; 3] = b"29\0";
+pub const HAP_CHAR_UUID_SATURATION: &[u8; 3] = b"2F\0";
+pub const HAP_CHAR_UUID_SECURITY_SYSTEM_ALARM_TYPE: &[u8; 3] = b"8E\0";
+pub const HAP_CHAR_UUID_SECURITY_SYSTEM_CURRENT_STATE: &[u8; 3] = b"66\0";
+pub const HAP_CHAR_UUID_SECURITY_SYSTEM_TARGET_STATE: &[u8; 3] = b"67\0";
+pub const HAP_CHAR_UUID_SERIAL_NUMBER: &[u8; 3] = b"30\0";
+pub const HAP_CHAR_UUID_SERVICE_LABEL_NAMESPACE: &[u8; 3] = b"CD\0";
+pub const HAP_CHAR_UUID_SLAT_TYPE: &[u8; 3] = b"C0\0";
+pub const HAP_CHAR_UUID_SMOKE_DETECTED: &[u8; 3] = b"76\0";
+pub const HAP_CHAR_UUID_STATUS_ACTIVE: &[u8; 3] = b"75\0";
+pub const HAP_CHAR_UUID_STATUS_FAULT: &[u8; 3] = b"77\0";
+pub const HAP_CHAR_UUID_STATUS_LOW_BATTERY: &[u8; 3] = b"79\0";
+pub const HAP_CHAR_UUID_STATUS_TAMPERED: &[u8; 3] = b"7A\0";
+pub const HAP_CHAR_UUID_SULPHUR_DIOXIDE_DENSITY: &[u8; 3] = b"C5\0";
+pub const HAP_CHAR_UUID_SWING_MODE: &[u8; 3] = b"B6\0";
+pub const HAP_CHAR_UUID_TARGET_AIR_PURIFIER_STATE: &[u8; 3] = b"A8\0";
+pub const HAP_CHAR_UUID_TARGET_DOOR_STATE: &[u8; 3] = b"32\0";
+pub const HAP_CHAR_UUID_TARGET_FAN_STATE: &[u8; 3] = b"BF\0";
+pub const HAP_CHAR_UUID_TARGET_HEATER_COOLER_STATE: &[u8; 3] = b"B2\0";
+pub const HAP_CHAR_UUID_TARGET_HEATING_COOLING_STATE: &[u8; 3] = b"33\0";
+pub const HAP_CHAR_UUID_TARGET_HORIZONTAL_TILT_ANGLE: &[u8; 3] = b"7B\0";
+pub const HAP_CHAR_UUID_TARGET_HUMIDIFIER_DEHUMIDIFIER_STATE: &[u8; 3] = b"B4\0";
+pub const HAP_CHAR_UUID_TARGET_POSITION: &[u8; 3] = b"7C\0";
+pub const HAP_CHAR_UUID_TARGET_RELATIVE_HUMIDITY: &[u8; 3] = b"34\0";
+pub const HAP_CHAR_UUID_TARGET_TEMPERATURE: &[u8; 3] = b"35\0";
+pub const HAP_CHAR_UUID_TARGET_TILT_ANGLE: &[u8; 3] = b"C2\0";
+pub const HAP_CHAR_UUID_TARGET_VERTICAL_TILT_ANGLE: &[u8; 3] = b"7D\0";
+pub const HAP_CHAR_UUID_TEMPERATURE_DISPLAY_UNITS: &[u8; 3] = b"36\0";
+pub const HAP_CHAR_UUID_VERSION: &[u8; 3] = b"37\0";
+pub const HAP_CHAR_UUID_VOC_DENSITY: &[u8; 3] = b"C8\0";
+pub const HAP_CHAR_UUID_WATER_LEVEL: &[u8; 3] = b"B5\0";
+pub const HAP_SERV_UUID_ACCESSORY_INFORMATION: &[u8; 3] = b"3E\0";
+pub const HAP_SERV_UUID_PROTOCOL_INFORMATION: &[u8; 3] = b"A2\0";
+pub const HAP_SERV_UUID_FAN: &[u8; 3] = b"40\0";
+pub const HAP_SERV_UUID_GARAGE_DOOR_OPENER: &[u8; 3] = b"41\0";
+pub const HAP_SERV_UUID_LIGHTBULB: &[u8; 3] = b"43\0";
+pub const HAP_SERV_UUID_LOCK_MANAGEMENT: &[u8; 3] = b"44\0";
+pub const HAP_SERV_UUID_LOCK_MECHANISM: &[u8; 3] = b"45\0";
+pub const HAP_SERV_UUID_OUTLET: &[u8; 3] = b"47\0";
+pub const HAP_SERV_UUID_SWITCH: &[u8; 3] = b"49\0";
+pub const HAP_SERV_UUID_THERMOSTAT: &[u8; 3] = b"4A\0";
+pub const HAP_SERV_UUID_AIR_QUALITY_SENSOR: &[u8; 3] = b"8D\0";
+pub const HAP_SERV_UUID_SECURITY_SYSTEM: &[u8; 3] = b"7E\0";
+pub const HAP_SERV_UUID_CARBON_MONOXIDE_SENSOR: &[u8; 3] = b"7F\0";
+pub const HAP_SERV_UUID_CONTACT_SENSOR: &[u8; 3] = b"80\0";
+pub const HAP_SERV_UUID_DOOR: &[u8; 3] = b"81\0";
+pub const HAP_SERV_UUID_HUMIDITY_SENSOR: &[u8; 3] = b"82\0";
+pub const HAP_SERV_UUID_LEAK_SENSOR: &[u8; 3] = b"83\0";
+pub const HAP_SERV_UUID_LIGHT_SENSOR: &[u8; 3] = b"84\0";
+pub const HAP_SERV_UUID_MOTION_SENSOR: &[u8; 3] = b"85\0";
+pub const HAP_SERV_UUID_OCCUPANCY_SENSOR: &[u8; 3] = b"86\0";
+pub const HAP_SERV_UUID_SMOKE_SENSOR: &[u8; 3] = b"87\0";
+pub const HAP_SERV_UUID_STATELESS_PROGRAMMABLE_SWITCH: &[u8; 3] = b"89\0";
+pub const HAP_SERV_UUID_TEMPERATURE_SENSOR: &[u8; 3] = b"8A\0";
+pub const HAP_SERV_UUID_WINDOW: &[u8; 3] = b"8B\0";
+pub const HAP_SERV_UUID_WINDOW_COVERING: &[u8; 3] = b"8C\0";
+pub const HAP_SERV_UUID_BATTERY_SERVICE: &[u8; 3] = b"96\0";
+pub const HAP_SERV_UUID_CARBON_DIOXIDE_SENSOR: &[u8; 3] = b"97\0";
+pub const HAP_SERV_UUID_FAN_V2: &[u8; 3] = b"B7\0";
+pub const HAP_SERV_UUID_SLAT: &[u8; 3] = b"B9\0";
+pub const HAP_SERV_UUID_FILTER_MAINTENANCE: &[u8; 3] = b"BA\0";
+pub const HAP_SERV_UUID_AIR_PURIFIER: &[u8; 3] = b"BB\0";
+pub const HAP_SERV_UUID_HEATER_COOLER: &[u8; 3] = b"BC\0";
+pub const HAP_SERV_UUID_HUMIDIFIER_DEHUMIDIFIER: &[u8; 3] = b"BD\0";
+pub const HAP_SERV_UUID_SERVICE_LABEL: &[u8; 3] = b"CC\0";
+pub const HAP_SERV_UUID_IRRIGATION_SYSTEM: &[u8; 3] = b"CF\0";
+pub const HAP_SERV_UUID_VALVE: &[u8; 3] = b"D0\0";
+pub const HAP_SERV_UUID_FAUCET: &[u8; 3] = b"D7\0";
+pub const HAP_SERV_UUID_DOORBELL: &[u8; 4] = b"121\0";
+pub const HAP_SERV_UUID_MICROPHONE: &[u8; 4] = b"112\0";
+pub const HAP_SERV_UUID_SPEAKER: &[u8; 4] = b"113\0";
+extern "C" {
+    pub fn hap_acc_get_aid(ha: *mut hap_acc_t) -> c_types::c_int;
+    pub fn hap_acc_get_char_by_iid(ha: *mut hap_acc_t, iid: c_types::c_int) -> *mut hap_char_t;
+    pub fn hap_add_bridged_accessory(ha: *mut hap_acc_t, aid: c_types::c_int) -> c_types::c_int;
+    pub fn hap_remove_bridged_accessory(ha: *mut hap_acc_t) -> c_types::c_int;
+}
+pub type esp_netif_t = c_types::c_void;
+extern "C" {
+    pub fn esp_netif_get_handle_from_ifkey(if_key: *const c_types::c_char) -> *mut esp_netif_t;
+    pub fn esp_netif_set_hostname(esp_netif: *mut esp_netif_t, hostname: *const c_types::c_char) -> esp_err_t;
+    pub fn mdns_hostname_set(hostname: *const c_types::c_char) -> esp_err_t;
+}
+extern "C" {
+    pub fn hap_http_debug_enable();
+    pub fn hap_http_debug_disable();
+}
+pub const HAP_CHAR_UNIT_CELSIUS: &[u8; 8] = b"celsius\0";
+pub const HAP_CHAR_UNIT_PERCENTAGE: &[u8; 11] = b"percentage\0";
+pub const HAP_CHAR_UNIT_ARCDEGREES: &[u8; 11] = b"arcdegrees\0";
+pub const HAP_CHAR_UNIT_LUX: &[u8; 4] = b"lux\0";
+pub const HAP_CHAR_UNIT_SECONDS: &[u8; 8] = b"seconds\0";
+extern "C" {
+    pub fn hap_char_add_unit(hc: *mut hap_char_t, unit: *const c_types::c_char) -> c_types::c_int;
+}
+extern "C" {
+    pub fn hap_serv_battery_service_create(battery_level: u8, charging_state: u8, status_low_battery: u8) -> *mut hap_serv_t;
+    pub fn hap_char_int_set_constraints(hc: *mut hap_char_t, min: c_types::c_int, max: c_types::c_int, step: c_types::c_int) -> c_types::c_int;
+}
+extern "C" {
+    pub fn hap_bct_change_name(name: *const c_types::c_char);
+    pub fn hap_bct_hot_plug();
+}
+extern "C" {
+    pub fn hap_acc_add_product_data(ha: *mut hap_acc_t, product_data: *mut u8, data_size: usize) -> c_types::c_int;
+}
+extern "C" {
+    pub fn esp_homekit_shim_remove_controller(id: *const c_types::c_char) -> c_types::c_int;
+}
+pub type nvs_type_t = c_types::c_uint;
+pub const nvs_type_t_NVS_TYPE_BLOB: nvs_type_t = 66;
+#[repr(C)]
+pub struct nvs_opaque_iterator_t { _p: [u8; 0] }
+pub type nvs_iterator_t = *mut nvs_opaque_iterator_t;
+#[repr(C)]
+pub struct nvs_entry_info_t {
+    pub namespace_name: [c_types::c_char; 16usize],
+    pub key: [c_types::c_char; 16usize],
+    pub type_: nvs_type_t,
+}
+extern "C" {
+    pub fn nvs_entry_find(part_name: *const c_types::c_char, namespace_name: *const c_types::c_char, type_: nvs_type_t) -> nvs_iterator_t;
+    pub fn nvs_entry_next(iterator: nvs_iterator_t) -> nvs_iterator_t;
+    pub fn nvs_entry_info(iterator: nvs_iterator_t, out_info: *mut nvs_entry_info_t);
+    pub fn nvs_release_iterator(iterator: nvs_iterator_t);
+}
+extern "C" {
+    pub fn hap_char_add_valid_vals(hc: *mut hap_char_t, valid_vals: *const u8, valid_val_cnt: usize) -> c_types::c_int;
+    pub fn hap_char_add_valid_vals_range(hc: *mut hap_char_t, start_val: u8, end_val: u8) -> c_types::c_int;
+    pub fn hap_serv_thermostat_create(curr_heating_cooling_state: u8, targ_heating_cooling_state: u8, curr_temp: f32, targ_temp: f32, temp_disp_units: u8) -> *mut hap_serv_t;
+}
+extern "C" {
+    pub fn hap_serv_mark_hidden(hs: *mut hap_serv_t) -> c_types::c_int;
+    pub fn hap_serv_get_type_uuid(hs: *mut hap_serv_t) -> *const c_types::c_char;
+}
+pub const CONFIG_HAP_HTTP_MAX_OPEN_SOCKETS: u32 = 12;
+#[repr(C)]
+pub struct hap_cfg_t {
+    pub task_stack_size: u32,
+    pub task_priority: u8,
+    pub max_event_notif_chars: u8,
+    pub unique_param: c_types::c_int,
+    pub recv_timeout: c_types::c_int,
+    pub send_timeout: c_types::c_int,
+    pub sw_token_max_len: usize,
+}
+extern "C" {
+    pub fn hap_get_config(cfg: *mut hap_cfg_t) -> c_types::c_int;
+}
+extern "C" {
+    pub fn hap_char_heating_threshold_temperature_create(heating_threshold_temp: f32) -> *mut hap_char_t;
+    pub fn hap_char_cooling_threshold_temperature_create(cooling_threshold_temp: f32) -> *mut hap_char_t;
+}
+pub const HAP_CHAR_PERM_PR: u32 = 1;
+pub const HAP_CHAR_PERM_PW: u32 = 2;
+pub const HAP_CHAR_PERM_EV: u32 = 4;
+extern "C" {
+    pub fn hap_char_string_create(type_uuid: *mut c_types::c_char, perms: u16, val: *mut c_types::c_char) -> *mut hap_char_t;
+    pub fn hap_char_add_description(hc: *mut hap_char_t, description: *const c_types::c_char) -> c_types::c_int;
+}
+extern "C" {
+    pub fn hap_platform_httpd_get_port() -> c_types::c_int;
+}
+extern "C" {
+    pub fn hap_acc_get_first_serv(ha: *mut hap_acc_t) -> *mut hap_serv_t;
+    pub fn hap_serv_get_next(hs: *mut hap_serv_t) -> *mut hap_serv_t;
+}
+pub type hap_char_format_t = c_types::c_uint;
+pub const hap_char_format_t_HAP_CHAR_FORMAT_BOOL: hap_char_format_t = 0;
+pub const hap_char_format_t_HAP_CHAR_FORMAT_UINT8: hap_char_format_t = 1;
+pub const hap_char_format_t_HAP_CHAR_FORMAT_UINT16: hap_char_format_t = 2;
+pub const hap_char_format_t_HAP_CHAR_FORMAT_UINT32: hap_char_format_t = 3;
+pub const hap_char_format_t_HAP_CHAR_FORMAT_UINT64: hap_char_format_t = 4;
+pub const hap_char_format_t_HAP_CHAR_FORMAT_INT: hap_char_format_t = 5;
+pub const hap_char_format_t_HAP_CHAR_FORMAT_FLOAT: hap_char_format_t = 6;
+pub const hap_char_format_t_HAP_CHAR_FORMAT_STRING: hap_char_format_t = 7;
+pub const hap_char_format_t_HAP_CHAR_FORMAT_TLV8: hap_char_format_t = 8;
+pub const hap_char_format_t_HAP_CHAR_FORMAT_DATA: hap_char_format_t = 9;
+pub const HAP_CHAR_PERM_AA: u32 = 8;
+pub const HAP_CHAR_PERM_TW: u32 = 16;
+pub const HAP_CHAR_PERM_HD: u32 = 32;
+pub const HAP_CHAR_PERM_WR: u32 = 64;
+extern "C" {
+    pub fn hap_get_first_acc() -> *mut hap_acc_t;
+    pub fn hap_acc_get_next(ha: *mut hap_acc_t) -> *mut hap_acc_t;
+    pub fn hap_serv_get_first_char(hs: *mut hap_serv_t) -> *mut hap_char_t;
+    pub fn hap_char_get_next(hc: *mut hap_char_t) -> *mut hap_char_t;
+    pub fn hap_serv_get_iid(hs: *mut hap_serv_t) -> u32;
+    pub fn hap_char_get_iid(hc: *mut hap_char_t) -> u32;
+    pub fn hap_char_get_perm(hc: *mut hap_char_t) -> u16;
+    pub fn hap_char_get_format(hc: *mut hap_char_t) -> hap_char_format_t;
+}
+extern "C" {
+    pub fn hap_serv_door_create(curr_pos: u8, targ_pos: u8, pos_state: u8) -> *mut hap_serv_t;
+    pub fn hap_serv_window_create(curr_pos: u8, targ_pos: u8, pos_state: u8) -> *mut hap_serv_t;
+    pub fn hap_serv_slat_create(curr_slat_state: u8, slat_type: u8) -> *mut hap_serv_t;
+    pub fn hap_char_obstruction_detect_create(obs: bool) -> *mut hap_char_t;
+    pub fn hap_char_hold_position_create(hold: bool) -> *mut hap_char_t;
+    pub fn hap_char_current_tilt_angle_create(angle: c_types::c_int) -> *mut hap_char_t;
+    pub fn hap_char_target_tilt_angle_create(angle: c_types::c_int) -> *mut hap_char_t;
+    pub fn hap_char_swing_mode_create(mode: u8) -> *mut hap_char_t;
+}
+extern "C" {
+    pub fn hap_acc_get_serv_by_uuid(ha: *mut hap_acc_t, type_uuid: *const c_types::c_char) -> *mut hap_serv_t;
+}
+extern "C" {
+    pub fn hap_set_config(cfg: *const hap_cfg_t) -> c_types::c_int;
+}
+pub type esp_event_base_t = *const c_types::c_char;
+pub type esp_event_handler_t = Option<unsafe extern "C" fn(event_handler_arg: *mut c_types::c_void, event_base: esp_event_base_t, event_id: i32, event_data: *mut c_types::c_void)>;
+pub const ESP_EVENT_ANY_ID: i32 = -1;
+pub type wifi_prov_cb_event_t = c_types::c_uint;
+pub const wifi_prov_cb_event_t_WIFI_PROV_START: wifi_prov_cb_event_t = 1;
+pub const wifi_prov_cb_event_t_WIFI_PROV_CRED_RECV: wifi_prov_cb_event_t = 2;
+pub const wifi_prov_cb_event_t_WIFI_PROV_CRED_FAIL: wifi_prov_cb_event_t = 3;
+pub type wifi_event_t = c_types::c_uint;
+pub const wifi_event_t_WIFI_EVENT_STA_START: wifi_event_t = 2;
+pub const wifi_event_t_WIFI_EVENT_STA_DISCONNECTED: wifi_event_t = 5;
+pub type ip_event_t = c_types::c_uint;
+pub const ip_event_t_IP_EVENT_STA_GOT_IP: ip_event_t = 0;
+extern "C" {
+    pub static WIFI_PROV_EVENT: esp_event_base_t;
+    pub static WIFI_EVENT: esp_event_base_t;
+    pub static IP_EVENT: esp_event_base_t;
+    pub fn esp_event_handler_register(event_base: esp_event_base_t, event_id: i32, event_handler: esp_event_handler_t, event_handler_arg: *mut c_types::c_void) -> esp_err_t;
+}
+extern "C" {
+    pub fn hap_char_bool_create(type_uuid: *mut c_types::c_char, perms: u16, val: bool) -> *mut hap_char_t;
+    pub fn hap_char_uint8_create(type_uuid: *mut c_types::c_char, perms: u16, val: u8) -> *mut hap_char_t;
+    pub fn hap_char_uint16_create(type_uuid: *mut c_types::c_char, perms: u16, val: u16) -> *mut hap_char_t;
+    pub fn hap_char_uint32_create(type_uuid: *mut c_types::c_char, perms: u16, val: u32) -> *mut hap_char_t;
+    pub fn hap_char_uint64_create(type_uuid: *mut c_types::c_char, perms: u16, val: u64) -> *mut hap_char_t;
+    pub fn hap_char_int_create(type_uuid: *mut c_types::c_char, perms: u16, val: c_types::c_int) -> *mut hap_char_t;
+    pub fn hap_char_float_create(type_uuid: *mut c_types::c_char, perms: u16, val: f32) -> *mut hap_char_t;
+    pub fn hap_char_data_create(type_uuid: *mut c_types::c_char, perms: u16, val: *mut hap_data_val_t) -> *mut hap_char_t;
+    pub fn hap_char_tlv8_create(type_uuid: *mut c_types::c_char, perms: u16, val: *mut hap_tlv8_val_t) -> *mut hap_char_t;
+}
+extern "C" {
+    pub fn hap_serv_mark_primary(hs: *mut hap_serv_t) -> c_types::c_int;
+}
+pub type hap_event_t = u32;
+pub const hap_event_t_HAP_EVENT_CTRL_CONNECTED: hap_event_t = 5;
+pub const hap_event_t_HAP_EVENT_CTRL_DISCONNECTED: hap_event_t = 6;
+extern "C" { pub static HAP_EVENT: esp_event_base_t; }
+extern "C" {
+    pub fn hap_platform_keystore_get_factory_nvs_partition_name() -> *const c_types::c_char;
+    pub fn hap_platform_keystore_delete(part_name: *const c_types::c_char, name_space: *const c_types::c_char, key: *const c_types::c_char) -> c_types::c_int;
+    pub fn hap_platform_keystore_delete_namespace(part_name: *const c_types::c_char, name_space: *const c_types::c_char) -> c_types::c_int;
+}
+extern "C" {
+    pub fn hap_serv_set_read_cb(hs: *mut hap_serv_t, read: hap_serv_read_t);
+    pub fn esp_homekit_shim_xTaskCreate(task: TaskFunction_t, name: *const c_types::c_char, stack_depth: u32, params: *mut c_types::c_void, priority: UBaseType_t, created_task: *mut TaskHandle_t) -> BaseType_t;
+}
+pub const MALLOC_CAP_8BIT: u32 = 4;
+extern "C" {
+    pub fn esp_get_free_heap_size() -> u32;
+    pub fn heap_caps_get_largest_free_block(caps: u32) -> usize;
+}
+pub type wifi_mode_t = c_types::c_uint;
+pub const wifi_mode_t_WIFI_MODE_STA: wifi_mode_t = 1;
+pub type wifi_interface_t = c_types::c_uint;
+pub const wifi_interface_t_WIFI_IF_STA: wifi_interface_t = 0;
+pub type wifi_prov_security = c_types::c_uint;
+pub use self::wifi_prov_security as wifi_prov_security_t;
+pub const wifi_prov_security_WIFI_PROV_SECURITY_1: wifi_prov_security = 1;
+extern "C" {
+    pub fn app_wifi_init() -> esp_err_t;
+    pub fn app_wifi_start(ticks_to_wait: u32) -> esp_err_t;
+    pub fn wifi_prov_mgr_is_provisioned(provisioned: *mut bool) -> esp_err_t;
+    pub fn wifi_prov_mgr_start_provisioning(security: wifi_prov_security_t, pop: *const c_types::c_void, service_name: *const c_types::c_char, service_key: *const c_types::c_char) -> esp_err_t;
+    pub fn wifi_prov_mgr_deinit();
+    pub fn esp_wifi_set_mode(mode: wifi_mode_t) -> esp_err_t;
+    pub fn esp_wifi_start() -> esp_err_t;
+    pub fn esp_wifi_get_mac(ifx: wifi_interface_t, mac: *mut u8) -> esp_err_t;
+}
+extern "C" {
+    pub fn esp_efuse_mac_get_default(mac: *mut u8) -> esp_err_t;
+}
+extern "C" {
+    pub fn esp_homekit_shim_set_advertising(advertise: bool) -> c_types::c_int;
+}
+extern "C" {
+    pub fn hap_serv_garage_door_opener_create(curr_door_state: u8, targ_door_state: u8, obstr_detect: bool) -> *mut hap_serv_t;
+}
+pub const hap_event_t_HAP_EVENT_SET_CHAR_COMPLETED: hap_event_t = 11;
+extern "C" {
+    pub fn hap_char_get_parent(hc: *mut hap_char_t) -> *mut hap_serv_t;
+    pub fn hap_serv_get_parent(hs: *mut hap_serv_t) -> *mut hap_acc_t;
+    pub fn esp_homekit_shim_char_subscribers(hc: *mut hap_char_t) -> c_types::c_int;
+}
+pub type gpio_num_t = i32;
+pub type gpio_mode_t = u32;
+pub const gpio_mode_t_GPIO_MODE_OUTPUT: gpio_mode_t = 2;
+extern "C" {
+    pub fn gpio_reset_pin(gpio_num: gpio_num_t) -> esp_err_t;
+    pub fn gpio_set_direction(gpio_num: gpio_num_t, mode: gpio_mode_t) -> esp_err_t;
+    pub fn gpio_set_level(gpio_num: gpio_num_t, level: u32) -> esp_err_t;
+}
+pub const ESP_HOMEKIT_SHIM_MAX_CONTROLLERS: u32 = 16;
+pub const ESP_ERR_NVS_NOT_FOUND: u32 = 4354;
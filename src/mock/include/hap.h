@@ -0,0 +1,45 @@
+/*
+ * The part of the esp-homekit-sdk `hap.h` implemented by `src/mock`, for the C code of
+ * dependents built against the `mock` feature. Keep in sync with `bindings.rs`.
+ */
+
+#ifndef _HAP_H_
+#define _HAP_H_
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#define HAP_SUCCESS 0
+#define HAP_FAIL -1
+
+typedef void *hap_acc_t;
+typedef void *hap_serv_t;
+typedef void *hap_char_t;
+
+typedef struct {
+    uint8_t *buf;
+    uint32_t buflen;
+} hap_data_val_t;
+
+typedef struct {
+    uint8_t *buf;
+    uint32_t buflen;
+} hap_tlv8_val_t;
+
+typedef union {
+    bool b;
+    uint32_t u;
+    int i;
+    uint64_t i64;
+    float f;
+    char *s;
+    hap_data_val_t d;
+    hap_tlv8_val_t t;
+} hap_val_t;
+
+int hap_char_update_val(hap_char_t *hc, hap_val_t *val);
+const hap_val_t *hap_char_get_val(hap_char_t *hc);
+const char *hap_char_get_type_uuid(hap_char_t *hc);
+uint16_t hap_char_get_perm(hap_char_t *hc);
+
+#endif /* _HAP_H_ */
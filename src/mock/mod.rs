@@ -0,0 +1,1899 @@
+//! An in-memory stand-in for the esp-homekit-sdk and the parts of ESP-IDF the wrappers
+//! use, built with the `mock` feature so the crate's tests run on the host.
+//!
+//! `build.rs` then compiles the crate against `src/mock/bindings.rs` instead of the
+//! generated bindings, and the `#[no_mangle]` functions here take the place of the C
+//! libraries. They model what the wrappers rely on: the accessory database with its
+//! instance ids, characteristic values, the write and read callbacks of services, the
+//! keystore, `esp_timer`, GPIOs and task creation. The HTTP server, pairing and mDNS
+//! are left out, their effects are recorded for the helpers below to inspect instead.
+//!
+//! Like the SDK the database is a single one, but per thread: every test sees a fresh
+//! device, see [`HapContext::new_isolated`](crate::context::HapContext::new_isolated)
+//! for the state of the wrappers. Timers fire only when a test calls [`fire_timers`],
+//! and tasks run as threads.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+
+use core::{ptr, slice};
+
+use crate::characteristic::{CharValue, HapCharacteristic};
+use crate::error::HapStatus;
+use crate::*;
+
+const ESP_ERR_INVALID_STATE: esp_err_t = 0x103;
+const ESP_FAIL: esp_err_t = -1;
+
+/// `pdPASS` of FreeRTOS.
+const PD_PASS: BaseType_t = 1;
+
+/// The base MAC address of every mock device.
+pub const MAC: [u8; 6] = [0x24, 0x0A, 0xC4, 0xA1, 0xB2, 0xC3];
+
+const PARTITION: &[u8] = b"nvs\0";
+const FACTORY_PARTITION: &[u8] = b"factory_nvs\0";
+
+struct MockAcc {
+    aid: u32,
+    next_iid: u32,
+    servs: Vec<*mut hap_serv_t>,
+    identify: hap_identify_routine_t,
+    product_data: Vec<u8>,
+}
+
+struct MockServ {
+    uuid: CString,
+    iid: u32,
+    parent: *mut hap_acc_t,
+    chars: Vec<*mut hap_char_t>,
+    linked: Vec<*mut hap_serv_t>,
+    priv_: *mut c_types::c_void,
+    write: hap_serv_write_t,
+    read: hap_serv_read_t,
+    hidden: bool,
+    primary: bool,
+}
+
+struct MockChar {
+    uuid: CString,
+    iid: u32,
+    parent: *mut hap_serv_t,
+    format: hap_char_format_t,
+    perms: u16,
+    val: hap_val_t,
+    /// The storage of string, data and TLV8 values, which `val` points into.
+    text: Option<CString>,
+    bytes: Vec<u8>,
+    priv_: *mut c_types::c_void,
+    unit: Option<CString>,
+    description: Option<CString>,
+    notifications: usize,
+    subscribers: usize,
+}
+
+impl MockChar {
+    unsafe fn store(&mut self, val: &hap_val_t) {
+        match self.format {
+            hap_char_format_t_HAP_CHAR_FORMAT_STRING => {
+                self.text = (!val.s.is_null()).then(|| CStr::from_ptr(val.s).to_owned());
+                self.val.s = self.text.as_ref().map_or(ptr::null_mut(), |text| text.as_ptr() as *mut _);
+            }
+            hap_char_format_t_HAP_CHAR_FORMAT_DATA | hap_char_format_t_HAP_CHAR_FORMAT_TLV8 => {
+                self.bytes = if val.d.buf.is_null() {
+                    Vec::new()
+                } else {
+                    slice::from_raw_parts(val.d.buf, val.d.buflen as usize).to_vec()
+                };
+                self.val.d = hap_data_val_t {
+                    buf: self.bytes.as_mut_ptr(),
+                    buflen: self.bytes.len() as u32,
+                };
+            }
+            _ => self.val = *val,
+        }
+    }
+}
+
+fn acc<'a>(ha: *mut hap_acc_t) -> &'a mut MockAcc {
+    unsafe { &mut *(ha as *mut MockAcc) }
+}
+
+fn serv<'a>(hs: *mut hap_serv_t) -> &'a mut MockServ {
+    unsafe { &mut *(hs as *mut MockServ) }
+}
+
+fn chr<'a>(hc: *mut hap_char_t) -> &'a mut MockChar {
+    unsafe { &mut *(hc as *mut MockChar) }
+}
+
+/// The partition, namespace and key of a keystore entry.
+type KeystoreKey = (Vec<u8>, Vec<u8>, Vec<u8>);
+
+/// The state of the device of the current thread.
+struct Device {
+    initialized: bool,
+    started: bool,
+    config: [u32; 7],
+    accessories: Vec<*mut hap_acc_t>,
+    keystore: BTreeMap<KeystoreKey, Vec<u8>>,
+    failures: Vec<(&'static str, i32)>,
+    gpio: BTreeMap<gpio_num_t, u32>,
+    heap: (u32, usize),
+    controllers: Vec<String>,
+    announcements: usize,
+    advertising: bool,
+    hostname: Option<String>,
+    setup: (Option<String>, Option<String>),
+    provisioned: bool,
+}
+
+impl Default for Device {
+    fn default() -> Self {
+        Device {
+            initialized: false,
+            started: false,
+            // The defaults of `HAP_DEFAULT_CFG`, in the order of `hap_cfg_t`.
+            config: [6144, 1, 8, 0, 10, 10, 1200],
+            accessories: Vec::new(),
+            keystore: BTreeMap::new(),
+            failures: Vec::new(),
+            gpio: BTreeMap::new(),
+            heap: (200 * 1024, 100 * 1024),
+            controllers: Vec::new(),
+            announcements: 0,
+            advertising: false,
+            hostname: None,
+            setup: (None, None),
+            provisioned: false,
+        }
+    }
+}
+
+thread_local! {
+    static DEVICE: RefCell<Device> = RefCell::new(Device::default());
+}
+
+fn device<R>(f: impl FnOnce(&mut Device) -> R) -> R {
+    DEVICE.with(|device| f(&mut device.borrow_mut()))
+}
+
+/// The code a call to `function` was told to fail with by [`fail_next`], if any.
+fn injected_failure(function: &str) -> Option<i32> {
+    device(|device| {
+        let index = device.failures.iter().position(|(f, _)| *f == function)?;
+        Some(device.failures.remove(index).1)
+    })
+}
+
+struct MockTimer {
+    callback: esp_timer_cb_t,
+    arg: usize,
+    thread: ThreadId,
+    armed: bool,
+}
+
+/// By handle, global since the wrappers may stop and delete timers from any task.
+static TIMERS: Mutex<BTreeMap<usize, MockTimer>> = Mutex::new(BTreeMap::new());
+static NEXT_TIMER: Mutex<usize> = Mutex::new(1);
+
+/// A task created through `xTaskCreate` or `xTaskCreatePinnedToCore`, see [`tasks`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskInfo {
+    pub name: String,
+    pub stack_size: u32,
+    pub priority: u32,
+    /// `None` without core affinity.
+    pub core: Option<i32>,
+}
+
+static TASKS: Mutex<Vec<TaskInfo>> = Mutex::new(Vec::new());
+
+struct EventHandler {
+    base: usize,
+    id: i32,
+    handler: esp_event_handler_t,
+    arg: usize,
+}
+
+static EVENT_HANDLERS: Mutex<Vec<EventHandler>> = Mutex::new(Vec::new());
+
+/// An `esp_event_base_t` defined here, the pointer is to a static string.
+#[repr(transparent)]
+struct EventBase(*const c_types::c_char);
+
+unsafe impl Sync for EventBase {}
+
+#[no_mangle]
+static WIFI_PROV_EVENT: EventBase = EventBase(b"WIFI_PROV_EVENT\0".as_ptr() as *const _);
+#[no_mangle]
+static WIFI_EVENT: EventBase = EventBase(b"WIFI_EVENT\0".as_ptr() as *const _);
+#[no_mangle]
+static IP_EVENT: EventBase = EventBase(b"IP_EVENT\0".as_ptr() as *const _);
+#[no_mangle]
+static HAP_EVENT: EventBase = EventBase(b"HAP_EVENT\0".as_ptr() as *const _);
+
+// Inspection and simulation, for tests.
+
+/// An accessory configuration named `name`, with placeholders for the rest.
+pub fn accessory_config(name: &str) -> hap::Config {
+    hap::Config {
+        name: CString::new(name).unwrap(),
+        model: CString::new("Mock").unwrap(),
+        manufacturer: CString::new("esp-homekit-sdk-sys").unwrap(),
+        serial_num: CString::new("000001").unwrap(),
+        fw_rev: CString::new("0.1.0").unwrap(),
+        hw_rev: CString::new("1").unwrap(),
+        pv: CString::default(),
+        cid: accessory::Category::OTHER,
+    }
+}
+
+/// Forget the accessory database, keystore and everything else recorded for the current
+/// thread, as after a reflash.
+pub fn reset() {
+    device(|device| *device = Device::default());
+}
+
+/// Make the next call of the SDK function `function` on this thread fail with `code`,
+/// e.g. `fail_next("hap_char_update_val", ESP_ERR_NO_MEM as i32)`. Calls queue up.
+pub fn fail_next(function: &'static str, code: i32) {
+    device(|device| device.failures.push((function, code)));
+}
+
+/// Write `value` to `characteristic` like a controller, through the write callback of
+/// its service, and return the status of the write.
+///
+/// Without a callback the value is stored as the HAP framework does.
+pub fn write(characteristic: HapCharacteristic, value: &CharValue) -> HapStatus {
+    let hc = characteristic.as_raw();
+    let hs = chr(hc).parent;
+    let (write, serv_priv) = match hs.is_null() {
+        true => (None, ptr::null_mut()),
+        false => (serv(hs).write, serv(hs).priv_),
+    };
+
+    let mut status = hap_status_t_HAP_STATUS_SUCCESS;
+    value.with_raw(|raw| unsafe {
+        match write {
+            Some(write) => {
+                let mut data = hap_write_data_t {
+                    hc,
+                    val: *raw,
+                    auth_data: hap_auth_data_t { data: ptr::null_mut(), len: 0 },
+                    remote: true,
+                    status: &mut status,
+                };
+                write(&mut data, 1, serv_priv, ptr::null_mut());
+            }
+            None => {
+                chr(hc).store(raw);
+            }
+        }
+    });
+
+    HapStatus::from_raw(status)
+}
+
+/// Read `characteristic` like a controller, through the read callback of its service,
+/// and return the status of the read.
+pub fn read(characteristic: HapCharacteristic) -> HapStatus {
+    let hc = characteristic.as_raw();
+    let hs = chr(hc).parent;
+
+    let mut status = hap_status_t_HAP_STATUS_SUCCESS;
+    if !hs.is_null() {
+        if let Some(read) = serv(hs).read {
+            unsafe { read(hc, &mut status, serv(hs).priv_, ptr::null_mut()) };
+        }
+    }
+
+    HapStatus::from_raw(status)
+}
+
+/// Ask the accessory `aid` to identify itself like a controller, `None` without such
+/// an accessory.
+pub fn identify(aid: u32) -> Option<i32> {
+    let ha = device(|device| device.accessories.iter().copied().find(|&ha| acc(ha).aid == aid))?;
+
+    acc(ha).identify.map(|identify| unsafe { identify(ha) })
+}
+
+/// The number of `hap_char_update_val` calls sending an event for `characteristic`,
+/// i.e. those of characteristics with the `EV` permission.
+pub fn notifications(characteristic: HapCharacteristic) -> usize {
+    chr(characteristic.as_raw()).notifications
+}
+
+/// Set the number of controllers subscribed to events of `characteristic`.
+pub fn set_subscribers(characteristic: HapCharacteristic, count: usize) {
+    chr(characteristic.as_raw()).subscribers = count;
+}
+
+/// The description added to `characteristic`, if any.
+pub fn description(characteristic: HapCharacteristic) -> Option<String> {
+    chr(characteristic.as_raw())
+        .description
+        .as_ref()
+        .map(|description| description.to_string_lossy().into_owned())
+}
+
+/// The level of `gpio` as last set, `None` if it was never set.
+pub fn gpio_level(gpio: u8) -> Option<u32> {
+    device(|device| device.gpio.get(&(gpio as gpio_num_t)).copied())
+}
+
+/// Set the free heap and largest free block reported by ESP-IDF.
+pub fn set_heap(free: u32, largest_block: usize) {
+    device(|device| device.heap = (free, largest_block));
+}
+
+/// Pair the controller `id`.
+pub fn pair_controller(id: &str) {
+    device(|device| device.controllers.push(id.to_owned()));
+}
+
+/// The paired controllers.
+pub fn controllers() -> Vec<String> {
+    device(|device| device.controllers.clone())
+}
+
+/// How often the mDNS records were announced, on start and re-announced since.
+pub fn announcements() -> usize {
+    device(|device| device.announcements)
+}
+
+/// The hostname set for the station interface and mDNS, if any.
+pub fn hostname() -> Option<String> {
+    device(|device| device.hostname.clone())
+}
+
+/// Whether the device has Wi-Fi credentials, see `wifi_prov_mgr_is_provisioned`.
+pub fn set_provisioned(provisioned: bool) {
+    device(|device| device.provisioned = provisioned);
+}
+
+/// Fire the armed timers created on this thread, regardless of their timeout.
+///
+/// Returns the number of timers fired.
+pub fn fire_timers() -> usize {
+    let current = thread::current().id();
+    let due = {
+        let mut timers = TIMERS.lock().unwrap();
+        timers
+            .values_mut()
+            .filter(|timer| timer.armed && timer.thread == current)
+            .map(|timer| {
+                timer.armed = false;
+                (timer.callback, timer.arg)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Callbacks may stop, delete or create timers.
+    for (callback, arg) in &due {
+        if let Some(callback) = callback {
+            unsafe { callback(*arg as *mut c_types::c_void) };
+        }
+    }
+
+    due.len()
+}
+
+/// The number of armed timers created on this thread.
+pub fn armed_timers() -> usize {
+    let current = thread::current().id();
+
+    TIMERS
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|timer| timer.armed && timer.thread == current)
+        .count()
+}
+
+/// The tasks created so far, by any thread.
+pub fn tasks() -> Vec<TaskInfo> {
+    TASKS.lock().unwrap().clone()
+}
+
+/// Post an ESP-IDF event to the handlers registered for it.
+pub fn post_event(base: esp_event_base_t, id: i32, data: *mut c_types::c_void) {
+    let handlers = EVENT_HANDLERS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|h| h.base == base as usize && (h.id == ESP_EVENT_ANY_ID || h.id == id))
+        .map(|h| (h.handler, h.arg))
+        .collect::<Vec<_>>();
+
+    for (handler, arg) in handlers {
+        if let Some(handler) = handler {
+            unsafe { handler(arg as *mut c_types::c_void, base, id, data) };
+        }
+    }
+}
+
+// The HAP framework.
+
+#[no_mangle]
+unsafe extern "C" fn hap_init(_method: hap_transport_t) -> c_types::c_int {
+    if let Some(code) = injected_failure("hap_init") {
+        return code;
+    }
+
+    let initialized = device(|device| core::mem::replace(&mut device.initialized, true));
+    if initialized {
+        return HAP_FAIL;
+    }
+
+    // The framework starts counting at 1 on the first boot.
+    let key = config_number_key();
+    device(|device| device.keystore.entry(key).or_insert_with(|| 1u32.to_ne_bytes().to_vec()).len());
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_start() -> c_types::c_int {
+    if let Some(code) = injected_failure("hap_start") {
+        return code;
+    }
+
+    let [stack_size, priority, ..] = device(|device| {
+        device.started = true;
+        device.advertising = true;
+        device.announcements += 1;
+        device.config
+    });
+
+    // The framework creates its task with `xTaskCreate`; nothing runs in it here.
+    TASKS.lock().unwrap().push(TaskInfo {
+        name: "hap_main".to_owned(),
+        stack_size,
+        priority,
+        core: None,
+    });
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_stop() -> c_types::c_int {
+    device(|device| {
+        device.started = false;
+        device.advertising = false;
+    });
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_get_config(cfg: *mut hap_cfg_t) -> c_types::c_int {
+    let [stack, priority, notif_chars, unique_param, recv, send, token] = device(|device| device.config);
+
+    *cfg = hap_cfg_t {
+        task_stack_size: stack,
+        task_priority: priority as u8,
+        max_event_notif_chars: notif_chars as u8,
+        unique_param: unique_param as c_types::c_int,
+        recv_timeout: recv as c_types::c_int,
+        send_timeout: send as c_types::c_int,
+        sw_token_max_len: token as usize,
+    };
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_set_config(cfg: *const hap_cfg_t) -> c_types::c_int {
+    let cfg = &*cfg;
+
+    device(|device| {
+        device.config = [
+            cfg.task_stack_size,
+            cfg.task_priority as u32,
+            cfg.max_event_notif_chars as u32,
+            cfg.unique_param as u32,
+            cfg.recv_timeout as u32,
+            cfg.send_timeout as u32,
+            cfg.sw_token_max_len as u32,
+        ]
+    });
+
+    HAP_SUCCESS as i32
+}
+
+fn config_number_key() -> KeystoreKey {
+    (b"nvs".to_vec(), b"hap_main".to_vec(), b"config_num".to_vec())
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_update_config_number() -> c_types::c_int {
+    if let Some(code) = injected_failure("hap_update_config_number") {
+        return code;
+    }
+
+    device(|device| {
+        let value = device.keystore.entry(config_number_key()).or_insert_with(|| 1u32.to_ne_bytes().to_vec());
+        let number = u32::from_ne_bytes(value[..4].try_into().unwrap());
+
+        // `c#` wraps around to 1 after 65535.
+        let number = if number >= u16::MAX as u32 { 1 } else { number + 1 };
+        *value = number.to_ne_bytes().to_vec();
+
+        // The framework re-announces the new number while mDNS is up.
+        if device.started {
+            device.announcements += 1;
+        }
+    });
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_set_setup_code(setup_code: *const c_types::c_char) -> c_types::c_int {
+    let code = CStr::from_ptr(setup_code).to_string_lossy().into_owned();
+    device(|device| device.setup.0 = Some(code));
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_set_setup_id(setup_id: *const c_types::c_char) -> c_types::c_int {
+    let id = CStr::from_ptr(setup_id).to_string_lossy().into_owned();
+    device(|device| device.setup.1 = Some(id));
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_http_debug_enable() {}
+
+#[no_mangle]
+unsafe extern "C" fn hap_http_debug_disable() {}
+
+#[no_mangle]
+unsafe extern "C" fn hap_bct_change_name(_name: *const c_types::c_char) {}
+
+#[no_mangle]
+unsafe extern "C" fn hap_bct_hot_plug() {}
+
+#[no_mangle]
+unsafe extern "C" fn hap_platform_httpd_get_port() -> c_types::c_int {
+    80
+}
+
+// Accessories.
+
+unsafe fn cfg_string(s: *const c_types::c_char) -> Option<CString> {
+    (!s.is_null()).then(|| CStr::from_ptr(s).to_owned())
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_acc_create(acc_cfg: *mut hap_acc_cfg_t) -> *mut hap_acc_t {
+    if injected_failure("hap_acc_create").is_some() {
+        return ptr::null_mut();
+    }
+
+    let cfg = &*acc_cfg;
+    let ha = Box::into_raw(Box::new(MockAcc {
+        aid: 0,
+        next_iid: 1,
+        servs: Vec::new(),
+        identify: cfg.identify_routine,
+        product_data: Vec::new(),
+    })) as *mut hap_acc_t;
+
+    // Every accessory gets the information and protocol information services.
+    let info = new_serv(HAP_SERV_UUID_ACCESSORY_INFORMATION);
+    hap_serv_add_char(info, new_char(HAP_CHAR_UUID_IDENTIFY, hap_char_format_t_HAP_CHAR_FORMAT_BOOL, PW, bool_val(false)));
+    for (uuid, value) in [
+        (HAP_CHAR_UUID_MANUFACTURER, cfg.manufacturer),
+        (HAP_CHAR_UUID_MODEL, cfg.model),
+        (HAP_CHAR_UUID_NAME, cfg.name),
+        (HAP_CHAR_UUID_SERIAL_NUMBER, cfg.serial_num),
+        (HAP_CHAR_UUID_FIRMWARE_REVISION, cfg.fw_rev),
+        (HAP_CHAR_UUID_HARDWARE_REVISION, cfg.hw_rev),
+    ] {
+        if let Some(value) = cfg_string(value) {
+            hap_serv_add_char(info, string_char(&uuid[..], PR, &value));
+        }
+    }
+    hap_acc_add_serv(ha, info);
+
+    let protocol = new_serv(HAP_SERV_UUID_PROTOCOL_INFORMATION);
+    let version = cfg_string(cfg.pv).unwrap_or_default();
+    hap_serv_add_char(protocol, string_char(HAP_CHAR_UUID_VERSION, PR, &version));
+    hap_acc_add_serv(ha, protocol);
+
+    ha
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_acc_add_serv(ha: *mut hap_acc_t, hs: *mut hap_serv_t) -> c_types::c_int {
+    if let Some(code) = injected_failure("hap_acc_add_serv") {
+        return code;
+    }
+    if !serv(hs).parent.is_null() {
+        return HAP_FAIL;
+    }
+
+    let acc = acc(ha);
+    let serv = serv(hs);
+    serv.parent = ha;
+    serv.iid = acc.next_iid;
+    acc.next_iid += 1;
+    for &hc in &serv.chars {
+        chr(hc).iid = acc.next_iid;
+        acc.next_iid += 1;
+    }
+    acc.servs.push(hs);
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_acc_add_product_data(ha: *mut hap_acc_t, product_data: *mut u8, data_size: usize) -> c_types::c_int {
+    acc(ha).product_data = slice::from_raw_parts(product_data, data_size).to_vec();
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_add_accessory(ha: *mut hap_acc_t) {
+    acc(ha).aid = 1;
+    device(|device| device.accessories.insert(0, ha));
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_add_bridged_accessory(ha: *mut hap_acc_t, aid: c_types::c_int) -> c_types::c_int {
+    if let Some(code) = injected_failure("hap_add_bridged_accessory") {
+        return code;
+    }
+
+    device(|device| {
+        let used = |aid: u32| device.accessories.iter().any(|&other| acc(other).aid == aid);
+        let aid = if aid > 0 {
+            aid as u32
+        } else {
+            (2..).find(|&aid| !used(aid)).unwrap()
+        };
+        if used(aid) {
+            return HAP_FAIL;
+        }
+
+        acc(ha).aid = aid;
+        device.accessories.push(ha);
+
+        HAP_SUCCESS as i32
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_remove_bridged_accessory(ha: *mut hap_acc_t) -> c_types::c_int {
+    device(|device| match device.accessories.iter().position(|&other| other == ha) {
+        Some(index) => {
+            device.accessories.remove(index);
+            HAP_SUCCESS as i32
+        }
+        None => HAP_FAIL,
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_get_first_acc() -> *mut hap_acc_t {
+    device(|device| device.accessories.first().copied().unwrap_or(ptr::null_mut()))
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_acc_get_next(ha: *mut hap_acc_t) -> *mut hap_acc_t {
+    device(|device| {
+        let index = device.accessories.iter().position(|&other| other == ha);
+        index
+            .and_then(|index| device.accessories.get(index + 1).copied())
+            .unwrap_or(ptr::null_mut())
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_acc_get_aid(ha: *mut hap_acc_t) -> c_types::c_int {
+    acc(ha).aid as c_types::c_int
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_acc_get_first_serv(ha: *mut hap_acc_t) -> *mut hap_serv_t {
+    acc(ha).servs.first().copied().unwrap_or(ptr::null_mut())
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_acc_get_serv_by_uuid(ha: *mut hap_acc_t, type_uuid: *const c_types::c_char) -> *mut hap_serv_t {
+    let uuid = CStr::from_ptr(type_uuid);
+
+    acc(ha)
+        .servs
+        .iter()
+        .copied()
+        .find(|&hs| serv(hs).uuid.as_c_str() == uuid)
+        .unwrap_or(ptr::null_mut())
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_acc_get_char_by_iid(ha: *mut hap_acc_t, iid: c_types::c_int) -> *mut hap_char_t {
+    acc(ha)
+        .servs
+        .iter()
+        .flat_map(|&hs| serv(hs).chars.iter().copied())
+        .find(|&hc| chr(hc).iid == iid as u32)
+        .unwrap_or(ptr::null_mut())
+}
+
+// Services.
+
+fn new_serv(uuid: &[u8]) -> *mut hap_serv_t {
+    Box::into_raw(Box::new(MockServ {
+        uuid: CStr::from_bytes_with_nul(uuid).unwrap().to_owned(),
+        iid: 0,
+        parent: ptr::null_mut(),
+        chars: Vec::new(),
+        linked: Vec::new(),
+        priv_: ptr::null_mut(),
+        write: None,
+        read: None,
+        hidden: false,
+        primary: false,
+    })) as *mut hap_serv_t
+}
+
+/// A service of `uuid` with `chars`, like the `hap_serv_*_create` functions.
+unsafe fn serv_with(uuid: &[u8], chars: &[*mut hap_char_t]) -> *mut hap_serv_t {
+    let hs = new_serv(uuid);
+    for &hc in chars {
+        hap_serv_add_char(hs, hc);
+    }
+
+    hs
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_add_char(hs: *mut hap_serv_t, hc: *mut hap_char_t) -> c_types::c_int {
+    if let Some(code) = injected_failure("hap_serv_add_char") {
+        return code;
+    }
+    if hc.is_null() || !chr(hc).parent.is_null() {
+        return HAP_FAIL;
+    }
+
+    let serv = serv(hs);
+    chr(hc).parent = hs;
+    // Characteristics added to a service already in an accessory get an iid at once.
+    if !serv.parent.is_null() {
+        chr(hc).iid = acc(serv.parent).next_iid;
+        acc(serv.parent).next_iid += 1;
+    }
+    serv.chars.push(hc);
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_get_next(hs: *mut hap_serv_t) -> *mut hap_serv_t {
+    let parent = serv(hs).parent;
+    if parent.is_null() {
+        return ptr::null_mut();
+    }
+
+    let servs = &acc(parent).servs;
+    let index = servs.iter().position(|&other| other == hs).unwrap();
+    servs.get(index + 1).copied().unwrap_or(ptr::null_mut())
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_get_parent(hs: *mut hap_serv_t) -> *mut hap_acc_t {
+    serv(hs).parent
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_get_first_char(hs: *mut hap_serv_t) -> *mut hap_char_t {
+    serv(hs).chars.first().copied().unwrap_or(ptr::null_mut())
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_get_char_by_uuid(hs: *mut hap_serv_t, type_uuid: *const c_types::c_char) -> *mut hap_char_t {
+    let uuid = CStr::from_ptr(type_uuid);
+
+    serv(hs)
+        .chars
+        .iter()
+        .copied()
+        .find(|&hc| chr(hc).uuid.as_c_str() == uuid)
+        .unwrap_or(ptr::null_mut())
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_get_iid(hs: *mut hap_serv_t) -> u32 {
+    serv(hs).iid
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_get_type_uuid(hs: *mut hap_serv_t) -> *const c_types::c_char {
+    serv(hs).uuid.as_ptr()
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_set_priv(hs: *mut hap_serv_t, priv_: *mut c_types::c_void) {
+    serv(hs).priv_ = priv_;
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_get_priv(hs: *mut hap_serv_t) -> *mut c_types::c_void {
+    serv(hs).priv_
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_set_write_cb(hs: *mut hap_serv_t, write: hap_serv_write_t) {
+    serv(hs).write = write;
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_set_read_cb(hs: *mut hap_serv_t, read: hap_serv_read_t) {
+    serv(hs).read = read;
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_link_serv(hs: *mut hap_serv_t, linked_serv: *mut hap_serv_t) -> c_types::c_int {
+    serv(hs).linked.push(linked_serv);
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_mark_hidden(hs: *mut hap_serv_t) -> c_types::c_int {
+    serv(hs).hidden = true;
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_mark_primary(hs: *mut hap_serv_t) -> c_types::c_int {
+    serv(hs).primary = true;
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_outlet_create(on: bool, outlet_in_use: bool) -> *mut hap_serv_t {
+    serv_with(HAP_SERV_UUID_OUTLET, &[on_char(on), bool_char(HAP_CHAR_UUID_OUTLET_IN_USE, PR | EV, outlet_in_use)])
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_lightbulb_create(on: bool) -> *mut hap_serv_t {
+    serv_with(HAP_SERV_UUID_LIGHTBULB, &[on_char(on)])
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_switch_create(on: bool) -> *mut hap_serv_t {
+    serv_with(HAP_SERV_UUID_SWITCH, &[on_char(on)])
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_valve_create(active: u8, in_use: u8, valve_type: u8) -> *mut hap_serv_t {
+    serv_with(
+        HAP_SERV_UUID_VALVE,
+        &[
+            u8_char(HAP_CHAR_UUID_ACTIVE, PR | PW | EV, active),
+            u8_char(HAP_CHAR_UUID_IN_USE, PR | EV, in_use),
+            u8_char(HAP_CHAR_UUID_VALVE_TYPE, PR | EV, valve_type),
+        ],
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_faucet_create(active: u8) -> *mut hap_serv_t {
+    serv_with(HAP_SERV_UUID_FAUCET, &[u8_char(HAP_CHAR_UUID_ACTIVE, PR | PW | EV, active)])
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_irrigation_system_create(active: u8, prog_mode: u8, in_use: u8) -> *mut hap_serv_t {
+    serv_with(
+        HAP_SERV_UUID_IRRIGATION_SYSTEM,
+        &[
+            u8_char(HAP_CHAR_UUID_ACTIVE, PR | PW | EV, active),
+            u8_char(HAP_CHAR_UUID_PROGRAM_MODE, PR | EV, prog_mode),
+            u8_char(HAP_CHAR_UUID_IN_USE, PR | EV, in_use),
+        ],
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_service_label_create(service_label_namespace: u8) -> *mut hap_serv_t {
+    serv_with(
+        HAP_SERV_UUID_SERVICE_LABEL,
+        &[u8_char(HAP_CHAR_UUID_SERVICE_LABEL_NAMESPACE, PR, service_label_namespace)],
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_garage_door_opener_create(
+    curr_door_state: u8,
+    targ_door_state: u8,
+    obstr_detect: bool,
+) -> *mut hap_serv_t {
+    serv_with(
+        HAP_SERV_UUID_GARAGE_DOOR_OPENER,
+        &[
+            u8_char(HAP_CHAR_UUID_CURRENT_DOOR_STATE, PR | EV, curr_door_state),
+            u8_char(HAP_CHAR_UUID_TARGET_DOOR_STATE, PR | PW | EV, targ_door_state),
+            hap_char_obstruction_detect_create(obstr_detect),
+        ],
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_battery_service_create(
+    battery_level: u8,
+    charging_state: u8,
+    status_low_battery: u8,
+) -> *mut hap_serv_t {
+    serv_with(
+        HAP_SERV_UUID_BATTERY_SERVICE,
+        &[
+            u8_char(HAP_CHAR_UUID_BATTERY_LEVEL, PR | EV, battery_level),
+            u8_char(HAP_CHAR_UUID_CHARGING_STATE, PR | EV, charging_state),
+            u8_char(HAP_CHAR_UUID_STATUS_LOW_BATTERY, PR | EV, status_low_battery),
+        ],
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_thermostat_create(
+    curr_heating_cooling_state: u8,
+    targ_heating_cooling_state: u8,
+    curr_temp: f32,
+    targ_temp: f32,
+    temp_disp_units: u8,
+) -> *mut hap_serv_t {
+    serv_with(
+        HAP_SERV_UUID_THERMOSTAT,
+        &[
+            u8_char(HAP_CHAR_UUID_CURRENT_HEATING_COOLING_STATE, PR | EV, curr_heating_cooling_state),
+            u8_char(HAP_CHAR_UUID_TARGET_HEATING_COOLING_STATE, PR | PW | EV, targ_heating_cooling_state),
+            float_char(HAP_CHAR_UUID_CURRENT_TEMPERATURE, PR | EV, curr_temp),
+            float_char(HAP_CHAR_UUID_TARGET_TEMPERATURE, PR | PW | EV, targ_temp),
+            u8_char(HAP_CHAR_UUID_TEMPERATURE_DISPLAY_UNITS, PR | PW | EV, temp_disp_units),
+        ],
+    )
+}
+
+unsafe fn position_serv(uuid: &[u8], curr_pos: u8, targ_pos: u8, pos_state: u8) -> *mut hap_serv_t {
+    serv_with(
+        uuid,
+        &[
+            u8_char(HAP_CHAR_UUID_CURRENT_POSITION, PR | EV, curr_pos),
+            u8_char(HAP_CHAR_UUID_TARGET_POSITION, PR | PW | EV, targ_pos),
+            u8_char(HAP_CHAR_UUID_POSITION_STATE, PR | EV, pos_state),
+        ],
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_door_create(curr_pos: u8, targ_pos: u8, pos_state: u8) -> *mut hap_serv_t {
+    position_serv(HAP_SERV_UUID_DOOR, curr_pos, targ_pos, pos_state)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_window_create(curr_pos: u8, targ_pos: u8, pos_state: u8) -> *mut hap_serv_t {
+    position_serv(HAP_SERV_UUID_WINDOW, curr_pos, targ_pos, pos_state)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_slat_create(curr_slat_state: u8, slat_type: u8) -> *mut hap_serv_t {
+    serv_with(
+        HAP_SERV_UUID_SLAT,
+        &[
+            u8_char(HAP_CHAR_UUID_CURRENT_SLAT_STATE, PR | EV, curr_slat_state),
+            u8_char(HAP_CHAR_UUID_SLAT_TYPE, PR, slat_type),
+        ],
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_doorbell_create(programmable_switch_event: u8) -> *mut hap_serv_t {
+    serv_with(
+        HAP_SERV_UUID_DOORBELL,
+        &[u8_char(HAP_CHAR_UUID_PROGRAMMABLE_SWITCH_EVENT, PR | EV, programmable_switch_event)],
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_stateless_programmable_switch_create(programmable_switch_event: u8) -> *mut hap_serv_t {
+    serv_with(
+        HAP_SERV_UUID_STATELESS_PROGRAMMABLE_SWITCH,
+        &[u8_char(HAP_CHAR_UUID_PROGRAMMABLE_SWITCH_EVENT, PR | EV, programmable_switch_event)],
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_speaker_create(mute: bool) -> *mut hap_serv_t {
+    serv_with(HAP_SERV_UUID_SPEAKER, &[bool_char(HAP_CHAR_UUID_MUTE, PR | PW | EV, mute)])
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_microphone_create(mute: bool) -> *mut hap_serv_t {
+    serv_with(HAP_SERV_UUID_MICROPHONE, &[bool_char(HAP_CHAR_UUID_MUTE, PR | PW | EV, mute)])
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_air_quality_sensor_create(air_quality: u8) -> *mut hap_serv_t {
+    serv_with(HAP_SERV_UUID_AIR_QUALITY_SENSOR, &[u8_char(HAP_CHAR_UUID_AIR_QUALITY, PR | EV, air_quality)])
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_serv_carbon_dioxide_sensor_create(carbon_dioxide_detected: u8) -> *mut hap_serv_t {
+    serv_with(
+        HAP_SERV_UUID_CARBON_DIOXIDE_SENSOR,
+        &[u8_char(HAP_CHAR_UUID_CARBON_DIOXIDE_DETECTED, PR | EV, carbon_dioxide_detected)],
+    )
+}
+
+// Characteristics.
+
+const PR: u16 = HAP_CHAR_PERM_PR as u16;
+const PW: u16 = HAP_CHAR_PERM_PW as u16;
+const EV: u16 = HAP_CHAR_PERM_EV as u16;
+
+fn bool_val(b: bool) -> hap_val_t {
+    hap_val_t { b }
+}
+
+fn new_char(uuid: &[u8], format: hap_char_format_t, perms: u16, val: hap_val_t) -> *mut hap_char_t {
+    let uuid = CStr::from_bytes_until_nul(uuid).unwrap().to_owned();
+
+    Box::into_raw(Box::new(MockChar {
+        uuid,
+        iid: 0,
+        parent: ptr::null_mut(),
+        format,
+        perms,
+        val,
+        text: None,
+        bytes: Vec::new(),
+        priv_: ptr::null_mut(),
+        unit: None,
+        description: None,
+        notifications: 0,
+        subscribers: 0,
+    })) as *mut hap_char_t
+}
+
+unsafe fn uuid_bytes<'a>(type_uuid: *mut c_types::c_char) -> &'a [u8] {
+    CStr::from_ptr(type_uuid).to_bytes_with_nul()
+}
+
+fn bool_char(uuid: &[u8], perms: u16, val: bool) -> *mut hap_char_t {
+    new_char(uuid, hap_char_format_t_HAP_CHAR_FORMAT_BOOL, perms, hap_val_t { b: val })
+}
+
+fn u8_char(uuid: &[u8], perms: u16, val: u8) -> *mut hap_char_t {
+    new_char(uuid, hap_char_format_t_HAP_CHAR_FORMAT_UINT8, perms, hap_val_t { u: val as u32 })
+}
+
+fn u32_char(uuid: &[u8], perms: u16, val: u32) -> *mut hap_char_t {
+    new_char(uuid, hap_char_format_t_HAP_CHAR_FORMAT_UINT32, perms, hap_val_t { u: val })
+}
+
+fn int_char(uuid: &[u8], perms: u16, val: c_types::c_int) -> *mut hap_char_t {
+    new_char(uuid, hap_char_format_t_HAP_CHAR_FORMAT_INT, perms, hap_val_t { i: val })
+}
+
+fn float_char(uuid: &[u8], perms: u16, val: f32) -> *mut hap_char_t {
+    new_char(uuid, hap_char_format_t_HAP_CHAR_FORMAT_FLOAT, perms, hap_val_t { f: val })
+}
+
+unsafe fn string_char(uuid: &[u8], perms: u16, val: &CStr) -> *mut hap_char_t {
+    let hc = new_char(uuid, hap_char_format_t_HAP_CHAR_FORMAT_STRING, perms, hap_val_t { s: ptr::null_mut() });
+    chr(hc).store(&hap_val_t { s: val.as_ptr() as *mut _ });
+
+    hc
+}
+
+fn on_char(on: bool) -> *mut hap_char_t {
+    bool_char(HAP_CHAR_UUID_ON, PR | PW | EV, on)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_bool_create(type_uuid: *mut c_types::c_char, perms: u16, val: bool) -> *mut hap_char_t {
+    bool_char(uuid_bytes(type_uuid), perms, val)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_uint8_create(type_uuid: *mut c_types::c_char, perms: u16, val: u8) -> *mut hap_char_t {
+    u8_char(uuid_bytes(type_uuid), perms, val)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_uint16_create(type_uuid: *mut c_types::c_char, perms: u16, val: u16) -> *mut hap_char_t {
+    new_char(uuid_bytes(type_uuid), hap_char_format_t_HAP_CHAR_FORMAT_UINT16, perms, hap_val_t { u: val as u32 })
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_uint32_create(type_uuid: *mut c_types::c_char, perms: u16, val: u32) -> *mut hap_char_t {
+    u32_char(uuid_bytes(type_uuid), perms, val)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_uint64_create(type_uuid: *mut c_types::c_char, perms: u16, val: u64) -> *mut hap_char_t {
+    new_char(uuid_bytes(type_uuid), hap_char_format_t_HAP_CHAR_FORMAT_UINT64, perms, hap_val_t { i64: val })
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_int_create(type_uuid: *mut c_types::c_char, perms: u16, val: c_types::c_int) -> *mut hap_char_t {
+    int_char(uuid_bytes(type_uuid), perms, val)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_float_create(type_uuid: *mut c_types::c_char, perms: u16, val: f32) -> *mut hap_char_t {
+    float_char(uuid_bytes(type_uuid), perms, val)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_string_create(
+    type_uuid: *mut c_types::c_char,
+    perms: u16,
+    val: *mut c_types::c_char,
+) -> *mut hap_char_t {
+    string_char(uuid_bytes(type_uuid), perms, CStr::from_ptr(val))
+}
+
+unsafe fn bytes_char(type_uuid: *mut c_types::c_char, format: hap_char_format_t, perms: u16, buf: *mut u8, buflen: u32) -> *mut hap_char_t {
+    let val = hap_val_t { d: hap_data_val_t { buf, buflen } };
+    let hc = new_char(uuid_bytes(type_uuid), format, perms, val);
+    chr(hc).store(&val);
+
+    hc
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_data_create(
+    type_uuid: *mut c_types::c_char,
+    perms: u16,
+    val: *mut hap_data_val_t,
+) -> *mut hap_char_t {
+    let val = &*val;
+    bytes_char(type_uuid, hap_char_format_t_HAP_CHAR_FORMAT_DATA, perms, val.buf, val.buflen)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_tlv8_create(
+    type_uuid: *mut c_types::c_char,
+    perms: u16,
+    val: *mut hap_tlv8_val_t,
+) -> *mut hap_char_t {
+    let val = &*val;
+    bytes_char(type_uuid, hap_char_format_t_HAP_CHAR_FORMAT_TLV8, perms, val.buf, val.buflen)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_name_create(name: *mut c_types::c_char) -> *mut hap_char_t {
+    string_char(HAP_CHAR_UUID_NAME, PR, CStr::from_ptr(name))
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_brightness_create(brightness: c_types::c_int) -> *mut hap_char_t {
+    int_char(HAP_CHAR_UUID_BRIGHTNESS, PR | PW | EV, brightness)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_service_label_index_create(service_label_index: u8) -> *mut hap_char_t {
+    u8_char(HAP_CHAR_UUID_SERVICE_LABEL_INDEX, PR, service_label_index)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_set_duration_create(set_duration: u32) -> *mut hap_char_t {
+    u32_char(HAP_CHAR_UUID_SET_DURATION, PR | PW | EV, set_duration)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_remaining_duration_create(remaining_duration: u32) -> *mut hap_char_t {
+    u32_char(HAP_CHAR_UUID_REMAINING_DURATION, PR | EV, remaining_duration)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_volume_create(volume: u8) -> *mut hap_char_t {
+    u8_char(HAP_CHAR_UUID_VOLUME, PR | PW | EV, volume)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_pm_2_5_density_create(pm_2_5_density: f32) -> *mut hap_char_t {
+    float_char(HAP_CHAR_UUID_PM_2_5_DENSITY, PR | EV, pm_2_5_density)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_pm_10_density_create(pm_10_density: f32) -> *mut hap_char_t {
+    float_char(HAP_CHAR_UUID_PM_10_DENSITY, PR | EV, pm_10_density)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_voc_density_create(voc_density: f32) -> *mut hap_char_t {
+    float_char(HAP_CHAR_UUID_VOC_DENSITY, PR | EV, voc_density)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_nitrogen_dioxide_density_create(nitrogen_dioxide_density: f32) -> *mut hap_char_t {
+    float_char(HAP_CHAR_UUID_NITROGEN_DIOXIDE_DENSITY, PR | EV, nitrogen_dioxide_density)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_sulphur_dioxide_density_create(sulphur_dioxide_density: f32) -> *mut hap_char_t {
+    float_char(HAP_CHAR_UUID_SULPHUR_DIOXIDE_DENSITY, PR | EV, sulphur_dioxide_density)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_ozone_density_create(ozone_density: f32) -> *mut hap_char_t {
+    float_char(HAP_CHAR_UUID_OZONE_DENSITY, PR | EV, ozone_density)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_carbon_dioxide_level_create(carbon_dioxide_level: f32) -> *mut hap_char_t {
+    float_char(HAP_CHAR_UUID_CARBON_DIOXIDE_LEVEL, PR | EV, carbon_dioxide_level)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_carbon_dioxide_peak_level_create(carbon_dioxide_peak_level: f32) -> *mut hap_char_t {
+    float_char(HAP_CHAR_UUID_CARBON_DIOXIDE_PEAK_LEVEL, PR | EV, carbon_dioxide_peak_level)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_heating_threshold_temperature_create(heating_threshold_temp: f32) -> *mut hap_char_t {
+    float_char(HAP_CHAR_UUID_HEATING_THRESHOLD_TEMPERATURE, PR | PW | EV, heating_threshold_temp)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_cooling_threshold_temperature_create(cooling_threshold_temp: f32) -> *mut hap_char_t {
+    float_char(HAP_CHAR_UUID_COOLING_THRESHOLD_TEMPERATURE, PR | PW | EV, cooling_threshold_temp)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_obstruction_detect_create(obs: bool) -> *mut hap_char_t {
+    bool_char(HAP_CHAR_UUID_OBSTRUCTION_DETECTED, PR | EV, obs)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_hold_position_create(hold: bool) -> *mut hap_char_t {
+    bool_char(HAP_CHAR_UUID_HOLD_POSITION, PW, hold)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_current_tilt_angle_create(angle: c_types::c_int) -> *mut hap_char_t {
+    int_char(HAP_CHAR_UUID_CURRENT_TILT_ANGLE, PR | EV, angle)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_target_tilt_angle_create(angle: c_types::c_int) -> *mut hap_char_t {
+    int_char(HAP_CHAR_UUID_TARGET_TILT_ANGLE, PR | PW | EV, angle)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_swing_mode_create(mode: u8) -> *mut hap_char_t {
+    u8_char(HAP_CHAR_UUID_SWING_MODE, PR | PW | EV, mode)
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_update_val(hc: *mut hap_char_t, val: *mut hap_val_t) -> c_types::c_int {
+    if let Some(code) = injected_failure("hap_char_update_val") {
+        return code;
+    }
+
+    let chr = chr(hc);
+    chr.store(&*val);
+    if chr.perms & EV != 0 {
+        chr.notifications += 1;
+    }
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_get_val(hc: *mut hap_char_t) -> *const hap_val_t {
+    &chr(hc).val
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_get_type_uuid(hc: *mut hap_char_t) -> *const c_types::c_char {
+    chr(hc).uuid.as_ptr()
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_get_iid(hc: *mut hap_char_t) -> u32 {
+    chr(hc).iid
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_get_perm(hc: *mut hap_char_t) -> u16 {
+    chr(hc).perms
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_get_format(hc: *mut hap_char_t) -> hap_char_format_t {
+    chr(hc).format
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_get_parent(hc: *mut hap_char_t) -> *mut hap_serv_t {
+    chr(hc).parent
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_get_next(hc: *mut hap_char_t) -> *mut hap_char_t {
+    let parent = chr(hc).parent;
+    if parent.is_null() {
+        return ptr::null_mut();
+    }
+
+    let chars = &serv(parent).chars;
+    let index = chars.iter().position(|&other| other == hc).unwrap();
+    chars.get(index + 1).copied().unwrap_or(ptr::null_mut())
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_set_priv(hc: *mut hap_char_t, priv_: *mut c_types::c_void) {
+    chr(hc).priv_ = priv_;
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_get_priv(hc: *mut hap_char_t) -> *mut c_types::c_void {
+    chr(hc).priv_
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_add_unit(hc: *mut hap_char_t, unit: *const c_types::c_char) -> c_types::c_int {
+    chr(hc).unit = Some(CStr::from_ptr(unit).to_owned());
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_add_description(hc: *mut hap_char_t, description: *const c_types::c_char) -> c_types::c_int {
+    chr(hc).description = Some(CStr::from_ptr(description).to_owned());
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_int_set_constraints(
+    _hc: *mut hap_char_t,
+    _min: c_types::c_int,
+    _max: c_types::c_int,
+    _step: c_types::c_int,
+) -> c_types::c_int {
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_float_set_constraints(_hc: *mut hap_char_t, _minval: f32, _maxval: f32, _step: f32) -> c_types::c_int {
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_add_valid_vals(_hc: *mut hap_char_t, _valid_vals: *const u8, _valid_val_cnt: usize) -> c_types::c_int {
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_char_add_valid_vals_range(_hc: *mut hap_char_t, _start_val: u8, _end_val: u8) -> c_types::c_int {
+    HAP_SUCCESS as i32
+}
+
+// The keystore.
+
+#[no_mangle]
+unsafe extern "C" fn hap_platform_keystore_get_nvs_partition_name() -> *const c_types::c_char {
+    PARTITION.as_ptr() as *const _
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_platform_keystore_get_factory_nvs_partition_name() -> *const c_types::c_char {
+    FACTORY_PARTITION.as_ptr() as *const _
+}
+
+unsafe fn keystore_key(
+    part_name: *const c_types::c_char,
+    name_space: *const c_types::c_char,
+    key: *const c_types::c_char,
+) -> KeystoreKey {
+    (
+        CStr::from_ptr(part_name).to_bytes().to_vec(),
+        CStr::from_ptr(name_space).to_bytes().to_vec(),
+        CStr::from_ptr(key).to_bytes().to_vec(),
+    )
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_platform_keystore_get(
+    part_name: *const c_types::c_char,
+    name_space: *const c_types::c_char,
+    key: *const c_types::c_char,
+    val: *mut u8,
+    val_size: *mut usize,
+) -> c_types::c_int {
+    let value = match device(|device| device.keystore.get(&keystore_key(part_name, name_space, key)).cloned()) {
+        Some(value) => value,
+        None => return HAP_FAIL,
+    };
+
+    // Like NVS, only the size is reported without a buffer.
+    if !val.is_null() {
+        if *val_size < value.len() {
+            return HAP_FAIL;
+        }
+        ptr::copy_nonoverlapping(value.as_ptr(), val, value.len());
+    }
+    *val_size = value.len();
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_platform_keystore_set(
+    part_name: *const c_types::c_char,
+    name_space: *const c_types::c_char,
+    key: *const c_types::c_char,
+    val: *const u8,
+    val_len: usize,
+) -> c_types::c_int {
+    if let Some(code) = injected_failure("hap_platform_keystore_set") {
+        return code;
+    }
+
+    let value = slice::from_raw_parts(val, val_len).to_vec();
+    device(|device| device.keystore.insert(keystore_key(part_name, name_space, key), value));
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_platform_keystore_delete(
+    part_name: *const c_types::c_char,
+    name_space: *const c_types::c_char,
+    key: *const c_types::c_char,
+) -> c_types::c_int {
+    match device(|device| device.keystore.remove(&keystore_key(part_name, name_space, key))) {
+        Some(_) => HAP_SUCCESS as i32,
+        None => HAP_FAIL,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn hap_platform_keystore_delete_namespace(
+    part_name: *const c_types::c_char,
+    name_space: *const c_types::c_char,
+) -> c_types::c_int {
+    let (part, namespace) = (CStr::from_ptr(part_name).to_bytes(), CStr::from_ptr(name_space).to_bytes());
+    device(|device| device.keystore.retain(|(p, n, _), _| (p.as_slice(), n.as_slice()) != (part, namespace)));
+
+    HAP_SUCCESS as i32
+}
+
+/// An NVS iterator over the blobs of the keystore, as of `nvs_entry_find`.
+struct EntryIter {
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    position: usize,
+}
+
+#[no_mangle]
+unsafe extern "C" fn nvs_entry_find(
+    part_name: *const c_types::c_char,
+    namespace_name: *const c_types::c_char,
+    _type_: nvs_type_t,
+) -> nvs_iterator_t {
+    let part = CStr::from_ptr(part_name).to_bytes().to_vec();
+    let namespace = (!namespace_name.is_null()).then(|| CStr::from_ptr(namespace_name).to_bytes().to_vec());
+
+    let entries = device(|device| {
+        device
+            .keystore
+            .keys()
+            .filter(|(p, n, _)| *p == part && namespace.as_ref().is_none_or(|namespace| n == namespace))
+            .map(|(_, n, k)| (n.clone(), k.clone()))
+            .collect::<Vec<_>>()
+    });
+    if entries.is_empty() {
+        return ptr::null_mut();
+    }
+
+    Box::into_raw(Box::new(EntryIter { entries, position: 0 })) as nvs_iterator_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn nvs_entry_next(iterator: nvs_iterator_t) -> nvs_iterator_t {
+    let iter = &mut *(iterator as *mut EntryIter);
+
+    iter.position += 1;
+    if iter.position < iter.entries.len() {
+        iterator
+    } else {
+        nvs_release_iterator(iterator);
+        ptr::null_mut()
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn nvs_entry_info(iterator: nvs_iterator_t, out_info: *mut nvs_entry_info_t) {
+    let iter = &*(iterator as *const EntryIter);
+    let (namespace, key) = &iter.entries[iter.position];
+    let info = &mut *out_info;
+
+    for (name, out) in [(namespace, &mut info.namespace_name), (key, &mut info.key)] {
+        *out = [0; 16];
+        for (o, &b) in out.iter_mut().zip(name.iter().take(15)) {
+            *o = b as c_types::c_char;
+        }
+    }
+    info.type_ = nvs_type_t_NVS_TYPE_BLOB;
+}
+
+#[no_mangle]
+unsafe extern "C" fn nvs_release_iterator(iterator: nvs_iterator_t) {
+    if !iterator.is_null() {
+        drop(Box::from_raw(iterator as *mut EntryIter));
+    }
+}
+
+// The shim of `src/shim`.
+
+#[no_mangle]
+unsafe extern "C" fn esp_homekit_shim_remove_controller(id: *const c_types::c_char) -> c_types::c_int {
+    let id = CStr::from_ptr(id).to_string_lossy();
+
+    device(|device| match device.controllers.iter().position(|c| *c == id) {
+        Some(index) => {
+            device.controllers.remove(index);
+            if device.controllers.is_empty() && device.advertising {
+                device.announcements += 1;
+            }
+            HAP_SUCCESS as i32
+        }
+        None => HAP_FAIL,
+    })
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_homekit_shim_set_advertising(advertise: bool) -> c_types::c_int {
+    device(|device| {
+        device.advertising = advertise;
+        if advertise {
+            device.announcements += 1;
+        }
+    });
+
+    HAP_SUCCESS as i32
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_homekit_shim_char_subscribers(hc: *mut hap_char_t) -> c_types::c_int {
+    chr(hc).subscribers as c_types::c_int
+}
+
+/// The task entry and its argument, moved to the thread running the task.
+struct TaskStart(unsafe extern "C" fn(*mut c_types::c_void), usize);
+
+unsafe impl Send for TaskStart {}
+
+fn spawn_task(task: TaskFunction_t, name: *const c_types::c_char, info: TaskInfo, params: *mut c_types::c_void) -> BaseType_t {
+    let task = match task {
+        Some(task) => TaskStart(task, params as usize),
+        None => return ESP_FAIL,
+    };
+    let _ = name;
+
+    TASKS.lock().unwrap().push(info.clone());
+    thread::Builder::new()
+        .name(info.name)
+        .spawn(move || {
+            let TaskStart(task, params) = task;
+            unsafe { task(params as *mut c_types::c_void) }
+        })
+        .map(|_| PD_PASS)
+        .unwrap_or(ESP_FAIL)
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_homekit_shim_xTaskCreate(
+    task: TaskFunction_t,
+    name: *const c_types::c_char,
+    stack_depth: u32,
+    params: *mut c_types::c_void,
+    priority: UBaseType_t,
+    _created_task: *mut TaskHandle_t,
+) -> BaseType_t {
+    let info = TaskInfo {
+        name: CStr::from_ptr(name).to_string_lossy().into_owned(),
+        stack_size: stack_depth,
+        priority,
+        core: None,
+    };
+
+    spawn_task(task, name, info, params)
+}
+
+#[no_mangle]
+unsafe extern "C" fn xTaskCreatePinnedToCore(
+    task: TaskFunction_t,
+    name: *const c_types::c_char,
+    stack_depth: u32,
+    params: *mut c_types::c_void,
+    priority: UBaseType_t,
+    _created_task: *mut TaskHandle_t,
+    core_id: BaseType_t,
+) -> BaseType_t {
+    let info = TaskInfo {
+        name: CStr::from_ptr(name).to_string_lossy().into_owned(),
+        stack_size: stack_depth,
+        priority,
+        core: Some(core_id),
+    };
+
+    spawn_task(task, name, info, params)
+}
+
+// ESP-IDF.
+
+#[no_mangle]
+unsafe extern "C" fn esp_timer_create(create_args: *const esp_timer_create_args_t, out_handle: *mut esp_timer_handle_t) -> esp_err_t {
+    if let Some(code) = injected_failure("esp_timer_create") {
+        return code;
+    }
+
+    let args = &*create_args;
+    let handle = {
+        let mut next = NEXT_TIMER.lock().unwrap();
+        *next += 1;
+        *next
+    };
+
+    TIMERS.lock().unwrap().insert(
+        handle,
+        MockTimer {
+            callback: args.callback,
+            arg: args.arg as usize,
+            thread: thread::current().id(),
+            armed: false,
+        },
+    );
+    *out_handle = handle as esp_timer_handle_t;
+
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_timer_start_once(timer: esp_timer_handle_t, _timeout_us: u64) -> esp_err_t {
+    match TIMERS.lock().unwrap().get_mut(&(timer as usize)) {
+        Some(timer) if !timer.armed => {
+            timer.armed = true;
+            ESP_OK as esp_err_t
+        }
+        _ => ESP_ERR_INVALID_STATE,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_timer_stop(timer: esp_timer_handle_t) -> esp_err_t {
+    match TIMERS.lock().unwrap().get_mut(&(timer as usize)) {
+        Some(timer) if timer.armed => {
+            timer.armed = false;
+            ESP_OK as esp_err_t
+        }
+        _ => ESP_ERR_INVALID_STATE,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_timer_delete(timer: esp_timer_handle_t) -> esp_err_t {
+    let mut timers = TIMERS.lock().unwrap();
+
+    match timers.get(&(timer as usize)) {
+        Some(t) if !t.armed => {
+            timers.remove(&(timer as usize));
+            ESP_OK as esp_err_t
+        }
+        _ => ESP_ERR_INVALID_STATE,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn gpio_reset_pin(gpio_num: gpio_num_t) -> esp_err_t {
+    if let Some(code) = injected_failure("gpio_reset_pin") {
+        return code;
+    }
+
+    device(|device| device.gpio.insert(gpio_num, 0));
+
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn gpio_set_direction(_gpio_num: gpio_num_t, _mode: gpio_mode_t) -> esp_err_t {
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn gpio_set_level(gpio_num: gpio_num_t, level: u32) -> esp_err_t {
+    if let Some(code) = injected_failure("gpio_set_level") {
+        return code;
+    }
+
+    device(|device| device.gpio.insert(gpio_num, level));
+
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_get_free_heap_size() -> u32 {
+    device(|device| device.heap.0)
+}
+
+#[no_mangle]
+unsafe extern "C" fn heap_caps_get_largest_free_block(_caps: u32) -> usize {
+    device(|device| device.heap.1)
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_efuse_mac_get_default(mac: *mut u8) -> esp_err_t {
+    ptr::copy_nonoverlapping(MAC.as_ptr(), mac, MAC.len());
+
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_wifi_get_mac(_ifx: wifi_interface_t, mac: *mut u8) -> esp_err_t {
+    esp_efuse_mac_get_default(mac)
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_wifi_set_mode(_mode: wifi_mode_t) -> esp_err_t {
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_wifi_start() -> esp_err_t {
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn app_wifi_init() -> esp_err_t {
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn app_wifi_start(_ticks_to_wait: u32) -> esp_err_t {
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn wifi_prov_mgr_is_provisioned(provisioned: *mut bool) -> esp_err_t {
+    *provisioned = device(|device| device.provisioned);
+
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn wifi_prov_mgr_start_provisioning(
+    _security: wifi_prov_security_t,
+    _pop: *const c_types::c_void,
+    _service_name: *const c_types::c_char,
+    _service_key: *const c_types::c_char,
+) -> esp_err_t {
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn wifi_prov_mgr_deinit() {}
+
+#[no_mangle]
+unsafe extern "C" fn esp_event_handler_register(
+    event_base: esp_event_base_t,
+    event_id: i32,
+    event_handler: esp_event_handler_t,
+    event_handler_arg: *mut c_types::c_void,
+) -> esp_err_t {
+    EVENT_HANDLERS.lock().unwrap().push(EventHandler {
+        base: event_base as usize,
+        id: event_id,
+        handler: event_handler,
+        arg: event_handler_arg as usize,
+    });
+
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_netif_get_handle_from_ifkey(_if_key: *const c_types::c_char) -> *mut esp_netif_t {
+    // Never dereferenced, only passed back to `esp_netif_set_hostname`.
+    ptr::NonNull::dangling().as_ptr()
+}
+
+#[no_mangle]
+unsafe extern "C" fn esp_netif_set_hostname(_esp_netif: *mut esp_netif_t, hostname: *const c_types::c_char) -> esp_err_t {
+    let hostname = CStr::from_ptr(hostname).to_string_lossy().into_owned();
+    device(|device| device.hostname = Some(hostname));
+
+    ESP_OK as esp_err_t
+}
+
+#[no_mangle]
+unsafe extern "C" fn mdns_hostname_set(hostname: *const c_types::c_char) -> esp_err_t {
+    esp_netif_set_hostname(ptr::null_mut(), hostname)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accessory::Accessory;
+    use crate::service::HapService;
+
+    #[test]
+    fn accessories_get_the_information_services() {
+        let accessory = Accessory::new(&accessory_config("Lamp")).unwrap();
+        let services = accessory.services();
+
+        assert_eq!(services.len(), 2);
+        assert_eq!(services[0].uuid().to_bytes_with_nul(), HAP_SERV_UUID_ACCESSORY_INFORMATION);
+        assert_eq!(services[0].iid(), 1);
+        assert_eq!(services[1].uuid().to_bytes_with_nul(), HAP_SERV_UUID_PROTOCOL_INFORMATION);
+    }
+
+    #[test]
+    fn iids_are_assigned_in_order_of_addition() {
+        let accessory = Accessory::new(&accessory_config("Lamp")).unwrap();
+        let iids = accessory.services().iter().map(HapService::iid).collect::<Vec<_>>();
+        let service = HapService::from_raw(unsafe { hap_serv_lightbulb_create(false) });
+        accessory.add_service(&service).unwrap();
+
+        let next = iids[1] + 2;
+        assert_eq!(service.iid(), next);
+        assert_eq!(service.characteristics()[0].iid(), next + 1);
+    }
+
+    #[test]
+    fn config_numbers_start_at_one_and_wrap_around() {
+        unsafe { hap_init(hap_transport_t_HAP_TRANSPORT_WIFI) };
+        assert_eq!(hap::config_number(), Ok(1));
+
+        unsafe { hap_update_config_number() };
+        assert_eq!(hap::config_number(), Ok(2));
+
+        device(|device| device.keystore.insert(config_number_key(), 65535u32.to_ne_bytes().to_vec()));
+        unsafe { hap_update_config_number() };
+        assert_eq!(hap::config_number(), Ok(1));
+    }
+
+    #[test]
+    fn timers_fire_once_when_armed() {
+        extern "C" fn count(arg: *mut c_types::c_void) {
+            unsafe { *(arg as *mut usize) += 1 };
+        }
+
+        let mut fired = 0usize;
+        let args = esp_timer_create_args_t {
+            callback: Some(count),
+            arg: &mut fired as *mut usize as *mut _,
+            dispatch_method: esp_timer_dispatch_t_ESP_TIMER_TASK,
+            name: ptr::null(),
+            skip_unhandled_events: false,
+        };
+        let mut timer = ptr::null_mut();
+
+        unsafe {
+            assert_eq!(esp_timer_create(&args, &mut timer), ESP_OK as esp_err_t);
+            assert_eq!(esp_timer_stop(timer), ESP_ERR_INVALID_STATE);
+            assert_eq!(esp_timer_start_once(timer, 1000), ESP_OK as esp_err_t);
+        }
+        assert_eq!(fire_timers(), 1);
+        assert_eq!(fire_timers(), 0);
+        assert_eq!(fired, 1);
+        assert_eq!(unsafe { esp_timer_delete(timer) }, ESP_OK as esp_err_t);
+    }
+}
@@ -0,0 +1,82 @@
+//! Thin wrappers of the HAP platform abstraction layer, for custom storage backends.
+//!
+//! The platform layer sits below the HAP framework, and its API changes more freely
+//! between SDK releases than `hap.h`. Entries the framework owns, i.e. the accessory
+//! keys and the controller pairings, must not be modified through it while HAP is
+//! running. The raw `hap_platform_*` functions are part of the bindings.
+
+use std::ffi::CString;
+
+use core::ptr;
+
+use crate::error::HapError;
+use crate::*;
+
+/// An NVS partition of the keystore.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Partition {
+    /// Where the HAP framework stores its state, `nvs` by default.
+    Main,
+    /// Read-only factory data such as setup codes, `factory_nvs` by default.
+    Factory,
+}
+
+impl Partition {
+    fn name(self) -> *const c_types::c_char {
+        unsafe {
+            match self {
+                Partition::Main => hap_platform_keystore_get_nvs_partition_name(),
+                Partition::Factory => hap_platform_keystore_get_factory_nvs_partition_name(),
+            }
+        }
+    }
+}
+
+fn c_string(s: &str) -> Result<CString, HapError> {
+    CString::new(s).map_err(|_| HapError::InvalidArg)
+}
+
+/// Read the blob `key` of `namespace`.
+///
+/// The keystore doesn't tell an absent key from other failures, both are a
+/// [`HapError::Fail`].
+pub fn keystore_get(partition: Partition, namespace: &str, key: &str) -> Result<Vec<u8>, HapError> {
+    let (namespace, key) = (c_string(namespace)?, c_string(key)?);
+    let mut size = 0;
+
+    // Without a buffer NVS only reports the size.
+    HapError::check(unsafe {
+        hap_platform_keystore_get(partition.name(), namespace.as_ptr(), key.as_ptr(), ptr::null_mut(), &mut size)
+    })?;
+
+    let mut value = vec![0u8; size];
+    HapError::check(unsafe {
+        hap_platform_keystore_get(partition.name(), namespace.as_ptr(), key.as_ptr(), value.as_mut_ptr(), &mut size)
+    })?;
+    value.truncate(size);
+
+    Ok(value)
+}
+
+/// Write `value` as the blob `key` of `namespace`, replacing any previous value.
+pub fn keystore_set(partition: Partition, namespace: &str, key: &str, value: &[u8]) -> Result<(), HapError> {
+    let (namespace, key) = (c_string(namespace)?, c_string(key)?);
+
+    HapError::check(unsafe {
+        hap_platform_keystore_set(partition.name(), namespace.as_ptr(), key.as_ptr(), value.as_ptr(), value.len())
+    })
+}
+
+/// Remove `key` from `namespace`.
+pub fn keystore_delete(partition: Partition, namespace: &str, key: &str) -> Result<(), HapError> {
+    let (namespace, key) = (c_string(namespace)?, c_string(key)?);
+
+    HapError::check(unsafe { hap_platform_keystore_delete(partition.name(), namespace.as_ptr(), key.as_ptr()) })
+}
+
+/// Remove all keys of `namespace`.
+pub fn keystore_delete_namespace(partition: Partition, namespace: &str) -> Result<(), HapError> {
+    let namespace = c_string(namespace)?;
+
+    HapError::check(unsafe { hap_platform_keystore_delete_namespace(partition.name(), namespace.as_ptr()) })
+}
@@ -0,0 +1,17 @@
+//! The commonly used items of the safe wrappers, for a single glob import:
+//!
+//! ```ignore
+//! use esp_homekit_sdk_sys::prelude::*;
+//! ```
+//!
+//! The lifecycle functions stay namespaced under `hap` and `server`, e.g. `hap::start()`.
+//! The raw bindings are not re-exported here. `examples/lightbulb.rs` uses nothing else.
+
+pub use crate::accessory::{Accessory, Bridge, Category};
+pub use crate::characteristic::{CharFormat, CharValue, HapCharacteristic, Unit};
+pub use crate::context::HapContext;
+pub use crate::error::{HapError, HapStatus};
+pub use crate::event::HapEvent;
+pub use crate::service::{HapService, WriteData};
+pub use crate::services::*;
+pub use crate::{event, hap, server};
@@ -0,0 +1,377 @@
+//! The Wi-Fi onboarding state, e.g. for a status LED.
+//!
+//! Tracked from the ESP-IDF Wi-Fi, IP and provisioning manager events, which
+//! `app_wifi` and custom provisioning flows both go through. The transitions are:
+//!
+//! - `Idle` to `Provisioning` when the provisioning manager starts, i.e. without
+//!   stored credentials.
+//! - `Idle` to `Connecting` when the station starts with stored credentials.
+//! - `Provisioning` to `Connecting` once credentials were received.
+//! - `Connecting` to `Connected` on getting an IP address.
+//! - `Connecting` to `Failed` if the credentials are rejected. The provisioning
+//!   manager keeps accepting new ones, which lead back to `Connecting`.
+//! - `Connected` to `Connecting` when the connection is lost, Wi-Fi reconnects on
+//!   its own.
+//!
+//! With the `app-wifi` feature, [`WifiProvisioner`] starts `app_wifi` with a custom
+//! provisioning service name instead of its fixed `PROV_` one.
+
+#[cfg(feature = "app-wifi")]
+use std::ffi::CString;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex, Once};
+
+use core::ptr;
+use log::*;
+
+use crate::context::HapContext;
+use crate::error::{self, HapError};
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProvState {
+    /// Wi-Fi is not started yet.
+    Idle = 0,
+    /// Waiting for a controller to send Wi-Fi credentials.
+    Provisioning = 1,
+    /// Connecting to the access point, or reconnecting after losing it.
+    Connecting = 2,
+    /// Connected, with an IP address.
+    Connected = 3,
+    /// The received credentials were rejected, e.g. a wrong password.
+    Failed = 4,
+}
+
+impl ProvState {
+    fn from_raw(value: u8) -> Self {
+        match value {
+            1 => ProvState::Provisioning,
+            2 => ProvState::Connecting,
+            3 => ProvState::Connected,
+            4 => ProvState::Failed,
+            _ => ProvState::Idle,
+        }
+    }
+}
+
+type Handler = Arc<dyn Fn(ProvState) + Send + Sync>;
+
+/// The part of a [`HapContext`] kept by this module.
+#[derive(Default)]
+pub(crate) struct State {
+    state: AtomicU8,
+    handlers: Mutex<Vec<Handler>>,
+}
+
+// The event handler is registered once and tracks the state of the global context.
+static REGISTER: Once = Once::new();
+
+/// Start tracking the onboarding state.
+///
+/// Must be called before Wi-Fi is started (e.g. by `app_wifi_start`), or the
+/// transitions up to then are missed.
+pub fn init() -> Result<(), HapError> {
+    let mut result = Ok(());
+
+    REGISTER.call_once(|| {
+        info!("Tracking the provisioning state");
+
+        result = [
+            unsafe { WIFI_PROV_EVENT },
+            unsafe { WIFI_EVENT },
+            unsafe { IP_EVENT },
+        ]
+        .into_iter()
+        .try_for_each(|base| {
+            let code = unsafe {
+                esp_event_handler_register(base, ESP_EVENT_ANY_ID, Some(event_handler), ptr::null_mut())
+            };
+
+            if code == ESP_OK as esp_err_t {
+                Ok(())
+            } else {
+                Err(HapError::Fail(code))
+            }
+        });
+    });
+
+    result
+}
+
+/// [`HapContext::provisioning_state`] of the global context.
+pub fn state() -> ProvState {
+    HapContext::global().provisioning_state()
+}
+
+/// [`HapContext::on_provisioning_state_change`] of the global context.
+pub fn on_state_change<F>(handler: F)
+where
+    F: Fn(ProvState) + Send + Sync + 'static,
+{
+    HapContext::global().on_provisioning_state_change(handler)
+}
+
+impl HapContext {
+    /// The current onboarding state, `Idle` until [`init`] is called.
+    pub fn provisioning_state(&self) -> ProvState {
+        ProvState::from_raw(self.state().provisioning.state.load(Ordering::SeqCst))
+    }
+
+    /// Register a handler called with the new onboarding state on every transition.
+    ///
+    /// Handlers run in the ESP-IDF event loop task and should return quickly.
+    pub fn on_provisioning_state_change<F>(&self, handler: F)
+    where
+        F: Fn(ProvState) + Send + Sync + 'static,
+    {
+        self.state().provisioning.handlers.lock().unwrap().push(Arc::new(handler));
+    }
+
+    fn transition(&self, state: ProvState) {
+        if self.state().provisioning.state.swap(state as u8, Ordering::SeqCst) == state as u8 {
+            return;
+        }
+
+        info!("Provisioning state {:?}", state);
+
+        // Call outside of the lock so handlers may register others.
+        let handlers = self.state().provisioning.handlers.lock().unwrap().clone();
+        for handler in handlers {
+            handler(state);
+        }
+    }
+}
+
+unsafe extern "C" fn event_handler(
+    _arg: *mut c_types::c_void,
+    base: esp_event_base_t,
+    id: i32,
+    _data: *mut c_types::c_void,
+) {
+    let context = HapContext::global();
+    let current = context.provisioning_state();
+
+    let next = if base == WIFI_PROV_EVENT {
+        match id as u32 {
+            wifi_prov_cb_event_t_WIFI_PROV_START => Some(ProvState::Provisioning),
+            wifi_prov_cb_event_t_WIFI_PROV_CRED_RECV => Some(ProvState::Connecting),
+            wifi_prov_cb_event_t_WIFI_PROV_CRED_FAIL => Some(ProvState::Failed),
+            _ => None,
+        }
+    } else if base == WIFI_EVENT {
+        match id as u32 {
+            // While provisioning the station is started to try the received credentials.
+            wifi_event_t_WIFI_EVENT_STA_START if current == ProvState::Idle => Some(ProvState::Connecting),
+            wifi_event_t_WIFI_EVENT_STA_DISCONNECTED if current == ProvState::Connected => {
+                Some(ProvState::Connecting)
+            }
+            _ => None,
+        }
+    } else if base == IP_EVENT && id as u32 == ip_event_t_IP_EVENT_STA_GOT_IP {
+        Some(ProvState::Connected)
+    } else {
+        None
+    };
+
+    if let Some(next) = next {
+        // Handlers are application code.
+        error::catch_panic("provisioning state handler", (), || context.transition(next));
+    }
+}
+
+/// The longest SoftAP SSID, in bytes.
+pub const MAX_SSID_LEN: usize = 32;
+
+/// The provisioning parameters of a [`WifiProvisioner`].
+///
+/// With the SoftAP transport the service name is the SSID of the provisioning access
+/// point. With the BLE transport (`CONFIG_APP_WIFI_PROV_TRANSPORT_BLE`) it is the
+/// advertised device name instead, no access point is started and the SSID has no
+/// meaning; the length limit still applies.
+#[cfg(feature = "app-wifi")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Followed by the last 3 bytes of the station MAC address in hex, e.g.
+    /// `PROV_A1B2C3`, if `service_name` is empty.
+    pub ssid_prefix: String,
+    /// The full service name, overriding `ssid_prefix`.
+    pub service_name: String,
+    /// The WPA2 passphrase of the SoftAP, open if `None`. Ignored with BLE.
+    pub service_key: Option<String>,
+    /// The proof of possession the provisioning app asks for. The default is the
+    /// well-known one of `app_wifi`, so set a per-device one for products.
+    pub pop: String,
+}
+
+#[cfg(feature = "app-wifi")]
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            ssid_prefix: "PROV_".into(),
+            service_name: String::new(),
+            service_key: None,
+            pop: "abcd1234".into(),
+        }
+    }
+}
+
+/// Wi-Fi onboarding through `app_wifi` with a custom service name.
+///
+/// Replaces `app_wifi_start`, but doesn't wait for the connection, see [`state`] and
+/// [`on_state_change`] for that.
+#[cfg(feature = "app-wifi")]
+pub struct WifiProvisioner {
+    config: Config,
+}
+
+#[cfg(feature = "app-wifi")]
+impl WifiProvisioner {
+    /// Fails with [`HapError::InvalidArg`] if the service name, or the prefix with the
+    /// MAC suffix, is longer than [`MAX_SSID_LEN`] bytes.
+    pub fn new(config: Config) -> Result<Self, HapError> {
+        let len = if config.service_name.is_empty() {
+            config.ssid_prefix.len() + 6
+        } else {
+            config.service_name.len()
+        };
+
+        if len > MAX_SSID_LEN {
+            warn!("Provisioning service name of {} bytes is too long", len);
+            return Err(HapError::InvalidArg);
+        }
+
+        Ok(WifiProvisioner { config })
+    }
+
+    /// Initialize Wi-Fi, then start provisioning if there are no stored credentials,
+    /// or connect with them otherwise.
+    pub fn start(&self) -> Result<(), HapError> {
+        HapError::check(unsafe { app_wifi_init() })?;
+
+        let mut provisioned = false;
+        HapError::check(unsafe { wifi_prov_mgr_is_provisioned(&mut provisioned) })?;
+
+        if provisioned {
+            info!("Already provisioned, connecting");
+
+            // Like `app_wifi_start`, the event handlers of `app_wifi` connect once the
+            // station is started.
+            unsafe {
+                wifi_prov_mgr_deinit();
+                HapError::check(esp_wifi_set_mode(wifi_mode_t_WIFI_MODE_STA))?;
+                return HapError::check(esp_wifi_start());
+            }
+        }
+
+        let service_name = self.service_name()?;
+        info!("Starting provisioning as {:?}", service_name);
+
+        let pop = CString::new(self.config.pop.as_str()).map_err(|_| HapError::InvalidArg)?;
+        let service_key = match &self.config.service_key {
+            Some(key) => Some(CString::new(key.as_str()).map_err(|_| HapError::InvalidArg)?),
+            None => None,
+        };
+
+        HapError::check(unsafe {
+            wifi_prov_mgr_start_provisioning(
+                wifi_prov_security_WIFI_PROV_SECURITY_1,
+                pop.as_ptr() as *const c_types::c_void,
+                service_name.as_ptr(),
+                service_key.as_ref().map_or(ptr::null(), |key| key.as_ptr()),
+            )
+        })
+    }
+
+    fn service_name(&self) -> Result<CString, HapError> {
+        let name = if self.config.service_name.is_empty() {
+            let mut mac = [0u8; 6];
+            HapError::check(unsafe { esp_wifi_get_mac(wifi_interface_t_WIFI_IF_STA, mac.as_mut_ptr()) })?;
+
+            format!("{}{:02X}{:02X}{:02X}", self.config.ssid_prefix, mac[3], mac[4], mac[5])
+        } else {
+            self.config.service_name.clone()
+        };
+
+        CString::new(name).map_err(|_| HapError::InvalidArg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock;
+
+    fn post(base: esp_event_base_t, id: u32) {
+        mock::post_event(base, id as i32, ptr::null_mut());
+    }
+
+    fn recorded(context: &HapContext) -> Arc<Mutex<Vec<ProvState>>> {
+        let states = Arc::new(Mutex::new(Vec::new()));
+
+        let recorder = states.clone();
+        context.on_provisioning_state_change(move |state| recorder.lock().unwrap().push(state));
+
+        states
+    }
+
+    #[test]
+    fn handlers_fire_on_transitions() {
+        let context = HapContext::new_isolated();
+        init().unwrap();
+        let states = recorded(&context);
+
+        post(unsafe { WIFI_PROV_EVENT }, wifi_prov_cb_event_t_WIFI_PROV_START);
+        post(unsafe { WIFI_PROV_EVENT }, wifi_prov_cb_event_t_WIFI_PROV_CRED_RECV);
+        post(unsafe { WIFI_PROV_EVENT }, wifi_prov_cb_event_t_WIFI_PROV_CRED_FAIL);
+        post(unsafe { WIFI_PROV_EVENT }, wifi_prov_cb_event_t_WIFI_PROV_CRED_RECV);
+        post(unsafe { IP_EVENT }, ip_event_t_IP_EVENT_STA_GOT_IP);
+        post(unsafe { WIFI_EVENT }, wifi_event_t_WIFI_EVENT_STA_DISCONNECTED);
+
+        assert_eq!(
+            *states.lock().unwrap(),
+            [
+                ProvState::Provisioning,
+                ProvState::Connecting,
+                ProvState::Failed,
+                ProvState::Connecting,
+                ProvState::Connected,
+                ProvState::Connecting,
+            ]
+        );
+        assert_eq!(context.provisioning_state(), ProvState::Connecting);
+    }
+
+    #[test]
+    fn repeated_states_are_reported_once() {
+        let context = HapContext::new_isolated();
+        init().unwrap();
+        let states = recorded(&context);
+
+        post(unsafe { WIFI_EVENT }, wifi_event_t_WIFI_EVENT_STA_START);
+        post(unsafe { IP_EVENT }, ip_event_t_IP_EVENT_STA_GOT_IP);
+        post(unsafe { IP_EVENT }, ip_event_t_IP_EVENT_STA_GOT_IP);
+        context.transition(ProvState::Connected);
+
+        assert_eq!(*states.lock().unwrap(), [ProvState::Connecting, ProvState::Connected]);
+    }
+
+    #[cfg(feature = "app-wifi")]
+    #[test]
+    fn too_long_service_names_are_rejected() {
+        let named = |service_name: &str| Config {
+            service_name: service_name.into(),
+            ..Config::default()
+        };
+        let prefixed = |ssid_prefix: &str| Config {
+            ssid_prefix: ssid_prefix.into(),
+            ..Config::default()
+        };
+
+        assert!(WifiProvisioner::new(named(&"a".repeat(MAX_SSID_LEN))).is_ok());
+        assert_eq!(WifiProvisioner::new(named(&"a".repeat(MAX_SSID_LEN + 1))).err(), Some(HapError::InvalidArg));
+
+        // The MAC suffix takes 6 of the bytes.
+        assert!(WifiProvisioner::new(prefixed(&"a".repeat(MAX_SSID_LEN - 6))).is_ok());
+        assert_eq!(WifiProvisioner::new(prefixed(&"a".repeat(MAX_SSID_LEN - 5))).err(), Some(HapError::InvalidArg));
+    }
+}
@@ -0,0 +1,627 @@
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
+
+use core::ptr;
+use log::*;
+
+use crate::context::HapContext;
+use crate::error::{self, HapError};
+use crate::event::HapEvent;
+use crate::*;
+
+type ErrorHandler = Arc<dyn Fn(HapError) + Send + Sync>;
+
+/// The part of a [`HapContext`] kept by this module.
+#[derive(Default)]
+pub(crate) struct State {
+    error_handler: Mutex<Option<ErrorHandler>>,
+    hostname: Mutex<Option<CString>>,
+    sessions: AtomicUsize,
+}
+
+// The session event handler is registered once and counts for the global context.
+static TRACK_SESSIONS: Once = Once::new();
+
+/// The keys of the setup credentials in the namespace passed to [`setup_credentials_from_nvs`].
+const SETUP_CODE_KEY: &[u8] = b"setup_code\0";
+const SETUP_ID_KEY: &[u8] = b"setup_id\0";
+
+/// [`HapContext::set_error_handler`] of the global context.
+pub fn set_error_handler<F>(handler: F)
+where
+    F: Fn(HapError) + Send + Sync + 'static,
+{
+    HapContext::global().set_error_handler(handler)
+}
+
+/// [`HapContext::report_error`] of the global context.
+pub(crate) fn report_error(err: HapError) {
+    HapContext::global().report_error(err)
+}
+
+/// Whether `hostname` is a legal DNS label: 1-63 ASCII letters, digits or
+/// hyphens, not starting or ending with a hyphen.
+fn is_valid_hostname(hostname: &str) -> bool {
+    (1..=63).contains(&hostname.len())
+        && hostname.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        && !hostname.starts_with('-')
+        && !hostname.ends_with('-')
+}
+
+/// [`HapContext::set_hostname`] of the global context.
+pub fn set_hostname(hostname: &str) -> Result<(), HapError> {
+    HapContext::global().set_hostname(hostname)
+}
+
+/// The longest controller pairing id, `HAP_CTRL_ID_LEN` of the private
+/// `esp_hap_controllers.h` without the NUL.
+const MAX_CONTROLLER_ID_LEN: usize = 63;
+
+/// A controller pairing id for the framework, [`HapError::InvalidArg`] for one that no
+/// controller can have.
+fn controller_id(id: &str) -> Result<CString, HapError> {
+    if id.is_empty() || id.len() > MAX_CONTROLLER_ID_LEN {
+        return Err(HapError::InvalidArg);
+    }
+
+    CString::new(id).map_err(|_| HapError::InvalidArg)
+}
+
+/// Remove the paired controller with the pairing `id`, as an admin controller could.
+///
+/// The sessions of the controller are closed. Removing the last controller leaves the
+/// accessory unpaired and advertised as such, the same state as after
+/// `hap_reset_pairings`. Fails with [`HapError::InvalidArg`] for a malformed id and
+/// with [`HapError::Fail`] if no such controller is paired.
+pub fn remove_controller(id: &str) -> Result<(), HapError> {
+    let id = controller_id(id)?;
+
+    info!("Removing controller {:?}", id);
+
+    HapError::check(unsafe { esp_homekit_shim_remove_controller(id.as_ptr()) })
+}
+
+/// The setup codes HAP forbids for being trivial to guess, besides those of a single
+/// repeated digit.
+const TRIVIAL_SETUP_CODES: &[&str] = &["123-45-678", "876-54-321"];
+
+/// Whether `code` is a setup code of the form `XXX-XX-XXX`, e.g. `111-22-333`, other
+/// than the trivial ones HAP forbids, e.g. `000-00-000` or `123-45-678`.
+fn is_valid_setup_code(code: &str) -> bool {
+    let well_formed = code.len() == 10
+        && code.bytes().enumerate().all(|(i, b)| match i {
+            3 | 6 => b == b'-',
+            _ => b.is_ascii_digit(),
+        });
+    let repeated = code.bytes().filter(|&b| b != b'-').all(|b| b == code.as_bytes()[0]);
+
+    well_formed && !repeated && !TRIVIAL_SETUP_CODES.contains(&code)
+}
+
+/// Whether `id` is a setup id of 4 uppercase letters or digits, e.g. `ES32`.
+fn is_valid_setup_id(id: &str) -> bool {
+    id.len() == 4 && id.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+}
+
+/// Set the setup code controllers enter when pairing, e.g. `111-22-333`.
+pub fn set_setup_code(code: &str) -> Result<(), HapError> {
+    if !is_valid_setup_code(code) {
+        warn!("Invalid setup code");
+        return Err(HapError::InvalidArg);
+    }
+
+    let code = CString::new(code).map_err(|_| HapError::InvalidArg)?;
+
+    HapError::check(unsafe { hap_set_setup_code(code.as_ptr()) })
+}
+
+/// Set the setup id advertised over mDNS and encoded in the setup payload, e.g. `ES32`.
+pub fn set_setup_id(id: &str) -> Result<(), HapError> {
+    if !is_valid_setup_id(id) {
+        warn!("Invalid setup id '{}'", id);
+        return Err(HapError::InvalidArg);
+    }
+
+    let id = CString::new(id).map_err(|_| HapError::InvalidArg)?;
+
+    HapError::check(unsafe { hap_set_setup_id(id.as_ptr()) })
+}
+
+/// A setup id derived from the last 3 bytes of `mac`, as 4 base 36 digits.
+fn setup_id_for_mac(mac: &[u8; 6]) -> String {
+    const DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+    let mut value = u32::from_be_bytes([0, mac[3], mac[4], mac[5]]) % 36u32.pow(4);
+    let mut id = [0u8; 4];
+    for digit in id.iter_mut().rev() {
+        *digit = DIGITS[(value % 36) as usize];
+        value /= 36;
+    }
+
+    String::from_utf8_lossy(&id).into_owned()
+}
+
+/// A setup id derived from the base MAC address, for [`set_setup_id`].
+///
+/// Stable across restarts and distinct for most devices of a batch, but not
+/// guaranteed unique; use provisioned ids, see [`setup_credentials_from_nvs`], where
+/// that matters.
+pub fn setup_id_from_mac() -> Result<String, HapError> {
+    Ok(setup_id_for_mac(&accessory::base_mac()?))
+}
+
+/// Read a string value from the HAP keystore partition, `Err` with the code if it is absent.
+fn read_keystore_str(namespace: &CStr, key: &[u8]) -> Result<String, i32> {
+    let mut buf = [0u8; 16];
+    let mut size = buf.len();
+
+    let code = unsafe {
+        hap_platform_keystore_get(
+            hap_platform_keystore_get_nvs_partition_name(),
+            namespace.as_ptr(),
+            key.as_ptr() as *const i8,
+            buf.as_mut_ptr(),
+            &mut size,
+        )
+    };
+    if code != hap::HAP_SUCCESS_ {
+        return Err(code);
+    }
+
+    // Provisioning tools differ on whether the terminating NUL is stored.
+    let value = buf[..size.min(buf.len())].split(|&b| b == 0).next().unwrap_or(&[]);
+
+    Ok(String::from_utf8_lossy(value).into_owned())
+}
+
+/// The stored (code, id), or else `fallback`, failing with the keystore code without one.
+fn stored_or_fallback(
+    stored: Result<(String, String), i32>,
+    fallback: Option<(&str, &str)>,
+) -> Result<(String, String), HapError> {
+    match (stored, fallback) {
+        (Ok(stored), _) => Ok(stored),
+        (Err(_), Some((code, id))) => Ok((code.to_owned(), id.to_owned())),
+        (Err(code), None) => Err(HapError::Fail(code)),
+    }
+}
+
+/// Load the setup code and id provisioned in NVS and apply them.
+///
+/// Both are read from `namespace` of the HAP keystore partition, as blobs or strings
+/// with or without a terminating NUL:
+///
+/// | Key          | Value        |
+/// |--------------|--------------|
+/// | `setup_code` | `XXX-XX-XXX` |
+/// | `setup_id`   | `XXXX`       |
+///
+/// If either key is absent, `fallback` (code, id) is applied instead, e.g. a
+/// development code for bench testing. Provisioned values that fail validation
+/// are an error rather than a reason to fall back. Returns the applied (code, id).
+pub fn setup_credentials_from_nvs(
+    namespace: &str,
+    fallback: Option<(&str, &str)>,
+) -> Result<(String, String), HapError> {
+    let namespace = CString::new(namespace).map_err(|_| HapError::InvalidArg)?;
+
+    let stored = read_keystore_str(&namespace, SETUP_CODE_KEY)
+        .and_then(|code| Ok((code, read_keystore_str(&namespace, SETUP_ID_KEY)?)));
+    if stored.is_err() {
+        warn!("No setup credentials in NVS namespace {:?}", namespace);
+    }
+
+    let (code, id) = stored_or_fallback(stored, fallback)?;
+
+    set_setup_code(&code)?;
+    set_setup_id(&id)?;
+
+    Ok((code, id))
+}
+
+/// The largest keystore entry [`export_pairing_data`] handles.
+#[cfg(feature = "unsafe-pairing-export")]
+const MAX_KEYSTORE_ENTRY: usize = 1024;
+
+/// Dump the HAP keystore, i.e. the accessory's long-term key pair and all controller
+/// pairings, e.g. to migrate an accessory to new hardware.
+///
+/// **The result holds the long-term secret key in plain text.** Anyone with it can
+/// impersonate the accessory to every paired controller; never log it, and only store
+/// or transmit it encrypted. Two devices restored from the same data must never be
+/// online at the same time.
+///
+/// Every blob of the keystore partition is exported as a record of
+/// `[namespace length: u8][namespace][key length: u8][key][value length: u16 LE][value]`.
+#[cfg(feature = "unsafe-pairing-export")]
+pub fn export_pairing_data() -> Result<Vec<u8>, HapError> {
+    warn!("Exporting the HAP pairing data, including the accessory's secret key");
+
+    let mut data = Vec::new();
+    let mut value = [0u8; MAX_KEYSTORE_ENTRY];
+
+    unsafe {
+        let partition = hap_platform_keystore_get_nvs_partition_name();
+        let mut it = nvs_entry_find(partition, core::ptr::null(), nvs_type_t_NVS_TYPE_BLOB);
+
+        while !it.is_null() {
+            let mut info: nvs_entry_info_t = core::mem::zeroed();
+            nvs_entry_info(it, &mut info);
+            it = nvs_entry_next(it);
+
+            let mut size = value.len();
+            let code = hap_platform_keystore_get(
+                partition,
+                info.namespace_name.as_ptr(),
+                info.key.as_ptr(),
+                value.as_mut_ptr(),
+                &mut size,
+            );
+            if let Err(err) = HapError::check(code) {
+                nvs_release_iterator(it);
+                return Err(err);
+            }
+
+            for name in [&info.namespace_name[..], &info.key[..]] {
+                let name = CStr::from_ptr(name.as_ptr()).to_bytes();
+                data.push(name.len() as u8);
+                data.extend_from_slice(name);
+            }
+            data.extend_from_slice(&(size as u16).to_le_bytes());
+            data.extend_from_slice(&value[..size]);
+        }
+    }
+
+    Ok(data)
+}
+
+/// Restore data from [`export_pairing_data`] into the HAP keystore.
+///
+/// Must be called before `hap::init`, and the same warnings apply. Entries already in
+/// the keystore are overwritten, entries missing from `data` are kept. Malformed data
+/// is rejected with [`HapError::InvalidArg`] before anything is written.
+#[cfg(feature = "unsafe-pairing-export")]
+pub fn import_pairing_data(data: &[u8]) -> Result<(), HapError> {
+    if hap::is_started() {
+        warn!("Pairing data imported while HAP is running, restart to apply it");
+    }
+
+    let entries = parse_pairing_data(data).ok_or(HapError::InvalidArg)?;
+
+    warn!("Importing {} HAP pairing data entries", entries.len());
+
+    for (namespace, key, value) in entries {
+        HapError::check(unsafe {
+            hap_platform_keystore_set(
+                hap_platform_keystore_get_nvs_partition_name(),
+                namespace.as_ptr(),
+                key.as_ptr(),
+                value.as_ptr(),
+                value.len(),
+            )
+        })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "unsafe-pairing-export")]
+fn parse_pairing_data(mut data: &[u8]) -> Option<Vec<(CString, CString, &[u8])>> {
+    fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+        if data.len() < len {
+            return None;
+        }
+
+        let (head, tail) = data.split_at(len);
+        *data = tail;
+
+        Some(head)
+    }
+
+    let mut entries = Vec::new();
+
+    while !data.is_empty() {
+        let len = take(&mut data, 1)?[0] as usize;
+        let namespace = CString::new(take(&mut data, len)?).ok()?;
+        let len = take(&mut data, 1)?[0] as usize;
+        let key = CString::new(take(&mut data, len)?).ok()?;
+        let len = take(&mut data, 2)?;
+        let value = take(&mut data, u16::from_le_bytes([len[0], len[1]]) as usize)?;
+
+        entries.push((namespace, key, value));
+    }
+
+    Some(entries)
+}
+
+/// The TCP port the HAP server listens on, `None` until `hap::start` succeeded.
+pub fn active_port() -> Option<u16> {
+    if !hap::is_started() {
+        return None;
+    }
+
+    u16::try_from(unsafe { hap_platform_httpd_get_port() }).ok().filter(|&port| port != 0)
+}
+
+/// Withdraw the mDNS advertisement of the HAP server, or announce it again, without
+/// stopping the HAP framework, e.g. during a firmware update.
+///
+/// While withdrawn the accessory can't be discovered, neither for pairing nor by
+/// controllers looking up its address, but open sessions are kept. The framework
+/// announces again by itself when the advertised state changes, e.g. on pairing or a
+/// new configuration number, and on `hap::start`. Fails if HAP isn't started.
+pub fn set_advertising(advertise: bool) -> Result<(), HapError> {
+    if !hap::is_started() {
+        return Err(HapError::Fail(hap::HAP_FAIL_));
+    }
+
+    info!("{} the mDNS advertisement", if advertise { "Announcing" } else { "Withdrawing" });
+
+    HapError::check(unsafe { esp_homekit_shim_set_advertising(advertise) })
+}
+
+/// [`HapContext::accessory_count`] of the global context.
+pub fn accessory_count() -> usize {
+    HapContext::global().accessory_count()
+}
+
+/// [`HapContext::active_sessions`] of the global context.
+pub fn active_sessions() -> usize {
+    HapContext::global().active_sessions()
+}
+
+/// Start counting sessions, called by `hap::start` before the server accepts any.
+pub(crate) fn track_sessions() {
+    TRACK_SESSIONS.call_once(|| {
+        let code = unsafe {
+            esp_event_handler_register(HAP_EVENT, ESP_EVENT_ANY_ID, Some(session_event_handler), ptr::null_mut())
+        };
+
+        if code != ESP_OK as esp_err_t {
+            warn!("Failed to track controller sessions: {}", code);
+        }
+    });
+}
+
+unsafe extern "C" fn session_event_handler(
+    _arg: *mut c_types::c_void,
+    _base: esp_event_base_t,
+    id: i32,
+    data: *mut c_types::c_void,
+) {
+    // The data of the controller events is the NUL-terminated controller id.
+    let controller = || {
+        if data.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(data as *const i8).to_string_lossy().into_owned()
+        }
+    };
+
+    let context = HapContext::global();
+
+    let event = match id as u32 {
+        hap_event_t_HAP_EVENT_CTRL_CONNECTED => {
+            context.state().server.sessions.fetch_add(1, Ordering::SeqCst);
+            HapEvent::ControllerConnected { id: controller() }
+        }
+        hap_event_t_HAP_EVENT_CTRL_DISCONNECTED => {
+            // Sessions opened before a reset still close afterwards.
+            let sessions = &context.state().server.sessions;
+            let _ = sessions.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+            // The subscriptions of a controller end with its session.
+            error::catch_panic("subscription event handler", (), || context.check_subscriptions());
+            HapEvent::ControllerDisconnected { id: controller() }
+        }
+        // Subscriptions are changed by write requests.
+        hap_event_t_HAP_EVENT_SET_CHAR_COMPLETED => {
+            error::catch_panic("subscription event handler", (), || context.check_subscriptions());
+            return;
+        }
+        _ => return,
+    };
+
+    // Handlers are application code.
+    error::catch_panic("session event handler", (), || context.emit(event));
+}
+
+impl HapContext {
+    /// Install a handler invoked whenever a `hap_*` call made by this crate fails.
+    ///
+    /// Meant for centralized telemetry; the failing call still returns its error.
+    /// Only calls made through this crate are observed, errors the HAP framework
+    /// handles internally are only logged to the console.
+    pub fn set_error_handler<F>(&self, handler: F)
+    where
+        F: Fn(HapError) + Send + Sync + 'static,
+    {
+        *self.state().server.error_handler.lock().unwrap() = Some(Arc::new(handler));
+    }
+
+    pub(crate) fn report_error(&self, err: HapError) {
+        // Call outside of the lock so the handler may itself use the wrapper.
+        let handler = self.state().server.error_handler.lock().unwrap().clone();
+
+        if let Some(handler) = handler {
+            handler(err);
+        }
+    }
+
+    /// Override the hostname used for DHCP and mDNS, e.g. `living-room-lamp`.
+    ///
+    /// Must be called before `hap::start`; the name is applied to the Wi-Fi station
+    /// interface and the mDNS responder once the HAP framework starts. Changing it after
+    /// the accessory has been advertised only takes effect after a restart.
+    pub fn set_hostname(&self, hostname: &str) -> Result<(), HapError> {
+        if !is_valid_hostname(hostname) {
+            warn!("Invalid hostname '{}'", hostname);
+            return Err(HapError::InvalidArg);
+        }
+
+        if self.is_started() {
+            warn!("Hostname changed after start, restart to advertise '{}'", hostname);
+        }
+
+        let hostname = CString::new(hostname).map_err(|_| HapError::InvalidArg)?;
+        *self.state().server.hostname.lock().unwrap() = Some(hostname);
+
+        Ok(())
+    }
+
+    pub(crate) fn apply_hostname(&self) {
+        let hostname = self.state().server.hostname.lock().unwrap().clone();
+
+        if let Some(hostname) = hostname {
+            info!("Setting hostname to {:?}", hostname);
+
+            unsafe {
+                let netif = esp_netif_get_handle_from_ifkey(b"WIFI_STA_DEF\0".as_ptr() as *const i8);
+                if !netif.is_null() {
+                    esp_netif_set_hostname(netif, hostname.as_ptr());
+                }
+
+                mdns_hostname_set(hostname.as_ptr());
+            }
+        }
+    }
+
+    /// The number of accessories in the database, the primary accessory included.
+    pub fn accessory_count(&self) -> usize {
+        self.aid_count()
+    }
+
+    /// The number of verified controller sessions currently open over IP.
+    ///
+    /// The HAP framework doesn't expose its session table, so this counts the
+    /// connect and disconnect events since `hap::start`. A controller may hold
+    /// several sessions, e.g. an iPhone and a home hub sharing a pairing. HAP over
+    /// BLE would allow a single session at a time, but the SDK only implements IP.
+    pub fn active_sessions(&self) -> usize {
+        self.state().server.sessions.load(Ordering::SeqCst)
+    }
+
+    /// All sessions are closed when the server stops.
+    pub(crate) fn reset_sessions(&self) {
+        self.state().server.sessions.store(0, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn controller_ids_no_controller_can_have_are_rejected() {
+        assert_eq!(controller_id(""), Err(HapError::InvalidArg));
+        assert_eq!(controller_id("ctrl\0id"), Err(HapError::InvalidArg));
+        assert_eq!(controller_id(&"A".repeat(MAX_CONTROLLER_ID_LEN + 1)), Err(HapError::InvalidArg));
+    }
+
+    #[test]
+    fn controller_ids_are_passed_through() {
+        let id = "2B6D3E0C-7A41-4C5F-9E1A-3F0B8D2C6A17";
+
+        assert_eq!(controller_id(id).unwrap().to_str(), Ok(id));
+        assert!(controller_id(&"A".repeat(MAX_CONTROLLER_ID_LEN)).is_ok());
+    }
+
+    #[test]
+    fn failed_calls_reach_the_error_handler() {
+        let context = HapContext::new_isolated();
+        let errors = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = errors.clone();
+        context.set_error_handler(move |err| recorded.lock().unwrap().push(err));
+
+        let accessory = crate::accessory::Accessory::new(&crate::mock::accessory_config("Lamp")).unwrap();
+        crate::mock::fail_next("hap_add_bridged_accessory", hap::HAP_FAIL_);
+
+        assert_eq!(context.add_bridged(&accessory, None), Err(HapError::Fail(hap::HAP_FAIL_)));
+        assert_eq!(*errors.lock().unwrap(), [HapError::Fail(hap::HAP_FAIL_)]);
+    }
+
+    #[test]
+    fn hostnames_must_be_dns_labels() {
+        let context = HapContext::new_isolated();
+
+        assert_eq!(context.set_hostname("living room"), Err(HapError::InvalidArg));
+        assert_eq!(context.set_hostname(""), Err(HapError::InvalidArg));
+        assert_eq!(context.set_hostname("-lamp"), Err(HapError::InvalidArg));
+        assert_eq!(context.set_hostname(&"a".repeat(64)), Err(HapError::InvalidArg));
+
+        context.apply_hostname();
+        assert_eq!(crate::mock::hostname(), None);
+
+        assert_eq!(context.set_hostname("living-room-lamp"), Ok(()));
+        context.apply_hostname();
+        assert_eq!(crate::mock::hostname().as_deref(), Some("living-room-lamp"));
+    }
+
+    #[test]
+    fn setup_codes_must_be_well_formed() {
+        assert!(is_valid_setup_code("111-22-333"));
+        assert!(is_valid_setup_code("031-45-154"));
+
+        assert!(!is_valid_setup_code("11122333"));
+        assert!(!is_valid_setup_code("111-22-33"));
+        assert!(!is_valid_setup_code("111-22-3333"));
+        assert!(!is_valid_setup_code("111 22 333"));
+        assert!(!is_valid_setup_code("11a-22-333"));
+    }
+
+    #[test]
+    fn trivial_setup_codes_are_rejected() {
+        for digit in '0'..='9' {
+            let code = format!("{0}{0}{0}-{0}{0}-{0}{0}{0}", digit);
+            assert!(!is_valid_setup_code(&code), "{}", code);
+        }
+
+        assert!(!is_valid_setup_code("123-45-678"));
+        assert!(!is_valid_setup_code("876-54-321"));
+    }
+
+    #[test]
+    fn setup_ids_are_four_uppercase_alphanumerics() {
+        assert!(is_valid_setup_id("ES32"));
+        assert!(is_valid_setup_id("0000"));
+
+        assert!(!is_valid_setup_id("es32"));
+        assert!(!is_valid_setup_id("ES3"));
+        assert!(!is_valid_setup_id("ES321"));
+        assert!(!is_valid_setup_id("ES-2"));
+    }
+
+    #[test]
+    fn setup_ids_for_macs_are_valid_base_36() {
+        assert_eq!(setup_id_for_mac(&[0x24, 0x0A, 0xC4, 0x00, 0x00, 0x01]), "0001");
+        assert_eq!(setup_id_for_mac(&[0x24, 0x0A, 0xC4, 0xA1, 0xB2, 0xC3]), "B4QR");
+        assert_eq!(setup_id_for_mac(&[0x24, 0x0A, 0xC4, 0xFF, 0xFF, 0xFF]), "ZLDR");
+
+        assert!(is_valid_setup_id(&setup_id_for_mac(&[0xFF; 6])));
+    }
+
+    #[test]
+    fn stored_setup_credentials_win_over_the_fallback() {
+        let stored = Ok(("111-22-333".to_owned(), "ES32".to_owned()));
+
+        assert_eq!(
+            stored_or_fallback(stored, Some(("031-45-154", "DEV1"))),
+            Ok(("111-22-333".to_owned(), "ES32".to_owned()))
+        );
+    }
+
+    #[test]
+    fn empty_namespace_falls_back() {
+        let empty = Err(ESP_ERR_NVS_NOT_FOUND as i32);
+
+        assert_eq!(
+            stored_or_fallback(empty.clone(), Some(("031-45-154", "DEV1"))),
+            Ok(("031-45-154".to_owned(), "DEV1".to_owned()))
+        );
+        assert_eq!(
+            stored_or_fallback(empty, None),
+            Err(HapError::Fail(ESP_ERR_NVS_NOT_FOUND as i32))
+        );
+    }
+}
@@ -1,6 +1,14 @@
-use core::ptr;
+use std::any::Any;
+use std::ffi::{CStr, CString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use core::{ptr, slice};
 use log::*;
 
+use crate::characteristic::{self, CharValue, HapCharacteristic};
+use crate::deferred;
+use crate::error::{self, HapError, HapStatus};
 use crate::*;
 
 pub fn create() -> *mut hap_serv_t {
@@ -32,3 +40,648 @@ pub fn set_write_cb(service: *mut hap_serv_t, write: hap_serv_write_t) {
         hap_serv_set_write_cb(service, write)
     }
 }
+
+/// A single entry of a write request, as passed to a service write handler.
+pub struct WriteData<'a> {
+    raw: &'a hap_write_data_t,
+}
+
+impl<'a> WriteData<'a> {
+    pub fn characteristic(&self) -> HapCharacteristic {
+        HapCharacteristic::from_raw(self.raw.hc)
+    }
+
+    /// The type UUID of the written characteristic, e.g. `HAP_CHAR_UUID_ON`.
+    pub fn uuid(&self) -> &CStr {
+        unsafe { CStr::from_ptr(hap_char_get_type_uuid(self.raw.hc)) }
+    }
+
+    pub fn is_uuid(&self, uuid: &[u8]) -> bool {
+        self.uuid().to_bytes_with_nul() == uuid
+    }
+
+    pub fn raw_value(&self) -> &hap_val_t {
+        &self.raw.val
+    }
+
+    /// The additional authorization data sent along with the write, for characteristics
+    /// with [`CharPermissions::ADDITIONAL_AUTHORIZATION`](characteristic::CharPermissions::ADDITIONAL_AUTHORIZATION).
+    ///
+    /// Checking it is up to the handler, which rejects an invalid or missing blob with
+    /// [`HapStatus::InsufficientAuthorization`]. The HAP framework has no API for the
+    /// setup of the authorization, so the data has to be provisioned out of band.
+    pub fn auth_data(&self) -> Option<&[u8]> {
+        let auth = &self.raw.auth_data;
+
+        if auth.data.is_null() || auth.len <= 0 {
+            None
+        } else {
+            Some(unsafe { slice::from_raw_parts(auth.data, auth.len as usize) })
+        }
+    }
+}
+
+type WriteHandler = Arc<Mutex<dyn FnMut(&WriteData) -> Result<Option<CharValue>, HapStatus> + Send>>;
+
+type RenameHandler = Arc<Mutex<dyn FnMut(&str) -> Result<(), HapStatus> + Send>>;
+
+type DeferredReadHandler = Arc<Mutex<dyn FnMut(HapCharacteristic) -> Option<CharValue> + Send>>;
+
+type DeferredWriteHandler = Arc<Mutex<dyn FnMut(HapCharacteristic, CharValue) -> Option<CharValue> + Send>>;
+
+type UserData = Arc<dyn Any + Send + Sync>;
+
+/// The type UUID of `ConfiguredName`, which the SDK doesn't define.
+pub(crate) const HAP_CHAR_UUID_CONFIGURED_NAME: &[u8] = b"E3\0";
+
+/// Rust-side state attached to a service through `hap_serv_set_priv`.
+///
+/// Services live for the lifetime of the accessory database, so the state is
+/// leaked on creation and never freed. The priv slot belongs to this crate: calling
+/// `hap_serv_set_priv` on a service used through [`HapService`] breaks it, attach
+/// application data with [`HapService::set_user_data`] instead.
+#[derive(Default)]
+struct ServiceState {
+    write: Mutex<Option<WriteHandler>>,
+    rename: Mutex<Option<RenameHandler>>,
+    deferred_read: Mutex<Option<DeferredReadHandler>>,
+    deferred_write: Mutex<Option<DeferredWriteHandler>>,
+    /// The characteristics with a deferred read queued, by address.
+    pending_reads: Mutex<Vec<usize>>,
+    /// Held while a write request is stored, so deferred writes apply after it.
+    writing: Mutex<()>,
+    user_data: Mutex<Option<UserData>>,
+    hidden: AtomicBool,
+    primary: AtomicBool,
+    unreachable: AtomicBool,
+}
+
+static STATE_LOCK: Mutex<()> = Mutex::new(());
+
+/// The states attached by this crate, to catch foreign `hap_serv_set_priv` calls.
+#[cfg(debug_assertions)]
+static OWNED_STATES: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+/// A handle to a `hap_serv_t` owned by the HAP framework.
+#[derive(Clone, Copy)]
+pub struct HapService {
+    ptr: *mut hap_serv_t,
+}
+
+// The HAP framework serializes access to the service internally and the
+// Rust-side state is guarded by mutexes.
+unsafe impl Send for HapService {}
+unsafe impl Sync for HapService {}
+
+impl HapService {
+    pub fn from_raw(ptr: *mut hap_serv_t) -> Self {
+        HapService { ptr }
+    }
+
+    pub fn as_raw(&self) -> *mut hap_serv_t {
+        self.ptr
+    }
+
+    /// The instance id, assigned once the accessory is added to the database.
+    pub fn iid(&self) -> u32 {
+        unsafe { hap_serv_get_iid(self.ptr) }
+    }
+
+    pub fn add_name(&self, name: &str) -> Result<(), HapError> {
+        let name = CString::new(name).map_err(|_| HapError::InvalidArg)?;
+
+        self.add_char(HapCharacteristic::from_raw(unsafe {
+            hap_char_name_create(name.as_ptr() as *mut i8)
+        }))
+    }
+
+    /// Add a characteristic, setting the unit of Apple-defined characteristics.
+    ///
+    /// Custom characteristics reusing an Apple UUID get the unit too, see
+    /// [`characteristic::default_unit`].
+    pub fn add_char(&self, characteristic: HapCharacteristic) -> Result<(), HapError> {
+        if let Some(unit) = characteristic::default_unit(characteristic.uuid().to_bytes_with_nul()) {
+            characteristic.set_unit(unit)?;
+        }
+
+        HapError::check(unsafe { hap_serv_add_char(self.ptr, characteristic.as_raw()) })
+    }
+
+    /// The type UUID, e.g. `HAP_SERV_UUID_LIGHTBULB`.
+    pub fn uuid(&self) -> &CStr {
+        unsafe { CStr::from_ptr(hap_serv_get_type_uuid(self.ptr)) }
+    }
+
+    /// The characteristics of the service, in the order they were added.
+    pub fn characteristics(&self) -> Vec<HapCharacteristic> {
+        let mut characteristics = Vec::new();
+        let mut ptr = unsafe { hap_serv_get_first_char(self.ptr) };
+
+        while !ptr.is_null() {
+            characteristics.push(HapCharacteristic::from_raw(ptr));
+            ptr = unsafe { hap_char_get_next(ptr) };
+        }
+
+        characteristics
+    }
+
+    /// Hide the service from the Home app, e.g. for a diagnostics service read by a companion app.
+    ///
+    /// Must be called before the service is added to an accessory. The HAP framework
+    /// can't unhide a service, so `false` only succeeds if it was never hidden. The
+    /// accessory and protocol information services can't be hidden.
+    pub fn set_hidden(&self, hidden: bool) -> Result<(), HapError> {
+        let state = self.state();
+
+        if !hidden && state.hidden.load(Ordering::SeqCst) {
+            return Err(HapError::InvalidArg);
+        }
+        if !hidden {
+            return Ok(());
+        }
+
+        let uuid = self.uuid().to_bytes_with_nul();
+        if uuid == HAP_SERV_UUID_ACCESSORY_INFORMATION || uuid == HAP_SERV_UUID_PROTOCOL_INFORMATION {
+            warn!("Service {:?} can't be hidden", self.uuid());
+            return Err(HapError::InvalidArg);
+        }
+
+        HapError::check(unsafe { hap_serv_mark_hidden(self.ptr) })?;
+        state.hidden.store(true, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Shorthand for [`set_hidden(true)`](Self::set_hidden).
+    pub fn mark_hidden(&self) -> Result<(), HapError> {
+        self.set_hidden(true)
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.state().hidden.load(Ordering::SeqCst)
+    }
+
+    /// Mark the service as the one the Home app shows prominently for its accessory.
+    ///
+    /// Must be called before the service is added to an accessory, which may only
+    /// have one primary service, see
+    /// [`Accessory::add_service`](crate::accessory::Accessory::add_service).
+    pub fn mark_primary(&self) -> Result<(), HapError> {
+        HapError::check(unsafe { hap_serv_mark_primary(self.ptr) })?;
+        self.state().primary.store(true, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    pub fn is_primary(&self) -> bool {
+        self.state().primary.load(Ordering::SeqCst)
+    }
+
+    /// See [`Accessory::set_reachable`](crate::accessory::Accessory::set_reachable).
+    pub(crate) fn set_reachable(&self, reachable: bool) {
+        self.state().unreachable.store(!reachable, Ordering::SeqCst);
+
+        // Reject writes even without a write handler.
+        unsafe {
+            hap_serv_set_write_cb(self.ptr, Some(write_trampoline));
+        }
+    }
+
+    /// Route writes through this crate without installing a handler, e.g. for
+    /// [`HapCharacteristic::bind_output_gpio`].
+    pub(crate) fn route_writes(&self) {
+        self.state();
+
+        unsafe {
+            hap_serv_set_write_cb(self.ptr, Some(write_trampoline));
+        }
+    }
+
+    /// Look up a characteristic by its type UUID, e.g. `HAP_CHAR_UUID_ON`.
+    pub fn char_by_uuid(&self, uuid: &[u8]) -> Option<HapCharacteristic> {
+        let ptr = unsafe { hap_serv_get_char_by_uuid(self.ptr, uuid.as_ptr() as *mut i8) };
+
+        if ptr.is_null() {
+            None
+        } else {
+            Some(HapCharacteristic::from_raw(ptr))
+        }
+    }
+
+    /// Declare `other` as linked to this service.
+    pub fn link(&self, other: &HapService) -> Result<(), HapError> {
+        HapError::check(unsafe { hap_serv_link_serv(self.ptr, other.ptr) })
+    }
+
+    /// Install a handler called for every characteristic written by a controller.
+    ///
+    /// On `Ok` the written value is stored in the characteristic, on `Err` the
+    /// status is reported back to the controller and the value is discarded.
+    pub fn on_write<F>(&self, mut handler: F)
+    where
+        F: FnMut(&WriteData) -> Result<(), HapStatus> + Send + 'static,
+    {
+        self.on_write_response(move |write| handler(write).map(|()| None));
+    }
+
+    /// Like [`on_write`](Self::on_write), but a value returned by the handler is stored
+    /// instead of the written one and sent back as the write response.
+    ///
+    /// Controllers only ask for a response from characteristics with the
+    /// [`CharPermissions::WRITE_RESPONSE`](characteristic::CharPermissions::WRITE_RESPONSE)
+    /// permission, e.g. the lock control point or the setup data stream transport.
+    /// Returning `None` stores the written value as with [`on_write`](Self::on_write).
+    pub fn on_write_response<F>(&self, handler: F)
+    where
+        F: FnMut(&WriteData) -> Result<Option<CharValue>, HapStatus> + Send + 'static,
+    {
+        let state = self.state();
+        state.deferred_write.lock().unwrap().take();
+        *state.write.lock().unwrap() = Some(Arc::new(Mutex::new(handler)));
+
+        unsafe {
+            hap_serv_set_write_cb(self.ptr, Some(write_trampoline));
+        }
+    }
+
+    /// Install a handler applying controller writes on the [`deferred`] worker task,
+    /// for devices too slow to drive from the HAP server task.
+    ///
+    /// Writes are acknowledged and stored at once, after the reachability and valid
+    /// values checks. The handler then runs with the written value; if it returns the
+    /// value actually applied, e.g. after clamping, that is stored and notified. Writes
+    /// the worker queue rejects fail with [`HapStatus::Busy`]. Replaces an
+    /// [`on_write`](Self::on_write) handler.
+    pub fn on_write_deferred<F>(&self, handler: F)
+    where
+        F: FnMut(HapCharacteristic, CharValue) -> Option<CharValue> + Send + 'static,
+    {
+        let state = self.state();
+        state.write.lock().unwrap().take();
+        *state.deferred_write.lock().unwrap() = Some(Arc::new(Mutex::new(handler)));
+
+        unsafe {
+            hap_serv_set_write_cb(self.ptr, Some(write_trampoline));
+        }
+    }
+
+    /// Install a handler refreshing a characteristic on the [`deferred`] worker task
+    /// whenever a controller reads it, e.g. from a slow sensor.
+    ///
+    /// The read is answered at once with the last known value. A value returned by the
+    /// handler is stored and notified, so it reaches the controller as an event or
+    /// with the next read. Reads while a refresh of the characteristic is queued don't
+    /// queue another.
+    pub fn on_read_deferred<F>(&self, handler: F)
+    where
+        F: FnMut(HapCharacteristic) -> Option<CharValue> + Send + 'static,
+    {
+        *self.state().deferred_read.lock().unwrap() = Some(Arc::new(Mutex::new(handler)));
+
+        unsafe {
+            hap_serv_set_read_cb(self.ptr, Some(read_trampoline));
+        }
+    }
+
+    /// Add a `ConfiguredName`, the name of the service as renamed by the user in the Home app.
+    ///
+    /// `on_rename` is called with the new name whenever a controller renames the service,
+    /// instead of the [`on_write`](Self::on_write) handler; store it there to restore it as
+    /// `name` on the next boot. Names beyond [`characteristic::MAX_STRING_LEN`] bytes are
+    /// rejected, both here and from controllers.
+    pub fn add_configured_name<F>(&self, name: &str, on_rename: F) -> Result<HapCharacteristic, HapError>
+    where
+        F: FnMut(&str) -> Result<(), HapStatus> + Send + 'static,
+    {
+        let characteristic = self.add_configured_name_char(name)?;
+        self.set_rename_handler(on_rename);
+
+        Ok(characteristic)
+    }
+
+    pub(crate) fn add_configured_name_char(&self, name: &str) -> Result<HapCharacteristic, HapError> {
+        if name.len() > characteristic::MAX_STRING_LEN {
+            return Err(HapError::InvalidArg);
+        }
+
+        let name = CString::new(name).map_err(|_| HapError::InvalidArg)?;
+        let perms = HAP_CHAR_PERM_PR | HAP_CHAR_PERM_PW | HAP_CHAR_PERM_EV;
+        let characteristic = HapCharacteristic::from_raw(unsafe {
+            hap_char_string_create(
+                HAP_CHAR_UUID_CONFIGURED_NAME.as_ptr() as *mut i8,
+                perms as u16,
+                name.as_ptr() as *mut i8,
+            )
+        });
+        self.add_char(characteristic)?;
+
+        Ok(characteristic)
+    }
+
+    pub(crate) fn set_rename_handler<F>(&self, on_rename: F)
+    where
+        F: FnMut(&str) -> Result<(), HapStatus> + Send + 'static,
+    {
+        *self.state().rename.lock().unwrap() = Some(Arc::new(Mutex::new(on_rename)));
+
+        unsafe {
+            hap_serv_set_write_cb(self.ptr, Some(write_trampoline));
+        }
+    }
+
+    /// Attach application data, replacing (and dropping) any previous data.
+    pub fn set_user_data<T>(&self, data: T)
+    where
+        T: Any + Send + Sync,
+    {
+        *self.state().user_data.lock().unwrap() = Some(Arc::new(data));
+    }
+
+    /// The data attached with [`set_user_data`](Self::set_user_data), if it is a `T`.
+    ///
+    /// Returned as an `Arc` since the data may be replaced while still in use.
+    pub fn user_data<T>(&self) -> Option<Arc<T>>
+    where
+        T: Any + Send + Sync,
+    {
+        let data = self.state().user_data.lock().unwrap().clone()?;
+
+        data.downcast::<T>().ok()
+    }
+
+    fn state(&self) -> &'static ServiceState {
+        let _guard = STATE_LOCK.lock().unwrap();
+
+        unsafe {
+            let state = hap_serv_get_priv(self.ptr) as *const ServiceState;
+            if !state.is_null() {
+                #[cfg(debug_assertions)]
+                debug_assert!(
+                    OWNED_STATES.lock().unwrap().contains(&(state as usize)),
+                    "service priv was set with hap_serv_set_priv, use HapService::set_user_data"
+                );
+
+                return &*state;
+            }
+
+            let state: &'static ServiceState = Box::leak(Box::default());
+            hap_serv_set_priv(self.ptr, state as *const ServiceState as *mut c_types::c_void);
+
+            #[cfg(debug_assertions)]
+            OWNED_STATES.lock().unwrap().push(state as *const ServiceState as usize);
+
+            state
+        }
+    }
+}
+
+unsafe extern "C" fn write_trampoline(
+    write_data: *mut hap_write_data_t,
+    count: c_types::c_int,
+    serv_priv: *mut c_types::c_void,
+    _write_priv: *mut c_types::c_void,
+) -> c_types::c_int {
+    let state: &'static ServiceState = &*(serv_priv as *const ServiceState);
+    let _writing = state.writing.lock().unwrap_or_else(PoisonError::into_inner);
+    // Cloned out, so handlers can replace themselves or add a `ConfiguredName`.
+    let handler = state.write.lock().unwrap().clone();
+    let rename = state.rename.lock().unwrap().clone();
+    let deferred = state.deferred_write.lock().unwrap().clone();
+    let mut ret = hap::HAP_SUCCESS_;
+
+    for raw in slice::from_raw_parts_mut(write_data, count as usize) {
+        let characteristic = HapCharacteristic::from_raw(raw.hc);
+        let renamed = rename
+            .as_ref()
+            .filter(|_| characteristic.uuid().to_bytes_with_nul() == HAP_CHAR_UUID_CONFIGURED_NAME);
+
+        // A panicking handler fails the write instead of unwinding into the framework.
+        let result = if state.unreachable.load(Ordering::SeqCst) {
+            Err(HapStatus::CommunicationError)
+        } else if !characteristic.accepts(&raw.val) {
+            Err(HapStatus::InvalidValue)
+        } else if let Some(rename) = renamed {
+            let name = CStr::from_ptr(raw.val.s).to_string_lossy();
+            if name.len() > characteristic::MAX_STRING_LEN {
+                Err(HapStatus::InvalidValue)
+            } else {
+                let failed = Err(HapStatus::Busy);
+                error::catch_panic("rename handler", failed, || error::lock_handler(rename)(&name)).map(|()| None)
+            }
+        } else if let Some(deferred) = &deferred {
+            defer_write(state, deferred.clone(), characteristic, &raw.val).map(|()| None)
+        } else if let Some(handler) = &handler {
+            let failed = Err(HapStatus::Busy);
+            error::catch_panic("write handler", failed, || error::lock_handler(handler)(&WriteData { raw }))
+        } else {
+            Ok(None)
+        };
+
+        // A bound GPIO follows the value the write stores, once the handler accepted it.
+        let result = result.and_then(|response| {
+            match &response {
+                Some(response) => response.with_raw(|value| characteristic.drive_gpio(value)),
+                None => characteristic.drive_gpio(&raw.val),
+            }
+            .map(|()| response)
+        });
+
+        *raw.status = match result {
+            // The framework answers a write asking for a response with the stored value.
+            Ok(Some(response)) => {
+                characteristic.store_write_response(&response);
+                hap_status_t_HAP_STATUS_SUCCESS
+            }
+            Ok(None) => {
+                hap_char_update_val(raw.hc, &mut raw.val);
+                characteristic.record_write(&raw.val);
+                hap_status_t_HAP_STATUS_SUCCESS
+            }
+            Err(status) => {
+                ret = hap::HAP_FAIL_;
+                status.as_raw()
+            }
+        };
+    }
+
+    ret
+}
+
+/// Queue a write for the [`HapService::on_write_deferred`] handler.
+fn defer_write(
+    state: &'static ServiceState,
+    handler: DeferredWriteHandler,
+    characteristic: HapCharacteristic,
+    raw: &hap_val_t,
+) -> Result<(), HapStatus> {
+    let format = characteristic.format().ok_or(HapStatus::InvalidValue)?;
+    let value = unsafe { CharValue::from_raw(format, raw) };
+
+    let job = Box::new(move || {
+        // Wait for the write to be stored, so the applied value isn't overwritten by it.
+        drop(state.writing.lock().unwrap_or_else(PoisonError::into_inner));
+
+        let applied = error::lock_handler(&handler)(characteristic, value);
+        if let Some(applied) = applied {
+            if let Err(err) = characteristic.update(&applied) {
+                warn!("Failed to store the applied value: {}", err);
+            }
+        }
+    });
+
+    deferred::submit(job).map_err(|_| HapStatus::Busy)
+}
+
+/// Marks a characteristic as having a deferred read queued, until the read starts or
+/// is dropped from the queue.
+struct PendingRead {
+    state: &'static ServiceState,
+    key: usize,
+}
+
+impl Drop for PendingRead {
+    fn drop(&mut self) {
+        self.state.pending_reads.lock().unwrap().retain(|&key| key != self.key);
+    }
+}
+
+unsafe extern "C" fn read_trampoline(
+    hc: *mut hap_char_t,
+    status_code: *mut hap_status_t,
+    serv_priv: *mut c_types::c_void,
+    _read_priv: *mut c_types::c_void,
+) -> c_types::c_int {
+    let state: &'static ServiceState = &*(serv_priv as *const ServiceState);
+
+    // The framework answers with the stored value, i.e. the last known one.
+    *status_code = hap_status_t_HAP_STATUS_SUCCESS;
+
+    let handler = match state.deferred_read.lock().unwrap().clone() {
+        Some(handler) => handler,
+        None => return hap::HAP_SUCCESS_,
+    };
+
+    let key = hc as usize;
+    {
+        let mut pending_reads = state.pending_reads.lock().unwrap();
+        if pending_reads.contains(&key) {
+            return hap::HAP_SUCCESS_;
+        }
+        pending_reads.push(key);
+    }
+    let pending = PendingRead { state, key };
+
+    let characteristic = HapCharacteristic::from_raw(hc);
+    let job = Box::new(move || {
+        // Reads from here on see a stale value again, so let them queue a refresh.
+        drop(pending);
+
+        let value = error::lock_handler(&handler)(characteristic);
+        if let Some(value) = value {
+            if let Err(err) = characteristic.update(&value) {
+                warn!("Failed to store the read value: {}", err);
+            }
+        }
+    });
+
+    // A rejected job is dropped, which clears the pending read.
+    let _ = deferred::submit(job);
+
+    hap::HAP_SUCCESS_
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::accessory::Accessory;
+    use crate::context::HapContext;
+    use crate::mock;
+
+    fn switch() -> (HapService, HapCharacteristic) {
+        let accessory = Accessory::new(&mock::accessory_config("Switch")).unwrap();
+        let service = HapService::from_raw(unsafe { hap_serv_switch_create(false) });
+        accessory.add_service(&service).unwrap();
+        let on = service.char_by_uuid(HAP_CHAR_UUID_ON).unwrap();
+
+        (service, on)
+    }
+
+    #[test]
+    fn write_handlers_can_replace_themselves() {
+        HapContext::new_isolated();
+        let (service, on) = switch();
+
+        service.on_write(move |_| {
+            service.on_write(|_| Err(HapStatus::InvalidValue));
+            Ok(())
+        });
+
+        assert_eq!(mock::write(on, &CharValue::Bool(true)), HapStatus::Success);
+        assert_eq!(mock::write(on, &CharValue::Bool(false)), HapStatus::InvalidValue);
+    }
+
+    #[test]
+    fn write_handlers_can_add_a_configured_name() {
+        HapContext::new_isolated();
+        let (service, on) = switch();
+        let (added_tx, added) = mpsc::channel();
+        let (renamed_tx, renamed) = mpsc::channel();
+
+        service.on_write(move |_| {
+            let renamed_tx = renamed_tx.clone();
+            let name = service
+                .add_configured_name("Switch", move |name| {
+                    renamed_tx.send(name.to_owned()).unwrap();
+                    Ok(())
+                })
+                .map_err(|_| HapStatus::OutOfResources)?;
+            added_tx.send(name).unwrap();
+            Ok(())
+        });
+
+        assert_eq!(mock::write(on, &CharValue::Bool(true)), HapStatus::Success);
+        let name = added.try_recv().unwrap();
+
+        let value = CharValue::String(CString::new("Garden").unwrap());
+        assert_eq!(mock::write(name, &value), HapStatus::Success);
+        assert_eq!(renamed.try_recv().as_deref(), Ok("Garden"));
+    }
+
+    #[test]
+    fn panicking_write_handlers_fail_the_write_and_keep_working() {
+        HapContext::new_isolated();
+        let (service, on) = switch();
+        let panics = crate::diag::panic_count();
+
+        service.on_write(|write| {
+            if unsafe { write.raw_value().b } {
+                panic!("the relay is stuck");
+            }
+            Ok(())
+        });
+
+        assert_eq!(mock::write(on, &CharValue::Bool(true)), HapStatus::Busy);
+        assert!(crate::diag::panic_count() > panics);
+        assert!(!unsafe { on.raw_value().b });
+
+        assert_eq!(mock::write(on, &CharValue::Bool(false)), HapStatus::Success);
+    }
+
+    #[test]
+    fn panicking_rename_handlers_fail_the_rename_and_keep_working() {
+        HapContext::new_isolated();
+        let (service, _) = switch();
+        let name = service
+            .add_configured_name("Switch", |name| {
+                assert_ne!(name, "Garden", "no garden here");
+                Ok(())
+            })
+            .unwrap();
+
+        let rename = |to: &str| mock::write(name, &CharValue::String(CString::new(to).unwrap()));
+        assert_eq!(rename("Garden"), HapStatus::Busy);
+        assert_eq!(rename("Porch"), HapStatus::Success);
+    }
+}
@@ -0,0 +1,284 @@
+use log::*;
+
+use crate::characteristic::{CharValue, HapCharacteristic};
+use crate::error::HapError;
+use crate::service::HapService;
+use crate::*;
+
+/// Clamp a sensor reading to the characteristic constraints.
+///
+/// The HAP framework silently rejects out-of-range updates, so readings are
+/// clamped instead and the adjustment is logged.
+fn clamp(name: &str, value: f32, min: f32, max: f32) -> f32 {
+    let clamped = value.max(min).min(max);
+
+    if clamped != value {
+        debug!("{} reading {} out of range {}..={}, clamped to {}", name, value, min, max, clamped);
+    }
+
+    clamped
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AirQuality {
+    Unknown = 0,
+    Excellent = 1,
+    Good = 2,
+    Fair = 3,
+    Inferior = 4,
+    Poor = 5,
+}
+
+/// The optional density characteristics of an [`AirQualitySensor`], all in µg/m³.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Density {
+    Pm2_5,
+    Pm10,
+    Voc,
+    NitrogenDioxide,
+    SulphurDioxide,
+    Ozone,
+}
+
+impl Density {
+    const MIN: f32 = 0.0;
+    const MAX: f32 = 1000.0;
+    const STEP: f32 = 1.0;
+
+    fn name(&self) -> &'static str {
+        match self {
+            Density::Pm2_5 => "PM2.5 density",
+            Density::Pm10 => "PM10 density",
+            Density::Voc => "VOC density",
+            Density::NitrogenDioxide => "NO2 density",
+            Density::SulphurDioxide => "SO2 density",
+            Density::Ozone => "Ozone density",
+        }
+    }
+
+    fn create(&self) -> *mut hap_char_t {
+        unsafe {
+            match self {
+                Density::Pm2_5 => hap_char_pm_2_5_density_create(Self::MIN),
+                Density::Pm10 => hap_char_pm_10_density_create(Self::MIN),
+                Density::Voc => hap_char_voc_density_create(Self::MIN),
+                Density::NitrogenDioxide => hap_char_nitrogen_dioxide_density_create(Self::MIN),
+                Density::SulphurDioxide => hap_char_sulphur_dioxide_density_create(Self::MIN),
+                Density::Ozone => hap_char_ozone_density_create(Self::MIN),
+            }
+        }
+    }
+}
+
+pub struct AirQualitySensorBuilder {
+    name: Option<String>,
+    primary: bool,
+    densities: Vec<Density>,
+}
+
+impl AirQualitySensorBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Mark the service as the primary service of its accessory, see [`HapService::mark_primary`].
+    pub fn primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    /// Enable an optional density characteristic.
+    pub fn density(mut self, density: Density) -> Self {
+        if !self.densities.contains(&density) {
+            self.densities.push(density);
+        }
+        self
+    }
+
+    pub fn build(self) -> Result<AirQualitySensor, HapError> {
+        info!("Creating air quality sensor service");
+
+        let service = HapService::from_raw(unsafe {
+            hap_serv_air_quality_sensor_create(AirQuality::Unknown as u8)
+        });
+        if let Some(name) = &self.name {
+            service.add_name(name)?;
+        }
+        if self.primary {
+            service.mark_primary()?;
+        }
+
+        let mut densities = Vec::with_capacity(self.densities.len());
+        for density in self.densities {
+            let characteristic = HapCharacteristic::from_raw(density.create());
+
+            unsafe {
+                hap_char_float_set_constraints(characteristic.as_raw(), Density::MIN, Density::MAX, Density::STEP);
+            }
+            service.add_char(characteristic)?;
+
+            densities.push((density, characteristic));
+        }
+
+        Ok(AirQualitySensor {
+            service,
+            air_quality: service
+                .char_by_uuid(HAP_CHAR_UUID_AIR_QUALITY)
+                .ok_or(HapError::Fail(hap::HAP_FAIL_))?,
+            densities,
+        })
+    }
+}
+
+pub struct AirQualitySensor {
+    service: HapService,
+    air_quality: HapCharacteristic,
+    densities: Vec<(Density, HapCharacteristic)>,
+}
+
+impl AirQualitySensor {
+    pub fn builder() -> AirQualitySensorBuilder {
+        AirQualitySensorBuilder {
+            name: None,
+            primary: false,
+            densities: Vec::new(),
+        }
+    }
+
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    pub fn set_air_quality(&self, air_quality: AirQuality) -> Result<(), HapError> {
+        self.air_quality.update(&CharValue::UInt8(air_quality as u8))
+    }
+
+    /// Update a density reading, clamped to 0-1000 µg/m³.
+    ///
+    /// Fails if the density was not enabled on the builder.
+    pub fn set_density(&self, density: Density, value: f32) -> Result<(), HapError> {
+        let characteristic = self
+            .densities
+            .iter()
+            .find(|(d, _)| *d == density)
+            .map(|(_, characteristic)| characteristic)
+            .ok_or(HapError::Fail(hap::HAP_FAIL_))?;
+
+        characteristic.update(&CharValue::Float(clamp(density.name(), value, Density::MIN, Density::MAX)))
+    }
+}
+
+pub struct Co2SensorBuilder {
+    name: Option<String>,
+    primary: bool,
+    level: bool,
+    peak_level: bool,
+}
+
+impl Co2SensorBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Mark the service as the primary service of its accessory, see [`HapService::mark_primary`].
+    pub fn primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    /// Enable the optional `CarbonDioxideLevel` characteristic.
+    pub fn level(mut self) -> Self {
+        self.level = true;
+        self
+    }
+
+    /// Enable the optional `CarbonDioxidePeakLevel` characteristic.
+    pub fn peak_level(mut self) -> Self {
+        self.peak_level = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Co2Sensor, HapError> {
+        info!("Creating carbon dioxide sensor service");
+
+        let service = HapService::from_raw(unsafe { hap_serv_carbon_dioxide_sensor_create(0) });
+        if let Some(name) = &self.name {
+            service.add_name(name)?;
+        }
+        if self.primary {
+            service.mark_primary()?;
+        }
+
+        let optional = |enabled: bool, create: unsafe extern "C" fn(f32) -> *mut hap_char_t| {
+            if !enabled {
+                return Ok(None);
+            }
+
+            let characteristic = HapCharacteristic::from_raw(unsafe { create(Co2Sensor::MIN) });
+            unsafe {
+                hap_char_float_set_constraints(characteristic.as_raw(), Co2Sensor::MIN, Co2Sensor::MAX, 1.0);
+            }
+            service.add_char(characteristic).map(|_| Some(characteristic))
+        };
+
+        Ok(Co2Sensor {
+            service,
+            detected: service
+                .char_by_uuid(HAP_CHAR_UUID_CARBON_DIOXIDE_DETECTED)
+                .ok_or(HapError::Fail(hap::HAP_FAIL_))?,
+            level: optional(self.level, hap_char_carbon_dioxide_level_create)?,
+            peak_level: optional(self.peak_level, hap_char_carbon_dioxide_peak_level_create)?,
+        })
+    }
+}
+
+pub struct Co2Sensor {
+    service: HapService,
+    detected: HapCharacteristic,
+    level: Option<HapCharacteristic>,
+    peak_level: Option<HapCharacteristic>,
+}
+
+impl Co2Sensor {
+    const MIN: f32 = 0.0;
+    const MAX: f32 = 100000.0;
+
+    pub fn builder() -> Co2SensorBuilder {
+        Co2SensorBuilder {
+            name: None,
+            primary: false,
+            level: false,
+            peak_level: false,
+        }
+    }
+
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    /// Report whether the CO2 level is abnormal.
+    pub fn set_detected(&self, abnormal: bool) -> Result<(), HapError> {
+        self.detected.update(&CharValue::UInt8(abnormal as u8))
+    }
+
+    /// Update the level in ppm, clamped to 0-100000. Fails if not enabled on the builder.
+    pub fn set_level(&self, ppm: f32) -> Result<(), HapError> {
+        Self::set_optional(&self.level, "CO2 level", ppm)
+    }
+
+    /// Update the peak level in ppm, clamped to 0-100000. Fails if not enabled on the builder.
+    pub fn set_peak_level(&self, ppm: f32) -> Result<(), HapError> {
+        Self::set_optional(&self.peak_level, "CO2 peak level", ppm)
+    }
+
+    fn set_optional(characteristic: &Option<HapCharacteristic>, name: &str, ppm: f32) -> Result<(), HapError> {
+        match characteristic {
+            Some(characteristic) => {
+                characteristic.update(&CharValue::Float(clamp(name, ppm, Self::MIN, Self::MAX)))
+            }
+            None => Err(HapError::Fail(hap::HAP_FAIL_)),
+        }
+    }
+}
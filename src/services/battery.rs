@@ -0,0 +1,57 @@
+use log::*;
+
+use crate::characteristic::{CharValue, HapCharacteristic};
+use crate::error::HapError;
+use crate::service::HapService;
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChargingState {
+    NotCharging = 0,
+    Charging = 1,
+    NotChargeable = 2,
+}
+
+/// A Battery service, to be added next to the primary service of battery-powered accessories.
+pub struct BatteryService {
+    service: HapService,
+    level: HapCharacteristic,
+    charging: HapCharacteristic,
+    low: HapCharacteristic,
+}
+
+impl BatteryService {
+    /// Create the service with its initial state, `level` in percent (0-100).
+    pub fn new(level: u8, charging: ChargingState, low: bool) -> Result<Self, HapError> {
+        info!("Creating battery service");
+
+        let service = HapService::from_raw(unsafe {
+            hap_serv_battery_service_create(level.min(100), charging as u8, low as u8)
+        });
+
+        let characteristic = |uuid: &[u8]| service.char_by_uuid(uuid).ok_or(HapError::Fail(hap::HAP_FAIL_));
+        let level = characteristic(HAP_CHAR_UUID_BATTERY_LEVEL)?;
+
+        unsafe {
+            hap_char_int_set_constraints(level.as_raw(), 0, 100, 1);
+        }
+
+        Ok(BatteryService {
+            service,
+            level,
+            charging: characteristic(HAP_CHAR_UUID_CHARGING_STATE)?,
+            low: characteristic(HAP_CHAR_UUID_STATUS_LOW_BATTERY)?,
+        })
+    }
+
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    /// Update all three characteristics, `level` is clamped to 100.
+    pub fn update_battery(&self, level: u8, charging: ChargingState, low: bool) -> Result<(), HapError> {
+        self.level.update(&CharValue::UInt8(level.min(100)))?;
+        self.charging.update(&CharValue::UInt8(charging as u8))?;
+        self.low.update(&CharValue::UInt8(low as u8))
+    }
+}
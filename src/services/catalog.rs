@@ -0,0 +1,580 @@
+//! Static metadata for the standard HAP services, mirroring `hap_apple_servs.h`.
+
+use crate::*;
+
+/// A characteristic of a [`ServiceSpec`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CharSpec {
+    pub name: &'static str,
+    /// The type UUID, e.g. `HAP_CHAR_UUID_ON`.
+    pub uuid: &'static [u8],
+}
+
+/// A standard service with the characteristics it must and may contain.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ServiceSpec {
+    pub name: &'static str,
+    /// The type UUID, e.g. `HAP_SERV_UUID_LIGHTBULB`.
+    pub uuid: &'static [u8],
+    pub required: &'static [CharSpec],
+    pub optional: &'static [CharSpec],
+}
+
+impl CharSpec {
+    const fn new(name: &'static str, uuid: &'static [u8]) -> Self {
+        CharSpec { name, uuid }
+    }
+}
+
+impl ServiceSpec {
+    pub fn char_by_uuid(&self, uuid: &[u8]) -> Option<&'static CharSpec> {
+        self.required.iter().chain(self.optional).find(|c| c.uuid == uuid)
+    }
+
+    pub fn is_required(&self, uuid: &[u8]) -> bool {
+        self.required.iter().any(|c| c.uuid == uuid)
+    }
+}
+
+/// All standard services supported by the SDK.
+pub fn catalog() -> &'static [ServiceSpec] {
+    CATALOG
+}
+
+/// Look up a standard service by its type UUID.
+pub fn spec_by_uuid(uuid: &[u8]) -> Option<&'static ServiceSpec> {
+    CATALOG.iter().find(|s| s.uuid == uuid)
+}
+
+const ACCESSORY_FLAGS: CharSpec = CharSpec::new("Accessory Flags", HAP_CHAR_UUID_ACCESSORY_FLAGS);
+const ACTIVE: CharSpec = CharSpec::new("Active", HAP_CHAR_UUID_ACTIVE);
+const ADMINISTRATOR_ONLY_ACCESS: CharSpec =
+    CharSpec::new("Administrator Only Access", HAP_CHAR_UUID_ADMINISTRATOR_ONLY_ACCESS);
+const AIR_QUALITY: CharSpec = CharSpec::new("Air Quality", HAP_CHAR_UUID_AIR_QUALITY);
+const AUDIO_FEEDBACK: CharSpec = CharSpec::new("Audio Feedback", HAP_CHAR_UUID_AUDIO_FEEDBACK);
+const BATTERY_LEVEL: CharSpec = CharSpec::new("Battery Level", HAP_CHAR_UUID_BATTERY_LEVEL);
+const BRIGHTNESS: CharSpec = CharSpec::new("Brightness", HAP_CHAR_UUID_BRIGHTNESS);
+const CARBON_DIOXIDE_DETECTED: CharSpec =
+    CharSpec::new("Carbon Dioxide Detected", HAP_CHAR_UUID_CARBON_DIOXIDE_DETECTED);
+const CARBON_DIOXIDE_LEVEL: CharSpec =
+    CharSpec::new("Carbon Dioxide Level", HAP_CHAR_UUID_CARBON_DIOXIDE_LEVEL);
+const CARBON_DIOXIDE_PEAK_LEVEL: CharSpec =
+    CharSpec::new("Carbon Dioxide Peak Level", HAP_CHAR_UUID_CARBON_DIOXIDE_PEAK_LEVEL);
+const CARBON_MONOXIDE_DETECTED: CharSpec =
+    CharSpec::new("Carbon Monoxide Detected", HAP_CHAR_UUID_CARBON_MONOXIDE_DETECTED);
+const CARBON_MONOXIDE_LEVEL: CharSpec =
+    CharSpec::new("Carbon Monoxide Level", HAP_CHAR_UUID_CARBON_MONOXIDE_LEVEL);
+const CARBON_MONOXIDE_PEAK_LEVEL: CharSpec =
+    CharSpec::new("Carbon Monoxide Peak Level", HAP_CHAR_UUID_CARBON_MONOXIDE_PEAK_LEVEL);
+const CHARGING_STATE: CharSpec = CharSpec::new("Charging State", HAP_CHAR_UUID_CHARGING_STATE);
+const COLOR_TEMPERATURE: CharSpec =
+    CharSpec::new("Color Temperature", HAP_CHAR_UUID_COLOR_TEMPERATURE);
+const CONTACT_SENSOR_STATE: CharSpec =
+    CharSpec::new("Contact Sensor State", HAP_CHAR_UUID_CONTACT_SENSOR_STATE);
+const COOLING_THRESHOLD_TEMPERATURE: CharSpec =
+    CharSpec::new("Cooling Threshold Temperature", HAP_CHAR_UUID_COOLING_THRESHOLD_TEMPERATURE);
+const CURRENT_AIR_PURIFIER_STATE: CharSpec =
+    CharSpec::new("Current Air Purifier State", HAP_CHAR_UUID_CURRENT_AIR_PURIFIER_STATE);
+const CURRENT_AMBIENT_LIGHT_LEVEL: CharSpec =
+    CharSpec::new("Current Ambient Light Level", HAP_CHAR_UUID_CURRENT_AMBIENT_LIGHT_LEVEL);
+const CURRENT_DOOR_STATE: CharSpec =
+    CharSpec::new("Current Door State", HAP_CHAR_UUID_CURRENT_DOOR_STATE);
+const CURRENT_FAN_STATE: CharSpec =
+    CharSpec::new("Current Fan State", HAP_CHAR_UUID_CURRENT_FAN_STATE);
+const CURRENT_HEATER_COOLER_STATE: CharSpec =
+    CharSpec::new("Current Heater Cooler State", HAP_CHAR_UUID_CURRENT_HEATER_COOLER_STATE);
+const CURRENT_HEATING_COOLING_STATE: CharSpec =
+    CharSpec::new("Current Heating Cooling State", HAP_CHAR_UUID_CURRENT_HEATING_COOLING_STATE);
+const CURRENT_HORIZONTAL_TILT_ANGLE: CharSpec =
+    CharSpec::new("Current Horizontal Tilt Angle", HAP_CHAR_UUID_CURRENT_HORIZONTAL_TILT_ANGLE);
+const CURRENT_HUMIDIFIER_DEHUMIDIFIER_STATE: CharSpec =
+    CharSpec::new("Current Humidifier Dehumidifier State", HAP_CHAR_UUID_CURRENT_HUMIDIFIER_DEHUMIDIFIER_STATE);
+const CURRENT_POSITION: CharSpec =
+    CharSpec::new("Current Position", HAP_CHAR_UUID_CURRENT_POSITION);
+const CURRENT_RELATIVE_HUMIDITY: CharSpec =
+    CharSpec::new("Current Relative Humidity", HAP_CHAR_UUID_CURRENT_RELATIVE_HUMIDITY);
+const CURRENT_SLAT_STATE: CharSpec =
+    CharSpec::new("Current Slat State", HAP_CHAR_UUID_CURRENT_SLAT_STATE);
+const CURRENT_TEMPERATURE: CharSpec =
+    CharSpec::new("Current Temperature", HAP_CHAR_UUID_CURRENT_TEMPERATURE);
+const CURRENT_TILT_ANGLE: CharSpec =
+    CharSpec::new("Current Tilt Angle", HAP_CHAR_UUID_CURRENT_TILT_ANGLE);
+const CURRENT_VERTICAL_TILT_ANGLE: CharSpec =
+    CharSpec::new("Current Vertical Tilt Angle", HAP_CHAR_UUID_CURRENT_VERTICAL_TILT_ANGLE);
+const FILTER_CHANGE_INDICATION: CharSpec =
+    CharSpec::new("Filter Change Indication", HAP_CHAR_UUID_FILTER_CHANGE_INDICATION);
+const FILTER_LIFE_LEVEL: CharSpec =
+    CharSpec::new("Filter Life Level", HAP_CHAR_UUID_FILTER_LIFE_LEVEL);
+const FIRMWARE_REVISION: CharSpec =
+    CharSpec::new("Firmware Revision", HAP_CHAR_UUID_FIRMWARE_REVISION);
+const HARDWARE_REVISION: CharSpec =
+    CharSpec::new("Hardware Revision", HAP_CHAR_UUID_HARDWARE_REVISION);
+const HEATING_THRESHOLD_TEMPERATURE: CharSpec =
+    CharSpec::new("Heating Threshold Temperature", HAP_CHAR_UUID_HEATING_THRESHOLD_TEMPERATURE);
+const HOLD_POSITION: CharSpec = CharSpec::new("Hold Position", HAP_CHAR_UUID_HOLD_POSITION);
+const HUE: CharSpec = CharSpec::new("Hue", HAP_CHAR_UUID_HUE);
+const IDENTIFY: CharSpec = CharSpec::new("Identify", HAP_CHAR_UUID_IDENTIFY);
+const IN_USE: CharSpec = CharSpec::new("In Use", HAP_CHAR_UUID_IN_USE);
+const IS_CONFIGURED: CharSpec = CharSpec::new("Is Configured", HAP_CHAR_UUID_IS_CONFIGURED);
+const LEAK_DETECTED: CharSpec = CharSpec::new("Leak Detected", HAP_CHAR_UUID_LEAK_DETECTED);
+const LOCK_CONTROL_POINT: CharSpec =
+    CharSpec::new("Lock Control Point", HAP_CHAR_UUID_LOCK_CONTROL_POINT);
+const LOCK_CURRENT_STATE: CharSpec =
+    CharSpec::new("Lock Current State", HAP_CHAR_UUID_LOCK_CURRENT_STATE);
+const LOCK_LAST_KNOWN_ACTION: CharSpec =
+    CharSpec::new("Lock Last Known Action", HAP_CHAR_UUID_LOCK_LAST_KNOWN_ACTION);
+const LOCK_MANAGEMENT_AUTO_SECURITY_TIMEOUT: CharSpec =
+    CharSpec::new("Lock Management Auto Security Timeout", HAP_CHAR_UUID_LOCK_MANAGEMENT_AUTO_SECURITY_TIMEOUT);
+const LOCK_PHYSICAL_CONTROLS: CharSpec =
+    CharSpec::new("Lock Physical Controls", HAP_CHAR_UUID_LOCK_PHYSICAL_CONTROLS);
+const LOCK_TARGET_STATE: CharSpec =
+    CharSpec::new("Lock Target State", HAP_CHAR_UUID_LOCK_TARGET_STATE);
+const LOGS: CharSpec = CharSpec::new("Logs", HAP_CHAR_UUID_LOGS);
+const MANUFACTURER: CharSpec = CharSpec::new("Manufacturer", HAP_CHAR_UUID_MANUFACTURER);
+const MODEL: CharSpec = CharSpec::new("Model", HAP_CHAR_UUID_MODEL);
+const MOTION_DETECTED: CharSpec = CharSpec::new("Motion Detected", HAP_CHAR_UUID_MOTION_DETECTED);
+const MUTE: CharSpec = CharSpec::new("Mute", HAP_CHAR_UUID_MUTE);
+const NAME: CharSpec = CharSpec::new("Name", HAP_CHAR_UUID_NAME);
+const NITROGEN_DIOXIDE_DENSITY: CharSpec =
+    CharSpec::new("Nitrogen Dioxide Density", HAP_CHAR_UUID_NITROGEN_DIOXIDE_DENSITY);
+const OBSTRUCTION_DETECTED: CharSpec =
+    CharSpec::new("Obstruction Detected", HAP_CHAR_UUID_OBSTRUCTION_DETECTED);
+const OCCUPANCY_DETECTED: CharSpec =
+    CharSpec::new("Occupancy Detected", HAP_CHAR_UUID_OCCUPANCY_DETECTED);
+const ON: CharSpec = CharSpec::new("On", HAP_CHAR_UUID_ON);
+const OUTLET_IN_USE: CharSpec = CharSpec::new("Outlet In Use", HAP_CHAR_UUID_OUTLET_IN_USE);
+const OZONE_DENSITY: CharSpec = CharSpec::new("Ozone Density", HAP_CHAR_UUID_OZONE_DENSITY);
+const PM_10_DENSITY: CharSpec = CharSpec::new("PM10 Density", HAP_CHAR_UUID_PM_10_DENSITY);
+const PM_2_5_DENSITY: CharSpec = CharSpec::new("PM2.5 Density", HAP_CHAR_UUID_PM_2_5_DENSITY);
+const POSITION_STATE: CharSpec = CharSpec::new("Position State", HAP_CHAR_UUID_POSITION_STATE);
+const PRODUCT_DATA: CharSpec = CharSpec::new("Product Data", HAP_CHAR_UUID_PRODUCT_DATA);
+const PROGRAMMABLE_SWITCH_EVENT: CharSpec =
+    CharSpec::new("Programmable Switch Event", HAP_CHAR_UUID_PROGRAMMABLE_SWITCH_EVENT);
+const PROGRAM_MODE: CharSpec = CharSpec::new("Program Mode", HAP_CHAR_UUID_PROGRAM_MODE);
+const RELATIVE_HUMIDITY_DEHUMIDIFIER_THRESHOLD: CharSpec =
+    CharSpec::new("Relative Humidity Dehumidifier Threshold", HAP_CHAR_UUID_RELATIVE_HUMIDITY_DEHUMIDIFIER_THRESHOLD);
+const RELATIVE_HUMIDITY_HUMIDIFIER_THRESHOLD: CharSpec =
+    CharSpec::new("Relative Humidity Humidifier Threshold", HAP_CHAR_UUID_RELATIVE_HUMIDITY_HUMIDIFIER_THRESHOLD);
+const REMAINING_DURATION: CharSpec =
+    CharSpec::new("Remaining Duration", HAP_CHAR_UUID_REMAINING_DURATION);
+const RESET_FILTER_INDICATION: CharSpec =
+    CharSpec::new("Reset Filter Indication", HAP_CHAR_UUID_RESET_FILTER_INDICATION);
+const ROTATION_DIRECTION: CharSpec =
+    CharSpec::new("Rotation Direction", HAP_CHAR_UUID_ROTATION_DIRECTION);
+const ROTATION_SPEED: CharSpec = CharSpec::new("Rotation Speed", HAP_CHAR_UUID_ROTATION_SPEED);
+const SATURATION: CharSpec = CharSpec::new("Saturation", HAP_CHAR_UUID_SATURATION);
+const SECURITY_SYSTEM_ALARM_TYPE: CharSpec =
+    CharSpec::new("Security System Alarm Type", HAP_CHAR_UUID_SECURITY_SYSTEM_ALARM_TYPE);
+const SECURITY_SYSTEM_CURRENT_STATE: CharSpec =
+    CharSpec::new("Security System Current State", HAP_CHAR_UUID_SECURITY_SYSTEM_CURRENT_STATE);
+const SECURITY_SYSTEM_TARGET_STATE: CharSpec =
+    CharSpec::new("Security System Target State", HAP_CHAR_UUID_SECURITY_SYSTEM_TARGET_STATE);
+const SERIAL_NUMBER: CharSpec = CharSpec::new("Serial Number", HAP_CHAR_UUID_SERIAL_NUMBER);
+const SERVICE_LABEL_INDEX: CharSpec =
+    CharSpec::new("Service Label Index", HAP_CHAR_UUID_SERVICE_LABEL_INDEX);
+const SERVICE_LABEL_NAMESPACE: CharSpec =
+    CharSpec::new("Service Label Namespace", HAP_CHAR_UUID_SERVICE_LABEL_NAMESPACE);
+const SET_DURATION: CharSpec = CharSpec::new("Set Duration", HAP_CHAR_UUID_SET_DURATION);
+const SLAT_TYPE: CharSpec = CharSpec::new("Slat Type", HAP_CHAR_UUID_SLAT_TYPE);
+const SMOKE_DETECTED: CharSpec = CharSpec::new("Smoke Detected", HAP_CHAR_UUID_SMOKE_DETECTED);
+const STATUS_ACTIVE: CharSpec = CharSpec::new("Status Active", HAP_CHAR_UUID_STATUS_ACTIVE);
+const STATUS_FAULT: CharSpec = CharSpec::new("Status Fault", HAP_CHAR_UUID_STATUS_FAULT);
+const STATUS_LOW_BATTERY: CharSpec =
+    CharSpec::new("Status Low Battery", HAP_CHAR_UUID_STATUS_LOW_BATTERY);
+const STATUS_TAMPERED: CharSpec = CharSpec::new("Status Tampered", HAP_CHAR_UUID_STATUS_TAMPERED);
+const SULPHUR_DIOXIDE_DENSITY: CharSpec =
+    CharSpec::new("Sulphur Dioxide Density", HAP_CHAR_UUID_SULPHUR_DIOXIDE_DENSITY);
+const SWING_MODE: CharSpec = CharSpec::new("Swing Mode", HAP_CHAR_UUID_SWING_MODE);
+const TARGET_AIR_PURIFIER_STATE: CharSpec =
+    CharSpec::new("Target Air Purifier State", HAP_CHAR_UUID_TARGET_AIR_PURIFIER_STATE);
+const TARGET_DOOR_STATE: CharSpec =
+    CharSpec::new("Target Door State", HAP_CHAR_UUID_TARGET_DOOR_STATE);
+const TARGET_FAN_STATE: CharSpec =
+    CharSpec::new("Target Fan State", HAP_CHAR_UUID_TARGET_FAN_STATE);
+const TARGET_HEATER_COOLER_STATE: CharSpec =
+    CharSpec::new("Target Heater Cooler State", HAP_CHAR_UUID_TARGET_HEATER_COOLER_STATE);
+const TARGET_HEATING_COOLING_STATE: CharSpec =
+    CharSpec::new("Target Heating Cooling State", HAP_CHAR_UUID_TARGET_HEATING_COOLING_STATE);
+const TARGET_HORIZONTAL_TILT_ANGLE: CharSpec =
+    CharSpec::new("Target Horizontal Tilt Angle", HAP_CHAR_UUID_TARGET_HORIZONTAL_TILT_ANGLE);
+const TARGET_HUMIDIFIER_DEHUMIDIFIER_STATE: CharSpec =
+    CharSpec::new("Target Humidifier Dehumidifier State", HAP_CHAR_UUID_TARGET_HUMIDIFIER_DEHUMIDIFIER_STATE);
+const TARGET_POSITION: CharSpec = CharSpec::new("Target Position", HAP_CHAR_UUID_TARGET_POSITION);
+const TARGET_RELATIVE_HUMIDITY: CharSpec =
+    CharSpec::new("Target Relative Humidity", HAP_CHAR_UUID_TARGET_RELATIVE_HUMIDITY);
+const TARGET_TEMPERATURE: CharSpec =
+    CharSpec::new("Target Temperature", HAP_CHAR_UUID_TARGET_TEMPERATURE);
+const TARGET_TILT_ANGLE: CharSpec =
+    CharSpec::new("Target Tilt Angle", HAP_CHAR_UUID_TARGET_TILT_ANGLE);
+const TARGET_VERTICAL_TILT_ANGLE: CharSpec =
+    CharSpec::new("Target Vertical Tilt Angle", HAP_CHAR_UUID_TARGET_VERTICAL_TILT_ANGLE);
+const TEMPERATURE_DISPLAY_UNITS: CharSpec =
+    CharSpec::new("Temperature Display Units", HAP_CHAR_UUID_TEMPERATURE_DISPLAY_UNITS);
+const VALVE_TYPE: CharSpec = CharSpec::new("Valve Type", HAP_CHAR_UUID_VALVE_TYPE);
+const VERSION: CharSpec = CharSpec::new("Version", HAP_CHAR_UUID_VERSION);
+const VOC_DENSITY: CharSpec = CharSpec::new("VOC Density", HAP_CHAR_UUID_VOC_DENSITY);
+const VOLUME: CharSpec = CharSpec::new("Volume", HAP_CHAR_UUID_VOLUME);
+const WATER_LEVEL: CharSpec = CharSpec::new("Water Level", HAP_CHAR_UUID_WATER_LEVEL);
+
+static CATALOG: &[ServiceSpec] = &[
+    ServiceSpec {
+        name: "Accessory Information",
+        uuid: HAP_SERV_UUID_ACCESSORY_INFORMATION,
+        required: &[IDENTIFY, MANUFACTURER, MODEL, NAME, SERIAL_NUMBER, FIRMWARE_REVISION],
+        optional: &[HARDWARE_REVISION, ACCESSORY_FLAGS, PRODUCT_DATA],
+    },
+    ServiceSpec {
+        name: "Protocol Information",
+        uuid: HAP_SERV_UUID_PROTOCOL_INFORMATION,
+        required: &[VERSION],
+        optional: &[],
+    },
+    ServiceSpec {
+        name: "Fan",
+        uuid: HAP_SERV_UUID_FAN,
+        required: &[ON],
+        optional: &[ROTATION_DIRECTION, ROTATION_SPEED, NAME],
+    },
+    ServiceSpec {
+        name: "Garage Door Opener",
+        uuid: HAP_SERV_UUID_GARAGE_DOOR_OPENER,
+        required: &[CURRENT_DOOR_STATE, TARGET_DOOR_STATE, OBSTRUCTION_DETECTED],
+        optional: &[LOCK_CURRENT_STATE, LOCK_TARGET_STATE, NAME],
+    },
+    ServiceSpec {
+        name: "Lightbulb",
+        uuid: HAP_SERV_UUID_LIGHTBULB,
+        required: &[ON],
+        optional: &[BRIGHTNESS, HUE, SATURATION, COLOR_TEMPERATURE, NAME],
+    },
+    ServiceSpec {
+        name: "Lock Management",
+        uuid: HAP_SERV_UUID_LOCK_MANAGEMENT,
+        required: &[LOCK_CONTROL_POINT, VERSION],
+        optional: &[
+            LOGS,
+            AUDIO_FEEDBACK,
+            LOCK_MANAGEMENT_AUTO_SECURITY_TIMEOUT,
+            ADMINISTRATOR_ONLY_ACCESS,
+            LOCK_LAST_KNOWN_ACTION,
+            CURRENT_DOOR_STATE,
+            MOTION_DETECTED,
+            NAME,
+        ],
+    },
+    ServiceSpec {
+        name: "Lock Mechanism",
+        uuid: HAP_SERV_UUID_LOCK_MECHANISM,
+        required: &[LOCK_CURRENT_STATE, LOCK_TARGET_STATE],
+        optional: &[NAME],
+    },
+    ServiceSpec {
+        name: "Outlet",
+        uuid: HAP_SERV_UUID_OUTLET,
+        required: &[ON, OUTLET_IN_USE],
+        optional: &[NAME],
+    },
+    ServiceSpec {
+        name: "Switch",
+        uuid: HAP_SERV_UUID_SWITCH,
+        required: &[ON],
+        optional: &[NAME],
+    },
+    ServiceSpec {
+        name: "Thermostat",
+        uuid: HAP_SERV_UUID_THERMOSTAT,
+        required: &[
+            CURRENT_HEATING_COOLING_STATE,
+            TARGET_HEATING_COOLING_STATE,
+            CURRENT_TEMPERATURE,
+            TARGET_TEMPERATURE,
+            TEMPERATURE_DISPLAY_UNITS,
+        ],
+        optional: &[
+            CURRENT_RELATIVE_HUMIDITY,
+            TARGET_RELATIVE_HUMIDITY,
+            COOLING_THRESHOLD_TEMPERATURE,
+            HEATING_THRESHOLD_TEMPERATURE,
+            NAME,
+        ],
+    },
+    ServiceSpec {
+        name: "Air Quality Sensor",
+        uuid: HAP_SERV_UUID_AIR_QUALITY_SENSOR,
+        required: &[AIR_QUALITY],
+        optional: &[
+            OZONE_DENSITY,
+            NITROGEN_DIOXIDE_DENSITY,
+            SULPHUR_DIOXIDE_DENSITY,
+            PM_2_5_DENSITY,
+            PM_10_DENSITY,
+            VOC_DENSITY,
+            STATUS_ACTIVE,
+            STATUS_FAULT,
+            STATUS_TAMPERED,
+            STATUS_LOW_BATTERY,
+            NAME,
+        ],
+    },
+    ServiceSpec {
+        name: "Security System",
+        uuid: HAP_SERV_UUID_SECURITY_SYSTEM,
+        required: &[SECURITY_SYSTEM_CURRENT_STATE, SECURITY_SYSTEM_TARGET_STATE],
+        optional: &[SECURITY_SYSTEM_ALARM_TYPE, STATUS_FAULT, STATUS_TAMPERED, NAME],
+    },
+    ServiceSpec {
+        name: "Carbon Monoxide Sensor",
+        uuid: HAP_SERV_UUID_CARBON_MONOXIDE_SENSOR,
+        required: &[CARBON_MONOXIDE_DETECTED],
+        optional: &[
+            CARBON_MONOXIDE_LEVEL,
+            CARBON_MONOXIDE_PEAK_LEVEL,
+            STATUS_ACTIVE,
+            STATUS_FAULT,
+            STATUS_TAMPERED,
+            STATUS_LOW_BATTERY,
+            NAME,
+        ],
+    },
+    ServiceSpec {
+        name: "Contact Sensor",
+        uuid: HAP_SERV_UUID_CONTACT_SENSOR,
+        required: &[CONTACT_SENSOR_STATE],
+        optional: &[STATUS_ACTIVE, STATUS_FAULT, STATUS_TAMPERED, STATUS_LOW_BATTERY, NAME],
+    },
+    ServiceSpec {
+        name: "Door",
+        uuid: HAP_SERV_UUID_DOOR,
+        required: &[CURRENT_POSITION, TARGET_POSITION, POSITION_STATE],
+        optional: &[HOLD_POSITION, OBSTRUCTION_DETECTED, NAME],
+    },
+    ServiceSpec {
+        name: "Humidity Sensor",
+        uuid: HAP_SERV_UUID_HUMIDITY_SENSOR,
+        required: &[CURRENT_RELATIVE_HUMIDITY],
+        optional: &[STATUS_ACTIVE, STATUS_FAULT, STATUS_TAMPERED, STATUS_LOW_BATTERY, NAME],
+    },
+    ServiceSpec {
+        name: "Leak Sensor",
+        uuid: HAP_SERV_UUID_LEAK_SENSOR,
+        required: &[LEAK_DETECTED],
+        optional: &[STATUS_ACTIVE, STATUS_FAULT, STATUS_TAMPERED, STATUS_LOW_BATTERY, NAME],
+    },
+    ServiceSpec {
+        name: "Light Sensor",
+        uuid: HAP_SERV_UUID_LIGHT_SENSOR,
+        required: &[CURRENT_AMBIENT_LIGHT_LEVEL],
+        optional: &[STATUS_ACTIVE, STATUS_FAULT, STATUS_TAMPERED, STATUS_LOW_BATTERY, NAME],
+    },
+    ServiceSpec {
+        name: "Motion Sensor",
+        uuid: HAP_SERV_UUID_MOTION_SENSOR,
+        required: &[MOTION_DETECTED],
+        optional: &[STATUS_ACTIVE, STATUS_FAULT, STATUS_TAMPERED, STATUS_LOW_BATTERY, NAME],
+    },
+    ServiceSpec {
+        name: "Occupancy Sensor",
+        uuid: HAP_SERV_UUID_OCCUPANCY_SENSOR,
+        required: &[OCCUPANCY_DETECTED],
+        optional: &[STATUS_ACTIVE, STATUS_FAULT, STATUS_TAMPERED, STATUS_LOW_BATTERY, NAME],
+    },
+    ServiceSpec {
+        name: "Smoke Sensor",
+        uuid: HAP_SERV_UUID_SMOKE_SENSOR,
+        required: &[SMOKE_DETECTED],
+        optional: &[STATUS_ACTIVE, STATUS_FAULT, STATUS_TAMPERED, STATUS_LOW_BATTERY, NAME],
+    },
+    ServiceSpec {
+        name: "Stateless Programmable Switch",
+        uuid: HAP_SERV_UUID_STATELESS_PROGRAMMABLE_SWITCH,
+        required: &[PROGRAMMABLE_SWITCH_EVENT],
+        optional: &[SERVICE_LABEL_INDEX, NAME],
+    },
+    ServiceSpec {
+        name: "Temperature Sensor",
+        uuid: HAP_SERV_UUID_TEMPERATURE_SENSOR,
+        required: &[CURRENT_TEMPERATURE],
+        optional: &[STATUS_ACTIVE, STATUS_FAULT, STATUS_TAMPERED, STATUS_LOW_BATTERY, NAME],
+    },
+    ServiceSpec {
+        name: "Window",
+        uuid: HAP_SERV_UUID_WINDOW,
+        required: &[CURRENT_POSITION, TARGET_POSITION, POSITION_STATE],
+        optional: &[HOLD_POSITION, OBSTRUCTION_DETECTED, NAME],
+    },
+    ServiceSpec {
+        name: "Window Covering",
+        uuid: HAP_SERV_UUID_WINDOW_COVERING,
+        required: &[CURRENT_POSITION, TARGET_POSITION, POSITION_STATE],
+        optional: &[
+            HOLD_POSITION,
+            CURRENT_HORIZONTAL_TILT_ANGLE,
+            TARGET_HORIZONTAL_TILT_ANGLE,
+            CURRENT_VERTICAL_TILT_ANGLE,
+            TARGET_VERTICAL_TILT_ANGLE,
+            OBSTRUCTION_DETECTED,
+            NAME,
+        ],
+    },
+    ServiceSpec {
+        name: "Battery Service",
+        uuid: HAP_SERV_UUID_BATTERY_SERVICE,
+        required: &[BATTERY_LEVEL, CHARGING_STATE, STATUS_LOW_BATTERY],
+        optional: &[NAME],
+    },
+    ServiceSpec {
+        name: "Carbon Dioxide Sensor",
+        uuid: HAP_SERV_UUID_CARBON_DIOXIDE_SENSOR,
+        required: &[CARBON_DIOXIDE_DETECTED],
+        optional: &[
+            CARBON_DIOXIDE_LEVEL,
+            CARBON_DIOXIDE_PEAK_LEVEL,
+            STATUS_ACTIVE,
+            STATUS_FAULT,
+            STATUS_TAMPERED,
+            STATUS_LOW_BATTERY,
+            NAME,
+        ],
+    },
+    ServiceSpec {
+        name: "Fan v2",
+        uuid: HAP_SERV_UUID_FAN_V2,
+        required: &[ACTIVE],
+        optional: &[
+            CURRENT_FAN_STATE,
+            TARGET_FAN_STATE,
+            LOCK_PHYSICAL_CONTROLS,
+            ROTATION_DIRECTION,
+            ROTATION_SPEED,
+            SWING_MODE,
+            NAME,
+        ],
+    },
+    ServiceSpec {
+        name: "Slat",
+        uuid: HAP_SERV_UUID_SLAT,
+        required: &[SLAT_TYPE, CURRENT_SLAT_STATE],
+        optional: &[CURRENT_TILT_ANGLE, TARGET_TILT_ANGLE, SWING_MODE, NAME],
+    },
+    ServiceSpec {
+        name: "Filter Maintenance",
+        uuid: HAP_SERV_UUID_FILTER_MAINTENANCE,
+        required: &[FILTER_CHANGE_INDICATION],
+        optional: &[FILTER_LIFE_LEVEL, RESET_FILTER_INDICATION, NAME],
+    },
+    ServiceSpec {
+        name: "Air Purifier",
+        uuid: HAP_SERV_UUID_AIR_PURIFIER,
+        required: &[ACTIVE, CURRENT_AIR_PURIFIER_STATE, TARGET_AIR_PURIFIER_STATE],
+        optional: &[ROTATION_SPEED, SWING_MODE, LOCK_PHYSICAL_CONTROLS, NAME],
+    },
+    ServiceSpec {
+        name: "Heater Cooler",
+        uuid: HAP_SERV_UUID_HEATER_COOLER,
+        required: &[
+            ACTIVE,
+            CURRENT_HEATER_COOLER_STATE,
+            TARGET_HEATER_COOLER_STATE,
+            CURRENT_TEMPERATURE,
+        ],
+        optional: &[
+            ROTATION_SPEED,
+            TEMPERATURE_DISPLAY_UNITS,
+            SWING_MODE,
+            COOLING_THRESHOLD_TEMPERATURE,
+            HEATING_THRESHOLD_TEMPERATURE,
+            LOCK_PHYSICAL_CONTROLS,
+            NAME,
+        ],
+    },
+    ServiceSpec {
+        name: "Humidifier Dehumidifier",
+        uuid: HAP_SERV_UUID_HUMIDIFIER_DEHUMIDIFIER,
+        required: &[
+            ACTIVE,
+            CURRENT_HUMIDIFIER_DEHUMIDIFIER_STATE,
+            TARGET_HUMIDIFIER_DEHUMIDIFIER_STATE,
+            CURRENT_RELATIVE_HUMIDITY,
+        ],
+        optional: &[
+            RELATIVE_HUMIDITY_DEHUMIDIFIER_THRESHOLD,
+            RELATIVE_HUMIDITY_HUMIDIFIER_THRESHOLD,
+            ROTATION_SPEED,
+            SWING_MODE,
+            WATER_LEVEL,
+            LOCK_PHYSICAL_CONTROLS,
+            NAME,
+        ],
+    },
+    ServiceSpec {
+        name: "Service Label",
+        uuid: HAP_SERV_UUID_SERVICE_LABEL,
+        required: &[SERVICE_LABEL_NAMESPACE],
+        optional: &[],
+    },
+    ServiceSpec {
+        name: "Irrigation System",
+        uuid: HAP_SERV_UUID_IRRIGATION_SYSTEM,
+        required: &[ACTIVE, PROGRAM_MODE, IN_USE],
+        optional: &[REMAINING_DURATION, STATUS_FAULT, NAME],
+    },
+    ServiceSpec {
+        name: "Valve",
+        uuid: HAP_SERV_UUID_VALVE,
+        required: &[ACTIVE, IN_USE, VALVE_TYPE],
+        optional: &[
+            SET_DURATION,
+            REMAINING_DURATION,
+            IS_CONFIGURED,
+            SERVICE_LABEL_INDEX,
+            STATUS_FAULT,
+            NAME,
+        ],
+    },
+    ServiceSpec {
+        name: "Faucet",
+        uuid: HAP_SERV_UUID_FAUCET,
+        required: &[ACTIVE],
+        optional: &[STATUS_FAULT, NAME],
+    },
+    ServiceSpec {
+        name: "Doorbell",
+        uuid: HAP_SERV_UUID_DOORBELL,
+        required: &[PROGRAMMABLE_SWITCH_EVENT],
+        optional: &[VOLUME, BRIGHTNESS, NAME],
+    },
+    ServiceSpec {
+        name: "Microphone",
+        uuid: HAP_SERV_UUID_MICROPHONE,
+        required: &[MUTE],
+        optional: &[VOLUME, NAME],
+    },
+    ServiceSpec {
+        name: "Speaker",
+        uuid: HAP_SERV_UUID_SPEAKER,
+        required: &[MUTE],
+        optional: &[VOLUME, NAME],
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_lightbulb_requires_on() {
+        let lightbulb = spec_by_uuid(HAP_SERV_UUID_LIGHTBULB).unwrap();
+
+        assert_eq!(lightbulb.name, "Lightbulb");
+        assert!(lightbulb.is_required(HAP_CHAR_UUID_ON));
+        assert!(!lightbulb.is_required(HAP_CHAR_UUID_BRIGHTNESS));
+        assert_eq!(lightbulb.char_by_uuid(HAP_CHAR_UUID_BRIGHTNESS), Some(&BRIGHTNESS));
+    }
+
+    #[test]
+    fn service_uuids_are_unique() {
+        for (i, spec) in catalog().iter().enumerate() {
+            assert!(catalog()[i + 1..].iter().all(|other| other.uuid != spec.uuid), "{}", spec.name);
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::sync::{Arc, Mutex};
+
+use log::*;
+
+use crate::characteristic::{CharValue, HapCharacteristic, SwitchEvent};
+use crate::error::HapError;
+use crate::service::HapService;
+use crate::*;
+
+pub struct DoorbellBuilder {
+    name: Option<String>,
+    primary: bool,
+}
+
+impl DoorbellBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Mark the service as the primary service of its accessory, see [`HapService::mark_primary`].
+    pub fn primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    pub fn build(self) -> Result<Doorbell, HapError> {
+        info!("Creating doorbell service");
+
+        let service = HapService::from_raw(unsafe {
+            hap_serv_doorbell_create(SwitchEvent::SinglePress as u8)
+        });
+        if let Some(name) = &self.name {
+            service.add_name(name)?;
+        }
+        if self.primary {
+            service.mark_primary()?;
+        }
+
+        let event = service
+            .char_by_uuid(HAP_CHAR_UUID_PROGRAMMABLE_SWITCH_EVENT)
+            .ok_or(HapError::Fail(hap::HAP_FAIL_))?;
+        event.set_event_only();
+
+        Ok(Doorbell { service, event })
+    }
+}
+
+pub struct Doorbell {
+    service: HapService,
+    event: HapCharacteristic,
+}
+
+impl Doorbell {
+    pub fn builder() -> DoorbellBuilder {
+        DoorbellBuilder {
+            name: None,
+            primary: false,
+        }
+    }
+
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    /// Notify controllers that the doorbell was pressed.
+    pub fn ring(&self) -> Result<(), HapError> {
+        self.event.fire_event(SwitchEvent::SinglePress)
+    }
+}
+
+#[derive(Default)]
+struct AudioCallbacks {
+    mute: Option<Box<dyn FnMut(bool) + Send>>,
+    volume: Option<Box<dyn FnMut(u8) + Send>>,
+}
+
+pub struct AudioBuilder {
+    create: unsafe extern "C" fn(bool) -> *mut hap_serv_t,
+    name: Option<String>,
+    primary: bool,
+    mute: bool,
+    volume: Option<u8>,
+}
+
+impl AudioBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Mark the service as the primary service of its accessory, see [`HapService::mark_primary`].
+    pub fn primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    pub fn mute(mut self, mute: bool) -> Self {
+        self.mute = mute;
+        self
+    }
+
+    /// Add the optional `Volume` characteristic with the given initial value (0-100).
+    pub fn volume(mut self, volume: u8) -> Self {
+        self.volume = Some(volume.min(100));
+        self
+    }
+
+    pub fn build(self) -> Result<Audio, HapError> {
+        let service = HapService::from_raw(unsafe { (self.create)(self.mute) });
+        if let Some(name) = &self.name {
+            service.add_name(name)?;
+        }
+        if self.primary {
+            service.mark_primary()?;
+        }
+        if let Some(volume) = self.volume {
+            service.add_char(HapCharacteristic::from_raw(unsafe { hap_char_volume_create(volume) }))?;
+        }
+
+        let callbacks = Arc::new(Mutex::new(AudioCallbacks::default()));
+
+        let handlers = callbacks.clone();
+        service.on_write(move |write| {
+            let mut handlers = handlers.lock().unwrap();
+
+            if write.is_uuid(HAP_CHAR_UUID_MUTE) {
+                if let Some(handler) = handlers.mute.as_mut() {
+                    handler(unsafe { write.raw_value().b });
+                }
+            } else if write.is_uuid(HAP_CHAR_UUID_VOLUME) {
+                if let Some(handler) = handlers.volume.as_mut() {
+                    handler(unsafe { write.raw_value().u } as u8);
+                }
+            }
+
+            Ok(())
+        });
+
+        Ok(Audio {
+            service,
+            mute: service
+                .char_by_uuid(HAP_CHAR_UUID_MUTE)
+                .ok_or(HapError::Fail(hap::HAP_FAIL_))?,
+            volume: service.char_by_uuid(HAP_CHAR_UUID_VOLUME),
+            callbacks,
+        })
+    }
+}
+
+/// A Speaker or Microphone service, see [`Speaker`] and [`Microphone`].
+pub struct Audio {
+    service: HapService,
+    mute: HapCharacteristic,
+    volume: Option<HapCharacteristic>,
+    callbacks: Arc<Mutex<AudioCallbacks>>,
+}
+
+impl Audio {
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    pub fn set_mute(&self, mute: bool) -> Result<(), HapError> {
+        self.mute.update(&CharValue::Bool(mute))
+    }
+
+    /// Update the volume, fails if the service was built without one.
+    pub fn set_volume(&self, volume: u8) -> Result<(), HapError> {
+        match &self.volume {
+            Some(characteristic) => characteristic.update(&CharValue::UInt8(volume.min(100))),
+            None => Err(HapError::Fail(hap::HAP_FAIL_)),
+        }
+    }
+
+    /// Called when a controller mutes or unmutes.
+    pub fn on_mute<F>(&self, handler: F)
+    where
+        F: FnMut(bool) + Send + 'static,
+    {
+        self.callbacks.lock().unwrap().mute = Some(Box::new(handler));
+    }
+
+    /// Called when a controller changes the volume.
+    pub fn on_volume<F>(&self, handler: F)
+    where
+        F: FnMut(u8) + Send + 'static,
+    {
+        self.callbacks.lock().unwrap().volume = Some(Box::new(handler));
+    }
+}
+
+pub struct Speaker;
+
+impl Speaker {
+    pub fn builder() -> AudioBuilder {
+        info!("Creating speaker service");
+
+        AudioBuilder {
+            create: hap_serv_speaker_create,
+            name: None,
+            primary: false,
+            mute: false,
+            volume: None,
+        }
+    }
+}
+
+pub struct Microphone;
+
+impl Microphone {
+    pub fn builder() -> AudioBuilder {
+        info!("Creating microphone service");
+
+        AudioBuilder {
+            create: hap_serv_microphone_create,
+            name: None,
+            primary: false,
+            mute: false,
+            volume: None,
+        }
+    }
+}
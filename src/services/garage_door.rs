@@ -0,0 +1,246 @@
+use std::sync::{Arc, Mutex};
+
+use log::*;
+
+use crate::characteristic::{CharValue, HapCharacteristic};
+use crate::error::{HapError, HapStatus};
+use crate::service::HapService;
+use crate::*;
+
+/// The `CurrentDoorState` of a garage door.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GarageDoorState {
+    Open = 0,
+    Closed = 1,
+    Opening = 2,
+    Closing = 3,
+    /// Stopped before reaching the target, e.g. on an obstruction.
+    Stopped = 4,
+}
+
+impl GarageDoorState {
+    /// Whether a door in this state can be reported as `next` right away.
+    ///
+    /// The door passes through `Opening` or `Closing` between `Open` and `Closed`. A
+    /// moving door may reverse or stop, a stopped one only start moving again.
+    pub fn can_become(self, next: GarageDoorState) -> bool {
+        use GarageDoorState::*;
+
+        match (self, next) {
+            (current, next) if current == next => true,
+            (Open, Closing) | (Closed, Opening) => true,
+            (Opening, Open) | (Opening, Closing) | (Opening, Stopped) => true,
+            (Closing, Closed) | (Closing, Opening) | (Closing, Stopped) => true,
+            (Stopped, Opening) | (Stopped, Closing) => true,
+            _ => false,
+        }
+    }
+
+    /// The `TargetDoorState` implied by this state, `None` for `Stopped`.
+    fn target(self) -> Option<TargetDoorState> {
+        match self {
+            GarageDoorState::Open | GarageDoorState::Opening => Some(TargetDoorState::Open),
+            GarageDoorState::Closed | GarageDoorState::Closing => Some(TargetDoorState::Closed),
+            GarageDoorState::Stopped => None,
+        }
+    }
+}
+
+/// The `TargetDoorState` of a garage door.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TargetDoorState {
+    Open = 0,
+    Closed = 1,
+}
+
+impl TargetDoorState {
+    fn from_raw(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(TargetDoorState::Open),
+            1 => Some(TargetDoorState::Closed),
+            _ => None,
+        }
+    }
+}
+
+pub struct GarageDoorBuilder {
+    name: Option<String>,
+    primary: bool,
+    state: GarageDoorState,
+}
+
+impl GarageDoorBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Mark the service as the primary service of its accessory, see [`HapService::mark_primary`].
+    pub fn primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    /// The initial state, `Closed` by default.
+    pub fn state(mut self, state: GarageDoorState) -> Self {
+        self.state = state;
+        self
+    }
+
+    pub fn build(self) -> Result<GarageDoorOpener, HapError> {
+        let target = self.state.target().unwrap_or(TargetDoorState::Closed);
+        let service = HapService::from_raw(unsafe {
+            hap_serv_garage_door_opener_create(self.state as u8, target as u8, false)
+        });
+        if let Some(name) = &self.name {
+            service.add_name(name)?;
+        }
+        if self.primary {
+            service.mark_primary()?;
+        }
+
+        let characteristic = |uuid: &[u8]| service.char_by_uuid(uuid).ok_or(HapError::Fail(hap::HAP_FAIL_));
+
+        Ok(GarageDoorOpener {
+            service,
+            current: characteristic(HAP_CHAR_UUID_CURRENT_DOOR_STATE)?,
+            target: characteristic(HAP_CHAR_UUID_TARGET_DOOR_STATE)?,
+            obstruction: characteristic(HAP_CHAR_UUID_OBSTRUCTION_DETECTED)?,
+            state: Arc::new(Mutex::new(self.state)),
+        })
+    }
+}
+
+/// A Garage Door Opener service, keeping `CurrentDoorState` and `TargetDoorState`
+/// consistent with the transitions of [`GarageDoorState::can_become`].
+pub struct GarageDoorOpener {
+    service: HapService,
+    current: HapCharacteristic,
+    target: HapCharacteristic,
+    obstruction: HapCharacteristic,
+    state: Arc<Mutex<GarageDoorState>>,
+}
+
+impl GarageDoorOpener {
+    pub fn builder() -> GarageDoorBuilder {
+        info!("Creating garage door opener service");
+
+        GarageDoorBuilder {
+            name: None,
+            primary: false,
+            state: GarageDoorState::Closed,
+        }
+    }
+
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    /// The last state reported through [`set_state`](Self::set_state).
+    pub fn state(&self) -> GarageDoorState {
+        *self.state.lock().unwrap()
+    }
+
+    /// Report the door moving or reaching a position.
+    ///
+    /// Fails with [`HapError::InvalidArg`] for a transition the door can't make, e.g.
+    /// from `Open` straight to `Closed`. `TargetDoorState` follows the direction of
+    /// movement, so opening or closing the door by hand shows up correctly; `Stopped`
+    /// keeps it.
+    pub fn set_state(&self, next: GarageDoorState) -> Result<(), HapError> {
+        let mut state = self.state.lock().unwrap();
+
+        if !state.can_become(next) {
+            warn!("Invalid garage door transition from {:?} to {:?}", *state, next);
+            return Err(HapError::InvalidArg);
+        }
+
+        if let Some(target) = next.target() {
+            self.target.update(&CharValue::UInt8(target as u8))?;
+        }
+        self.current.update(&CharValue::UInt8(next as u8))?;
+        *state = next;
+
+        Ok(())
+    }
+
+    /// Update `ObstructionDetected`, typically along with a [`GarageDoorState::Stopped`].
+    pub fn set_obstructed(&self, obstructed: bool) -> Result<(), HapError> {
+        self.obstruction.update(&CharValue::Bool(obstructed))
+    }
+
+    /// Install the handler for controller requests to open or close the door.
+    ///
+    /// The handler is expected to start the motor and report the movement through
+    /// [`set_state`](Self::set_state). Requests for the position the door is already
+    /// in or moving to don't reach it.
+    pub fn on_target<F>(&self, mut on_target: F)
+    where
+        F: FnMut(TargetDoorState) -> Result<(), HapStatus> + Send + 'static,
+    {
+        let state = self.state.clone();
+
+        self.service.on_write(move |write| {
+            if !write.is_uuid(HAP_CHAR_UUID_TARGET_DOOR_STATE) {
+                return Ok(());
+            }
+
+            let target = TargetDoorState::from_raw(unsafe { write.raw_value().u }).ok_or(HapStatus::InvalidValue)?;
+            if state.lock().unwrap().target() == Some(target) {
+                return Ok(());
+            }
+
+            on_target(target)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GarageDoorState::*;
+    use super::*;
+
+    const STATES: [GarageDoorState; 5] = [Open, Closed, Opening, Closing, Stopped];
+
+    #[test]
+    fn doors_move_between_open_and_closed() {
+        assert!(Closed.can_become(Opening));
+        assert!(Opening.can_become(Open));
+        assert!(Open.can_become(Closing));
+        assert!(Closing.can_become(Closed));
+
+        assert!(!Closed.can_become(Open));
+        assert!(!Open.can_become(Closed));
+    }
+
+    #[test]
+    fn moving_doors_reverse_or_stop() {
+        assert!(Opening.can_become(Closing));
+        assert!(Closing.can_become(Opening));
+        assert!(Opening.can_become(Stopped));
+        assert!(Closing.can_become(Stopped));
+
+        assert!(Stopped.can_become(Opening));
+        assert!(Stopped.can_become(Closing));
+        assert!(!Stopped.can_become(Open));
+        assert!(!Stopped.can_become(Closed));
+        assert!(!Open.can_become(Stopped));
+        assert!(!Closed.can_become(Stopped));
+    }
+
+    #[test]
+    fn every_state_can_stay() {
+        for state in STATES {
+            assert!(state.can_become(state), "{:?}", state);
+        }
+    }
+
+    #[test]
+    fn states_imply_their_target() {
+        assert_eq!(Open.target(), Some(TargetDoorState::Open));
+        assert_eq!(Opening.target(), Some(TargetDoorState::Open));
+        assert_eq!(Closed.target(), Some(TargetDoorState::Closed));
+        assert_eq!(Closing.target(), Some(TargetDoorState::Closed));
+        assert_eq!(Stopped.target(), None);
+    }
+}
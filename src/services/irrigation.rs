@@ -0,0 +1,459 @@
+use std::ffi::CString;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::*;
+
+use crate::accessory::Accessory;
+use crate::characteristic::{CharValue, HapCharacteristic};
+use crate::error::{HapError, HapStatus};
+use crate::service::HapService;
+use crate::*;
+
+const ACTIVE: u8 = 1;
+const INACTIVE: u8 = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValveType {
+    Generic = 0,
+    Irrigation = 1,
+    ShowerHead = 2,
+    WaterFaucet = 3,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgramMode {
+    NoProgramScheduled = 0,
+    ProgramScheduled = 1,
+    ProgramScheduledManualMode = 2,
+}
+
+struct System {
+    service: HapService,
+    in_use: HapCharacteristic,
+    program_mode: HapCharacteristic,
+    state: Mutex<SystemState>,
+}
+
+struct SystemState {
+    program_mode: ProgramMode,
+    running: usize,
+    /// The zones of [`running`](Self::running) started manually.
+    running_manually: usize,
+}
+
+impl System {
+    fn set_program_mode(&self, state: &mut SystemState, mode: ProgramMode) -> Result<(), HapError> {
+        if state.program_mode != mode {
+            state.program_mode = mode;
+            self.program_mode.update(&CharValue::UInt8(mode as u8))?;
+        }
+
+        Ok(())
+    }
+
+    /// Called whenever a zone starts or stops, `manual` if the run was started from the
+    /// Home app or with [`ZoneHandle::start_manual`] rather than by the program.
+    ///
+    /// Keeps the system `InUse` in sync with its zones and flips a scheduled
+    /// program into manual mode while any zone runs outside of it.
+    fn zone_changed(&self, started: bool, manual: bool) -> Result<(), HapError> {
+        let mut state = self.state.lock().unwrap();
+
+        if started {
+            state.running += 1;
+            state.running_manually += manual as usize;
+            if state.running == 1 {
+                self.in_use.update(&CharValue::UInt8(ACTIVE))?;
+            }
+            if manual && state.program_mode == ProgramMode::ProgramScheduled {
+                self.set_program_mode(&mut state, ProgramMode::ProgramScheduledManualMode)?;
+            }
+        } else {
+            state.running = state.running.saturating_sub(1);
+            state.running_manually = state.running_manually.saturating_sub(manual as usize);
+            if state.running == 0 {
+                self.in_use.update(&CharValue::UInt8(INACTIVE))?;
+            }
+            if state.running_manually == 0 && state.program_mode == ProgramMode::ProgramScheduledManualMode {
+                self.set_program_mode(&mut state, ProgramMode::ProgramScheduled)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Zone {
+    valve: HapService,
+    active: HapCharacteristic,
+    in_use: HapCharacteristic,
+    set_duration: HapCharacteristic,
+    remaining_duration: HapCharacteristic,
+    run: Mutex<Option<Run>>,
+}
+
+/// A running zone.
+#[derive(Clone, Copy)]
+struct Run {
+    until: Instant,
+    manual: bool,
+}
+
+/// A single valve of an [`IrrigationSystem`].
+#[derive(Clone)]
+pub struct ZoneHandle {
+    zone: Arc<Zone>,
+    system: Arc<System>,
+}
+
+impl ZoneHandle {
+    pub fn service(&self) -> HapService {
+        self.zone.valve
+    }
+
+    /// Open the valve for `duration` as part of the watering program, counting
+    /// `RemainingDuration` down on [`IrrigationSystem::tick`].
+    pub fn start(&self, duration: Duration) -> Result<(), HapError> {
+        self.run(duration, false)
+    }
+
+    /// Like [`start`](Self::start), but outside of the watering program, e.g. from a
+    /// button on the device. Zones opened from the Home app run manually too.
+    pub fn start_manual(&self, duration: Duration) -> Result<(), HapError> {
+        self.run(duration, true)
+    }
+
+    fn run(&self, duration: Duration, manual: bool) -> Result<(), HapError> {
+        let run = Run {
+            until: Instant::now() + duration,
+            manual,
+        };
+        let previous = self.zone.run.lock().unwrap().replace(run);
+
+        self.zone.active.update(&CharValue::UInt8(ACTIVE))?;
+        self.zone.in_use.update(&CharValue::UInt8(ACTIVE))?;
+        self.zone
+            .remaining_duration
+            .update(&CharValue::UInt32(duration.as_secs() as u32))?;
+
+        match previous {
+            None => self.system.zone_changed(true, manual)?,
+            // Taken over by or from the program, started first so `InUse` stays set.
+            Some(previous) if previous.manual != manual => {
+                self.system.zone_changed(true, manual)?;
+                self.system.zone_changed(false, previous.manual)?;
+            }
+            Some(_) => {}
+        }
+
+        Ok(())
+    }
+
+    /// Close the valve.
+    pub fn stop(&self) -> Result<(), HapError> {
+        let previous = self.zone.run.lock().unwrap().take();
+
+        self.zone.active.update(&CharValue::UInt8(INACTIVE))?;
+        self.zone.in_use.update(&CharValue::UInt8(INACTIVE))?;
+        self.zone.remaining_duration.update(&CharValue::UInt32(0))?;
+
+        if let Some(previous) = previous {
+            self.system.zone_changed(false, previous.manual)?;
+        }
+
+        Ok(())
+    }
+
+    /// Time left until the valve closes, `None` if it is not running.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.zone
+            .run
+            .lock()
+            .unwrap()
+            .map(|run| run.until.saturating_duration_since(Instant::now()))
+    }
+
+    /// The duration configured by the controller through `SetDuration`.
+    pub fn set_duration(&self) -> Duration {
+        Duration::from_secs(unsafe { self.zone.set_duration.raw_value().u } as u64)
+    }
+
+    fn tick(&self) -> Result<(), HapError> {
+        match self.remaining() {
+            Some(remaining) if remaining.is_zero() => self.stop(),
+            Some(remaining) => self
+                .zone
+                .remaining_duration
+                .update(&CharValue::UInt32(remaining.as_secs() as u32)),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct IrrigationSystemBuilder {
+    system: Arc<System>,
+    faucet: Option<HapService>,
+    zones: Vec<ZoneHandle>,
+    primary: bool,
+}
+
+impl IrrigationSystemBuilder {
+    /// Add a valve linked to the irrigation system, labelled with its 1-based zone index.
+    pub fn add_zone(&mut self, name: &str, valve_type: ValveType) -> Result<ZoneHandle, HapError> {
+        let label_index = self.zones.len() as u8 + 1;
+
+        info!("Creating irrigation zone {} ({})", label_index, name);
+
+        let valve = HapService::from_raw(unsafe {
+            hap_serv_valve_create(INACTIVE, INACTIVE, valve_type as u8)
+        });
+        let name = CString::new(name).map_err(|_| HapError::InvalidArg)?;
+
+        unsafe {
+            valve.add_char(HapCharacteristic::from_raw(hap_char_name_create(name.as_ptr() as *mut i8)))?;
+            valve.add_char(HapCharacteristic::from_raw(hap_char_service_label_index_create(label_index)))?;
+            valve.add_char(HapCharacteristic::from_raw(hap_char_set_duration_create(300)))?;
+            valve.add_char(HapCharacteristic::from_raw(hap_char_remaining_duration_create(0)))?;
+        }
+
+        let missing = || HapError::Fail(hap::HAP_FAIL_);
+        let zone = ZoneHandle {
+            zone: Arc::new(Zone {
+                valve,
+                active: valve.char_by_uuid(HAP_CHAR_UUID_ACTIVE).ok_or_else(missing)?,
+                in_use: valve.char_by_uuid(HAP_CHAR_UUID_IN_USE).ok_or_else(missing)?,
+                set_duration: valve.char_by_uuid(HAP_CHAR_UUID_SET_DURATION).ok_or_else(missing)?,
+                remaining_duration: valve
+                    .char_by_uuid(HAP_CHAR_UUID_REMAINING_DURATION)
+                    .ok_or_else(missing)?,
+                run: Mutex::new(None),
+            }),
+            system: self.system.clone(),
+        };
+
+        let handle = zone.clone();
+        valve.on_write(move |write| {
+            if write.is_uuid(HAP_CHAR_UUID_ACTIVE) {
+                let result = if unsafe { write.raw_value().u } as u8 == ACTIVE {
+                    handle.start_manual(handle.set_duration())
+                } else {
+                    handle.stop()
+                };
+
+                result.map_err(|_| HapStatus::Busy)?;
+            }
+
+            Ok(())
+        });
+
+        self.system.service.link(&valve)?;
+
+        self.zones.push(zone.clone());
+
+        Ok(zone)
+    }
+
+    /// Also expose a Faucet service as the parent of all zones.
+    pub fn faucet(&mut self) -> &mut Self {
+        if self.faucet.is_none() {
+            self.faucet = Some(HapService::from_raw(unsafe { hap_serv_faucet_create(ACTIVE) }));
+        }
+
+        self
+    }
+
+    /// Mark the irrigation system as the primary service of the accessory.
+    pub fn primary(&mut self, primary: bool) -> &mut Self {
+        self.primary = primary;
+        self
+    }
+
+    /// Add the irrigation system, the faucet and all zones to `accessory`.
+    pub fn build(self, accessory: &Accessory) -> Result<IrrigationSystem, HapError> {
+        if self.primary {
+            self.system.service.mark_primary()?;
+        }
+        if let Some(faucet) = &self.faucet {
+            for zone in &self.zones {
+                faucet.link(&zone.zone.valve)?;
+            }
+        }
+
+        let services = self
+            .faucet
+            .iter()
+            .chain(Some(&self.system.service))
+            .chain(self.zones.iter().map(|zone| &zone.zone.valve));
+
+        hap::batch(|| {
+            for service in services {
+                accessory.add_service(service)?;
+            }
+
+            Ok::<(), HapError>(())
+        })?;
+
+        Ok(IrrigationSystem {
+            system: self.system,
+            zones: self.zones,
+        })
+    }
+}
+
+pub struct IrrigationSystem {
+    system: Arc<System>,
+    zones: Vec<ZoneHandle>,
+}
+
+impl IrrigationSystem {
+    pub fn builder() -> Result<IrrigationSystemBuilder, HapError> {
+        info!("Creating irrigation system");
+
+        let service = HapService::from_raw(unsafe {
+            hap_serv_irrigation_system_create(ACTIVE, ProgramMode::NoProgramScheduled as u8, INACTIVE)
+        });
+
+        let missing = || HapError::Fail(hap::HAP_FAIL_);
+        Ok(IrrigationSystemBuilder {
+            system: Arc::new(System {
+                service,
+                in_use: service.char_by_uuid(HAP_CHAR_UUID_IN_USE).ok_or_else(missing)?,
+                program_mode: service.char_by_uuid(HAP_CHAR_UUID_PROGRAM_MODE).ok_or_else(missing)?,
+                state: Mutex::new(SystemState {
+                    program_mode: ProgramMode::NoProgramScheduled,
+                    running: 0,
+                    running_manually: 0,
+                }),
+            }),
+            faucet: None,
+            zones: Vec::new(),
+            primary: false,
+        })
+    }
+
+    pub fn service(&self) -> HapService {
+        self.system.service
+    }
+
+    pub fn zones(&self) -> &[ZoneHandle] {
+        &self.zones
+    }
+
+    /// Declare whether a watering program is scheduled.
+    ///
+    /// While a program is scheduled, manually started zones switch the system
+    /// into `ProgramScheduledManualMode` until they are stopped again.
+    pub fn set_program_scheduled(&self, scheduled: bool) -> Result<(), HapError> {
+        let mut state = self.system.state.lock().unwrap();
+        let mode = match (scheduled, state.running_manually) {
+            (false, _) => ProgramMode::NoProgramScheduled,
+            (true, 0) => ProgramMode::ProgramScheduled,
+            (true, _) => ProgramMode::ProgramScheduledManualMode,
+        };
+
+        self.system.set_program_mode(&mut state, mode)
+    }
+
+    /// Advance the countdown of all running zones, closing the ones that expired.
+    ///
+    /// Call this periodically, e.g. once per second from the main loop.
+    pub fn tick(&self) -> Result<(), HapError> {
+        for zone in &self.zones {
+            zone.tick()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::HapContext;
+    use crate::mock;
+
+    /// An irrigation system with two zones, with a program scheduled.
+    fn scheduled_system() -> IrrigationSystem {
+        HapContext::new_isolated();
+        let accessory = Accessory::new(&mock::accessory_config("Sprinkler")).unwrap();
+
+        let mut builder = IrrigationSystem::builder().unwrap();
+        builder.add_zone("Lawn", ValveType::Irrigation).unwrap();
+        builder.add_zone("Beds", ValveType::Irrigation).unwrap();
+        let system = builder.build(&accessory).unwrap();
+        system.set_program_scheduled(true).unwrap();
+
+        system
+    }
+
+    fn program_mode(system: &IrrigationSystem) -> u8 {
+        unsafe { system.system.program_mode.raw_value().u as u8 }
+    }
+
+    fn in_use(system: &IrrigationSystem) -> u8 {
+        unsafe { system.system.in_use.raw_value().u as u8 }
+    }
+
+    #[test]
+    fn program_runs_keep_the_program_mode() {
+        let system = scheduled_system();
+        let lawn = &system.zones()[0];
+
+        lawn.start(Duration::from_secs(60)).unwrap();
+        assert_eq!(in_use(&system), ACTIVE);
+        assert_eq!(program_mode(&system), ProgramMode::ProgramScheduled as u8);
+
+        lawn.stop().unwrap();
+        assert_eq!(in_use(&system), INACTIVE);
+    }
+
+    #[test]
+    fn controller_starts_switch_to_manual_mode() {
+        let system = scheduled_system();
+        let (lawn, beds) = (&system.zones()[0], &system.zones()[1]);
+        let active = |zone: &ZoneHandle| zone.zone.active;
+
+        lawn.start(Duration::from_secs(60)).unwrap();
+        assert_eq!(mock::write(active(beds), &CharValue::UInt8(ACTIVE)), HapStatus::Success);
+        assert_eq!(program_mode(&system), ProgramMode::ProgramScheduledManualMode as u8);
+        // Controllers start zones for their `SetDuration`.
+        assert!(beds.remaining().unwrap() > beds.set_duration() - Duration::from_secs(1));
+
+        // The program zone keeps running, but the manual run is over.
+        assert_eq!(mock::write(active(beds), &CharValue::UInt8(INACTIVE)), HapStatus::Success);
+        assert_eq!(program_mode(&system), ProgramMode::ProgramScheduled as u8);
+        assert_eq!(in_use(&system), ACTIVE);
+    }
+
+    #[test]
+    fn taking_over_a_manual_run_ends_manual_mode() {
+        let system = scheduled_system();
+        let lawn = &system.zones()[0];
+
+        lawn.start_manual(Duration::from_secs(60)).unwrap();
+        assert_eq!(program_mode(&system), ProgramMode::ProgramScheduledManualMode as u8);
+
+        lawn.start(Duration::from_secs(60)).unwrap();
+        assert_eq!(program_mode(&system), ProgramMode::ProgramScheduled as u8);
+        assert_eq!(in_use(&system), ACTIVE);
+    }
+
+    #[test]
+    fn ticks_count_down_and_close_expired_zones() {
+        let system = scheduled_system();
+        let (lawn, beds) = (&system.zones()[0], &system.zones()[1]);
+
+        lawn.start(Duration::from_secs(600)).unwrap();
+        beds.start(Duration::ZERO).unwrap();
+        system.tick().unwrap();
+
+        let remaining = unsafe { lawn.zone.remaining_duration.raw_value().u };
+        assert!((599..=600).contains(&remaining), "{}", remaining);
+        assert!(lawn.remaining().is_some());
+
+        assert_eq!(beds.remaining(), None);
+        assert_eq!(unsafe { beds.zone.active.raw_value().u } as u8, INACTIVE);
+        assert_eq!(unsafe { beds.zone.remaining_duration.raw_value().u }, 0);
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::{Arc, Mutex};
+
+use log::*;
+
+use crate::characteristic::{CharValue, HapCharacteristic};
+use crate::error::HapError;
+use crate::service::HapService;
+use crate::*;
+
+#[derive(Default)]
+struct LightbulbCallbacks {
+    power: Option<Box<dyn FnMut(bool) + Send>>,
+    brightness: Option<Box<dyn FnMut(u8) + Send>>,
+}
+
+pub struct LightbulbBuilder {
+    name: Option<String>,
+    primary: bool,
+    on: bool,
+    brightness: Option<u8>,
+}
+
+impl LightbulbBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Mark the service as the primary service of its accessory, see [`HapService::mark_primary`].
+    pub fn primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    pub fn on(mut self, on: bool) -> Self {
+        self.on = on;
+        self
+    }
+
+    /// Add the optional `Brightness` characteristic with the given initial value (0-100).
+    pub fn brightness(mut self, brightness: u8) -> Self {
+        self.brightness = Some(brightness.min(100));
+        self
+    }
+
+    pub fn build(self) -> Result<Lightbulb, HapError> {
+        info!("Creating lightbulb service");
+
+        let service = HapService::from_raw(unsafe { hap_serv_lightbulb_create(self.on) });
+        if let Some(name) = &self.name {
+            service.add_name(name)?;
+        }
+        if self.primary {
+            service.mark_primary()?;
+        }
+        if let Some(brightness) = self.brightness {
+            service.add_char(HapCharacteristic::from_raw(unsafe {
+                hap_char_brightness_create(brightness as i32)
+            }))?;
+        }
+
+        let callbacks = Arc::new(Mutex::new(LightbulbCallbacks::default()));
+
+        let handlers = callbacks.clone();
+        service.on_write(move |write| {
+            let mut handlers = handlers.lock().unwrap();
+
+            if write.is_uuid(HAP_CHAR_UUID_ON) {
+                if let Some(handler) = handlers.power.as_mut() {
+                    handler(unsafe { write.raw_value().b });
+                }
+            } else if write.is_uuid(HAP_CHAR_UUID_BRIGHTNESS) {
+                if let Some(handler) = handlers.brightness.as_mut() {
+                    handler(unsafe { write.raw_value().i }.clamp(0, 100) as u8);
+                }
+            }
+
+            Ok(())
+        });
+
+        Ok(Lightbulb {
+            service,
+            on: service
+                .char_by_uuid(HAP_CHAR_UUID_ON)
+                .ok_or(HapError::Fail(hap::HAP_FAIL_))?,
+            brightness: service.char_by_uuid(HAP_CHAR_UUID_BRIGHTNESS),
+            callbacks,
+        })
+    }
+}
+
+/// A Lightbulb service, optionally dimmable.
+pub struct Lightbulb {
+    service: HapService,
+    on: HapCharacteristic,
+    brightness: Option<HapCharacteristic>,
+    callbacks: Arc<Mutex<LightbulbCallbacks>>,
+}
+
+impl Lightbulb {
+    pub fn builder() -> LightbulbBuilder {
+        LightbulbBuilder {
+            name: None,
+            primary: false,
+            on: false,
+            brightness: None,
+        }
+    }
+
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    pub fn set_on(&self, on: bool) -> Result<(), HapError> {
+        self.on.update(&CharValue::Bool(on))
+    }
+
+    /// Update the brightness, fails if the service was built without one.
+    pub fn set_brightness(&self, brightness: u8) -> Result<(), HapError> {
+        match &self.brightness {
+            Some(characteristic) => characteristic.update(&CharValue::Int(brightness.min(100) as i32)),
+            None => Err(HapError::Fail(hap::HAP_FAIL_)),
+        }
+    }
+
+    /// Called when a controller switches the light on or off.
+    pub fn on_power<F>(&self, handler: F)
+    where
+        F: FnMut(bool) + Send + 'static,
+    {
+        self.callbacks.lock().unwrap().power = Some(Box::new(handler));
+    }
+
+    /// Called when a controller changes the brightness.
+    pub fn on_brightness<F>(&self, handler: F)
+    where
+        F: FnMut(u8) + Send + 'static,
+    {
+        self.callbacks.lock().unwrap().brightness = Some(Box::new(handler));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::HapContext;
+    use crate::error::HapStatus;
+    use crate::mock;
+
+    #[test]
+    fn writes_reach_the_handlers() {
+        HapContext::new_isolated();
+        let lightbulb = Lightbulb::builder().brightness(50).build().unwrap();
+        let written = Arc::new(Mutex::new(Vec::new()));
+
+        let power = written.clone();
+        lightbulb.on_power(move |on| power.lock().unwrap().push(format!("on {}", on)));
+        let brightness = written.clone();
+        lightbulb.on_brightness(move |level| brightness.lock().unwrap().push(format!("brightness {}", level)));
+
+        assert_eq!(mock::write(lightbulb.on, &CharValue::Bool(true)), HapStatus::Success);
+        assert_eq!(mock::write(lightbulb.brightness.unwrap(), &CharValue::Int(75)), HapStatus::Success);
+
+        assert_eq!(*written.lock().unwrap(), ["on true", "brightness 75"]);
+    }
+
+    #[test]
+    fn brightness_needs_the_characteristic() {
+        HapContext::new_isolated();
+        let lightbulb = Lightbulb::builder().on(true).build().unwrap();
+
+        assert!(unsafe { lightbulb.on.raw_value().b });
+        assert_eq!(lightbulb.set_brightness(10), Err(HapError::Fail(hap::HAP_FAIL_)));
+    }
+}
@@ -0,0 +1,25 @@
+pub mod air_quality;
+pub mod battery;
+pub mod catalog;
+pub mod doorbell;
+pub mod garage_door;
+pub mod irrigation;
+pub mod lightbulb;
+pub mod opening;
+mod positionable;
+pub mod programmable_switch;
+pub mod slat;
+pub mod thermostat;
+
+pub use air_quality::*;
+pub use battery::*;
+pub use catalog::*;
+pub use doorbell::*;
+pub use garage_door::*;
+pub use irrigation::*;
+pub use lightbulb::*;
+pub use opening::*;
+pub use positionable::{PositionCommand, PositionState};
+pub use programmable_switch::*;
+pub use slat::*;
+pub use thermostat::*;
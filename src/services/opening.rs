@@ -0,0 +1,144 @@
+use log::*;
+
+use crate::characteristic::HapCharacteristic;
+use crate::error::{HapError, HapStatus};
+use crate::service::HapService;
+use crate::services::positionable::{self, Position, PositionCommand, PositionState};
+use crate::*;
+
+pub struct OpeningBuilder {
+    create: unsafe extern "C" fn(u8, u8, u8) -> *mut hap_serv_t,
+    name: Option<String>,
+    primary: bool,
+    position: u8,
+    obstruction_detection: bool,
+    hold_position: bool,
+}
+
+impl OpeningBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Mark the service as the primary service of its accessory, see [`HapService::mark_primary`].
+    pub fn primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    /// The initial position (0-100), closed by default.
+    pub fn position(mut self, position: u8) -> Self {
+        self.position = positionable::clamp(position);
+        self
+    }
+
+    /// Add the optional `ObstructionDetected` characteristic, see [`Opening::set_obstructed`].
+    pub fn obstruction_detection(mut self) -> Self {
+        self.obstruction_detection = true;
+        self
+    }
+
+    /// Add the optional `HoldPosition` characteristic, so controllers can send
+    /// [`PositionCommand::Hold`].
+    pub fn hold_position(mut self) -> Self {
+        self.hold_position = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Opening, HapError> {
+        let service = HapService::from_raw(unsafe {
+            (self.create)(self.position, self.position, PositionState::Stopped as u8)
+        });
+        if let Some(name) = &self.name {
+            service.add_name(name)?;
+        }
+        if self.primary {
+            service.mark_primary()?;
+        }
+        if self.obstruction_detection {
+            service.add_char(HapCharacteristic::from_raw(unsafe { hap_char_obstruction_detect_create(false) }))?;
+        }
+        if self.hold_position {
+            service.add_char(HapCharacteristic::from_raw(unsafe { hap_char_hold_position_create(false) }))?;
+        }
+
+        Ok(Opening {
+            service,
+            position: Position::from_service(service)?,
+        })
+    }
+}
+
+/// A Door or Window service, see [`Door`] and [`Window`].
+pub struct Opening {
+    service: HapService,
+    position: Position,
+}
+
+impl Opening {
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    /// Report the current and target position (0-100), e.g. while the motor runs.
+    ///
+    /// `PositionState` follows from the two and is `Stopped` once they are equal.
+    /// Out-of-range positions are clamped.
+    pub fn update(&self, current: u8, target: u8) -> Result<(), HapError> {
+        self.position.update(current, target)
+    }
+
+    /// Update `ObstructionDetected`, fails if the service was built without it.
+    pub fn set_obstructed(&self, obstructed: bool) -> Result<(), HapError> {
+        self.position.set_obstructed(obstructed)
+    }
+
+    /// Install the handler for controller requests to move or stop.
+    ///
+    /// Target positions beyond 100 are rejected before reaching `on_command`. The
+    /// handler is expected to start moving and report progress through [`update`](Self::update).
+    pub fn on_command<F>(&self, mut on_command: F)
+    where
+        F: FnMut(PositionCommand) -> Result<(), HapStatus> + Send + 'static,
+    {
+        self.service.on_write(move |write| match Position::command_of(write) {
+            Some(command) => on_command(command?),
+            None => Ok(()),
+        });
+    }
+}
+
+pub struct Door;
+
+impl Door {
+    pub fn builder() -> OpeningBuilder {
+        info!("Creating door service");
+
+        OpeningBuilder {
+            create: hap_serv_door_create,
+            name: None,
+            primary: false,
+            position: 0,
+            obstruction_detection: false,
+            hold_position: false,
+        }
+    }
+}
+
+pub struct Window;
+
+impl Window {
+    pub fn builder() -> OpeningBuilder {
+        info!("Creating window service");
+
+        OpeningBuilder {
+            create: hap_serv_window_create,
+            name: None,
+            primary: false,
+            position: 0,
+            obstruction_detection: false,
+            hold_position: false,
+        }
+    }
+}
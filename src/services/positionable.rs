@@ -0,0 +1,130 @@
+//! The `CurrentPosition`, `TargetPosition` and `PositionState` characteristics
+//! shared by services that open and close, e.g. [`Door`](super::Door) and
+//! [`Window`](super::Window).
+
+use core::cmp::Ordering;
+
+use crate::characteristic::{CharValue, HapCharacteristic};
+use crate::error::{HapError, HapStatus};
+use crate::service::{HapService, WriteData};
+use crate::*;
+
+/// Positions are percentages, 0 is fully closed and 100 fully open.
+pub(crate) const MAX_POSITION: u8 = 100;
+
+/// The value of `PositionState`, i.e. where the device is moving.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionState {
+    Decreasing = 0,
+    Increasing = 1,
+    Stopped = 2,
+}
+
+impl PositionState {
+    /// The state while moving from `current` to `target`.
+    pub(crate) fn between(current: u8, target: u8) -> Self {
+        match current.cmp(&target) {
+            Ordering::Less => PositionState::Increasing,
+            Ordering::Greater => PositionState::Decreasing,
+            Ordering::Equal => PositionState::Stopped,
+        }
+    }
+}
+
+/// A controller request to move a [`Door`](super::Door) or [`Window`](super::Window).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PositionCommand {
+    /// Move to the position, 0-100.
+    MoveTo(u8),
+    /// Stop where it is, only sent if built with `hold_position`.
+    Hold,
+}
+
+pub(crate) fn clamp(position: u8) -> u8 {
+    position.min(MAX_POSITION)
+}
+
+/// The position characteristics of a service, plus `ObstructionDetected` if it has one.
+pub(crate) struct Position {
+    current: HapCharacteristic,
+    target: HapCharacteristic,
+    state: HapCharacteristic,
+    obstruction: Option<HapCharacteristic>,
+}
+
+impl Position {
+    /// Look up the characteristics on a service created with all of them.
+    pub(crate) fn from_service(service: HapService) -> Result<Self, HapError> {
+        let characteristic = |uuid: &[u8]| service.char_by_uuid(uuid).ok_or(HapError::Fail(hap::HAP_FAIL_));
+
+        let position = Position {
+            current: characteristic(HAP_CHAR_UUID_CURRENT_POSITION)?,
+            target: characteristic(HAP_CHAR_UUID_TARGET_POSITION)?,
+            state: characteristic(HAP_CHAR_UUID_POSITION_STATE)?,
+            obstruction: service.char_by_uuid(HAP_CHAR_UUID_OBSTRUCTION_DETECTED),
+        };
+
+        position.current.set_int_constraints(0, MAX_POSITION as i32, 1)?;
+        position.target.set_int_constraints(0, MAX_POSITION as i32, 1)?;
+
+        Ok(position)
+    }
+
+    /// Report the current and target position, clamped to 0-100.
+    ///
+    /// `PositionState` is derived from the two, so it is `Stopped` once they match.
+    pub(crate) fn update(&self, current: u8, target: u8) -> Result<(), HapError> {
+        let (current, target) = (clamp(current), clamp(target));
+
+        self.current.update(&CharValue::UInt8(current))?;
+        self.target.update(&CharValue::UInt8(target))?;
+        self.state
+            .update(&CharValue::UInt8(PositionState::between(current, target) as u8))
+    }
+
+    /// Fails if the service was built without `ObstructionDetected`.
+    pub(crate) fn set_obstructed(&self, obstructed: bool) -> Result<(), HapError> {
+        match &self.obstruction {
+            Some(characteristic) => characteristic.update(&CharValue::Bool(obstructed)),
+            None => Err(HapError::Fail(hap::HAP_FAIL_)),
+        }
+    }
+
+    /// Decode a controller write, `None` for writes to other characteristics.
+    pub(crate) fn command_of(write: &WriteData) -> Option<Result<PositionCommand, HapStatus>> {
+        if write.is_uuid(HAP_CHAR_UUID_TARGET_POSITION) {
+            let target = unsafe { write.raw_value().u };
+            if target > MAX_POSITION as u32 {
+                return Some(Err(HapStatus::InvalidValue));
+            }
+
+            Some(Ok(PositionCommand::MoveTo(target as u8)))
+        } else if write.is_uuid(HAP_CHAR_UUID_HOLD_POSITION) {
+            // Only `true` is meaningful, HAP defines no action for `false`.
+            unsafe { write.raw_value().b }.then_some(Ok(PositionCommand::Hold))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positions_are_clamped_to_percentages() {
+        assert_eq!(clamp(0), 0);
+        assert_eq!(clamp(42), 42);
+        assert_eq!(clamp(MAX_POSITION), MAX_POSITION);
+        assert_eq!(clamp(101), MAX_POSITION);
+        assert_eq!(clamp(u8::MAX), MAX_POSITION);
+    }
+
+    #[test]
+    fn the_state_follows_the_direction() {
+        assert_eq!(PositionState::between(0, 100), PositionState::Increasing);
+        assert_eq!(PositionState::between(100, 30), PositionState::Decreasing);
+        assert_eq!(PositionState::between(30, 30), PositionState::Stopped);
+    }
+}
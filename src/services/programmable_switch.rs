@@ -0,0 +1,177 @@
+use std::sync::Mutex;
+
+use log::*;
+
+use crate::characteristic::{HapCharacteristic, SwitchEvent};
+use crate::error::HapError;
+use crate::service::HapService;
+use crate::*;
+
+/// How the labels of the buttons on an accessory are printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Namespace {
+    Dots = 0,
+    ArabicNumerals = 1,
+}
+
+/// The Service Label service of a multi-button accessory.
+///
+/// Each [`ProgrammableSwitch`] built against it gets a `ServiceLabelIndex`,
+/// which must be unique within the accessory and start at 1.
+pub struct ServiceLabel {
+    service: HapService,
+    indices: Mutex<Vec<u8>>,
+}
+
+impl ServiceLabel {
+    pub fn new(namespace: Namespace) -> Self {
+        info!("Creating service label service");
+
+        ServiceLabel {
+            service: HapService::from_raw(unsafe { hap_serv_service_label_create(namespace as u8) }),
+            indices: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    /// Reserve `index`, failing if it is 0 or already taken.
+    fn claim(&self, index: u8) -> Result<(), HapError> {
+        let mut indices = self.indices.lock().unwrap();
+
+        if index == 0 || indices.contains(&index) {
+            warn!("Service label index {} is invalid or already in use", index);
+            return Err(HapError::InvalidArg);
+        }
+
+        indices.push(index);
+
+        Ok(())
+    }
+
+    fn release(&self, index: u8) {
+        self.indices.lock().unwrap().retain(|i| *i != index);
+    }
+}
+
+pub struct ProgrammableSwitchBuilder {
+    name: Option<String>,
+    primary: bool,
+    label_index: Option<u8>,
+}
+
+impl ProgrammableSwitchBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Mark the service as the primary service of its accessory, see [`HapService::mark_primary`].
+    pub fn primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    /// Tag the switch with a `ServiceLabelIndex`, requires building against a [`ServiceLabel`].
+    pub fn label_index(mut self, index: u8) -> Self {
+        self.label_index = Some(index);
+        self
+    }
+
+    /// Create the switch, linking it to `label` when a label index was set.
+    ///
+    /// The label index is validated before any service is created.
+    pub fn build(self, label: Option<&ServiceLabel>) -> Result<ProgrammableSwitch, HapError> {
+        let labelled = match (self.label_index, label) {
+            (Some(index), Some(label)) => {
+                label.claim(index)?;
+                Some((index, label))
+            }
+            (None, None) => None,
+            _ => return Err(HapError::InvalidArg),
+        };
+
+        match self.create(labelled) {
+            Ok(switch) => Ok(switch),
+            Err(err) => {
+                if let Some((index, label)) = labelled {
+                    label.release(index);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn create(&self, labelled: Option<(u8, &ServiceLabel)>) -> Result<ProgrammableSwitch, HapError> {
+        info!("Creating stateless programmable switch service");
+
+        let service = HapService::from_raw(unsafe {
+            hap_serv_stateless_programmable_switch_create(SwitchEvent::SinglePress as u8)
+        });
+        if let Some(name) = &self.name {
+            service.add_name(name)?;
+        }
+        if self.primary {
+            service.mark_primary()?;
+        }
+
+        if let Some((index, label)) = labelled {
+            service.add_char(HapCharacteristic::from_raw(unsafe {
+                hap_char_service_label_index_create(index)
+            }))?;
+            label.service.link(&service)?;
+        }
+
+        let event = service
+            .char_by_uuid(HAP_CHAR_UUID_PROGRAMMABLE_SWITCH_EVENT)
+            .ok_or(HapError::Fail(hap::HAP_FAIL_))?;
+        event.set_event_only();
+
+        Ok(ProgrammableSwitch { service, event })
+    }
+}
+
+pub struct ProgrammableSwitch {
+    service: HapService,
+    event: HapCharacteristic,
+}
+
+impl ProgrammableSwitch {
+    pub fn builder() -> ProgrammableSwitchBuilder {
+        ProgrammableSwitchBuilder {
+            name: None,
+            primary: false,
+            label_index: None,
+        }
+    }
+
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    pub fn fire_event(&self, event: SwitchEvent) -> Result<(), HapError> {
+        self.event.fire_event(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::HapContext;
+    use crate::mock;
+
+    #[test]
+    fn events_map_to_their_values() {
+        let _context = HapContext::new_isolated();
+        let switch = ProgrammableSwitch::builder().build(None).unwrap();
+
+        for (event, raw) in [(SwitchEvent::SinglePress, 0), (SwitchEvent::DoublePress, 1), (SwitchEvent::LongPress, 2)] {
+            switch.fire_event(event).unwrap();
+
+            assert_eq!(unsafe { (*hap_char_get_val(switch.event.as_raw())).u }, raw, "{:?}", event);
+        }
+        assert_eq!(mock::notifications(switch.event), 3);
+    }
+}
@@ -0,0 +1,197 @@
+use log::*;
+
+use crate::characteristic::{CharValue, HapCharacteristic};
+use crate::error::{HapError, HapStatus};
+use crate::service::HapService;
+use crate::*;
+
+/// Tilt angles are in degrees, from -90 to 90.
+const TILT_RANGE: (i32, i32) = (-90, 90);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlatType {
+    Horizontal = 0,
+    Vertical = 1,
+}
+
+/// The value of `CurrentSlatState`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlatState {
+    Fixed = 0,
+    Jammed = 1,
+    Swinging = 2,
+}
+
+/// A controller request to a [`Slat`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlatCommand {
+    /// Tilt to the angle in degrees, -90 to 90.
+    TiltTo(i32),
+    /// Start or stop swinging.
+    Swing(bool),
+}
+
+pub struct SlatBuilder {
+    name: Option<String>,
+    primary: bool,
+    slat_type: SlatType,
+    tilt: bool,
+    swing: bool,
+}
+
+impl SlatBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Mark the service as the primary service of its accessory, see [`HapService::mark_primary`].
+    pub fn primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    pub fn slat_type(mut self, slat_type: SlatType) -> Self {
+        self.slat_type = slat_type;
+        self
+    }
+
+    /// Add the optional `CurrentTiltAngle` and `TargetTiltAngle` characteristics.
+    pub fn tilt(mut self) -> Self {
+        self.tilt = true;
+        self
+    }
+
+    /// Add the optional `SwingMode` characteristic.
+    pub fn swing_mode(mut self) -> Self {
+        self.swing = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Slat, HapError> {
+        info!("Creating slat service");
+
+        let service = HapService::from_raw(unsafe {
+            hap_serv_slat_create(SlatState::Fixed as u8, self.slat_type as u8)
+        });
+        if let Some(name) = &self.name {
+            service.add_name(name)?;
+        }
+        if self.primary {
+            service.mark_primary()?;
+        }
+
+        let tilt = if self.tilt {
+            let angle = |ptr| -> Result<HapCharacteristic, HapError> {
+                let characteristic = HapCharacteristic::from_raw(ptr);
+                service.add_char(characteristic)?;
+                characteristic.set_int_constraints(TILT_RANGE.0, TILT_RANGE.1, 1)?;
+
+                Ok(characteristic)
+            };
+
+            let (current, target) =
+                unsafe { (hap_char_current_tilt_angle_create(0), hap_char_target_tilt_angle_create(0)) };
+            Some((angle(current)?, angle(target)?))
+        } else {
+            None
+        };
+
+        let swing = if self.swing {
+            let characteristic = HapCharacteristic::from_raw(unsafe { hap_char_swing_mode_create(0) });
+            service.add_char(characteristic)?;
+
+            Some(characteristic)
+        } else {
+            None
+        };
+
+        Ok(Slat {
+            service,
+            state: service
+                .char_by_uuid(HAP_CHAR_UUID_CURRENT_SLAT_STATE)
+                .ok_or(HapError::Fail(hap::HAP_FAIL_))?,
+            tilt,
+            swing,
+        })
+    }
+}
+
+/// A Slat service, e.g. the louvres of a fan or air conditioner.
+pub struct Slat {
+    service: HapService,
+    state: HapCharacteristic,
+    /// The current and target tilt angle, if built with [`SlatBuilder::tilt`].
+    tilt: Option<(HapCharacteristic, HapCharacteristic)>,
+    swing: Option<HapCharacteristic>,
+}
+
+impl Slat {
+    pub fn builder() -> SlatBuilder {
+        SlatBuilder {
+            name: None,
+            primary: false,
+            slat_type: SlatType::Horizontal,
+            tilt: false,
+            swing: false,
+        }
+    }
+
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    pub fn set_state(&self, state: SlatState) -> Result<(), HapError> {
+        self.state.update(&CharValue::UInt8(state as u8))
+    }
+
+    /// Report the current and target tilt angle, clamped to -90..90 degrees.
+    ///
+    /// Fails if the service was built without tilt.
+    pub fn set_tilt(&self, current: i32, target: i32) -> Result<(), HapError> {
+        let (current_angle, target_angle) = self.tilt.as_ref().ok_or(HapError::Fail(hap::HAP_FAIL_))?;
+        let clamp = |angle: i32| angle.clamp(TILT_RANGE.0, TILT_RANGE.1);
+
+        current_angle.update(&CharValue::Int(clamp(current)))?;
+        target_angle.update(&CharValue::Int(clamp(target)))
+    }
+
+    /// Report whether the slats are swinging, fails if the service was built without
+    /// swing mode.
+    pub fn set_swing(&self, swinging: bool) -> Result<(), HapError> {
+        match &self.swing {
+            Some(characteristic) => characteristic.update(&CharValue::UInt8(swinging as u8)),
+            None => Err(HapError::Fail(hap::HAP_FAIL_)),
+        }
+    }
+
+    /// Install the handler for controller requests.
+    ///
+    /// Tilt angles outside of -90..90 and unknown swing modes are rejected before
+    /// reaching `on_command`.
+    pub fn on_command<F>(&self, mut on_command: F)
+    where
+        F: FnMut(SlatCommand) -> Result<(), HapStatus> + Send + 'static,
+    {
+        self.service.on_write(move |write| {
+            let command = if write.is_uuid(HAP_CHAR_UUID_TARGET_TILT_ANGLE) {
+                let angle = unsafe { write.raw_value().i };
+                if !(TILT_RANGE.0..=TILT_RANGE.1).contains(&angle) {
+                    return Err(HapStatus::InvalidValue);
+                }
+
+                SlatCommand::TiltTo(angle)
+            } else if write.is_uuid(HAP_CHAR_UUID_SWING_MODE) {
+                match unsafe { write.raw_value().u } {
+                    0 => SlatCommand::Swing(false),
+                    1 => SlatCommand::Swing(true),
+                    _ => return Err(HapStatus::InvalidValue),
+                }
+            } else {
+                return Ok(());
+            };
+
+            on_command(command)
+        });
+    }
+}
@@ -0,0 +1,266 @@
+use log::*;
+
+use crate::characteristic::{CharValue, HapCharacteristic};
+use crate::error::{HapError, HapStatus};
+use crate::service::HapService;
+use crate::*;
+
+/// A value of `TargetHeatingCoolingState`; all but `Auto` are also `CurrentHeatingCoolingState`s.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeatingCoolingMode {
+    Off = 0,
+    Heat = 1,
+    Cool = 2,
+    Auto = 3,
+}
+
+impl HeatingCoolingMode {
+    fn from_raw(value: u32) -> Option<Self> {
+        match value {
+            0 => Some(HeatingCoolingMode::Off),
+            1 => Some(HeatingCoolingMode::Heat),
+            2 => Some(HeatingCoolingMode::Cool),
+            3 => Some(HeatingCoolingMode::Auto),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TemperatureDisplayUnits {
+    Celsius = 0,
+    Fahrenheit = 1,
+}
+
+/// The ranges of the threshold temperatures in °C, per HAP.
+const HEATING_THRESHOLD_RANGE: (f32, f32) = (0.0, 25.0);
+const COOLING_THRESHOLD_RANGE: (f32, f32) = (10.0, 35.0);
+const THRESHOLD_STEP: f32 = 0.1;
+
+/// A coordinated update of all characteristics of a [`Thermostat`], see [`Thermostat::update`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThermostatState {
+    pub current_mode: HeatingCoolingMode,
+    pub current_temperature: f32,
+    pub target_mode: HeatingCoolingMode,
+    pub target_temperature: f32,
+    /// Only used by thermostats supporting [`HeatingCoolingMode::Auto`].
+    pub heating_threshold: Option<f32>,
+    /// Only used by thermostats supporting [`HeatingCoolingMode::Auto`].
+    pub cooling_threshold: Option<f32>,
+}
+
+pub struct ThermostatBuilder {
+    name: Option<String>,
+    primary: bool,
+    modes: Vec<HeatingCoolingMode>,
+    range: (f32, f32, f32),
+    display_units: TemperatureDisplayUnits,
+}
+
+impl ThermostatBuilder {
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Mark the service as the primary service of its accessory, see [`HapService::mark_primary`].
+    pub fn primary(mut self, primary: bool) -> Self {
+        self.primary = primary;
+        self
+    }
+
+    /// The target modes the device supports, e.g. `&[Off, Heat]` for a heat-only thermostat.
+    ///
+    /// Advertised as valid values, so the Home app only offers these modes.
+    pub fn modes(mut self, modes: &[HeatingCoolingMode]) -> Self {
+        self.modes = modes.to_vec();
+        self
+    }
+
+    /// The range of the target temperature in °C, 10-38 in 0.5 steps by default.
+    pub fn temperature_range(mut self, min: f32, max: f32, step: f32) -> Self {
+        self.range = (min, max, step);
+        self
+    }
+
+    /// The units the Home app shows temperatures in, values are always in °C.
+    pub fn display_units(mut self, units: TemperatureDisplayUnits) -> Self {
+        self.display_units = units;
+        self
+    }
+
+    /// Build the service with all characteristics and their constraints.
+    ///
+    /// With [`HeatingCoolingMode::Auto`] among the modes, the heating and cooling
+    /// threshold temperatures HAP requires for it are added too.
+    pub fn build(self) -> Result<Thermostat, HapError> {
+        info!("Creating thermostat service");
+
+        if !self.modes.contains(&HeatingCoolingMode::Off) {
+            warn!("Thermostat modes must include Off");
+            return Err(HapError::InvalidArg);
+        }
+
+        let (min, max, step) = self.range;
+        let service = HapService::from_raw(unsafe {
+            hap_serv_thermostat_create(
+                HeatingCoolingMode::Off as u8,
+                HeatingCoolingMode::Off as u8,
+                min,
+                min,
+                self.display_units as u8,
+            )
+        });
+        if let Some(name) = &self.name {
+            service.add_name(name)?;
+        }
+        if self.primary {
+            service.mark_primary()?;
+        }
+
+        let thresholds = if self.modes.contains(&HeatingCoolingMode::Auto) {
+            let threshold = |ptr, (min, max): (f32, f32)| -> Result<HapCharacteristic, HapError> {
+                let characteristic = HapCharacteristic::from_raw(ptr);
+                service.add_char(characteristic)?;
+                characteristic.set_float_constraints(min, max, THRESHOLD_STEP)?;
+
+                Ok(characteristic)
+            };
+
+            let (heating, cooling) = unsafe {
+                (
+                    hap_char_heating_threshold_temperature_create(HEATING_THRESHOLD_RANGE.1),
+                    hap_char_cooling_threshold_temperature_create(COOLING_THRESHOLD_RANGE.0),
+                )
+            };
+            Some((
+                threshold(heating, HEATING_THRESHOLD_RANGE)?,
+                threshold(cooling, COOLING_THRESHOLD_RANGE)?,
+            ))
+        } else {
+            None
+        };
+
+        let characteristic = |uuid: &[u8]| service.char_by_uuid(uuid).ok_or(HapError::Fail(hap::HAP_FAIL_));
+        let thermostat = Thermostat {
+            service,
+            current_mode: characteristic(HAP_CHAR_UUID_CURRENT_HEATING_COOLING_STATE)?,
+            target_mode: characteristic(HAP_CHAR_UUID_TARGET_HEATING_COOLING_STATE)?,
+            current_temperature: characteristic(HAP_CHAR_UUID_CURRENT_TEMPERATURE)?,
+            target_temperature: characteristic(HAP_CHAR_UUID_TARGET_TEMPERATURE)?,
+            thresholds,
+            modes: self.modes.clone(),
+        };
+
+        characteristic(HAP_CHAR_UUID_TEMPERATURE_DISPLAY_UNITS)?.set_valid_values_range(
+            TemperatureDisplayUnits::Celsius as u8,
+            TemperatureDisplayUnits::Fahrenheit as u8,
+        )?;
+
+        // Applied right away: controllers read the valid values on pairing, after `hap::start`
+        // it is too late to add them.
+        let modes = self.modes.iter().map(|&mode| mode as u8).collect::<Vec<_>>();
+        let current = modes
+            .iter()
+            .copied()
+            .filter(|&mode| mode != HeatingCoolingMode::Auto as u8)
+            .collect::<Vec<_>>();
+        thermostat.target_mode.set_valid_values(&modes)?;
+        thermostat.current_mode.set_valid_values(&current)?;
+        thermostat.target_temperature.set_float_constraints(min, max, step)?;
+
+        Ok(thermostat)
+    }
+}
+
+/// A Thermostat service, in °C.
+pub struct Thermostat {
+    service: HapService,
+    current_mode: HapCharacteristic,
+    target_mode: HapCharacteristic,
+    current_temperature: HapCharacteristic,
+    target_temperature: HapCharacteristic,
+    /// The heating and cooling threshold temperatures, with `Auto` mode only.
+    thresholds: Option<(HapCharacteristic, HapCharacteristic)>,
+    modes: Vec<HeatingCoolingMode>,
+}
+
+impl Thermostat {
+    pub fn builder() -> ThermostatBuilder {
+        ThermostatBuilder {
+            name: None,
+            primary: false,
+            modes: vec![
+                HeatingCoolingMode::Off,
+                HeatingCoolingMode::Heat,
+                HeatingCoolingMode::Cool,
+                HeatingCoolingMode::Auto,
+            ],
+            range: (10.0, 38.0, 0.5),
+            display_units: TemperatureDisplayUnits::Celsius,
+        }
+    }
+
+    pub fn service(&self) -> HapService {
+        self.service
+    }
+
+    /// Report what the device is currently doing and the measured temperature.
+    pub fn update_current(&self, mode: HeatingCoolingMode, temperature: f32) -> Result<(), HapError> {
+        if mode == HeatingCoolingMode::Auto {
+            return Err(HapError::InvalidArg);
+        }
+
+        self.current_mode.update(&CharValue::UInt8(mode as u8))?;
+        self.current_temperature.update(&CharValue::Float(temperature))
+    }
+
+    /// Push all characteristics at once, e.g. after the device reported its state.
+    ///
+    /// Fails with [`HapError::InvalidArg`] if `target_mode` is not one of the configured
+    /// modes or `current_mode` is `Auto`, before anything is updated. Thresholds are
+    /// ignored without `Auto` mode.
+    pub fn update(&self, state: &ThermostatState) -> Result<(), HapError> {
+        if state.current_mode == HeatingCoolingMode::Auto || !self.modes.contains(&state.target_mode) {
+            return Err(HapError::InvalidArg);
+        }
+
+        self.current_mode.update(&CharValue::UInt8(state.current_mode as u8))?;
+        self.current_temperature.update(&CharValue::Float(state.current_temperature))?;
+        self.target_mode.update(&CharValue::UInt8(state.target_mode as u8))?;
+        self.target_temperature.update(&CharValue::Float(state.target_temperature))?;
+
+        if let Some((heating, cooling)) = &self.thresholds {
+            if let Some(threshold) = state.heating_threshold {
+                heating.update(&CharValue::Float(threshold))?;
+            }
+            if let Some(threshold) = state.cooling_threshold {
+                cooling.update(&CharValue::Float(threshold))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Install the handlers for target mode and temperature changes by a controller.
+    ///
+    /// Modes outside of [`ThermostatBuilder::modes`] are rejected before reaching `on_mode`.
+    pub fn on_write<M, T>(&self, mut on_mode: M, mut on_temperature: T)
+    where
+        M: FnMut(HeatingCoolingMode) -> Result<(), HapStatus> + Send + 'static,
+        T: FnMut(f32) -> Result<(), HapStatus> + Send + 'static,
+    {
+        self.service.on_write(move |write| {
+            if write.is_uuid(HAP_CHAR_UUID_TARGET_HEATING_COOLING_STATE) {
+                let mode = HeatingCoolingMode::from_raw(unsafe { write.raw_value().u })
+                    .ok_or(HapStatus::InvalidValue)?;
+                on_mode(mode)
+            } else if write.is_uuid(HAP_CHAR_UUID_TARGET_TEMPERATURE) {
+                on_temperature(unsafe { write.raw_value().f })
+            } else {
+                Ok(())
+            }
+        });
+    }
+}
@@ -0,0 +1,63 @@
+#include "esp_homekit_shim.h"
+
+#include "hap.h"
+
+/* From the private esp_hap_controllers.h, only ever used through pointers here. */
+struct hap_ctrl_data;
+struct hap_ctrl_data *hap_get_controller(char *ctrl_id);
+void hap_controller_remove(struct hap_ctrl_data *ctrl);
+
+/* From the private esp_hap_ip_services.h. */
+void hap_close_ctrl_sessions(struct hap_ctrl_data *ctrl);
+int hap_mdns_announce(bool first_announce);
+int hap_mdns_deannounce(void);
+
+/* From the private esp_hap_char.h, `index` is the slot of a paired controller. */
+bool hap_char_is_ctrl_subscribed(hap_char_t *hc, int index);
+
+BaseType_t esp_homekit_shim_xTaskCreate(TaskFunction_t task, const char *name, uint32_t stack_depth,
+                                        void *params, UBaseType_t priority, TaskHandle_t *created_task)
+{
+    return xTaskCreate(task, name, stack_depth, params, priority, created_task);
+}
+
+BaseType_t esp_homekit_shim_xPortGetCoreID(void)
+{
+    return xPortGetCoreID();
+}
+
+int esp_homekit_shim_remove_controller(const char *id)
+{
+    struct hap_ctrl_data *ctrl = hap_get_controller((char *)id);
+    if (!ctrl) {
+        return HAP_FAIL;
+    }
+
+    hap_close_ctrl_sessions(ctrl);
+    hap_controller_remove(ctrl);
+
+    /* The `sf` flag of the records tells controllers the accessory is unpaired. */
+    if (hap_get_paired_controller_count() == 0) {
+        hap_mdns_announce(false);
+    }
+
+    return HAP_SUCCESS;
+}
+
+int esp_homekit_shim_set_advertising(bool advertise)
+{
+    return advertise ? hap_mdns_announce(false) : hap_mdns_deannounce();
+}
+
+int esp_homekit_shim_char_subscribers(hap_char_t *hc)
+{
+    int count = 0;
+
+    for (int index = 0; index < ESP_HOMEKIT_SHIM_MAX_CONTROLLERS; index++) {
+        if (hap_char_is_ctrl_subscribed(hc, index)) {
+            count++;
+        }
+    }
+
+    return count;
+}
@@ -0,0 +1,49 @@
+/*
+ * Linkable wrappers for the `static inline` functions the Rust wrappers use.
+ *
+ * bindgen only emits declarations for functions with external linkage, so each
+ * `static inline` function gets an `esp_homekit_shim_` prefixed counterpart here.
+ * The implementations are compiled as part of the PlatformIO project.
+ *
+ * Shimmed:
+ * - xTaskCreate (FreeRTOS, inline over xTaskCreatePinnedToCore)
+ * - xPortGetCoreID (FreeRTOS port)
+ *
+ * Also wraps the parts of the HAP framework's internal API the Rust wrappers need:
+ * - hap_get_controller/hap_controller_remove (esp_hap_controllers.h)
+ * - hap_close_ctrl_sessions (esp_hap_ip_services.h)
+ * - hap_mdns_announce/hap_mdns_deannounce (esp_hap_ip_services.h)
+ * - hap_char_is_ctrl_subscribed (esp_hap_char.h)
+ */
+#pragma once
+
+#include <stdbool.h>
+
+#include "freertos/FreeRTOS.h"
+#include "freertos/task.h"
+
+#include "hap.h"
+
+/*
+ * The paired controllers the HAP framework stores, HAP_MAX_CONTROLLERS of the private
+ * esp_hap_controllers.h, which isn't on the include path of the project.
+ */
+#define ESP_HOMEKIT_SHIM_MAX_CONTROLLERS 16
+
+BaseType_t esp_homekit_shim_xTaskCreate(TaskFunction_t task, const char *name, uint32_t stack_depth,
+                                        void *params, UBaseType_t priority, TaskHandle_t *created_task);
+
+BaseType_t esp_homekit_shim_xPortGetCoreID(void);
+
+/*
+ * Remove the paired controller `id` like the remove pairing request of an admin: its
+ * sessions are closed, and the mDNS records re-announced with the `sf` flag once the
+ * last pairing is gone. Returns HAP_FAIL if there is no such controller.
+ */
+int esp_homekit_shim_remove_controller(const char *id);
+
+/* Re-announce or withdraw the mDNS records of the HAP server. */
+int esp_homekit_shim_set_advertising(bool advertise);
+
+/* The number of controllers subscribed to events of `hc`. */
+int esp_homekit_shim_char_subscribers(hap_char_t *hc);
@@ -0,0 +1,119 @@
+//! One-shot timers running a closure on the `esp_timer` task, e.g. to reboot a
+//! while after unpairing or to stop blinking after an identify request.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use core::ptr;
+
+use crate::error::{self, HapError};
+use crate::*;
+
+type Callback = Box<dyn FnOnce() + Send>;
+
+/// Shared by the [`Timer`] and the pending `esp_timer` callback, which each hold a reference.
+struct Slot {
+    handle: Mutex<esp_timer_handle_t>,
+    callback: Mutex<Option<Callback>>,
+}
+
+// The handle is only passed to the thread-safe `esp_timer_*` functions.
+unsafe impl Send for Slot {}
+unsafe impl Sync for Slot {}
+
+impl Drop for Slot {
+    // Also runs from within `fire` for fired timers, deleting a timer from its own
+    // callback is allowed once it is no longer armed.
+    fn drop(&mut self) {
+        let handle = *self.handle.get_mut().unwrap();
+
+        if !handle.is_null() {
+            unsafe {
+                esp_timer_delete(handle);
+            }
+        }
+    }
+}
+
+/// A pending [`after`] call, canceled when dropped unless [`detach`](Self::detach)ed.
+#[must_use = "the timer is canceled when dropped, use `detach` to let it run"]
+pub struct Timer {
+    slot: Option<Arc<Slot>>,
+}
+
+impl Timer {
+    /// Cancel the timer; a no-op if it already fired.
+    pub fn cancel(self) {}
+
+    /// Let the timer fire without keeping the guard around, e.g. for a delayed reboot.
+    pub fn detach(mut self) {
+        self.slot = None;
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let slot = match self.slot.take() {
+            Some(slot) => slot,
+            None => return,
+        };
+
+        slot.callback.lock().unwrap().take();
+
+        let handle = *slot.handle.lock().unwrap();
+        if unsafe { esp_timer_stop(handle) } == ESP_OK as i32 {
+            // Stopped before firing, so the callback won't release its reference.
+            unsafe { drop(Arc::from_raw(Arc::as_ptr(&slot))) };
+        }
+    }
+}
+
+/// Run `f` once after `delay`, on the `esp_timer` task.
+///
+/// `f` should return quickly, it delays all other timers of the system. Dropping the
+/// returned [`Timer`] cancels it.
+pub fn after<F>(delay: Duration, f: F) -> Result<Timer, HapError>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let slot = Arc::new(Slot {
+        handle: Mutex::new(ptr::null_mut()),
+        callback: Mutex::new(Some(Box::new(f))),
+    });
+
+    // The reference of the callback, released by `fire` or when stopped before firing.
+    let arg = Arc::into_raw(slot.clone()) as *mut c_types::c_void;
+
+    let args = esp_timer_create_args_t {
+        callback: Some(fire),
+        arg,
+        dispatch_method: esp_timer_dispatch_t_ESP_TIMER_TASK,
+        name: b"hap_timer\0".as_ptr() as *const i8,
+        skip_unhandled_events: false,
+    };
+    let mut handle: esp_timer_handle_t = ptr::null_mut();
+
+    let code = unsafe { esp_timer_create(&args, &mut handle) };
+    if code != ESP_OK as i32 {
+        unsafe { drop(Arc::from_raw(arg as *const Slot)) };
+        return Err(HapError::Fail(code));
+    }
+    *slot.handle.lock().unwrap() = handle;
+
+    let code = unsafe { esp_timer_start_once(handle, delay.as_micros() as u64) };
+    if code != ESP_OK as i32 {
+        unsafe { drop(Arc::from_raw(arg as *const Slot)) };
+        return Err(HapError::Fail(code));
+    }
+
+    Ok(Timer { slot: Some(slot) })
+}
+
+unsafe extern "C" fn fire(arg: *mut c_types::c_void) {
+    let slot = Arc::from_raw(arg as *const Slot);
+    let callback = slot.callback.lock().unwrap().take();
+
+    if let Some(callback) = callback {
+        error::catch_panic("timer callback", (), callback);
+    }
+}